@@ -1,3 +1,4 @@
+#[cfg(feature = "libraw")]
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("cargo:rustc-flags=-l raw");
 
@@ -22,3 +23,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+/// Without the `libraw` feature, raw files are read by the built-in reader in `src/raw.rs`, which
+/// needs neither LibRaw nor bindgen.
+#[cfg(not(feature = "libraw"))]
+fn main() {}
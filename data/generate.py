@@ -0,0 +1,118 @@
+#!/usr/bin/env python3
+"""Generates the time zone boundary database embedded by `src/time_zone_boundaries.rs`.
+
+The input is a GeoJSON release of the timezone-boundary-builder project
+(https://github.com/evansiroky/timezone-boundary-builder/releases), preferably
+`timezones-with-oceans.geojson.zip`, so that positions at sea resolve to the nautical time zones.
+Use the variant without "-now" or "-1970" in its name, which keeps zones apart that only agree on
+their offsets since a recent date, since images may have been taken long before.
+
+Rings are simplified with the Douglas-Peucker algorithm to keep the database small. The tolerance
+is given in degrees; the default of 0.001 degrees (about 100 meters) keeps positions near borders
+in the right time zone.
+
+Usage: python3 generate.py timezones-with-oceans.geojson data/time_zones.bin [tolerance]
+"""
+import json, struct, sys
+
+MAGIC = b"ciid-tz1"
+COORDINATE_SCALE = 100_000
+
+
+def simplify(ring, tolerance):
+    """Simplify a closed ring with the Douglas-Peucker algorithm, keeping its first point."""
+    if len(ring) < 4:
+        return ring
+
+    keep = [False] * len(ring)
+    keep[0] = keep[-1] = True
+    stack = [(0, len(ring) - 1)]
+
+    while stack:
+        start, end = stack.pop()
+        (x1, y1), (x2, y2) = ring[start], ring[end]
+        dx, dy = x2 - x1, y2 - y1
+        length = (dx * dx + dy * dy) ** 0.5
+
+        farthest, distance = None, tolerance
+
+        for index in range(start + 1, end):
+            x, y = ring[index]
+
+            if length == 0:
+                current = ((x - x1) ** 2 + (y - y1) ** 2) ** 0.5
+            else:
+                current = abs(dy * x - dx * y + x2 * y1 - y2 * x1) / length
+
+            if current > distance:
+                farthest, distance = index, current
+
+        if farthest is not None:
+            keep[farthest] = True
+            stack.append((start, farthest))
+            stack.append((farthest, end))
+
+    return [point for point, kept in zip(ring, keep) if kept]
+
+
+def scale(value):
+    return int(round(value * COORDINATE_SCALE))
+
+
+def encode_polygon(rings, tolerance):
+    encoded = []
+
+    for ring in rings:
+        # GeoJSON rings repeat their first point at the end, the database closes rings implicitly.
+        points = simplify(ring, tolerance)[:-1]
+        points = [(scale(longitude), scale(latitude)) for longitude, latitude in points]
+
+        if len(points) >= 3:
+            encoded.append(points)
+
+    if not encoded:
+        return None
+
+    longitudes = [longitude for longitude, _ in encoded[0]]
+    latitudes = [latitude for _, latitude in encoded[0]]
+
+    data = struct.pack(
+        "<4iI", min(longitudes), min(latitudes), max(longitudes), max(latitudes), len(encoded)
+    )
+
+    for points in encoded:
+        data += struct.pack("<I", len(points))
+        data += b"".join(struct.pack("<2i", *point) for point in points)
+
+    return data
+
+
+def main():
+    source, target = sys.argv[1], sys.argv[2]
+    tolerance = float(sys.argv[3]) if len(sys.argv) > 3 else 0.001
+
+    with open(source) as file:
+        features = json.load(file)["features"]
+
+    zones = []
+
+    for feature in sorted(features, key=lambda feature: feature["properties"]["tzid"]):
+        geometry = feature["geometry"]
+        polygons = (
+            [geometry["coordinates"]]
+            if geometry["type"] == "Polygon"
+            else geometry["coordinates"]
+        )
+
+        encoded = [encode_polygon(rings, tolerance) for rings in polygons]
+        encoded = [polygon for polygon in encoded if polygon is not None]
+
+        name = feature["properties"]["tzid"].encode()
+        zones.append(struct.pack("<B", len(name)) + name + struct.pack("<I", len(encoded)) + b"".join(encoded))
+
+    with open(target, "wb") as file:
+        file.write(MAGIC + struct.pack("<I", len(zones)) + b"".join(zones))
+
+
+if __name__ == "__main__":
+    main()
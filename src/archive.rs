@@ -0,0 +1,309 @@
+use crate::walk;
+
+/// Separator between the path of an archive and the name of a member inside of it, e.g.
+/// `batch.zip!/DCIM/IMG_1234.JPG`.
+pub const SEPARATOR: &str = "!/";
+
+/// Archive formats images can be read from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Zip,
+    Tar,
+    /// TAR archive compressed with gzip.
+    TarGz,
+}
+
+impl Kind {
+    /// The archive format of a file, judging by its extension. `None` if it is no archive.
+    ///
+    /// # Arguments
+    /// * `file_path` – Path to file.
+    pub fn from_path(file_path: &std::path::Path) -> Option<Self> {
+        let file_name = file_path.file_name()?.to_str()?.to_lowercase();
+
+        if file_name.ends_with(".zip") {
+            Some(Kind::Zip)
+        } else if file_name.ends_with(".tar") {
+            Some(Kind::Tar)
+        } else if file_name.ends_with(".tar.gz") || file_name.ends_with(".tgz") {
+            Some(Kind::TarGz)
+        } else {
+            None
+        }
+    }
+}
+
+/// Split a path referring to a single member of an archive, e.g. `batch.zip!/DCIM/IMG_1234.JPG`,
+/// into the path of the archive and the name of the member. `None` if the path does not refer to
+/// an archive member.
+///
+/// # Arguments
+/// * `path` – Path to file or archive member.
+pub fn split_member_path(path: &str) -> Option<(&std::path::Path, &str)> {
+    path.match_indices(SEPARATOR).find_map(|(index, _)| {
+        let archive_path = std::path::Path::new(&path[..index]);
+
+        Kind::from_path(archive_path).map(|_| (archive_path, &path[index + SEPARATOR.len()..]))
+    })
+}
+
+/// Path under which an archive member is reported, e.g. `batch.zip!/DCIM/IMG_1234.JPG`.
+///
+/// # Arguments
+/// * `archive_path` – Path to the archive.
+/// * `member` – Name of the member inside of the archive.
+pub fn member_path(archive_path: &std::path::Path, member: &str) -> std::path::PathBuf {
+    let mut path = archive_path.as_os_str().to_owned();
+    path.push(SEPARATOR);
+    path.push(member);
+
+    path.into()
+}
+
+/// Read the image members of an archive one at a time, in the order they are stored, so that only
+/// a single member is held in memory. Members are recognized as images by their extension.
+///
+/// # Arguments
+/// * `archive_path` – Path to the archive.
+/// * `member` – Name of the only member to be read, if any.
+/// * `function` – Function called with the name and contents of each member.
+pub fn for_each_image<F>(
+    archive_path: &std::path::Path,
+    member: Option<&str>,
+    mut function: F,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    F: FnMut(&str, &[u8]) -> Result<(), Box<dyn std::error::Error>>,
+{
+    let kind = Kind::from_path(archive_path)
+        .ok_or_else(|| format!("Unknown archive format: {}", archive_path.display()))?;
+
+    let file = std::fs::File::open(archive_path)
+        .map_err(|error| format!("Failed opening archive: {}", error))?;
+
+    let mut found = false;
+
+    let mut select = |name: &str| {
+        let selected = match member {
+            Some(member) => name == member,
+            None => walk::is_image(std::path::Path::new(name)),
+        };
+
+        found |= selected;
+
+        selected
+    };
+
+    match kind {
+        Kind::Zip => {
+            let mut archive = zip::ZipArchive::new(std::io::BufReader::new(file))
+                .map_err(|error| format!("Failed reading ZIP archive: {}", error))?;
+
+            for index in 0..archive.len() {
+                let mut entry = archive
+                    .by_index(index)
+                    .map_err(|error| format!("Failed reading ZIP archive: {}", error))?;
+
+                let name = entry.name().to_owned();
+
+                if !entry.is_file() || !select(&name) {
+                    continue;
+                }
+
+                let mut data = Vec::new();
+                std::io::Read::read_to_end(&mut entry, &mut data)
+                    .map_err(|error| format!("Failed reading {}: {}", name, error))?;
+
+                function(&name, &data)?;
+            }
+        }
+        Kind::Tar => for_each_tar_image(file, &mut select, &mut function)?,
+        Kind::TarGz => for_each_tar_image(
+            flate2::read::GzDecoder::new(std::io::BufReader::new(file)),
+            &mut select,
+            &mut function,
+        )?,
+    }
+
+    match member {
+        Some(member) if !found => Err(format!(
+            "No member {} in archive {}",
+            member,
+            archive_path.display()
+        ))?,
+        _ => Ok(()),
+    }
+}
+
+fn for_each_tar_image<R, S, F>(
+    reader: R,
+    select: &mut S,
+    function: &mut F,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    R: std::io::Read,
+    S: FnMut(&str) -> bool,
+    F: FnMut(&str, &[u8]) -> Result<(), Box<dyn std::error::Error>>,
+{
+    let mut archive = tar::Archive::new(reader);
+
+    let entries = archive
+        .entries()
+        .map_err(|error| format!("Failed reading TAR archive: {}", error))?;
+
+    for entry in entries {
+        let mut entry = entry.map_err(|error| format!("Failed reading TAR archive: {}", error))?;
+
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let path = entry
+            .path()
+            .map_err(|error| format!("Failed reading TAR archive: {}", error))?;
+
+        let name = match path.to_str() {
+            None => Err(format!("Invalid member name: {:?}", path))?,
+            Some(name) => name.trim_start_matches("./").to_owned(),
+        };
+
+        if !select(&name) {
+            continue;
+        }
+
+        let mut data = Vec::new();
+        std::io::Read::read_to_end(&mut entry, &mut data)
+            .map_err(|error| format!("Failed reading {}: {}", name, error))?;
+
+        function(&name, &data)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kind_from_path() {
+        let kind = |file_path| Kind::from_path(std::path::Path::new(file_path));
+
+        assert_eq!(kind("/batches/batch.zip"), Some(Kind::Zip));
+        assert_eq!(kind("batch.TAR"), Some(Kind::Tar));
+        assert_eq!(kind("batch.tar.gz"), Some(Kind::TarGz));
+        assert_eq!(kind("batch.tgz"), Some(Kind::TarGz));
+        assert_eq!(kind("IMG_1234.JPG"), None);
+        assert_eq!(kind("zip"), None);
+    }
+
+    #[test]
+    fn test_split_member_path() {
+        assert_eq!(
+            split_member_path("/batches/batch.zip!/DCIM/IMG_1234.JPG"),
+            Some((
+                std::path::Path::new("/batches/batch.zip"),
+                "DCIM/IMG_1234.JPG"
+            ))
+        );
+        assert_eq!(
+            split_member_path("/batches/a!/b.tar!/IMG_1234.CR2"),
+            Some((std::path::Path::new("/batches/a!/b.tar"), "IMG_1234.CR2"))
+        );
+        assert_eq!(split_member_path("/images!/IMG_1234.JPG"), None);
+        assert_eq!(split_member_path("/images/IMG_1234.JPG"), None);
+    }
+
+    #[test]
+    fn test_member_path() {
+        assert_eq!(
+            member_path(
+                std::path::Path::new("/batches/batch.zip"),
+                "DCIM/IMG_1234.JPG"
+            ),
+            std::path::PathBuf::from("/batches/batch.zip!/DCIM/IMG_1234.JPG")
+        );
+    }
+
+    #[test]
+    fn test_for_each_image_tar() -> Result<(), Box<dyn std::error::Error>> {
+        let directory = std::env::temp_dir().join("ciid-test_for_each_image_tar");
+        std::fs::create_dir_all(&directory)?;
+
+        let archive_path = directory.join("batch.tar");
+
+        let mut builder = tar::Builder::new(std::fs::File::create(&archive_path)?);
+
+        for (name, data) in &[
+            ("./DCIM/IMG_1.JPG", &b"first"[..]),
+            ("DCIM/notes.txt", b"notes"),
+            ("DCIM/IMG_2.CR2", b"second"),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_cksum();
+            builder.append_data(&mut header, name, *data)?;
+        }
+
+        builder.into_inner()?;
+
+        let mut members = Vec::new();
+        for_each_image(&archive_path, None, |name, data| {
+            members.push((name.to_owned(), data.to_vec()));
+            Ok(())
+        })?;
+
+        assert_eq!(
+            members,
+            vec![
+                ("DCIM/IMG_1.JPG".to_owned(), b"first".to_vec()),
+                ("DCIM/IMG_2.CR2".to_owned(), b"second".to_vec()),
+            ]
+        );
+
+        let mut members = Vec::new();
+        for_each_image(&archive_path, Some("DCIM/IMG_2.CR2"), |name, _| {
+            members.push(name.to_owned());
+            Ok(())
+        })?;
+
+        assert_eq!(members, vec!["DCIM/IMG_2.CR2".to_owned()]);
+
+        assert!(for_each_image(&archive_path, Some("DCIM/IMG_3.CR2"), |_, _| Ok(())).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_for_each_image_zip() -> Result<(), Box<dyn std::error::Error>> {
+        let directory = std::env::temp_dir().join("ciid-test_for_each_image_zip");
+        std::fs::create_dir_all(&directory)?;
+
+        let archive_path = directory.join("batch.zip");
+
+        let mut writer = zip::ZipWriter::new(std::fs::File::create(&archive_path)?);
+
+        for (name, data) in &[
+            ("DCIM/IMG_1.jpg", &b"first"[..]),
+            ("DCIM/notes.txt", b"notes"),
+        ] {
+            writer.start_file(*name, zip::write::FileOptions::default())?;
+            std::io::Write::write_all(&mut writer, data)?;
+        }
+
+        writer.finish()?;
+
+        let mut members = Vec::new();
+        for_each_image(&archive_path, None, |name, data| {
+            members.push((name.to_owned(), data.to_vec()));
+            Ok(())
+        })?;
+
+        assert_eq!(
+            members,
+            vec![("DCIM/IMG_1.jpg".to_owned(), b"first".to_vec())]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,323 @@
+use crate::{hash_image_matching_name, jpeg, parse_identifier, raw_hash, walk};
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("audit")
+        .about("Checks the integrity of image files by comparing the hash of their image buffer to the hash in their file name")
+        .arg(
+            Arg::with_name("path")
+                .takes_value(true)
+                .required(true)
+                .multiple(true)
+                .help("Path to image file or directory. Directories are searched recursively"),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .long("--quiet")
+                .help("Only reports files that are not OK"),
+        )
+        .arg(
+            Arg::with_name("json")
+                .long("--json")
+                .help("Prints a JSON object to stdout, containing the report of each file and a summary"),
+        )
+        .arg(jpeg::arg())
+        .arg(raw_hash::arg())
+}
+
+/// Outcome of the audit of a file.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Status {
+    /// The hash of the image buffer matches the hash in the file name.
+    Ok,
+    /// The hash of the image buffer does not match the hash in the file name.
+    Corrupt,
+    /// The file name does not carry an identifier with a full hash, e.g. because it has been
+    /// renamed by hand.
+    Renamed,
+    /// The image buffer could not be decoded.
+    Undecodable,
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let status = match self {
+            Status::Ok => "ok",
+            Status::Corrupt => "corrupt",
+            Status::Renamed => "renamed",
+            Status::Undecodable => "undecodable",
+        };
+
+        formatter.pad(status)
+    }
+}
+
+/// Report of the audit of a file.
+#[derive(Debug, PartialEq, serde::Serialize)]
+struct Report {
+    file_path: std::path::PathBuf,
+    status: Status,
+    /// Hash carried by the file name.
+    expected: Option<String>,
+    /// Hash of the image buffer.
+    actual: Option<String>,
+    /// Why the file is not OK.
+    reason: Option<String>,
+}
+
+/// Number of files per status.
+#[derive(Debug, Default, PartialEq, serde::Serialize)]
+struct Summary {
+    files: usize,
+    ok: usize,
+    corrupt: usize,
+    renamed: usize,
+    undecodable: usize,
+}
+
+impl Summary {
+    fn add(&mut self, status: Status) {
+        self.files += 1;
+
+        match status {
+            Status::Ok => self.ok += 1,
+            Status::Corrupt => self.corrupt += 1,
+            Status::Renamed => self.renamed += 1,
+            Status::Undecodable => self.undecodable += 1,
+        }
+    }
+}
+
+/// Classify a file by comparing the hash in its file name to the hash of its image buffer.
+///
+/// # Arguments
+/// * `file_path` – Path to the file.
+/// * `hash` – Hash of the image buffer, or why it could not be derived.
+fn classify(file_path: &std::path::Path, hash: Result<[u8; 32], String>) -> Report {
+    let report = |status, expected: Option<&[u8]>, actual: Option<&[u8]>, reason| Report {
+        file_path: file_path.to_owned(),
+        status,
+        expected: expected.map(|hash| data_encoding::HEXLOWER.encode(hash)),
+        actual: actual.map(|hash| data_encoding::HEXLOWER.encode(hash)),
+        reason,
+    };
+
+    let expected = match file_path
+        .file_stem()
+        .and_then(|file_stem| file_stem.to_str())
+        .ok_or_else(|| format!("Invalid file path: {:?}", file_path))
+        .and_then(|file_stem| parse_identifier(file_stem).map_err(|error| error.to_string()))
+    {
+        Ok(parsed) => match parsed.hash {
+            Some(hash) if hash.len() == 32 => hash,
+            _ => {
+                return report(
+                    Status::Renamed,
+                    None,
+                    None,
+                    Some("File name does not carry a hash".to_owned()),
+                )
+            }
+        },
+        Err(error) => return report(Status::Renamed, None, None, Some(error)),
+    };
+
+    match hash {
+        Ok(hash) if hash[..] == expected[..] => {
+            report(Status::Ok, Some(&expected), Some(&hash), None)
+        }
+        Ok(hash) => report(
+            Status::Corrupt,
+            Some(&expected),
+            Some(&hash),
+            Some("Hash of the image buffer does not match the hash in the file name".to_owned()),
+        ),
+        Err(error) => report(Status::Undecodable, Some(&expected), None, Some(error)),
+    }
+}
+
+/// Audit image files, printing a report line for each file, followed by a summary. Lines of
+/// undecodable files are printed to stderr.
+///
+/// # Arguments
+/// * `matches` – Command line arguments of the `audit` subcommand.
+pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let paths: Vec<&str> = matches
+        .values_of("path")
+        .ok_or("No path provided")?
+        .collect();
+
+    let quiet = matches.is_present("quiet");
+
+    let jpeg_hash = jpeg::HashMode::parse(matches.value_of("jpeg hash").unwrap_or("decoded"))?;
+    let raw_hash = raw_hash::HashMode::parse(matches.value_of("raw hash").unwrap_or("sensor"))?;
+
+    let file_paths = walk::walk(&paths)?;
+
+    let reports = walk::par_map(&file_paths, |file_path| {
+        classify(
+            file_path,
+            hash_image_matching_name(file_path, jpeg_hash, raw_hash)
+                .map_err(|error| error.to_string()),
+        )
+    });
+
+    let mut summary = Summary::default();
+
+    for report in &reports {
+        summary.add(report.status);
+    }
+
+    let reports: Vec<Report> = reports
+        .into_iter()
+        .filter(|report| !quiet || report.status != Status::Ok)
+        .collect();
+
+    if matches.is_present("json") {
+        let output = serde_json::json!({
+            "files": reports,
+            "summary": summary,
+        });
+
+        println!("{}", output);
+    } else {
+        for report in &reports {
+            match (
+                report.status,
+                &report.expected,
+                &report.actual,
+                &report.reason,
+            ) {
+                (Status::Corrupt, Some(expected), Some(actual), _) => println!(
+                    "{:<11} {}: expected {}, got {}",
+                    report.status,
+                    report.file_path.display(),
+                    expected,
+                    actual
+                ),
+                // Files that could not be checked are failures rather than results of the audit.
+                (Status::Undecodable, _, _, Some(reason)) => eprintln!(
+                    "{:<11} {}: {}",
+                    report.status,
+                    report.file_path.display(),
+                    reason
+                ),
+                (_, _, _, Some(reason)) => println!(
+                    "{:<11} {}: {}",
+                    report.status,
+                    report.file_path.display(),
+                    reason
+                ),
+                _ => println!("{:<11} {}", report.status, report.file_path.display()),
+            }
+        }
+
+        println!(
+            "{} file(s): {} ok, {} corrupt, {} renamed, {} undecodable",
+            summary.files, summary.ok, summary.corrupt, summary.renamed, summary.undecodable
+        );
+    }
+
+    if summary.corrupt > 0 || summary.undecodable > 0 {
+        Err(format!(
+            "Found {} corrupt and {} undecodable file(s)",
+            summary.corrupt, summary.undecodable
+        ))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9";
+
+    fn hash(hex: &str) -> [u8; 32] {
+        let mut hash = [0; 32];
+        hash.copy_from_slice(&data_encoding::HEXLOWER.decode(hex.as_bytes()).unwrap());
+        hash
+    }
+
+    macro_rules! test_classify {
+        ($test_name:ident, $file_name:expr, $hash:expr, $expected:expr) => {
+            #[test]
+            fn $test_name() {
+                let file_path = std::path::PathBuf::from($file_name);
+
+                assert_eq!(classify(&file_path, $hash).status, $expected);
+            }
+        };
+    }
+
+    test_classify!(
+        test_classify_ok,
+        format!("01483624375960-{}.jpg", HASH),
+        Ok(hash(HASH)),
+        Status::Ok
+    );
+
+    test_classify!(
+        test_classify_ok_camera_id_scheme_version,
+        format!("01483624375960-a1b2c3-{}-v2.jpg", HASH),
+        Ok(hash(HASH)),
+        Status::Ok
+    );
+
+    test_classify!(
+        test_classify_corrupt,
+        format!("01483624375960-{}.jpg", HASH),
+        Ok([0; 32]),
+        Status::Corrupt
+    );
+
+    test_classify!(
+        test_classify_renamed,
+        "IMG_1234.jpg",
+        Ok(hash(HASH)),
+        Status::Renamed
+    );
+
+    test_classify!(
+        test_classify_renamed_no_hash,
+        "01483624375960.jpg",
+        Ok(hash(HASH)),
+        Status::Renamed
+    );
+
+    test_classify!(
+        test_classify_renamed_short_hash,
+        "01483624375960-5704ec43140d.jpg",
+        Ok(hash(HASH)),
+        Status::Renamed
+    );
+
+    test_classify!(
+        test_classify_undecodable,
+        format!("01483624375960-{}.jpg", HASH),
+        Err("Failed hashing .jpg file".to_owned()),
+        Status::Undecodable
+    );
+
+    #[test]
+    fn test_summary() {
+        let mut summary = Summary::default();
+
+        for status in &[Status::Ok, Status::Ok, Status::Corrupt, Status::Undecodable] {
+            summary.add(*status);
+        }
+
+        assert_eq!(
+            summary,
+            Summary {
+                files: 4,
+                ok: 2,
+                corrupt: 1,
+                renamed: 0,
+                undecodable: 1,
+            }
+        );
+    }
+}
@@ -0,0 +1,362 @@
+use clap::{Arg, ArgMatches};
+use sha2::Digest;
+
+/// Results are keyed by the identity and path of the file and the settings they have been derived
+/// with. The path is part of the key, since results may depend on it, e.g. through the file
+/// counter of the file name or the patterns of clock corrections. Size and modification time are
+/// stored alongside, so that results of files that have changed since are not used.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS results (
+    device INTEGER NOT NULL,
+    inode INTEGER NOT NULL,
+    path TEXT NOT NULL,
+    settings TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    modified INTEGER NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (device, inode, path, settings)
+);
+";
+
+/// Command line arguments controlling the cache.
+pub fn args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("cache file")
+            .takes_value(true)
+            .long("--cache-file")
+            .help("Path to a cache of previous results. Files whose device, inode, path, size and modification time are unchanged are skipped. Created if it does not exist"),
+        Arg::with_name("rehash")
+            .long("--rehash")
+            .requires("cache file")
+            .help("Derives the results of all files again, replacing the entries in the cache"),
+    ]
+}
+
+/// Identity, location and state of a file. Renaming or writing to a file changes the key.
+#[derive(Debug, PartialEq)]
+struct FileKey {
+    device: i64,
+    inode: i64,
+    /// Canonical path of the file.
+    path: String,
+    size: i64,
+    /// Modification time, as unix timestamp in nanoseconds.
+    modified: i64,
+}
+
+impl FileKey {
+    /// Read the key of a file.
+    ///
+    /// # Arguments
+    /// * `file_path` – Path to file.
+    #[cfg(unix)]
+    fn read(file_path: &std::path::Path) -> Result<Self, Box<dyn std::error::Error>> {
+        use std::os::unix::fs::MetadataExt;
+
+        let metadata = std::fs::metadata(file_path)?;
+        let path = file_path.canonicalize()?;
+
+        Ok(FileKey {
+            device: metadata.dev() as i64,
+            inode: metadata.ino() as i64,
+            path: path
+                .to_str()
+                .ok_or_else(|| format!("Invalid file path: {:?}", path))?
+                .to_owned(),
+            size: metadata.size() as i64,
+            modified: metadata.mtime() * 1_000_000_000 + metadata.mtime_nsec(),
+        })
+    }
+
+    #[cfg(not(unix))]
+    fn read(_file_path: &std::path::Path) -> Result<Self, Box<dyn std::error::Error>> {
+        Err("The cache is not supported on this platform")?
+    }
+}
+
+/// SQLite database of results derived from files. Can be shared by concurrent runs.
+pub struct Cache {
+    connection: std::sync::Mutex<rusqlite::Connection>,
+    /// Hash of the settings results are derived with.
+    settings: String,
+    /// Derive results again instead of reading them from the cache.
+    rehash: bool,
+}
+
+impl Cache {
+    /// Open the cache provided by `--cache-file`, creating it if it does not exist. Returns `None`
+    /// if no cache file has been provided.
+    ///
+    /// # Arguments
+    /// * `matches` – Command line arguments that include the arguments of `cache::args`.
+    /// * `settings` – Names of the arguments that affect the cached results.
+    /// * `context` – Further information that affects the cached results, e.g. the subcommand.
+    pub fn from_matches(
+        matches: &ArgMatches,
+        settings: &[&str],
+        context: &str,
+    ) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let file_path = match matches.value_of("cache file") {
+            Some(file_path) => std::path::Path::new(file_path),
+            None => return Ok(None),
+        };
+
+        let connection = rusqlite::Connection::open(file_path)
+            .map_err(|error| format!("Failed opening cache {}: {}", file_path.display(), error))?;
+
+        Ok(Some(Cache::init(
+            connection,
+            hash_settings(matches, settings, context),
+            matches.is_present("rehash"),
+        )?))
+    }
+
+    /// Create the schema, unless it exists already.
+    ///
+    /// # Arguments
+    /// * `connection` – Connection to the cache database.
+    /// * `settings` – Hash of the settings results are derived with.
+    /// * `rehash` – Derive results again instead of reading them from the cache.
+    fn init(
+        connection: rusqlite::Connection,
+        settings: String,
+        rehash: bool,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        // Other processes may be writing to the same cache. Write-ahead logging lets them read
+        // while another process is writing.
+        connection.busy_timeout(std::time::Duration::from_secs(10))?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.execute_batch(SCHEMA)?;
+
+        Ok(Cache {
+            connection: std::sync::Mutex::new(connection),
+            settings,
+            rehash,
+        })
+    }
+
+    /// Read the result of a file from the cache, or derive it and store it in the cache. Results
+    /// of files that change while being derived are not stored. Entries that can not be read are
+    /// treated as missing.
+    ///
+    /// # Arguments
+    /// * `file_path` – Path to file.
+    /// * `derive` – Derives the result of the file.
+    pub fn get_or_derive<T, F>(
+        &self,
+        file_path: &std::path::Path,
+        derive: F,
+    ) -> Result<T, Box<dyn std::error::Error>>
+    where
+        T: serde::de::DeserializeOwned + serde::Serialize,
+        F: FnOnce() -> Result<T, Box<dyn std::error::Error>>,
+    {
+        let key = match FileKey::read(file_path) {
+            Ok(key) => key,
+            Err(_) => return derive(),
+        };
+
+        if !self.rehash {
+            if let Some(value) = self.get(&key) {
+                return Ok(value);
+            }
+        }
+
+        let value = derive()?;
+
+        if FileKey::read(file_path).ok().as_ref() == Some(&key) {
+            self.put(&key, &value)
+                .map_err(|error| format!("Failed writing cache: {}", error))?;
+        }
+
+        Ok(value)
+    }
+
+    fn get<T: serde::de::DeserializeOwned>(&self, key: &FileKey) -> Option<T> {
+        let connection = self.connection.lock().ok()?;
+
+        let value: String = connection
+            .query_row(
+                "SELECT value FROM results WHERE device = ? AND inode = ? AND path = ? \
+                 AND settings = ? AND size = ? AND modified = ?",
+                rusqlite::params![
+                    key.device,
+                    key.inode,
+                    key.path,
+                    self.settings,
+                    key.size,
+                    key.modified
+                ],
+                |row| row.get(0),
+            )
+            .ok()?;
+
+        serde_json::from_str(&value).ok()
+    }
+
+    fn put<T: serde::Serialize>(
+        &self,
+        key: &FileKey,
+        value: &T,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let value = serde_json::to_string(value)?;
+
+        let connection = self
+            .connection
+            .lock()
+            .map_err(|_| "Cache connection poisoned")?;
+
+        connection.execute(
+            "INSERT OR REPLACE INTO results (device, inode, path, settings, size, modified, value) \
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+            rusqlite::params![
+                key.device,
+                key.inode,
+                key.path,
+                self.settings,
+                key.size,
+                key.modified,
+                value
+            ],
+        )?;
+
+        Ok(())
+    }
+}
+
+/// Hash the values of the arguments that affect cached results, so that results derived with
+/// other settings or another version of `ciid` are not used.
+///
+/// # Arguments
+/// * `matches` – Parsed command line arguments.
+/// * `settings` – Names of the arguments that affect the cached results.
+/// * `context` – Further information that affects the cached results.
+fn hash_settings(matches: &ArgMatches, settings: &[&str], context: &str) -> String {
+    let mut hasher = sha2::Sha256::new();
+
+    hasher.input(clap::crate_version!());
+    hasher.input("\0");
+    hasher.input(context);
+
+    for name in settings {
+        hasher.input("\0");
+        hasher.input(name);
+
+        if let Some(values) = matches.values_of(name) {
+            for value in values {
+                hasher.input("=");
+                hasher.input(value);
+            }
+        } else if matches.is_present(name) {
+            hasher.input("!");
+        }
+    }
+
+    data_encoding::HEXLOWER.encode(hasher.result().as_slice())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(settings: &str, rehash: bool) -> Cache {
+        Cache::init(
+            rusqlite::Connection::open_in_memory().unwrap(),
+            settings.to_owned(),
+            rehash,
+        )
+        .unwrap()
+    }
+
+    fn temp_file(name: &str) -> std::path::PathBuf {
+        let file_path = std::env::temp_dir().join(format!("ciid-cache-{}", name));
+        std::fs::write(&file_path, "a").unwrap();
+        file_path
+    }
+
+    #[test]
+    fn test_get_or_derive_hit() {
+        let file_path = temp_file("hit");
+        let cache = cache("a", false);
+
+        let derive = |value: u64| move || -> Result<u64, Box<dyn std::error::Error>> { Ok(value) };
+
+        assert_eq!(cache.get_or_derive(&file_path, derive(1)).unwrap(), 1);
+        assert_eq!(cache.get_or_derive(&file_path, derive(2)).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_get_or_derive_rehash() {
+        let file_path = temp_file("rehash");
+        let cache = cache("a", true);
+
+        let derive = |value: u64| move || -> Result<u64, Box<dyn std::error::Error>> { Ok(value) };
+
+        assert_eq!(cache.get_or_derive(&file_path, derive(1)).unwrap(), 1);
+        assert_eq!(cache.get_or_derive(&file_path, derive(2)).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_get_or_derive_changed() {
+        let file_path = temp_file("changed");
+        let cache = cache("a", false);
+
+        let derive = |value: u64| move || -> Result<u64, Box<dyn std::error::Error>> { Ok(value) };
+
+        assert_eq!(cache.get_or_derive(&file_path, derive(1)).unwrap(), 1);
+
+        std::fs::write(&file_path, "ab").unwrap();
+
+        assert_eq!(cache.get_or_derive(&file_path, derive(2)).unwrap(), 2);
+        assert_eq!(cache.get_or_derive(&file_path, derive(3)).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_get_or_derive_renamed() {
+        let file_path = temp_file("renamed");
+        let renamed = file_path.with_file_name("ciid-cache-renamed-IMG_1234");
+        let cache = cache("a", false);
+
+        let derive = |value: u64| move || -> Result<u64, Box<dyn std::error::Error>> { Ok(value) };
+
+        assert_eq!(cache.get_or_derive(&file_path, derive(1)).unwrap(), 1);
+
+        std::fs::rename(&file_path, &renamed).unwrap();
+
+        assert_eq!(cache.get_or_derive(&renamed, derive(2)).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_get_or_derive_settings() {
+        let file_path = temp_file("settings");
+        let connection = rusqlite::Connection::open_in_memory().unwrap();
+        let cache_a = Cache::init(connection, "a".to_owned(), false).unwrap();
+
+        let derive = |value: u64| move || -> Result<u64, Box<dyn std::error::Error>> { Ok(value) };
+
+        assert_eq!(cache_a.get_or_derive(&file_path, derive(1)).unwrap(), 1);
+
+        let connection = cache_a.connection.into_inner().unwrap();
+        let cache_b = Cache::init(connection, "b".to_owned(), false).unwrap();
+
+        assert_eq!(cache_b.get_or_derive(&file_path, derive(2)).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_get_or_derive_error() {
+        let file_path = temp_file("error");
+        let cache = cache("a", false);
+
+        assert!(cache
+            .get_or_derive::<u64, _>(&file_path, || Err("Failed".into()))
+            .is_err());
+        assert_eq!(
+            cache
+                .get_or_derive(&file_path, || -> Result<u64, Box<dyn std::error::Error>> {
+                    Ok(1)
+                })
+                .unwrap(),
+            1
+        );
+    }
+}
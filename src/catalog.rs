@@ -0,0 +1,876 @@
+use crate::{cache, derive_file, jpeg, raw_hash, walk, DateOptions, DeriveOptions, ImageSource};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use rusqlite::types::Value;
+
+/// Version of the catalog schema, stored as `user_version` of the SQLite database.
+const SCHEMA_VERSION: i64 = 1;
+
+/// Images are keyed by identifier. Copies of the same image share the identifier, but are stored
+/// with their own path.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS images (
+    identifier TEXT NOT NULL,
+    path TEXT NOT NULL UNIQUE,
+    size INTEGER NOT NULL,
+    modified INTEGER NOT NULL,
+    timestamp INTEGER NOT NULL,
+    date_time TEXT NOT NULL,
+    hash TEXT NOT NULL,
+    format TEXT NOT NULL,
+    date_tag TEXT NOT NULL,
+    time_zone_source TEXT NOT NULL,
+    camera TEXT,
+    PRIMARY KEY (identifier, path)
+);
+CREATE INDEX IF NOT EXISTS images_timestamp ON images (timestamp);
+CREATE INDEX IF NOT EXISTS images_hash ON images (hash);
+";
+
+/// Columns of the `images` table, in the order of the fields of `Entry`.
+const COLUMNS: &str = "identifier, path, size, modified, timestamp, date_time, hash, format, \
+                       date_tag, time_zone_source, camera";
+
+fn catalog_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("catalog")
+        .takes_value(true)
+        .long("--catalog")
+        .default_value("ciid.sqlite")
+        .help("Path to the catalog database. Created by `index` if it does not exist")
+}
+
+pub fn subcommands<'a, 'b>() -> Vec<App<'a, 'b>> {
+    vec![
+        SubCommand::with_name("index")
+            .about("Adds images to the catalog, replacing the entries of files that have been indexed before")
+            .arg(
+                Arg::with_name("path")
+                    .takes_value(true)
+                    .required(true)
+                    .multiple(true)
+                    .help("Path to image file or directory. Directories are searched recursively"),
+            )
+            .arg(catalog_arg())
+            .arg(
+                Arg::with_name("prune")
+                    .long("--prune")
+                    .help("Removes entries of files that no longer exist from the catalog"),
+            )
+            .args(&DateOptions::args())
+            .arg(
+                Arg::with_name("timestamp digits")
+                    .takes_value(true)
+                    .long("--timestamp-digits")
+                    .help("Minimum number of digits the timestamp should carry. Will be padded with zeros from the left"),
+            )
+            .args(&cache::args()),
+        SubCommand::with_name("query")
+            .about("Lists images of the catalog, ordered by date")
+            .arg(catalog_arg())
+            .arg(
+                Arg::with_name("identifier")
+                    .takes_value(true)
+                    .long("--identifier")
+                    .help("Only lists images whose identifier starts with the provided prefix"),
+            )
+            .arg(
+                Arg::with_name("hash")
+                    .takes_value(true)
+                    .long("--hash")
+                    .help("Only lists images whose hash starts with the provided prefix"),
+            )
+            .arg(
+                Arg::with_name("from")
+                    .takes_value(true)
+                    .long("--from")
+                    .help("Only lists images taken at or after the date, e.g. \"2017-01-05\" or \"2017-01-05T13:52:55+02:00\""),
+            )
+            .arg(
+                Arg::with_name("to")
+                    .takes_value(true)
+                    .long("--to")
+                    .help("Only lists images taken before the date, e.g. \"2017-01-06\" or \"2017-01-05T14:00:00+02:00\""),
+            )
+            .arg(
+                Arg::with_name("camera")
+                    .takes_value(true)
+                    .long("--camera")
+                    .help("Only lists images whose camera description contains the provided text"),
+            )
+            .arg(
+                Arg::with_name("format")
+                    .takes_value(true)
+                    .long("--format")
+                    .help("Only lists images with the provided file extension, e.g. \"cr2\""),
+            )
+            .arg(
+                Arg::with_name("duplicates")
+                    .long("--duplicates")
+                    .help("Only lists images that are stored at more than one path"),
+            )
+            .arg(
+                Arg::with_name("json")
+                    .long("--json")
+                    .help("Prints a JSON object per image to stdout, containing all stored fields"),
+            ),
+        SubCommand::with_name("stats")
+            .about("Summarizes the contents of the catalog")
+            .arg(catalog_arg()),
+    ]
+}
+
+/// An indexed image file.
+#[derive(Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+struct Entry {
+    identifier: String,
+    path: String,
+    size: i64,
+    /// Modification time of the file, as unix timestamp in milliseconds.
+    modified: i64,
+    /// Date the image has been taken, as unix timestamp in milliseconds.
+    timestamp: i64,
+    date_time: String,
+    hash: String,
+    /// Lowercase file extension, e.g. `cr2`.
+    format: String,
+    /// EXIF tag the date has been taken from.
+    date_tag: String,
+    time_zone_source: String,
+    camera: Option<String>,
+}
+
+impl Entry {
+    /// Derive the catalog entry of an image file, the same way the identifier of the file is
+    /// derived when renaming it, see `derive_file`.
+    ///
+    /// # Arguments
+    /// * `file_path` – Path to the image file.
+    /// * `matches` – Command line arguments of the `index` subcommand.
+    /// * `options` – How the identifier of the image is derived.
+    fn derive(
+        file_path: &std::path::Path,
+        matches: &ArgMatches,
+        options: &DeriveOptions,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = canonical_path(file_path)?;
+
+        let metadata = std::fs::metadata(&path)?;
+
+        let modified = metadata
+            .modified()?
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_err(|error| format!("Invalid modification time: {}", error))?
+            .as_millis();
+
+        let derivation = derive_file(
+            ImageSource::File(std::path::Path::new(&path)),
+            0,
+            matches,
+            options,
+        )?;
+
+        let hash = derivation
+            .hash
+            .ok_or("Failed deriving image hash: No hash derived")?;
+
+        let format = std::path::Path::new(&path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+
+        Ok(Entry {
+            identifier: derivation.identifier,
+            path,
+            size: metadata.len() as i64,
+            modified: modified as i64,
+            timestamp: derivation.timestamp.timestamp_millis(),
+            date_time: derivation
+                .timestamp
+                .to_rfc3339_opts(chrono::SecondsFormat::Millis, false),
+            hash: data_encoding::HEXLOWER.encode(&hash),
+            format,
+            date_tag: derivation.date_tag.to_string(),
+            time_zone_source: derivation.time_zone_source.to_string(),
+            camera: derivation.camera,
+        })
+    }
+
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(Entry {
+            identifier: row.get(0)?,
+            path: row.get(1)?,
+            size: row.get(2)?,
+            modified: row.get(3)?,
+            timestamp: row.get(4)?,
+            date_time: row.get(5)?,
+            hash: row.get(6)?,
+            format: row.get(7)?,
+            date_tag: row.get(8)?,
+            time_zone_source: row.get(9)?,
+            camera: row.get(10)?,
+        })
+    }
+}
+
+/// Absolute path of a file, as stored in the catalog.
+///
+/// # Arguments
+/// * `file_path` – Path to file.
+fn canonical_path(file_path: &std::path::Path) -> Result<String, Box<dyn std::error::Error>> {
+    let path = file_path
+        .canonicalize()
+        .map_err(|error| format!("Invalid file path: {}", error))?;
+
+    Ok(path
+        .to_str()
+        .ok_or_else(|| format!("Invalid file path: {:?}", path))?
+        .to_owned())
+}
+
+/// Conditions images have to meet to be listed by `ciid query`. Conditions that are `None` are
+/// not applied.
+#[derive(Debug, Default)]
+struct Filter {
+    identifier: Option<String>,
+    hash: Option<String>,
+    /// Unix timestamp in milliseconds, inclusive.
+    from: Option<i64>,
+    /// Unix timestamp in milliseconds, exclusive.
+    to: Option<i64>,
+    camera: Option<String>,
+    format: Option<String>,
+    duplicates: bool,
+}
+
+impl Filter {
+    /// Build the `WHERE` clause of the filter, along with the values of its parameters.
+    fn to_sql(&self) -> (String, Vec<Value>) {
+        let mut conditions = Vec::new();
+        let mut values = Vec::new();
+
+        if let Some(identifier) = &self.identifier {
+            conditions.push("instr(identifier, ?) = 1");
+            values.push(Value::Text(identifier.to_lowercase()));
+        }
+
+        if let Some(hash) = &self.hash {
+            conditions.push("instr(hash, ?) = 1");
+            values.push(Value::Text(hash.to_lowercase()));
+        }
+
+        if let Some(from) = self.from {
+            conditions.push("timestamp >= ?");
+            values.push(Value::Integer(from));
+        }
+
+        if let Some(to) = self.to {
+            conditions.push("timestamp < ?");
+            values.push(Value::Integer(to));
+        }
+
+        if let Some(camera) = &self.camera {
+            conditions.push("instr(camera, ?) > 0");
+            values.push(Value::Text(camera.to_owned()));
+        }
+
+        if let Some(format) = &self.format {
+            conditions.push("format = ?");
+            values.push(Value::Text(format.to_lowercase()));
+        }
+
+        if self.duplicates {
+            conditions.push(
+                "identifier IN (SELECT identifier FROM images GROUP BY identifier HAVING count(*) > 1)",
+            );
+        }
+
+        if conditions.is_empty() {
+            (String::new(), values)
+        } else {
+            (format!("WHERE {}", conditions.join(" AND ")), values)
+        }
+    }
+}
+
+/// Summary of the contents of the catalog.
+#[derive(Debug, PartialEq)]
+struct Stats {
+    files: i64,
+    /// Number of distinct identifiers.
+    images: i64,
+    bytes: i64,
+    first: Option<String>,
+    last: Option<String>,
+    /// Number of files per format, date tag and time zone source.
+    formats: Vec<(String, i64)>,
+    date_tags: Vec<(String, i64)>,
+    time_zone_sources: Vec<(String, i64)>,
+}
+
+/// SQLite database of indexed images.
+struct Catalog {
+    connection: rusqlite::Connection,
+}
+
+impl Catalog {
+    /// Open the catalog, creating it if it does not exist.
+    ///
+    /// # Arguments
+    /// * `file_path` – Path to the catalog database.
+    fn open(file_path: &std::path::Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let connection = rusqlite::Connection::open(file_path).map_err(|error| {
+            format!("Failed opening catalog {}: {}", file_path.display(), error)
+        })?;
+
+        Catalog::init(connection)
+    }
+
+    /// Open an existing catalog for reading. Fails if it does not exist, instead of creating an
+    /// empty one.
+    ///
+    /// # Arguments
+    /// * `file_path` – Path to the catalog database.
+    fn open_read_only(file_path: &std::path::Path) -> Result<Self, Box<dyn std::error::Error>> {
+        if !file_path.is_file() {
+            Err(format!(
+                "Catalog {} does not exist, create it with `ciid index`",
+                file_path.display()
+            ))?;
+        }
+
+        let connection = rusqlite::Connection::open_with_flags(
+            file_path,
+            rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY,
+        )
+        .map_err(|error| format!("Failed opening catalog {}: {}", file_path.display(), error))?;
+
+        connection.busy_timeout(std::time::Duration::from_secs(10))?;
+
+        match Catalog::schema_version(&connection)? {
+            SCHEMA_VERSION => Ok(Catalog { connection }),
+            _ => Err(format!(
+                "Catalog {} has not been created by `ciid index` of this version of ciid, \
+                 run `ciid index` to update it",
+                file_path.display()
+            ))?,
+        }
+    }
+
+    /// Read the schema version of the catalog, failing if it has been created by a newer version
+    /// of ciid.
+    ///
+    /// # Arguments
+    /// * `connection` – Connection to the catalog database.
+    fn schema_version(
+        connection: &rusqlite::Connection,
+    ) -> Result<i64, Box<dyn std::error::Error>> {
+        let version: i64 = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+        if version > SCHEMA_VERSION {
+            Err(format!(
+                "The catalog has been created by a newer version of ciid (schema version {})",
+                version
+            ))?;
+        }
+
+        Ok(version)
+    }
+
+    /// Create the schema, unless it exists already.
+    ///
+    /// # Arguments
+    /// * `connection` – Connection to the catalog database.
+    fn init(connection: rusqlite::Connection) -> Result<Self, Box<dyn std::error::Error>> {
+        // Other processes may be writing to the same catalog.
+        connection.busy_timeout(std::time::Duration::from_secs(10))?;
+
+        Catalog::schema_version(&connection)?;
+
+        connection.execute_batch(SCHEMA)?;
+        connection.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+
+        Ok(Catalog { connection })
+    }
+
+    /// Store entries, replacing existing entries of the same path.
+    ///
+    /// # Arguments
+    /// * `entries` – Entries to be stored.
+    fn insert(&mut self, entries: &[Entry]) -> Result<(), Box<dyn std::error::Error>> {
+        let transaction = self.connection.transaction()?;
+
+        {
+            let mut delete = transaction.prepare("DELETE FROM images WHERE path = ?")?;
+            let mut insert = transaction.prepare(&format!(
+                "INSERT INTO images ({}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                COLUMNS
+            ))?;
+
+            for entry in entries {
+                delete.execute([&entry.path])?;
+                insert.execute(rusqlite::params![
+                    entry.identifier,
+                    entry.path,
+                    entry.size,
+                    entry.modified,
+                    entry.timestamp,
+                    entry.date_time,
+                    entry.hash,
+                    entry.format,
+                    entry.date_tag,
+                    entry.time_zone_source,
+                    entry.camera,
+                ])?;
+            }
+        }
+
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    /// Remove entries of files that no longer exist, returning their paths.
+    fn prune(&mut self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let transaction = self.connection.transaction()?;
+
+        let paths = transaction
+            .prepare("SELECT path FROM images ORDER BY path")?
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut removed = Vec::new();
+
+        for path in paths {
+            if !std::path::Path::new(&path).exists() {
+                transaction.execute("DELETE FROM images WHERE path = ?", [&path])?;
+                removed.push(path);
+            }
+        }
+
+        transaction.commit()?;
+
+        Ok(removed)
+    }
+
+    /// List the entries matching the filter, ordered by date.
+    ///
+    /// # Arguments
+    /// * `filter` – Conditions entries have to meet.
+    fn query(&self, filter: &Filter) -> Result<Vec<Entry>, Box<dyn std::error::Error>> {
+        let (condition, values) = filter.to_sql();
+
+        let entries = self
+            .connection
+            .prepare(&format!(
+                "SELECT {} FROM images {} ORDER BY timestamp, identifier, path",
+                COLUMNS, condition
+            ))?
+            .query_map(rusqlite::params_from_iter(values), Entry::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(entries)
+    }
+
+    /// Count the files per distinct value of a column, ordered by value.
+    ///
+    /// # Arguments
+    /// * `column` – Name of the column.
+    fn count_by(&self, column: &str) -> Result<Vec<(String, i64)>, Box<dyn std::error::Error>> {
+        let counts = self
+            .connection
+            .prepare(&format!(
+                "SELECT {column}, count(*) FROM images GROUP BY {column} ORDER BY {column}",
+                column = column
+            ))?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(counts)
+    }
+
+    fn stats(&self) -> Result<Stats, Box<dyn std::error::Error>> {
+        let (files, images, bytes) = self.connection.query_row(
+            "SELECT count(*), count(DISTINCT identifier), coalesce(sum(size), 0) FROM images",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+
+        let date_time = |order: &str| -> rusqlite::Result<Option<String>> {
+            self.connection
+                .prepare(&format!(
+                    "SELECT date_time FROM images ORDER BY timestamp {} LIMIT 1",
+                    order
+                ))?
+                .query_map([], |row| row.get(0))?
+                .next()
+                .transpose()
+        };
+
+        Ok(Stats {
+            files,
+            images,
+            bytes,
+            first: date_time("ASC")?,
+            last: date_time("DESC")?,
+            formats: self.count_by("format")?,
+            date_tags: self.count_by("date_tag")?,
+            time_zone_sources: self.count_by("time_zone_source")?,
+        })
+    }
+}
+
+/// Parse a date of the `--from` and `--to` options into a unix timestamp in milliseconds. Dates
+/// without time are taken as midnight UTC.
+///
+/// # Arguments
+/// * `date` – Date in RFC 3339 format, or a plain date, e.g. `2017-01-05`.
+fn parse_date(date: &str) -> Result<i64, Box<dyn std::error::Error>> {
+    if let Ok(date) = chrono::DateTime::parse_from_rfc3339(date) {
+        return Ok(date.timestamp_millis());
+    }
+
+    let date = chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|error| format!("Failed parsing date \"{}\": {}", date, error))?;
+
+    Ok(date
+        .and_hms_opt(0, 0, 0)
+        .ok_or("Invalid date")?
+        .and_utc()
+        .timestamp_millis())
+}
+
+/// Index image files, printing a report line for each file.
+///
+/// # Arguments
+/// * `matches` – Command line arguments of the `index` subcommand.
+pub fn run_index(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let paths: Vec<&str> = matches
+        .values_of("path")
+        .ok_or("No path provided")?
+        .collect();
+
+    let mut catalog = Catalog::open(std::path::Path::new(
+        matches.value_of("catalog").ok_or("No catalog provided")?,
+    ))?;
+
+    let date_options = DateOptions::from_matches(matches)?;
+
+    let timestamp_digits = matches
+        .value_of("timestamp digits")
+        .unwrap_or("14")
+        .parse::<u64>()
+        .map_err(|error| format!("Failed parsing timestamp digits: {}", error))?;
+
+    let file_paths = walk::walk(&paths)?;
+
+    let cache = cache::Cache::from_matches(
+        matches,
+        &[
+            "date tags",
+            "time zone from gps",
+            "default time zone",
+            "require time zone",
+            "timestamp digits",
+        ],
+        "index",
+    )?;
+
+    // Catalog entries carry plain identifiers, since the `index` subcommand does not accept the
+    // flags that extend them, e.g. `--camera-id`.
+    let options = DeriveOptions {
+        timestamp_digits,
+        identifier_format: None,
+        clock_corrections: None,
+        date_options: &date_options,
+        perceptual: false,
+        jpeg_hash: jpeg::HashMode::Decoded,
+        raw_hash: raw_hash::HashMode::Sensor,
+    };
+
+    let results = walk::par_map(&file_paths, |file_path| {
+        let derive = || Entry::derive(file_path, matches, &options);
+
+        match &cache {
+            Some(cache) => cache.get_or_derive(file_path, derive),
+            None => derive(),
+        }
+        .map_err(|error| error.to_string())
+    });
+
+    let mut failures = 0;
+    let mut entries = Vec::new();
+
+    for (file_path, result) in file_paths.into_iter().zip(results) {
+        match result {
+            Ok(entry) => {
+                println!("index   {} -> {}", file_path.display(), entry.identifier);
+                entries.push(entry);
+            }
+            Err(error) => {
+                failures += 1;
+                eprintln!("failed  {}: {}", file_path.display(), error);
+            }
+        }
+    }
+
+    catalog.insert(&entries)?;
+
+    if matches.is_present("prune") {
+        for path in catalog.prune()? {
+            println!("prune   {}", path);
+        }
+    }
+
+    if failures > 0 {
+        Err(format!("Failed indexing {} file(s)", failures))?;
+    }
+
+    Ok(())
+}
+
+/// List images of the catalog, printing identifier and path of each image.
+///
+/// # Arguments
+/// * `matches` – Command line arguments of the `query` subcommand.
+pub fn run_query(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let catalog = Catalog::open_read_only(std::path::Path::new(
+        matches.value_of("catalog").ok_or("No catalog provided")?,
+    ))?;
+
+    let filter = Filter {
+        identifier: matches.value_of("identifier").map(str::to_owned),
+        hash: matches.value_of("hash").map(str::to_owned),
+        from: matches.value_of("from").map(parse_date).transpose()?,
+        to: matches.value_of("to").map(parse_date).transpose()?,
+        camera: matches.value_of("camera").map(str::to_owned),
+        format: matches.value_of("format").map(str::to_owned),
+        duplicates: matches.is_present("duplicates"),
+    };
+
+    for entry in catalog.query(&filter)? {
+        if matches.is_present("json") {
+            println!("{}", serde_json::to_string(&entry)?);
+        } else {
+            println!("{} {}", entry.identifier, entry.path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Print a summary of the contents of the catalog.
+///
+/// # Arguments
+/// * `matches` – Command line arguments of the `stats` subcommand.
+pub fn run_stats(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let catalog = Catalog::open_read_only(std::path::Path::new(
+        matches.value_of("catalog").ok_or("No catalog provided")?,
+    ))?;
+
+    let stats = catalog.stats()?;
+
+    println!("files             {}", stats.files);
+    println!("images            {}", stats.images);
+    println!("bytes             {}", stats.bytes);
+
+    if let Some(first) = &stats.first {
+        println!("first             {}", first);
+    }
+
+    if let Some(last) = &stats.last {
+        println!("last              {}", last);
+    }
+
+    for (format, count) in &stats.formats {
+        println!("format            {} {}", format, count);
+    }
+
+    for (date_tag, count) in &stats.date_tags {
+        println!("date_tag          {} {}", date_tag, count);
+    }
+
+    for (time_zone_source, count) in &stats.time_zone_sources {
+        println!("time_zone_source  {} {}", time_zone_source, count);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(identifier: &str, path: &str, timestamp: i64, format: &str) -> Entry {
+        Entry {
+            identifier: identifier.to_owned(),
+            path: path.to_owned(),
+            size: 100,
+            modified: 0,
+            timestamp,
+            date_time: format!("{}", timestamp),
+            hash: identifier.rsplit('-').next().unwrap().to_owned(),
+            format: format.to_owned(),
+            date_tag: "DateTimeOriginal".to_owned(),
+            time_zone_source: "offset_time_original".to_owned(),
+            camera: Some("Canon EOS 5D Mark III".to_owned()),
+        }
+    }
+
+    fn catalog() -> Catalog {
+        let mut catalog = Catalog::init(rusqlite::Connection::open_in_memory().unwrap()).unwrap();
+
+        catalog
+            .insert(&[
+                entry("00000000002000-bb", "/b.cr2", 2000, "cr2"),
+                entry("00000000001000-aa", "/a.jpg", 1000, "jpg"),
+                entry("00000000001000-aa", "/copy/a.jpg", 1000, "jpg"),
+            ])
+            .unwrap();
+
+        catalog
+    }
+
+    fn paths(entries: Vec<Entry>) -> Vec<String> {
+        entries.into_iter().map(|entry| entry.path).collect()
+    }
+
+    #[test]
+    fn test_insert_replaces_path() {
+        let mut catalog = catalog();
+
+        catalog
+            .insert(&[entry("00000000003000-cc", "/a.jpg", 3000, "jpg")])
+            .unwrap();
+
+        assert_eq!(
+            catalog
+                .query(&Filter::default())
+                .unwrap()
+                .into_iter()
+                .map(|entry| (entry.identifier, entry.path))
+                .collect::<Vec<_>>(),
+            vec![
+                ("00000000001000-aa".to_owned(), "/copy/a.jpg".to_owned()),
+                ("00000000002000-bb".to_owned(), "/b.cr2".to_owned()),
+                ("00000000003000-cc".to_owned(), "/a.jpg".to_owned()),
+            ]
+        );
+    }
+
+    macro_rules! test_query {
+        ($name:ident, $filter:expr, $expected:expr) => {
+            #[test]
+            fn $name() {
+                assert_eq!(paths(catalog().query(&$filter).unwrap()), $expected);
+            }
+        };
+    }
+
+    test_query!(
+        test_query_all,
+        Filter::default(),
+        vec!["/a.jpg", "/copy/a.jpg", "/b.cr2"]
+    );
+
+    test_query!(
+        test_query_identifier,
+        Filter {
+            identifier: Some("00000000002".to_owned()),
+            ..Filter::default()
+        },
+        vec!["/b.cr2"]
+    );
+
+    test_query!(
+        test_query_hash,
+        Filter {
+            hash: Some("B".to_owned()),
+            ..Filter::default()
+        },
+        vec!["/b.cr2"]
+    );
+
+    test_query!(
+        test_query_from_to,
+        Filter {
+            from: Some(1000),
+            to: Some(2000),
+            ..Filter::default()
+        },
+        vec!["/a.jpg", "/copy/a.jpg"]
+    );
+
+    test_query!(
+        test_query_camera,
+        Filter {
+            camera: Some("EOS 5D".to_owned()),
+            format: Some("CR2".to_owned()),
+            ..Filter::default()
+        },
+        vec!["/b.cr2"]
+    );
+
+    test_query!(
+        test_query_camera_mismatch,
+        Filter {
+            camera: Some("Nikon".to_owned()),
+            ..Filter::default()
+        },
+        Vec::<String>::new()
+    );
+
+    test_query!(
+        test_query_duplicates,
+        Filter {
+            duplicates: true,
+            ..Filter::default()
+        },
+        vec!["/a.jpg", "/copy/a.jpg"]
+    );
+
+    #[test]
+    fn test_stats() {
+        assert_eq!(
+            catalog().stats().unwrap(),
+            Stats {
+                files: 3,
+                images: 2,
+                bytes: 300,
+                first: Some("1000".to_owned()),
+                last: Some("2000".to_owned()),
+                formats: vec![("cr2".to_owned(), 1), ("jpg".to_owned(), 2)],
+                date_tags: vec![("DateTimeOriginal".to_owned(), 3)],
+                time_zone_sources: vec![("offset_time_original".to_owned(), 3)],
+            }
+        );
+    }
+
+    #[test]
+    fn test_stats_empty() {
+        let catalog = Catalog::init(rusqlite::Connection::open_in_memory().unwrap()).unwrap();
+
+        assert_eq!(
+            catalog.stats().unwrap(),
+            Stats {
+                files: 0,
+                images: 0,
+                bytes: 0,
+                first: None,
+                last: None,
+                formats: vec![],
+                date_tags: vec![],
+                time_zone_sources: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(parse_date("2017-01-05").unwrap(), 1483574400000);
+        assert_eq!(
+            parse_date("2017-01-05T13:52:55.960+02:00").unwrap(),
+            1483617175960
+        );
+        assert!(parse_date("05.01.2017").is_err());
+    }
+}
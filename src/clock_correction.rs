@@ -0,0 +1,436 @@
+use crate::time_zone::DefaultTimeZone;
+use crate::{get_date_original_from_exif, get_exif, DateOptions, ExifCamera};
+use chrono::{DateTime, Duration, FixedOffset};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use regex::Regex;
+
+/// A rule of the clock correction file. All criteria that are provided need to match for the
+/// rule to be applied.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+struct Rule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    serial_number: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    model: Option<String>,
+    /// Glob pattern matched against the canonicalized file path, e.g. `/photos/second-body/**`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+    /// Duration added to the date, e.g. `+00:03:12` if the camera clock was 3 minutes and 12
+    /// seconds behind.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    offset: Option<String>,
+    /// Time zone the camera clock was set to, either an offset like `+02:00` or a name like
+    /// `Europe/Berlin`. Replaces the time zone recorded in the metadata, keeping the local date and
+    /// time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    time_zone: Option<String>,
+}
+
+/// A parsed rule of the clock correction file.
+struct ClockCorrection {
+    serial_number: Option<String>,
+    model: Option<String>,
+    path: Option<glob::Pattern>,
+    offset: Option<Duration>,
+    time_zone: Option<DefaultTimeZone>,
+}
+
+/// Rules correcting dates of cameras with wrongly set clocks, read from a JSON file.
+pub struct ClockCorrections {
+    rules: Vec<ClockCorrection>,
+}
+
+/// A correction that has been applied to a date.
+#[derive(Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct AppliedCorrection {
+    /// Index of the matching rule in the clock correction file.
+    pub rule: usize,
+    pub offset: Option<String>,
+    pub time_zone: Option<String>,
+}
+
+/// A date after applying the clock correction rules.
+pub struct CorrectedDate {
+    pub date: DateTime<FixedOffset>,
+    /// The applied correction, if a rule matched.
+    pub correction: Option<AppliedCorrection>,
+    /// Warning about a local date that is ambiguous in the time zone of the rule.
+    pub warning: Option<String>,
+}
+
+/// Parse a signed duration, e.g. `+00:03:12` or `-01:00:00.500`.
+///
+/// # Arguments
+/// * `offset` – Duration to be parsed.
+fn parse_offset(offset: &str) -> Result<Duration, Box<dyn std::error::Error>> {
+    let regex = Regex::new(r"^([+-])?([0-9]+):([0-9]{2}):([0-9]{2})(?:\.([0-9]{1,9}))?$")?;
+
+    let captures = regex.captures(offset).ok_or_else(|| {
+        format!(
+            r#"Invalid offset "{}", expected format "[+-]HH:MM:SS[.fff]""#,
+            offset
+        )
+    })?;
+
+    let nanos = match captures.get(5) {
+        Some(fraction) => format!("{:0<9}", fraction.as_str()).parse()?,
+        None => 0,
+    };
+
+    let duration = Duration::hours(captures[2].parse()?)
+        + Duration::minutes(captures[3].parse()?)
+        + Duration::seconds(captures[4].parse()?)
+        + Duration::nanoseconds(nanos);
+
+    match captures.get(1).map(|sign| sign.as_str()) {
+        Some("-") => Ok(-duration),
+        _ => Ok(duration),
+    }
+}
+
+/// Format a signed duration, e.g. `+00:03:12.000`.
+///
+/// # Arguments
+/// * `offset` – Duration to be formatted.
+pub fn format_offset(offset: Duration) -> String {
+    let sign = if offset < Duration::zero() { "-" } else { "+" };
+    let millis = offset.num_milliseconds().abs();
+
+    format!(
+        "{}{:02}:{:02}:{:02}.{:03}",
+        sign,
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+impl ClockCorrection {
+    fn from_rule(rule: Rule) -> Result<Self, Box<dyn std::error::Error>> {
+        let correction = ClockCorrection {
+            serial_number: rule.serial_number,
+            model: rule.model,
+            path: rule.path.as_deref().map(glob::Pattern::new).transpose()?,
+            offset: rule.offset.as_deref().map(parse_offset).transpose()?,
+            time_zone: rule
+                .time_zone
+                .as_deref()
+                .map(DefaultTimeZone::parse)
+                .transpose()?,
+        };
+
+        if correction.offset.is_none() && correction.time_zone.is_none() {
+            Err("Expected either an offset or a time zone")?;
+        }
+
+        Ok(correction)
+    }
+}
+
+impl ClockCorrections {
+    /// Read clock correction rules from a JSON file.
+    ///
+    /// # Arguments
+    /// * `file_path` – Path to the clock correction file.
+    pub fn from_file(file_path: &std::path::Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let contents = std::fs::read_to_string(file_path)
+            .map_err(|error| format!("Failed reading {}: {}", file_path.display(), error))?;
+
+        Self::from_json(&contents)
+    }
+
+    fn from_json(contents: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let rules: Vec<Rule> = serde_json::from_str(contents)?;
+
+        let rules = rules
+            .into_iter()
+            .enumerate()
+            .map(|(index, rule)| {
+                ClockCorrection::from_rule(rule)
+                    .map_err(|error| format!("Invalid clock correction rule {}: {}", index, error))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(ClockCorrections { rules })
+    }
+
+    /// Apply the first rule matching the file to its date.
+    ///
+    /// # Arguments
+    /// * `date` – Date derived from the metadata of the file.
+    /// * `file_path` – Path to the file.
+    /// * `camera` – Camera information of the file.
+    pub fn apply(
+        &self,
+        date: DateTime<FixedOffset>,
+        file_path: &std::path::Path,
+        camera: &ExifCamera,
+    ) -> Result<CorrectedDate, Box<dyn std::error::Error>> {
+        let rule = self.rules.iter().enumerate().find(|(_, rule)| {
+            rule.serial_number.as_deref().is_none_or(|serial_number| {
+                camera.serial_number().map(str::trim) == Some(serial_number)
+            }) && rule
+                .model
+                .as_deref()
+                .is_none_or(|model| camera.Model.as_deref().map(str::trim) == Some(model))
+                && rule
+                    .path
+                    .as_ref()
+                    .is_none_or(|pattern| pattern.matches_path(file_path))
+        });
+
+        let (index, rule) = match rule {
+            Some(rule) => rule,
+            None => {
+                return Ok(CorrectedDate {
+                    date,
+                    correction: None,
+                    warning: None,
+                })
+            }
+        };
+
+        let (date, warning) = match rule.time_zone {
+            Some(time_zone) => time_zone.localize(&date.naive_local())?,
+            None => (date, None),
+        };
+
+        let date = match rule.offset {
+            Some(offset) => date + offset,
+            None => date,
+        };
+
+        Ok(CorrectedDate {
+            date,
+            correction: Some(AppliedCorrection {
+                rule: index,
+                offset: rule.offset.map(format_offset),
+                time_zone: rule.time_zone.map(|time_zone| time_zone.to_string()),
+            }),
+            warning,
+        })
+    }
+}
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("clock-offset")
+        .about("Computes the clock offset of a camera from two images that show the same moment")
+        .arg(
+            Arg::with_name("reference file path")
+                .takes_value(true)
+                .required(true)
+                .help("Path to image file taken by a camera with a correctly set clock"),
+        )
+        .arg(
+            Arg::with_name("file path")
+                .takes_value(true)
+                .required(true)
+                .help("Path to image file taken by the camera whose clock should be corrected"),
+        )
+        .args(&DateOptions::args())
+}
+
+/// Print the offset between the dates of a reference image and an image of the same moment,
+/// along with a clock correction rule for the camera of the latter.
+///
+/// # Arguments
+/// * `matches` – Command line arguments of the `clock-offset` subcommand.
+pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let date = |name: &str| -> Result<_, Box<dyn std::error::Error>> {
+        let file_path = matches.value_of(name).ok_or("No file path provided")?;
+        let file_path = std::path::Path::new(file_path)
+            .canonicalize()
+            .map_err(|error| format!("Invalid file path: {}", error))?;
+
+        let exif =
+            get_exif(&file_path).map_err(|error| format!("Failed reading metadata: {}", error))?;
+        let date_options = DateOptions::from_matches(matches)?;
+
+        let date = get_date_original_from_exif(&exif.date_time, &date_options)
+            .map_err(|error| format!("Failed deriving timestamp data: {}", error))?
+            .date;
+
+        Ok((file_path, exif.camera, date))
+    };
+
+    let (_, _, reference_date) = date("reference file path")?;
+    let (file_path, camera, date) = date("file path")?;
+
+    let offset = format_offset(reference_date - date);
+
+    let rule = Rule {
+        serial_number: camera.serial_number().map(str::to_owned),
+        model: match camera.serial_number() {
+            Some(_) => None,
+            None => camera.Model.clone(),
+        },
+        path: match (camera.serial_number(), &camera.Model) {
+            (None, None) => file_path
+                .parent()
+                .map(|parent| format!("{}/*", glob::Pattern::escape(&parent.to_string_lossy()))),
+            _ => None,
+        },
+        offset: Some(offset.clone()),
+        time_zone: None,
+    };
+
+    println!("{}", offset);
+    println!("{}", serde_json::to_string_pretty(&rule)?);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! test_parse_offset {
+        ($test_name:ident, $input:literal, $expected_millis:literal) => {
+            #[test]
+            fn $test_name() -> Result<(), Box<dyn std::error::Error>> {
+                let offset = parse_offset($input)?;
+
+                assert_eq!(offset, Duration::milliseconds($expected_millis));
+                assert_eq!(parse_offset(&format_offset(offset))?, offset);
+
+                Ok(())
+            }
+        };
+    }
+
+    test_parse_offset!(test_parse_offset_positive, "+00:03:12", 192_000);
+
+    test_parse_offset!(test_parse_offset_unsigned, "00:03:12", 192_000);
+
+    test_parse_offset!(test_parse_offset_negative, "-01:00:00.5", -3_600_500);
+
+    test_parse_offset!(test_parse_offset_hours, "+25:00:00", 90_000_000);
+
+    #[test]
+    fn test_parse_offset_invalid() {
+        assert!(parse_offset("3m12s").is_err());
+        assert!(parse_offset("+00:3:12").is_err());
+    }
+
+    #[test]
+    fn test_format_offset() {
+        assert_eq!(
+            format_offset(Duration::milliseconds(192_000)),
+            "+00:03:12.000"
+        );
+        assert_eq!(
+            format_offset(Duration::milliseconds(-3_600_500)),
+            "-01:00:00.500"
+        );
+    }
+
+    fn camera(model: &str, serial_number: &str) -> ExifCamera {
+        serde_json::from_str(&format!(
+            r#"{{ "Model": "{}", "SerialNumber": "{}" }}"#,
+            model, serial_number
+        ))
+        .unwrap()
+    }
+
+    macro_rules! test_apply {
+        ($test_name:ident, $model:literal, $serial_number:literal, $file_path:literal, $date:literal, $expected:literal, $expected_rule:expr) => {
+            #[test]
+            fn $test_name() -> Result<(), Box<dyn std::error::Error>> {
+                let corrections = ClockCorrections::from_json(
+                    r#"[
+                        { "serial_number": "123", "offset": "+00:03:12" },
+                        { "model": "Model B", "time_zone": "+02:00" },
+                        { "path": "/photos/second-body/**", "time_zone": "-01:00", "offset": "-00:00:01" },
+                        { "model": "Model D", "time_zone": "Europe/Berlin" }
+                    ]"#,
+                )?;
+
+                let date = DateTime::parse_from_rfc3339($date)?;
+
+                let corrected_date = corrections.apply(
+                    date,
+                    std::path::Path::new($file_path),
+                    &camera($model, $serial_number),
+                )?;
+
+                assert_eq!(corrected_date.date, DateTime::parse_from_rfc3339($expected)?);
+                assert_eq!(
+                    corrected_date.correction.map(|correction| correction.rule),
+                    $expected_rule
+                );
+
+                Ok(())
+            }
+        };
+    }
+
+    test_apply!(
+        test_apply_serial_number,
+        "Model A",
+        "123",
+        "/photos/IMG_1.CR2",
+        "2017-01-05T13:52:55.960+00:00",
+        "2017-01-05T13:56:07.960+00:00",
+        Some(0)
+    );
+
+    test_apply!(
+        test_apply_model,
+        "Model B",
+        "456",
+        "/photos/IMG_1.CR2",
+        "2017-01-05T13:52:55.960+00:00",
+        "2017-01-05T13:52:55.960+02:00",
+        Some(1)
+    );
+
+    test_apply!(
+        test_apply_path,
+        "Model C",
+        "789",
+        "/photos/second-body/IMG_1.CR2",
+        "2017-01-05T13:52:55.960+00:00",
+        "2017-01-05T13:52:54.960-01:00",
+        Some(2)
+    );
+
+    test_apply!(
+        test_apply_time_zone_name,
+        "Model D",
+        "012",
+        "/photos/IMG_1.CR2",
+        "2017-01-05T13:52:55.960+00:00",
+        "2017-01-05T13:52:55.960+01:00",
+        Some(3)
+    );
+
+    test_apply!(
+        test_apply_time_zone_name_daylight_saving_time,
+        "Model D",
+        "012",
+        "/photos/IMG_1.CR2",
+        "2017-07-05T13:52:55.960+00:00",
+        "2017-07-05T13:52:55.960+02:00",
+        Some(3)
+    );
+
+    test_apply!(
+        test_apply_none,
+        "Model C",
+        "789",
+        "/photos/IMG_1.CR2",
+        "2017-01-05T13:52:55.960+00:00",
+        "2017-01-05T13:52:55.960+00:00",
+        None
+    );
+
+    #[test]
+    fn test_from_json_invalid_rule() {
+        assert!(ClockCorrections::from_json(r#"[{ "model": "Model A" }]"#).is_err());
+        assert!(ClockCorrections::from_json(r#"[{ "offset": "3m" }]"#).is_err());
+        assert!(ClockCorrections::from_json(r#"[{ "offst": "+00:00:01" }]"#).is_err());
+        assert!(ClockCorrections::from_json(r#"[{ "time_zone": "Europe/Nowhere" }]"#).is_err());
+    }
+}
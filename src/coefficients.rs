@@ -0,0 +1,977 @@
+use crate::jpeg;
+
+/// Baseline DCT frame.
+const SOF0: u8 = 0xc0;
+/// Extended sequential DCT frame, e.g. with 12-bit precision.
+const SOF1: u8 = 0xc1;
+/// Progressive DCT frame.
+const SOF2: u8 = 0xc2;
+/// Huffman tables.
+const DHT: u8 = 0xc4;
+/// Quantization tables.
+const DQT: u8 = 0xdb;
+/// Restart interval.
+const DRI: u8 = 0xdd;
+
+/// Largest number of pixels of an image whose coefficients are decoded.
+const MAX_PIXELS: usize = 1 << 28;
+
+/// Index into a block in natural order of each coefficient, in the zigzag order they are coded
+/// in.
+pub const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20,
+    13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59,
+    52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+/// A component of a JPEG image, e.g. the luma or one of the chroma channels, as quantized DCT
+/// coefficients.
+pub struct Component {
+    id: u8,
+    /// Horizontal sampling factor.
+    pub horizontal: usize,
+    /// Vertical sampling factor.
+    pub vertical: usize,
+    /// Index of the quantization table.
+    table: usize,
+    /// Quantization table in natural order, taken when the first scan of the component starts.
+    quantization: Option<[u16; 64]>,
+    /// Number of blocks covering the component horizontally, without blocks that only pad the
+    /// last MCU.
+    pub blocks_wide: usize,
+    /// Number of blocks covering the component vertically, without blocks that only pad the
+    /// last MCU.
+    pub blocks_high: usize,
+    /// Number of blocks per row in `coefficients`, including padding blocks.
+    stride: usize,
+    /// Coefficients in natural order, 64 per block, blocks in raster order.
+    coefficients: Vec<i32>,
+}
+
+impl Component {
+    /// Quantization table in natural order.
+    pub fn quantization(&self) -> &[u16; 64] {
+        self.quantization.as_ref().unwrap_or(&[0; 64])
+    }
+
+    /// Quantized coefficients of a block in natural order.
+    ///
+    /// # Arguments
+    /// * `x` – Column of the block.
+    /// * `y` – Row of the block.
+    pub fn block(&self, x: usize, y: usize) -> &[i32] {
+        let start = (y * self.stride + x) * 64;
+
+        &self.coefficients[start..start + 64]
+    }
+
+    fn block_mut(&mut self, x: usize, y: usize) -> &mut [i32] {
+        let start = (y * self.stride + x) * 64;
+
+        &mut self.coefficients[start..start + 64]
+    }
+}
+
+/// Canonical Huffman code, decoded as described in section F.2.2.3 of the JPEG specification.
+#[derive(Clone)]
+struct Huffman {
+    /// Length and value of the codes of up to 8 bits, indexed by the next 8 bits. Length 0 for
+    /// longer codes.
+    lookup: [(u8, u8); 256],
+    /// Largest code of each length, -1 if there is none.
+    max_code: [i32; 17],
+    /// Difference between the index of the first value and the first code of each length.
+    offset: [i32; 17],
+    values: Vec<u8>,
+}
+
+impl Huffman {
+    /// # Arguments
+    /// * `counts` – Number of codes per length, from 1 to 16 bits.
+    /// * `values` – Values of the codes, ordered by length.
+    fn new(counts: &[u8], values: &[u8]) -> Self {
+        let mut lookup = [(0, 0); 256];
+        let mut max_code = [-1; 17];
+        let mut offset = [0; 17];
+        let mut code = 0;
+        let mut index = 0;
+
+        for length in 1..=16 {
+            let count = i32::from(counts[length - 1]);
+
+            if count > 0 {
+                offset[length] = index - code;
+
+                if length <= 8 {
+                    for c in code..code + count {
+                        // Codes without a value are left to fail in the slow path.
+                        if let Some(&value) = values.get((c + offset[length]) as usize) {
+                            let first = (c as usize) << (8 - length);
+
+                            for entry in lookup.iter_mut().skip(first).take(1 << (8 - length)) {
+                                *entry = (length as u8, value);
+                            }
+                        }
+                    }
+                }
+
+                code += count;
+                index += count;
+                max_code[length] = code - 1;
+            }
+
+            code <<= 1;
+        }
+
+        Huffman {
+            lookup,
+            max_code,
+            offset,
+            values: values.to_vec(),
+        }
+    }
+}
+
+/// Reads the entropy-coded data of a scan, most significant bit first. Stuffed bytes are removed.
+/// At markers, zeros are read until the reader is restarted.
+///
+/// This is not shared with the bit reader and Huffman tables of `raw`, which reproduce how dcraw
+/// reads truncated and invalid data, so that raw hashes match those derived with LibRaw. Here,
+/// invalid codes are errors instead, and restart markers are checked.
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    buffer: u64,
+    bits: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        BitReader {
+            data,
+            position: 0,
+            buffer: 0,
+            bits: 0,
+        }
+    }
+
+    fn fill(&mut self) {
+        while self.bits <= 56 {
+            let byte = match self.data.get(self.position) {
+                Some(0xff) if self.data.get(self.position + 1) == Some(&0x00) => {
+                    self.position += 2;
+                    0xff
+                }
+                Some(0xff) | None => 0,
+                Some(&byte) => {
+                    self.position += 1;
+                    byte
+                }
+            };
+
+            self.buffer |= u64::from(byte) << (56 - self.bits);
+            self.bits += 8;
+        }
+    }
+
+    /// Read a number of bits, up to 16.
+    fn bits(&mut self, count: u32) -> u32 {
+        if count == 0 {
+            return 0;
+        }
+
+        if self.bits < count {
+            self.fill();
+        }
+
+        let value = (self.buffer >> (64 - count)) as u32;
+        self.buffer <<= count;
+        self.bits -= count;
+
+        value
+    }
+
+    /// Read a number of bits holding a signed value, as described in section F.2.2.1 of the
+    /// JPEG specification.
+    fn signed(&mut self, count: u32) -> i32 {
+        let value = self.bits(count) as i32;
+
+        if count > 0 && value < 1 << (count - 1) {
+            value - (1 << count) + 1
+        } else {
+            value
+        }
+    }
+
+    /// Read a Huffman coded value, looking up codes of up to 8 bits at once.
+    fn huffman(&mut self, huffman: &Huffman) -> Result<u8, Box<dyn std::error::Error>> {
+        if self.bits < 8 {
+            self.fill();
+        }
+
+        let (length, value) = huffman.lookup[(self.buffer >> 56) as usize];
+
+        if length > 0 {
+            self.buffer <<= length;
+            self.bits -= u32::from(length);
+
+            return Ok(value);
+        }
+
+        let mut code = 0;
+
+        for length in 1..=16 {
+            code = code << 1 | self.bits(1) as i32;
+
+            if code <= huffman.max_code[length] {
+                return huffman
+                    .values
+                    .get((code + huffman.offset[length]) as usize)
+                    .copied()
+                    .ok_or_else(|| "Invalid JPEG Huffman code".into());
+            }
+        }
+
+        Err("Invalid JPEG Huffman code")?
+    }
+
+    /// Discard the buffered bits, which pad the data up to the restart marker, and skip the
+    /// marker.
+    fn restart(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.buffer = 0;
+        self.bits = 0;
+
+        match self.data.get(self.position..self.position + 2) {
+            Some([0xff, marker]) if (0xd0..=0xd7).contains(marker) => {
+                self.position += 2;
+                Ok(())
+            }
+            _ => Err(format!(
+                "Missing JPEG restart marker at offset {} of scan",
+                self.position
+            ))?,
+        }
+    }
+}
+
+/// State of a scan while decoding it.
+struct Scan {
+    /// Index into the components of the frame, per component of the scan.
+    components: Vec<usize>,
+    /// Huffman tables for the DC and AC coefficients, per component of the scan.
+    tables: Vec<(Option<Huffman>, Option<Huffman>)>,
+    /// Index of the first coefficient in zigzag order.
+    start: usize,
+    /// Index of the last coefficient in zigzag order.
+    end: usize,
+    /// Bit position of the previous successive approximation, 0 for the first one.
+    high: u32,
+    /// Bit position of the successive approximation.
+    low: u32,
+    /// DC prediction per component of the scan.
+    predictions: Vec<i32>,
+    /// Largest size of a DC difference in bits, 11 for 8-bit and 15 for 12-bit samples.
+    max_dc_size: u8,
+    /// Remaining number of blocks in the current end of band run.
+    end_of_band: u32,
+}
+
+impl Scan {
+    fn dc(&self, index: usize) -> Result<&Huffman, Box<dyn std::error::Error>> {
+        Ok(self.tables[index]
+            .0
+            .as_ref()
+            .ok_or("Missing JPEG DC Huffman table")?)
+    }
+
+    fn ac(&self, index: usize) -> Result<&Huffman, Box<dyn std::error::Error>> {
+        Ok(self.tables[index]
+            .1
+            .as_ref()
+            .ok_or("Missing JPEG AC Huffman table")?)
+    }
+
+    /// Decode the difference of a DC coefficient to its prediction, see section F.2.2.1 of the
+    /// JPEG specification.
+    fn dc_difference(
+        &self,
+        reader: &mut BitReader,
+        index: usize,
+    ) -> Result<i32, Box<dyn std::error::Error>> {
+        let size = reader.huffman(self.dc(index)?)?;
+
+        if size > self.max_dc_size {
+            Err(format!("Invalid JPEG DC difference size: {}", size))?;
+        }
+
+        Ok(reader.signed(u32::from(size)))
+    }
+
+    /// Decode a block of a sequential scan.
+    fn sequential(
+        &mut self,
+        reader: &mut BitReader,
+        index: usize,
+        block: &mut [i32],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.predictions[index] += self.dc_difference(reader, index)?;
+        block[0] = self.predictions[index];
+
+        let ac = self.ac(index)?;
+        let mut k = 1;
+
+        while k < 64 {
+            let symbol = reader.huffman(ac)?;
+            let run = usize::from(symbol >> 4);
+            let size = u32::from(symbol & 0x0f);
+
+            if size == 0 {
+                if run != 15 {
+                    break;
+                }
+
+                k += 16;
+                continue;
+            }
+
+            k += run;
+
+            if k > 63 {
+                Err("Invalid JPEG AC coefficient run")?;
+            }
+
+            block[ZIGZAG[k]] = reader.signed(size);
+            k += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Decode the DC coefficient of a block in a progressive scan, see section G.1.2.1 of the
+    /// JPEG specification.
+    fn progressive_dc(
+        &mut self,
+        reader: &mut BitReader,
+        index: usize,
+        block: &mut [i32],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if self.high == 0 {
+            self.predictions[index] += self.dc_difference(reader, index)?;
+            block[0] = self.predictions[index] << self.low;
+        } else if reader.bits(1) == 1 {
+            block[0] |= 1 << self.low;
+        }
+
+        Ok(())
+    }
+
+    /// Decode the AC coefficients of a block in the first progressive scan of their band, see
+    /// section G.1.2.2 of the JPEG specification.
+    fn progressive_ac_first(
+        &mut self,
+        reader: &mut BitReader,
+        block: &mut [i32],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if self.end_of_band > 0 {
+            self.end_of_band -= 1;
+            return Ok(());
+        }
+
+        let ac = self.ac(0)?;
+        let mut end_of_band = 0;
+        let mut k = self.start;
+
+        while k <= self.end {
+            let symbol = reader.huffman(ac)?;
+            let run = u32::from(symbol >> 4);
+            let size = u32::from(symbol & 0x0f);
+
+            if size == 0 {
+                if run < 15 {
+                    end_of_band = (1 << run) + reader.bits(run) - 1;
+                    break;
+                }
+
+                k += 16;
+                continue;
+            }
+
+            k += run as usize;
+
+            if k > self.end {
+                Err("Invalid JPEG AC coefficient run")?;
+            }
+
+            block[ZIGZAG[k]] = reader.signed(size) << self.low;
+            k += 1;
+        }
+
+        self.end_of_band = end_of_band;
+
+        Ok(())
+    }
+
+    /// Refine the AC coefficients of a block by one bit, in the way of libjpeg's
+    /// `decode_mcu_AC_refine`.
+    fn progressive_ac_refine(
+        &mut self,
+        reader: &mut BitReader,
+        block: &mut [i32],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let positive = 1 << self.low;
+        let negative = -1 << self.low;
+
+        let refine = |reader: &mut BitReader, coefficient: &mut i32| {
+            if reader.bits(1) == 1 && *coefficient & positive == 0 {
+                *coefficient += if *coefficient >= 0 {
+                    positive
+                } else {
+                    negative
+                };
+            }
+        };
+
+        let mut end_of_band = self.end_of_band;
+        let mut k = self.start;
+
+        if end_of_band == 0 {
+            let ac = self.ac(0)?;
+
+            while k <= self.end {
+                let symbol = reader.huffman(ac)?;
+                let mut run = symbol >> 4;
+                let size = symbol & 0x0f;
+
+                let value = match size {
+                    0 if run < 15 => {
+                        end_of_band = (1 << run) + reader.bits(u32::from(run));
+                        break;
+                    }
+                    0 => 0,
+                    1 => {
+                        if reader.bits(1) == 1 {
+                            positive
+                        } else {
+                            negative
+                        }
+                    }
+                    _ => Err("Invalid JPEG AC refinement")?,
+                };
+
+                // Skip `run` coefficients that are still zero, refining the others on the way.
+                while k <= self.end {
+                    let coefficient = &mut block[ZIGZAG[k]];
+
+                    if *coefficient != 0 {
+                        refine(reader, coefficient);
+                    } else if run == 0 {
+                        break;
+                    } else {
+                        run -= 1;
+                    }
+
+                    k += 1;
+                }
+
+                if value != 0 {
+                    if k > self.end {
+                        Err("Invalid JPEG AC coefficient run")?;
+                    }
+
+                    block[ZIGZAG[k]] = value;
+                }
+
+                k += 1;
+            }
+        }
+
+        if end_of_band > 0 {
+            while k <= self.end {
+                let coefficient = &mut block[ZIGZAG[k]];
+
+                if *coefficient != 0 {
+                    refine(reader, coefficient);
+                }
+
+                k += 1;
+            }
+
+            end_of_band -= 1;
+        }
+
+        self.end_of_band = end_of_band;
+
+        Ok(())
+    }
+}
+
+/// Decoder of the quantized DCT coefficients of a JPEG image, stopping short of the inverse DCT.
+struct Decoder {
+    progressive: bool,
+    /// Bits per sample, 8 or 12.
+    precision: u8,
+    width: usize,
+    height: usize,
+    /// Number of MCUs covering the image horizontally.
+    mcus_wide: usize,
+    /// Number of MCUs covering the image vertically.
+    mcus_high: usize,
+    components: Vec<Component>,
+    quantization: [Option<[u16; 64]>; 4],
+    dc: [Option<Huffman>; 4],
+    ac: [Option<Huffman>; 4],
+    restart_interval: usize,
+}
+
+impl Decoder {
+    fn quantization_tables(&mut self, segment: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        let mut data = &segment[2..];
+
+        while let Some(&info) = data.first() {
+            let wide = info >> 4 == 1;
+            let length = if wide { 128 } else { 64 };
+
+            let values = data
+                .get(1..1 + length)
+                .ok_or("Truncated JPEG quantization table")?;
+
+            let mut table = [0; 64];
+
+            for (k, position) in ZIGZAG.iter().enumerate() {
+                table[*position] = if wide {
+                    u16::from_be_bytes([values[2 * k], values[2 * k + 1]])
+                } else {
+                    u16::from(values[k])
+                };
+            }
+
+            *self
+                .quantization
+                .get_mut(usize::from(info & 0x0f))
+                .ok_or("Invalid JPEG quantization table")? = Some(table);
+
+            data = &data[1 + length..];
+        }
+
+        Ok(())
+    }
+
+    fn huffman_tables(&mut self, segment: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        let mut data = &segment[2..];
+
+        while let Some(&info) = data.first() {
+            let counts = data.get(1..17).ok_or("Truncated JPEG Huffman table")?;
+            let count = counts
+                .iter()
+                .map(|count| usize::from(*count))
+                .sum::<usize>();
+
+            let values = data
+                .get(17..17 + count)
+                .ok_or("Truncated JPEG Huffman table")?;
+
+            let tables = match info >> 4 {
+                0 => &mut self.dc,
+                1 => &mut self.ac,
+                _ => Err("Invalid JPEG Huffman table")?,
+            };
+
+            *tables
+                .get_mut(usize::from(info & 0x0f))
+                .ok_or("Invalid JPEG Huffman table")? = Some(Huffman::new(counts, values));
+
+            data = &data[17 + count..];
+        }
+
+        Ok(())
+    }
+
+    fn frame(&mut self, marker: u8, segment: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.components.is_empty() {
+            Err("Multiple JPEG frames")?;
+        }
+
+        let header = segment.get(2..8).ok_or("Truncated JPEG frame header")?;
+
+        self.progressive = marker == SOF2;
+        self.precision = header[0];
+
+        if self.precision != 8 && self.precision != 12 {
+            Err(format!(
+                "Unsupported JPEG sample precision: {}",
+                self.precision
+            ))?;
+        }
+
+        self.height = usize::from(u16::from_be_bytes([header[1], header[2]]));
+        self.width = usize::from(u16::from_be_bytes([header[3], header[4]]));
+
+        if self.width == 0 || self.height == 0 {
+            Err("Unsupported JPEG image without dimensions in its frame header")?;
+        }
+
+        if self.width * self.height > MAX_PIXELS {
+            Err(format!(
+                "JPEG image too large: {}x{}",
+                self.width, self.height
+            ))?;
+        }
+
+        let count = usize::from(header[5]);
+
+        let specifications = segment
+            .get(8..8 + 3 * count)
+            .ok_or("Truncated JPEG frame header")?;
+
+        let sampling =
+            |index: usize, shift: u32| usize::from(specifications[index] >> shift & 0x0f);
+
+        let max_horizontal = (0..count)
+            .map(|c| sampling(3 * c + 1, 4))
+            .max()
+            .unwrap_or(0);
+        let max_vertical = (0..count)
+            .map(|c| sampling(3 * c + 1, 0))
+            .max()
+            .unwrap_or(0);
+
+        if count == 0 || !(1..=4).contains(&max_horizontal) || !(1..=4).contains(&max_vertical) {
+            Err("Invalid JPEG frame header")?;
+        }
+
+        self.mcus_wide = self.width.div_ceil(8 * max_horizontal);
+        self.mcus_high = self.height.div_ceil(8 * max_vertical);
+
+        for c in 0..count {
+            let horizontal = sampling(3 * c + 1, 4);
+            let vertical = sampling(3 * c + 1, 0);
+
+            if horizontal == 0 || vertical == 0 {
+                Err("Invalid JPEG sampling factors")?;
+            }
+
+            let width = (self.width * horizontal).div_ceil(max_horizontal);
+            let height = (self.height * vertical).div_ceil(max_vertical);
+
+            let stride = self.mcus_wide * horizontal;
+
+            self.components.push(Component {
+                id: specifications[3 * c],
+                horizontal,
+                vertical,
+                table: usize::from(specifications[3 * c + 2] & 0x03),
+                quantization: None,
+                blocks_wide: width.div_ceil(8),
+                blocks_high: height.div_ceil(8),
+                stride,
+                coefficients: vec![0; stride * self.mcus_high * vertical * 64],
+            });
+        }
+
+        Ok(())
+    }
+
+    fn scan(&mut self, segment: &[u8], data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        if self.components.is_empty() {
+            Err("JPEG scan before frame header")?;
+        }
+
+        let count = usize::from(*segment.get(2).ok_or("Truncated JPEG scan header")?);
+
+        let header = segment
+            .get(3..6 + 2 * count)
+            .ok_or("Truncated JPEG scan header")?;
+
+        let mut scan = Scan {
+            components: Vec::with_capacity(count),
+            tables: Vec::with_capacity(count),
+            start: usize::from(header[2 * count]),
+            end: usize::from(header[2 * count + 1]),
+            high: u32::from(header[2 * count + 2] >> 4),
+            low: u32::from(header[2 * count + 2] & 0x0f),
+            predictions: vec![0; count],
+            max_dc_size: self.precision + 3,
+            end_of_band: 0,
+        };
+
+        for c in 0..count {
+            let index = self
+                .components
+                .iter()
+                .position(|component| component.id == header[2 * c])
+                .ok_or("Invalid JPEG scan component")?;
+
+            let component = &mut self.components[index];
+
+            if component.quantization.is_none() {
+                component.quantization = Some(
+                    self.quantization[component.table].ok_or("Missing JPEG quantization table")?,
+                );
+            }
+
+            scan.components.push(index);
+            scan.tables.push((
+                self.dc[usize::from(header[2 * c + 1] >> 4) & 0x03].clone(),
+                self.ac[usize::from(header[2 * c + 1] & 0x0f) & 0x03].clone(),
+            ));
+        }
+
+        if scan.end > 63 || scan.start > scan.end || scan.low > 13 {
+            Err("Invalid JPEG scan header")?;
+        }
+
+        if self.progressive {
+            if (scan.start == 0) != (scan.end == 0) || (scan.start > 0 && count != 1) {
+                Err("Invalid JPEG progressive scan")?;
+            }
+        } else if scan.start != 0 || scan.end != 63 {
+            Err("Invalid JPEG sequential scan")?;
+        }
+
+        // A single component is coded block by block, without padding blocks. Otherwise, each
+        // MCU holds the blocks of all components.
+        let units: Vec<Vec<(usize, usize, usize)>> = if count == 1 {
+            let component = &self.components[scan.components[0]];
+
+            (0..component.blocks_high)
+                .flat_map(|y| (0..component.blocks_wide).map(move |x| vec![(0, x, y)]))
+                .collect()
+        } else {
+            let (mcus_wide, mcus_high) = (self.mcus_wide, self.mcus_high);
+            let components = &self.components;
+            let indices = &scan.components;
+
+            (0..mcus_high)
+                .flat_map(|mcu_y| {
+                    (0..mcus_wide).map(move |mcu_x| {
+                        let mut blocks = Vec::new();
+
+                        for (index, component) in indices.iter().enumerate() {
+                            let component = &components[*component];
+
+                            for y in 0..component.vertical {
+                                for x in 0..component.horizontal {
+                                    blocks.push((
+                                        index,
+                                        mcu_x * component.horizontal + x,
+                                        mcu_y * component.vertical + y,
+                                    ));
+                                }
+                            }
+                        }
+
+                        blocks
+                    })
+                })
+                .collect()
+        };
+
+        let mut reader = BitReader::new(data);
+
+        for (number, blocks) in units.iter().enumerate() {
+            if self.restart_interval > 0 && number > 0 && number % self.restart_interval == 0 {
+                reader.restart()?;
+
+                scan.predictions.iter_mut().for_each(|p| *p = 0);
+                scan.end_of_band = 0;
+            }
+
+            for (index, x, y) in blocks {
+                let block = self.components[scan.components[*index]].block_mut(*x, *y);
+
+                if !self.progressive {
+                    scan.sequential(&mut reader, *index, block)?;
+                } else if scan.start == 0 {
+                    scan.progressive_dc(&mut reader, *index, block)?;
+                } else if scan.high == 0 {
+                    scan.progressive_ac_first(&mut reader, block)?;
+                } else {
+                    scan.progressive_ac_refine(&mut reader, block)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Decode the quantized DCT coefficients of a JPEG image, without the inverse DCT, so that they
+/// do not depend on how a decoder computes pixels. Supports baseline, extended sequential and
+/// progressive images with Huffman coding.
+///
+/// # Arguments
+/// * `data` – Contents of the JPEG file.
+pub fn decode(data: &[u8]) -> Result<Vec<Component>, Box<dyn std::error::Error>> {
+    let mut decoder = Decoder {
+        progressive: false,
+        precision: 8,
+        width: 0,
+        height: 0,
+        mcus_wide: 0,
+        mcus_high: 0,
+        components: Vec::new(),
+        quantization: [None; 4],
+        dc: [None, None, None, None],
+        ac: [None, None, None, None],
+        restart_interval: 0,
+    };
+
+    jpeg::for_each_segment(data, |marker, segment, entropy_coded| {
+        match marker {
+            DQT => decoder.quantization_tables(segment)?,
+            DHT => decoder.huffman_tables(segment)?,
+            SOF0 | SOF1 | SOF2 => decoder.frame(marker, segment)?,
+            // Lossless, hierarchical and arithmetic coded frames.
+            0xc3 | 0xc5..=0xc7 | 0xc9..=0xcb | 0xcd..=0xcf => {
+                Err(format!("Unsupported JPEG frame type: 0x{:02x}", marker))?
+            }
+            DRI => {
+                decoder.restart_interval = match segment.get(2..4) {
+                    Some(interval) => usize::from(u16::from_be_bytes([interval[0], interval[1]])),
+                    None => Err("Truncated JPEG restart interval")?,
+                }
+            }
+            jpeg::SOS => decoder.scan(segment, entropy_coded)?,
+            _ => {}
+        }
+
+        Ok(())
+    })?;
+
+    if decoder.components.is_empty() {
+        Err("Missing JPEG frame header")?;
+    }
+
+    if decoder
+        .components
+        .iter()
+        .any(|component| component.quantization.is_none())
+    {
+        Err("Missing JPEG scan of a component")?;
+    }
+
+    Ok(decoder.components)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_huffman() -> Result<(), Box<dyn std::error::Error>> {
+        // Two codes of 2 bits, 00 and 01, and one of 10 bits, 1000000000.
+        let mut counts = [0; 16];
+        counts[1] = 2;
+        counts[9] = 1;
+        let huffman = Huffman::new(&counts, &[5, 7, 9]);
+
+        let mut reader = BitReader::new(&[0b0110_0000, 0b0000_0000]);
+        assert_eq!(reader.huffman(&huffman)?, 7);
+        assert_eq!(reader.huffman(&huffman)?, 9);
+        assert_eq!(reader.huffman(&huffman)?, 5);
+
+        let mut reader = BitReader::new(&[0xfe, 0xfe]);
+        assert!(reader.huffman(&huffman).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bit_reader_signed() {
+        let mut reader = BitReader::new(&[0b0101_1000]);
+
+        assert_eq!(reader.signed(3), -5);
+        assert_eq!(reader.signed(2), 3);
+        assert_eq!(reader.signed(0), 0);
+    }
+
+    #[test]
+    fn test_bit_reader_stuffed_bytes_and_restart() -> Result<(), Box<dyn std::error::Error>> {
+        let mut reader = BitReader::new(&[0xff, 0x00, 0x80, 0xff, 0xd0, 0x40]);
+
+        assert_eq!(reader.bits(8), 0xff);
+        assert_eq!(reader.bits(1), 1);
+        // Zeros are read at the marker.
+        assert_eq!(reader.bits(16), 0);
+
+        reader.restart()?;
+        assert_eq!(reader.bits(2), 1);
+        assert!(reader.restart().is_err());
+
+        Ok(())
+    }
+
+    /// A single block of a baseline JPEG image, whose DC difference has the given size.
+    fn single_block(frame: u8, precision: u8, dc_size: u8) -> Vec<u8> {
+        let mut data = vec![0xff, 0xd8, 0xff, DQT, 0x00, 0x43, 0x00];
+        data.extend(&[1; 64]);
+        data.extend(&[
+            0xff, frame, 0x00, 0x0b, precision, 0x00, 0x08, 0x00, 0x08, 0x01,
+        ]);
+        data.extend(&[0x01, 0x11, 0x00]);
+
+        // A single code of 1 bit per table, the DC difference size and the end of block.
+        for (class, value) in &[(0x00, dc_size), (0x10, 0x00)] {
+            data.extend(&[0xff, DHT, 0x00, 0x14, *class, 0x01]);
+            data.extend(&[0; 15]);
+            data.push(*value);
+        }
+
+        data.extend(&[
+            0xff,
+            jpeg::SOS,
+            0x00,
+            0x08,
+            0x01,
+            0x01,
+            0x00,
+            0x00,
+            0x3f,
+            0x00,
+        ]);
+        data.extend(&[0x00, 0x00, 0x00, 0xff, 0xd9]);
+        data
+    }
+
+    #[test]
+    fn test_decode_dc_difference_size() {
+        assert!(decode(&single_block(SOF0, 8, 11)).is_ok());
+        assert!(decode(&single_block(SOF1, 12, 15)).is_ok());
+
+        assert_eq!(
+            decode(&single_block(SOF0, 8, 12))
+                .err()
+                .map(|error| error.to_string()),
+            Some("Invalid JPEG DC difference size: 12".to_owned())
+        );
+        assert_eq!(
+            decode(&single_block(SOF1, 12, 255))
+                .err()
+                .map(|error| error.to_string()),
+            Some("Invalid JPEG DC difference size: 255".to_owned())
+        );
+        assert_eq!(
+            decode(&single_block(SOF0, 16, 11))
+                .err()
+                .map(|error| error.to_string()),
+            Some("Unsupported JPEG sample precision: 16".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        // Arithmetic coding.
+        let arithmetic = [0xff, 0xd8, 0xff, 0xc9, 0x00, 0x02, 0xff, 0xd9];
+
+        assert_eq!(
+            decode(&arithmetic).err().map(|error| error.to_string()),
+            Some("Unsupported JPEG frame type: 0xc9".to_owned())
+        );
+        assert_eq!(
+            decode(&[0xff, 0xd8, 0xff, 0xd9])
+                .err()
+                .map(|error| error.to_string()),
+            Some("Missing JPEG frame header".to_owned())
+        );
+    }
+}
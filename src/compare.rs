@@ -0,0 +1,388 @@
+use crate::{normalize_raw, raw, raw_hash};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use sha2::Digest;
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("compare")
+        .about("Compares a raw file with its DNG conversion, reporting whether both yield the same hash with --raw-hash normalized and why not")
+        .arg(
+            Arg::with_name("original")
+                .takes_value(true)
+                .required(true)
+                .help("Path to the original raw file, e.g. a CR2, NEF or ARW file"),
+        )
+        .arg(
+            Arg::with_name("conversion")
+                .takes_value(true)
+                .required(true)
+                .help("Path to the DNG file converted from it"),
+        )
+        .arg(
+            Arg::with_name("json")
+                .long("--json")
+                .help("Prints a JSON object to stdout, containing what has been found out about both files and the reasons they diverge"),
+        )
+}
+
+/// What has been found out about one of the compared files.
+#[derive(Debug, Default, PartialEq, serde::Serialize)]
+struct Inspection {
+    file_path: std::path::PathBuf,
+    /// Hash in the `normalized` raw hash mode.
+    hash: Option<String>,
+    sensor_width: Option<usize>,
+    sensor_height: Option<usize>,
+    /// Area the sensor data has been cropped to.
+    area: Option<raw::Area>,
+    /// Number of low bits that were zero in all values and have been dropped.
+    shift: Option<u32>,
+    /// Software that wrote the DNG file.
+    software: Option<String>,
+    /// Name of the raw file the DNG file has been converted from.
+    original_raw_file_name: Option<String>,
+    /// Whether the DNG file embeds the raw file it has been converted from, which is hashed
+    /// instead of its own sensor data.
+    embeds_original: bool,
+    /// Why the file could not be normalized.
+    error: Option<String>,
+}
+
+/// Report comparing a raw file with its DNG conversion.
+#[derive(Debug, PartialEq, serde::Serialize)]
+struct Report {
+    original: Inspection,
+    conversion: Inspection,
+    /// Whether both yield the same hash in the `normalized` raw hash mode.
+    equivalent: bool,
+    /// Why they do not.
+    reasons: Vec<String>,
+}
+
+/// Read and normalize a raw file, see `raw_hash::normalize`.
+///
+/// # Arguments
+/// * `file_path` – Path to the raw file.
+fn inspect(
+    file_path: &std::path::Path,
+) -> (
+    Inspection,
+    Result<raw_hash::Normalized, Box<dyn std::error::Error>>,
+) {
+    let mut inspection = Inspection {
+        file_path: file_path.to_owned(),
+        ..Inspection::default()
+    };
+
+    let normalized = std::fs::read(file_path)
+        .map_err(|error| format!("Failed opening file: {}", error).into())
+        .and_then(|data| {
+            let original = match raw::provenance(&data)? {
+                Some(provenance) => {
+                    inspection.software = provenance.software;
+                    inspection.original_raw_file_name = provenance.original_raw_file_name;
+                    inspection.embeds_original = provenance.original_raw_file_data.is_some();
+
+                    provenance.original_raw_file_data
+                }
+                None => None,
+            };
+
+            normalize_raw(original.as_deref().unwrap_or(&data))
+        });
+
+    match &normalized {
+        Ok(normalized) => {
+            let mut hasher = sha2::Sha256::new();
+            normalized.hash(&mut hasher);
+
+            inspection.hash = Some(data_encoding::HEXLOWER.encode(&hasher.result()));
+            inspection.sensor_width = Some(normalized.sensor_width);
+            inspection.sensor_height = Some(normalized.sensor_height);
+            inspection.area = Some(normalized.area);
+            inspection.shift = Some(normalized.shift);
+        }
+        Err(error) => inspection.error = Some(error.to_string()),
+    }
+
+    (inspection, normalized)
+}
+
+/// Explain why the normalized sensor data of a raw file and of its conversion differ. Empty if
+/// they are equal.
+///
+/// # Arguments
+/// * `original` – Normalized sensor data of the raw file.
+/// * `conversion` – Normalized sensor data of the DNG file.
+fn divergences(original: &raw_hash::Normalized, conversion: &raw_hash::Normalized) -> Vec<String> {
+    let size =
+        |normalized: &raw_hash::Normalized| (normalized.area.width(), normalized.area.height());
+
+    if size(original) != size(conversion) {
+        let mut reasons = vec![format!(
+            "The active area of the original is {}x{} pixels, of the conversion {}x{}",
+            original.area.width(),
+            original.area.height(),
+            conversion.area.width(),
+            conversion.area.height()
+        )];
+
+        if size(conversion) == (original.sensor_width, original.sensor_height) {
+            reasons.push(
+                "The conversion does not record an active area, e.g. because the converter does not know the camera"
+                    .to_owned(),
+            );
+        } else if size(original) == (original.sensor_width, original.sensor_height) {
+            reasons.push(
+                "The original does not record an active area, e.g. because only the maker notes of CR2 files are read"
+                    .to_owned(),
+            );
+        }
+
+        return reasons;
+    }
+
+    let differences: Vec<(usize, i32)> = original
+        .pixels
+        .iter()
+        .zip(&conversion.pixels)
+        .enumerate()
+        .filter(|(_, (a, b))| a != b)
+        .map(|(index, (&a, &b))| (index, i32::from(b) - i32::from(a)))
+        .collect();
+
+    let (index, offset) = match differences.first() {
+        Some(&first) => first,
+        None => return Vec::new(),
+    };
+
+    let mut reasons = vec![format!(
+        "{} of {} values differ, the first at row {}, column {} of the active area: {} in the original, {} in the conversion",
+        differences.len(),
+        original.pixels.len(),
+        index / original.area.width(),
+        index % original.area.width(),
+        original.pixels[index],
+        conversion.pixels[index]
+    )];
+
+    if differences.len() == original.pixels.len()
+        && differences.iter().all(|&(_, other)| other == offset)
+    {
+        reasons.push(format!(
+            "All values of the conversion are offset by {}, e.g. because the converter subtracted the black level",
+            offset
+        ));
+    }
+
+    if original.shift != conversion.shift {
+        reasons.push(format!(
+            "The original has {} unused low bits, the conversion {}, e.g. because the converter rescaled the values",
+            original.shift, conversion.shift
+        ));
+    }
+
+    reasons
+}
+
+/// Compare a raw file with its DNG conversion.
+///
+/// # Arguments
+/// * `original` – Path to the raw file.
+/// * `conversion` – Path to the DNG file.
+fn compare(original: &std::path::Path, conversion: &std::path::Path) -> Report {
+    let (original, normalized_original) = inspect(original);
+    let (conversion, normalized_conversion) = inspect(conversion);
+
+    let mut reasons = Vec::new();
+
+    if let Some(error) = &original.error {
+        reasons.push(format!("The original can not be normalized: {}", error));
+    }
+
+    if let Some(error) = &conversion.error {
+        reasons.push(format!("The conversion can not be normalized: {}", error));
+    }
+
+    if let (Ok(normalized_original), Ok(normalized_conversion)) =
+        (&normalized_original, &normalized_conversion)
+    {
+        reasons.extend(divergences(normalized_original, normalized_conversion));
+    }
+
+    Report {
+        equivalent: original.hash.is_some() && original.hash == conversion.hash,
+        original,
+        conversion,
+        reasons,
+    }
+}
+
+/// Describe an inspected file on a single line.
+fn describe(inspection: &Inspection) -> String {
+    let mut description = format!("{}: ", inspection.file_path.display());
+
+    match (
+        &inspection.hash,
+        inspection.sensor_width,
+        inspection.sensor_height,
+        inspection.area,
+    ) {
+        (Some(hash), Some(width), Some(height), Some(area)) => {
+            description += &format!(
+                "{}, {}x{} pixels cropped to {}x{} at row {}, column {}",
+                hash,
+                width,
+                height,
+                area.width(),
+                area.height(),
+                area.top,
+                area.left
+            );
+        }
+        _ => description += inspection.error.as_deref().unwrap_or("not normalized"),
+    }
+
+    if let Some(shift) = inspection.shift.filter(|&shift| shift > 0) {
+        description += &format!(", {} unused low bits dropped", shift);
+    }
+
+    if let Some(software) = &inspection.software {
+        description += &format!(", written by {}", software);
+    }
+
+    if let Some(original_raw_file_name) = &inspection.original_raw_file_name {
+        description += &format!(", converted from {}", original_raw_file_name);
+    }
+
+    if inspection.embeds_original {
+        description += ", embeds its original raw file";
+    }
+
+    description
+}
+
+/// Compare a raw file with its DNG conversion, printing a report.
+///
+/// # Arguments
+/// * `matches` – Command line arguments of the `compare` subcommand.
+pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let original = matches.value_of("original").ok_or("No original provided")?;
+    let conversion = matches
+        .value_of("conversion")
+        .ok_or("No conversion provided")?;
+
+    let report = compare(
+        std::path::Path::new(original),
+        std::path::Path::new(conversion),
+    );
+
+    if matches.is_present("json") {
+        println!("{}", serde_json::to_string(&report)?);
+    } else {
+        println!("original   {}", describe(&report.original));
+        println!("conversion {}", describe(&report.conversion));
+
+        if report.equivalent {
+            println!("equivalent");
+        } else {
+            println!("divergent");
+
+            for reason in &report.reasons {
+                println!("- {}", reason);
+            }
+        }
+    }
+
+    if !report.equivalent {
+        Err("The normalized hashes of the original and the conversion diverge")?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalized(
+        sensor_width: usize,
+        sensor_height: usize,
+        area: (usize, usize),
+        pixels: &[u16],
+    ) -> raw_hash::Normalized {
+        raw_hash::Normalized {
+            sensor_width,
+            sensor_height,
+            area: raw::Area {
+                top: 0,
+                left: 0,
+                bottom: area.1,
+                right: area.0,
+            },
+            shift: 0,
+            pixels: pixels.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_divergences_equal() {
+        let original = normalized(2, 2, (2, 2), &[1, 2, 3, 4]);
+
+        assert!(divergences(&original, &normalized(4, 4, (2, 2), &[1, 2, 3, 4])).is_empty());
+    }
+
+    #[test]
+    fn test_divergences_active_area() {
+        let reasons = divergences(
+            &normalized(4, 2, (2, 2), &[1, 2, 3, 4]),
+            &normalized(4, 2, (4, 2), &[0, 1, 2, 0, 0, 3, 4, 0]),
+        );
+
+        assert_eq!(
+            reasons,
+            vec![
+                "The active area of the original is 2x2 pixels, of the conversion 4x2",
+                "The conversion does not record an active area, e.g. because the converter does not know the camera",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_divergences_values() {
+        let reasons = divergences(
+            &normalized(2, 2, (2, 2), &[1, 2, 3, 4]),
+            &normalized(2, 2, (2, 2), &[1, 2, 5, 4]),
+        );
+
+        assert_eq!(
+            reasons,
+            vec!["1 of 4 values differ, the first at row 1, column 0 of the active area: 3 in the original, 5 in the conversion"]
+        );
+    }
+
+    #[test]
+    fn test_divergences_black_level() {
+        let reasons = divergences(
+            &normalized(2, 1, (2, 1), &[512, 600]),
+            &normalized(2, 1, (2, 1), &[0, 88]),
+        );
+
+        assert_eq!(reasons.len(), 2);
+        assert_eq!(
+            reasons[1],
+            "All values of the conversion are offset by -512, e.g. because the converter subtracted the black level"
+        );
+    }
+
+    #[test]
+    fn test_compare_missing_files() {
+        let report = compare(
+            std::path::Path::new("/nonexistent.CR2"),
+            std::path::Path::new("/nonexistent.dng"),
+        );
+
+        assert!(!report.equivalent);
+        assert_eq!(report.reasons.len(), 2);
+        assert!(report.reasons[0].starts_with("The original can not be normalized"));
+    }
+}
@@ -0,0 +1,214 @@
+use crate::time_zone::TimeZoneSource;
+use crate::ExifDateTime;
+use chrono::{DateTime, FixedOffset, NaiveDateTime};
+
+/// Formats of dates that include a time zone, as emitted by `exiftool`.
+const ZONED_FORMATS: [&str; 2] = ["%Y:%m:%d %H:%M:%S%.f %#z", "%Y:%m:%d %H:%M %#z"];
+
+/// Formats of local dates without time zone, as emitted by `exiftool`.
+const LOCAL_FORMATS: [&str; 2] = ["%Y:%m:%d %H:%M:%S%.f", "%Y:%m:%d %H:%M"];
+
+/// EXIF tag the date of an image can be taken from. Named like the tags of `exiftool`.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum DateTag {
+    /// When the image has been taken.
+    DateTimeOriginal,
+    /// When the image has been digitized, stored in the EXIF `DateTimeDigitized` field.
+    CreateDate,
+    /// When the image has been digitized, stored in XMP metadata.
+    DateTimeDigitized,
+    /// When the file has last been modified, e.g. by editing software.
+    ModifyDate,
+}
+
+/// Order in which date tags are tried, if no priority has been provided.
+pub const DEFAULT_PRIORITY: [DateTag; 4] = [
+    DateTag::DateTimeOriginal,
+    DateTag::CreateDate,
+    DateTag::DateTimeDigitized,
+    DateTag::ModifyDate,
+];
+
+impl std::fmt::Display for DateTag {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let tag = match self {
+            DateTag::DateTimeOriginal => "DateTimeOriginal",
+            DateTag::CreateDate => "CreateDate",
+            DateTag::DateTimeDigitized => "DateTimeDigitized",
+            DateTag::ModifyDate => "ModifyDate",
+        };
+
+        write!(formatter, "{}", tag)
+    }
+}
+
+impl DateTag {
+    /// Parse a comma-separated list of date tags, e.g. `DateTimeOriginal,CreateDate`.
+    ///
+    /// # Arguments
+    /// * `priority` – Date tags in the order they should be tried.
+    pub fn parse_priority(priority: &str) -> Result<Vec<DateTag>, Box<dyn std::error::Error>> {
+        let mut tags = Vec::new();
+
+        for name in priority.split(',').map(str::trim) {
+            let tag = DEFAULT_PRIORITY
+                .iter()
+                .find(|tag| tag.to_string() == name)
+                .ok_or_else(|| {
+                    format!(
+                        r#"Unknown date tag "{}", expected one of {}"#,
+                        name,
+                        join(&DEFAULT_PRIORITY)
+                    )
+                })?;
+
+            if tags.contains(tag) {
+                Err(format!(r#"Date tag "{}" provided more than once"#, name))?;
+            }
+
+            tags.push(*tag);
+        }
+
+        Ok(tags)
+    }
+
+    /// Values of the tag, preferring the variant that includes sub-second precision.
+    ///
+    /// # Arguments
+    /// * `exif` – The Exif data to be examined.
+    fn values<'a>(&self, exif: &'a ExifDateTime) -> Vec<&'a str> {
+        let values = match self {
+            DateTag::DateTimeOriginal => vec![&exif.SubSecDateTimeOriginal, &exif.DateTimeOriginal],
+            DateTag::CreateDate => vec![&exif.SubSecCreateDate, &exif.CreateDate],
+            DateTag::DateTimeDigitized => vec![&exif.DateTimeDigitized],
+            DateTag::ModifyDate => vec![&exif.SubSecModifyDate, &exif.ModifyDate],
+        };
+
+        values.into_iter().flatten().map(String::as_str).collect()
+    }
+
+    /// Time zone offset recorded along with the tag, and the source it has been taken from.
+    ///
+    /// # Arguments
+    /// * `exif` – The Exif data to be examined.
+    pub fn offset_time<'a>(&self, exif: &'a ExifDateTime) -> Option<(&'a str, TimeZoneSource)> {
+        let (offset, source) = match self {
+            DateTag::DateTimeOriginal => {
+                (&exif.OffsetTimeOriginal, TimeZoneSource::OffsetTimeOriginal)
+            }
+            DateTag::CreateDate | DateTag::DateTimeDigitized => (
+                &exif.OffsetTimeDigitized,
+                TimeZoneSource::OffsetTimeDigitized,
+            ),
+            DateTag::ModifyDate => (&exif.OffsetTime, TimeZoneSource::OffsetTime),
+        };
+
+        offset.as_deref().map(|offset| (offset, source))
+    }
+}
+
+/// Join date tags into a comma-separated list.
+///
+/// # Arguments
+/// * `tags` – Date tags to be joined.
+fn join(tags: &[DateTag]) -> String {
+    tags.iter()
+        .map(|tag| tag.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Date read from a date tag.
+#[derive(Debug, PartialEq)]
+pub enum TagDate {
+    /// Local date without time zone.
+    Local(NaiveDateTime),
+    Zoned(DateTime<FixedOffset>),
+}
+
+/// Whether the value is a placeholder written by cameras whose clock has not been set, e.g.
+/// `0000:00:00 00:00:00` or `    :  :     :  :  `.
+///
+/// # Arguments
+/// * `value` – Value of a date tag.
+fn is_placeholder(value: &str) -> bool {
+    let date = value.split_whitespace().next().unwrap_or("");
+
+    !date
+        .chars()
+        .any(|character| ('1'..='9').contains(&character))
+}
+
+/// Parse the value of a date tag. Returns `None` if the value is a placeholder.
+///
+/// # Arguments
+/// * `value` – Value of a date tag, e.g. `2017:01:05 13:52:55.96+02:00`.
+pub fn parse_date(value: &str) -> Result<Option<TagDate>, Box<dyn std::error::Error>> {
+    let value = value.trim();
+
+    if is_placeholder(value) {
+        return Ok(None);
+    }
+
+    for format in &ZONED_FORMATS {
+        if let Ok(date) = DateTime::parse_from_str(value, format) {
+            return Ok(Some(TagDate::Zoned(date)));
+        }
+    }
+
+    for format in &LOCAL_FORMATS {
+        if let Ok(date) = NaiveDateTime::parse_from_str(value, format) {
+            return Ok(Some(TagDate::Local(date)));
+        }
+    }
+
+    Err(format!(r#"Failed parsing date "{}""#, value))?
+}
+
+/// Find the first date tag in order of priority that holds a valid date. Placeholder and invalid
+/// dates are skipped, adding a warning.
+///
+/// # Arguments
+/// * `exif` – The Exif data to be examined.
+/// * `priority` – Date tags in the order they should be tried.
+/// * `warnings` – Warnings about skipped date tags are appended to it.
+pub fn resolve(
+    exif: &ExifDateTime,
+    priority: &[DateTag],
+    warnings: &mut Vec<String>,
+) -> Result<(DateTag, TagDate), Box<dyn std::error::Error>> {
+    let skipped = warnings.len();
+
+    for tag in priority {
+        let mut placeholder = None;
+        let mut error = None;
+
+        for value in tag.values(exif) {
+            match parse_date(value) {
+                Ok(Some(date)) => return Ok((*tag, date)),
+                Ok(None) => placeholder = Some(value),
+                Err(parse_error) => error = Some(parse_error),
+            }
+        }
+
+        if let Some(value) = placeholder {
+            warnings.push(format!(
+                r#"Ignored placeholder date "{}" in {}"#,
+                value.trim(),
+                tag
+            ));
+        } else if let Some(error) = error {
+            warnings.push(format!("Ignored invalid date in {}: {}", tag, error));
+        }
+    }
+
+    if warnings.len() > skipped {
+        Err(format!(
+            "No valid date found in {}: {}",
+            join(priority),
+            warnings[skipped..].join(", ")
+        ))?;
+    }
+
+    Err(format!("No date found in {}", join(priority)))?
+}
@@ -0,0 +1,728 @@
+use crate::{
+    get_date_original_from_exif, get_exif, hash_image, hash_image_with_phash, phash, walk,
+    DateOptions,
+};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::io::Write;
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("dedupe")
+        .about("Finds files that contain the same image, based on the hash of the image buffer")
+        .arg(
+            Arg::with_name("path")
+                .takes_value(true)
+                .required(true)
+                .multiple(true)
+                .help("Path to image file or directory. Directories are searched recursively"),
+        )
+        .arg(
+            Arg::with_name("action")
+                .takes_value(true)
+                .long("--action")
+                .possible_values(&["report", "hardlink", "quarantine", "delete"])
+                .default_value("report")
+                .help("What to do with duplicates. Of each set, the file with the first path in sort order is kept"),
+        )
+        .arg(
+            Arg::with_name("quarantine directory")
+                .takes_value(true)
+                .long("--quarantine-dir")
+                .required_if("action", "quarantine")
+                .help("Directory duplicates are moved to by --action quarantine"),
+        )
+        .arg(
+            Arg::with_name("similar")
+                .takes_value(true)
+                .long("--similar")
+                .help("Reports images that look alike instead of exact duplicates, based on a perceptual hash. Takes the maximum number of differing bits out of 64, e.g. 10"),
+        )
+        .arg(
+            Arg::with_name("dry run")
+                .long("--dry-run")
+                .help("Reports which actions would be taken, without taking them"),
+        )
+        .arg(
+            Arg::with_name("yes")
+                .long("--yes")
+                .help("Deletes duplicates without asking for confirmation"),
+        )
+}
+
+/// What to do with the duplicates of a set.
+#[derive(Debug, PartialEq)]
+enum Action {
+    Report,
+    /// Replace duplicates with hard links to the kept file.
+    Hardlink,
+    /// Move duplicates to the provided directory.
+    Quarantine(std::path::PathBuf),
+    Delete,
+}
+
+/// Files that contain the same image.
+#[derive(Debug, PartialEq)]
+struct DuplicateSet {
+    hash: [u8; 32],
+    /// The file that is kept.
+    keep: std::path::PathBuf,
+    duplicates: Vec<std::path::PathBuf>,
+}
+
+/// Device and inode of a file, which identify it regardless of the path it is reached by. `None`
+/// if its metadata can not be read.
+///
+/// # Arguments
+/// * `file_path` – Path to file.
+fn file_id(file_path: &std::path::Path) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    std::fs::metadata(file_path)
+        .ok()
+        .map(|metadata| (metadata.dev(), metadata.ino()))
+}
+
+/// Group files by hash, returning sets of files that share the same hash. Within a set, the file
+/// with the first path in sort order is kept. Paths leading to the same file, e.g. hard links,
+/// are not duplicates of each other, only the first of them is part of a set.
+///
+/// # Arguments
+/// * `hashes` – Files along with the hash of their image buffer.
+fn group_duplicates(hashes: Vec<(std::path::PathBuf, [u8; 32])>) -> Vec<DuplicateSet> {
+    let mut groups = std::collections::BTreeMap::<[u8; 32], Vec<std::path::PathBuf>>::new();
+
+    for (file_path, hash) in hashes {
+        groups.entry(hash).or_default().push(file_path);
+    }
+
+    let mut sets: Vec<DuplicateSet> = groups
+        .into_iter()
+        .filter_map(|(hash, mut file_paths)| {
+            file_paths.sort();
+
+            let mut file_ids = std::collections::HashSet::new();
+
+            file_paths.retain(|file_path| match file_id(file_path) {
+                Some(file_id) => file_ids.insert(file_id),
+                None => true,
+            });
+
+            if file_paths.len() < 2 {
+                return None;
+            }
+
+            let keep = file_paths.remove(0);
+
+            Some(DuplicateSet {
+                hash,
+                keep,
+                duplicates: file_paths,
+            })
+        })
+        .collect();
+
+    sets.sort_by(|a, b| a.keep.cmp(&b.keep));
+
+    sets
+}
+
+/// Metadata of a file that is compared within a duplicate set.
+#[derive(Debug, PartialEq)]
+struct FileInfo {
+    size: Option<u64>,
+    date_time: Option<String>,
+    camera: Option<String>,
+}
+
+impl FileInfo {
+    /// Read the metadata of a file. Metadata that can not be read is left empty.
+    ///
+    /// # Arguments
+    /// * `file_path` – Path to file.
+    fn read(file_path: &std::path::Path) -> Self {
+        let size = std::fs::metadata(file_path)
+            .ok()
+            .map(|metadata| metadata.len());
+
+        let exif = get_exif(file_path).ok();
+
+        let date_time = exif.as_ref().and_then(|exif| {
+            get_date_original_from_exif(&exif.date_time, &DateOptions::default())
+                .ok()
+                .map(|date_original| {
+                    date_original
+                        .date
+                        .to_rfc3339_opts(chrono::SecondsFormat::Millis, false)
+                })
+        });
+
+        let camera = exif.and_then(|exif| exif.camera.description());
+
+        FileInfo {
+            size,
+            date_time,
+            camera,
+        }
+    }
+}
+
+/// Names of the metadata fields that are not equal across all files.
+///
+/// # Arguments
+/// * `infos` – Metadata of the files of a duplicate set.
+fn differences(infos: &[FileInfo]) -> Vec<&'static str> {
+    let mut differences = Vec::new();
+
+    if let Some((first, rest)) = infos.split_first() {
+        if rest.iter().any(|info| info.size != first.size) {
+            differences.push("size");
+        }
+
+        if rest.iter().any(|info| info.date_time != first.date_time) {
+            differences.push("date_time");
+        }
+
+        if rest.iter().any(|info| info.camera != first.camera) {
+            differences.push("camera");
+        }
+    }
+
+    differences
+}
+
+/// Ask for confirmation on stderr, reading the answer from stdin.
+///
+/// # Arguments
+/// * `prompt` – Question to be asked.
+fn confirm(prompt: &str) -> Result<bool, Box<dyn std::error::Error>> {
+    eprint!("{} [y/N] ", prompt);
+    std::io::stderr().flush()?;
+
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Path of a file relative to the directory it has been found in, out of the paths provided on the
+/// command line. Files that have been provided explicitly are reduced to their file name.
+///
+/// # Arguments
+/// * `roots` – Paths provided on the command line.
+/// * `file_path` – Path to the file, as found by `walk::walk`.
+fn relative_path(roots: &[&std::path::Path], file_path: &std::path::Path) -> std::path::PathBuf {
+    roots
+        .iter()
+        .filter_map(|root| file_path.strip_prefix(root).ok())
+        .filter(|relative_path| relative_path.file_name().is_some())
+        .min_by_key(|relative_path| relative_path.components().count())
+        .or_else(|| file_path.file_name().map(std::path::Path::new))
+        .unwrap_or(file_path)
+        .to_owned()
+}
+
+/// Choose where a duplicate is moved to within the quarantine directory. If the path has already
+/// been taken, a counter is appended to the file stem, e.g. `IMG_1234-1.JPG`.
+///
+/// # Arguments
+/// * `directory` – Quarantine directory.
+/// * `relative_path` – Path of the duplicate relative to the directory it has been found in.
+/// * `taken` – Paths already chosen for other duplicates, which the chosen path is added to.
+fn quarantine_path(
+    directory: &std::path::Path,
+    relative_path: &std::path::Path,
+    taken: &mut std::collections::HashSet<std::path::PathBuf>,
+) -> std::path::PathBuf {
+    let path = directory.join(relative_path);
+    let mut candidate = path.clone();
+    let mut counter = 0;
+
+    while taken.contains(&candidate) || std::fs::symlink_metadata(&candidate).is_ok() {
+        counter += 1;
+
+        let mut file_name = path.file_stem().unwrap_or_default().to_owned();
+        file_name.push(format!("-{}", counter));
+
+        if let Some(extension) = path.extension() {
+            file_name.push(".");
+            file_name.push(extension);
+        }
+
+        candidate = path.with_file_name(file_name);
+    }
+
+    taken.insert(candidate.clone());
+
+    candidate
+}
+
+/// Move a file without replacing an existing file at the target path.
+///
+/// # Arguments
+/// * `source` – Path to the file to be moved.
+/// * `target` – Path the file is moved to.
+fn move_file(
+    source: &std::path::Path,
+    target: &std::path::Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let already_exists = || format!("Target file already exists: {}", target.display());
+
+    // Unlike renaming, hard linking fails if the target exists.
+    match std::fs::hard_link(source, target) {
+        Ok(()) => {}
+        Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => Err(already_exists())?,
+        // E.g. because the quarantine directory is on another file system.
+        Err(_) => {
+            let mut reader = std::fs::File::open(source)?;
+            let mut writer = std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(target)
+                .map_err(|error| match error.kind() {
+                    std::io::ErrorKind::AlreadyExists => already_exists(),
+                    _ => format!("Failed creating {}: {}", target.display(), error),
+                })?;
+
+            std::io::copy(&mut reader, &mut writer)?;
+            writer.set_permissions(reader.metadata()?.permissions())?;
+        }
+    }
+
+    std::fs::remove_file(source)?;
+
+    Ok(())
+}
+
+/// Apply an action to a duplicate, returning the report line.
+///
+/// # Arguments
+/// * `action` – What to do with the duplicate.
+/// * `keep` – The file that is kept.
+/// * `duplicate` – The duplicate of the kept file.
+/// * `destination` – Where the duplicate is moved to by `Action::Quarantine`, see
+///   `quarantine_path`.
+/// * `dry_run` – Only report the action, without taking it.
+fn apply(
+    action: &Action,
+    keep: &std::path::Path,
+    duplicate: &std::path::Path,
+    destination: Option<&std::path::Path>,
+    dry_run: bool,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let file_name = duplicate
+        .file_name()
+        .ok_or_else(|| format!("Invalid file path: {:?}", duplicate))?;
+
+    // Removing or replacing a path to the kept file would lose the image.
+    if file_id(duplicate).is_some() && file_id(duplicate) == file_id(keep) {
+        Err(format!("Same file as {}", keep.display()))?;
+    }
+
+    let line = match action {
+        Action::Report => return Ok(None),
+        Action::Hardlink => {
+            if !dry_run {
+                let mut temporary_file_name = std::ffi::OsString::from(".");
+                temporary_file_name.push(file_name);
+                temporary_file_name.push(".ciid-link");
+
+                let temporary_path = duplicate.with_file_name(temporary_file_name);
+
+                std::fs::hard_link(keep, &temporary_path)?;
+
+                if let Err(error) = std::fs::rename(&temporary_path, duplicate) {
+                    std::fs::remove_file(&temporary_path)?;
+                    Err(error)?;
+                }
+            }
+
+            format!("link    {} -> {}", duplicate.display(), keep.display())
+        }
+        Action::Quarantine(_) => {
+            let path = destination.ok_or("No quarantine destination provided")?;
+
+            if !dry_run {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+
+                move_file(duplicate, path)?;
+            }
+
+            format!("move    {} -> {}", duplicate.display(), path.display())
+        }
+        Action::Delete => {
+            if !dry_run {
+                std::fs::remove_file(duplicate)?;
+            }
+
+            format!("delete  {}", duplicate.display())
+        }
+    };
+
+    Ok(Some(if dry_run {
+        format!("{} (dry run)", line)
+    } else {
+        line
+    }))
+}
+
+/// Report sets of files whose perceptual hashes are similar, printing the distance of each file to
+/// the first file of its set.
+///
+/// # Arguments
+/// * `file_paths` – Paths to image files.
+/// * `max_distance` – Maximum number of differing bits for images to be considered similar.
+fn report_similar(
+    file_paths: Vec<std::path::PathBuf>,
+    max_distance: u32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let results = walk::par_map(&file_paths, |file_path| {
+        hash_image_with_phash(file_path, true)
+            .map_err(|error| error.to_string())
+            .and_then(|hashes| {
+                hashes
+                    .phash
+                    .ok_or_else(|| "Missing perceptual hash".to_owned())
+            })
+    });
+
+    let mut failures = 0;
+    let mut phashes = Vec::new();
+
+    for (file_path, result) in file_paths.into_iter().zip(results) {
+        match result {
+            Ok(phash) => phashes.push((file_path, phash)),
+            Err(error) => {
+                failures += 1;
+                eprintln!("failed  {}: {}", file_path.display(), error);
+            }
+        }
+    }
+
+    let groups = phash::group_similar(
+        &phashes.iter().map(|(_, phash)| *phash).collect::<Vec<_>>(),
+        max_distance,
+    );
+
+    for group in &groups {
+        println!("similar {} files", group.len());
+
+        let first = phashes[group[0]].1;
+
+        for index in group {
+            let (file_path, phash) = &phashes[*index];
+
+            println!(
+                "  {} {:>2} {}",
+                phash::format(*phash),
+                phash::distance(first, *phash),
+                file_path.display()
+            );
+        }
+    }
+
+    println!("{} similar set(s)", groups.len());
+
+    if failures > 0 {
+        Err(format!("Failed processing {} file(s)", failures))?;
+    }
+
+    Ok(())
+}
+
+/// Find files that contain the same image, report them and apply the requested action to the
+/// duplicates.
+///
+/// # Arguments
+/// * `matches` – Command line arguments of the `dedupe` subcommand.
+pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let paths: Vec<&str> = matches
+        .values_of("path")
+        .ok_or("No path provided")?
+        .collect();
+
+    let action = match matches.value_of("action") {
+        Some("hardlink") => Action::Hardlink,
+        Some("quarantine") => Action::Quarantine(
+            matches
+                .value_of("quarantine directory")
+                .ok_or("No quarantine directory provided")?
+                .into(),
+        ),
+        Some("delete") => Action::Delete,
+        _ => Action::Report,
+    };
+
+    let dry_run = matches.is_present("dry run");
+
+    let file_paths = walk::walk(&paths)?;
+
+    if let Some(max_distance) = matches.value_of("similar") {
+        let max_distance = max_distance
+            .parse::<u32>()
+            .map_err(|error| format!("Failed parsing maximum distance: {}", error))?;
+
+        if action != Action::Report {
+            Err("--similar only supports --action report, since similar images may differ")?;
+        }
+
+        return report_similar(file_paths, max_distance);
+    }
+
+    let results = walk::par_map(&file_paths, |file_path| {
+        hash_image(file_path).map_err(|error| error.to_string())
+    });
+
+    let mut failures = 0;
+    let mut hashes = Vec::new();
+
+    for (file_path, result) in file_paths.into_iter().zip(results) {
+        match result {
+            Ok(hash) => hashes.push((file_path, hash)),
+            Err(error) => {
+                failures += 1;
+                eprintln!("failed  {}: {}", file_path.display(), error);
+            }
+        }
+    }
+
+    let sets = group_duplicates(hashes);
+
+    let mut duplicates = 0;
+    let mut reclaimable = 0;
+
+    for set in &sets {
+        println!("duplicates {}", data_encoding::HEXLOWER.encode(&set.hash));
+
+        let infos: Vec<FileInfo> = std::iter::once(&set.keep)
+            .chain(&set.duplicates)
+            .map(|file_path| FileInfo::read(file_path))
+            .collect();
+
+        for (index, (file_path, info)) in std::iter::once(&set.keep)
+            .chain(&set.duplicates)
+            .zip(&infos)
+            .enumerate()
+        {
+            println!(
+                "  {} {} ({} bytes)",
+                if index == 0 { "keep   " } else { "dup    " },
+                file_path.display(),
+                info.size.unwrap_or(0)
+            );
+
+            if index > 0 {
+                duplicates += 1;
+                reclaimable += info.size.unwrap_or(0);
+            }
+        }
+
+        let differences = differences(&infos);
+
+        if !differences.is_empty() {
+            println!("  differs in {}", differences.join(", "));
+        }
+    }
+
+    println!(
+        "{} duplicate set(s), {} duplicate file(s), {} bytes reclaimable",
+        sets.len(),
+        duplicates,
+        reclaimable
+    );
+
+    if action == Action::Delete
+        && !dry_run
+        && duplicates > 0
+        && !matches.is_present("yes")
+        && !confirm(&format!("Delete {} duplicate file(s)?", duplicates))?
+    {
+        Err("Aborted, no files have been deleted")?;
+    }
+
+    let roots: Vec<&std::path::Path> = paths.iter().map(std::path::Path::new).collect();
+    let mut taken = std::collections::HashSet::new();
+
+    for set in &sets {
+        for duplicate in &set.duplicates {
+            let destination = match &action {
+                Action::Quarantine(directory) => Some(quarantine_path(
+                    directory,
+                    &relative_path(&roots, duplicate),
+                    &mut taken,
+                )),
+                _ => None,
+            };
+
+            match apply(
+                &action,
+                &set.keep,
+                duplicate,
+                destination.as_deref(),
+                dry_run,
+            ) {
+                Ok(Some(line)) => println!("{}", line),
+                Ok(None) => {}
+                Err(error) => {
+                    failures += 1;
+                    eprintln!("failed  {}: {}", duplicate.display(), error);
+                }
+            }
+        }
+    }
+
+    if failures > 0 {
+        Err(format!("Failed processing {} file(s)", failures))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_duplicates() {
+        let sets = group_duplicates(vec![
+            ("b/2.jpg".into(), [2; 32]),
+            ("b/1.jpg".into(), [1; 32]),
+            ("a/1.jpg".into(), [1; 32]),
+            ("c/1.jpg".into(), [1; 32]),
+            ("a/3.jpg".into(), [3; 32]),
+            ("a/2.jpg".into(), [2; 32]),
+        ]);
+
+        assert_eq!(
+            sets,
+            vec![
+                DuplicateSet {
+                    hash: [1; 32],
+                    keep: "a/1.jpg".into(),
+                    duplicates: vec!["b/1.jpg".into(), "c/1.jpg".into()],
+                },
+                DuplicateSet {
+                    hash: [2; 32],
+                    keep: "a/2.jpg".into(),
+                    duplicates: vec!["b/2.jpg".into()],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_same_file() -> Result<(), Box<dyn std::error::Error>> {
+        let directory = std::env::temp_dir().join("ciid-test_same_file");
+        std::fs::create_dir_all(&directory)?;
+
+        let keep = directory.join("a.jpg");
+        let link = directory.join("b.jpg");
+        std::fs::write(&keep, "image")?;
+
+        if !link.exists() {
+            std::fs::hard_link(&keep, &link)?;
+        }
+
+        assert_eq!(
+            group_duplicates(vec![(link.clone(), [1; 32]), (keep.clone(), [1; 32])]),
+            Vec::new()
+        );
+
+        for action in &[
+            Action::Hardlink,
+            Action::Quarantine(directory.join("quarantine")),
+            Action::Delete,
+        ] {
+            let destination = directory.join("quarantine").join("b.jpg");
+
+            assert!(apply(action, &keep, &link, Some(&destination), false).is_err());
+            assert!(apply(action, &keep, &keep, Some(&destination), false).is_err());
+            assert_eq!(std::fs::read_to_string(&keep)?, "image");
+            assert_eq!(std::fs::read_to_string(&link)?, "image");
+        }
+
+        std::fs::remove_dir_all(&directory)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_relative_path() {
+        let roots = [
+            std::path::Path::new("."),
+            std::path::Path::new("photos/2017"),
+        ];
+
+        for (file_path, expected) in &[
+            ("./sub/IMG_1.JPG", "sub/IMG_1.JPG"),
+            ("photos/2017/01/IMG_1.JPG", "01/IMG_1.JPG"),
+            ("/elsewhere/IMG_1.JPG", "IMG_1.JPG"),
+        ] {
+            assert_eq!(
+                relative_path(&roots, std::path::Path::new(file_path)),
+                std::path::Path::new(expected)
+            );
+        }
+    }
+
+    #[test]
+    fn test_quarantine_path() {
+        let directory = std::path::Path::new("/nonexistent/quarantine");
+        let mut taken = std::collections::HashSet::new();
+
+        let paths: Vec<std::path::PathBuf> =
+            ["a/IMG_1.JPG", "a/IMG_1.JPG", "a/IMG_1.JPG", "b/IMG_1"]
+                .iter()
+                .map(|relative_path| {
+                    quarantine_path(directory, std::path::Path::new(relative_path), &mut taken)
+                })
+                .collect();
+
+        assert_eq!(
+            paths,
+            vec![
+                directory.join("a/IMG_1.JPG"),
+                directory.join("a/IMG_1-1.JPG"),
+                directory.join("a/IMG_1-2.JPG"),
+                directory.join("b/IMG_1"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_move_file_existing_target() -> Result<(), Box<dyn std::error::Error>> {
+        let directory = std::env::temp_dir().join("ciid-test_move_file_existing_target");
+        std::fs::create_dir_all(&directory)?;
+
+        let source = directory.join("source");
+        let target = directory.join("target");
+        std::fs::write(&source, "source")?;
+        std::fs::write(&target, "target")?;
+
+        assert!(move_file(&source, &target).is_err());
+        assert_eq!(std::fs::read_to_string(&source)?, "source");
+        assert_eq!(std::fs::read_to_string(&target)?, "target");
+
+        std::fs::remove_dir_all(&directory)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_differences() {
+        let info = |size: u64, date_time: &str| FileInfo {
+            size: Some(size),
+            date_time: Some(date_time.to_owned()),
+            camera: None,
+        };
+
+        assert_eq!(
+            differences(&[info(1, "a"), info(1, "a")]),
+            Vec::<&str>::new()
+        );
+        assert_eq!(
+            differences(&[info(1, "a"), info(2, "a"), info(1, "b")]),
+            vec!["size", "date_time"]
+        );
+    }
+}
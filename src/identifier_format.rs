@@ -0,0 +1,513 @@
+use chrono::{DateTime, FixedOffset, Utc};
+use regex::Regex;
+use std::convert::TryInto;
+
+/// Format of the date when `${date}` is used without an explicit format. An ISO 8601 date in UTC,
+/// using the basic format and a comma as decimal sign so that it is safe to use in file names.
+const DEFAULT_DATE_FORMAT: &str = "%Y%m%dT%H%M%S,%3fZ";
+
+/// A single component of an identifier format.
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+    Literal(String),
+    /// Unix timestamp with second precision.
+    TimestampSeconds,
+    /// Unix timestamp with millisecond precision.
+    TimestampMillis,
+    /// Unix timestamp with microsecond precision.
+    TimestampMicros,
+    /// Date in UTC, formatted with the provided `strftime`-like format.
+    Date(String),
+    /// Hex encoded hash, optionally truncated to the provided number of characters.
+    Hash(Option<usize>),
+    /// Key ordering images with identical timestamps, padded with zeros.
+    SequenceKey,
+    /// Short discriminator of the camera, derived from make, model and serial number.
+    CameraId,
+    CameraModel,
+    SerialNumber,
+    /// Position of the file in the list of processed files, optionally padded with zeros to the
+    /// provided number of digits.
+    Sequence(Option<usize>),
+}
+
+/// Template describing how an identifier is assembled from file information, e.g.
+/// `${date:%Y%m%d-%H%M%S}-${hash:8}`.
+#[derive(Debug, PartialEq)]
+pub struct IdentifierFormat {
+    segments: Vec<Segment>,
+}
+
+/// File information that can be substituted into an identifier format.
+pub struct IdentifierFields<'a> {
+    pub timestamp: &'a DateTime<FixedOffset>,
+    /// Minimum number of digits timestamps should carry. Will be padded with zeros from the left.
+    pub timestamp_digits: u64,
+    pub hash: Option<&'a [u8]>,
+    pub sequence_key: Option<u32>,
+    pub camera_id: Option<&'a str>,
+    pub camera_model: Option<&'a str>,
+    pub serial_number: Option<&'a str>,
+    pub sequence: u64,
+}
+
+/// Replace all characters that are not safe to use in file names and URLs with underscores.
+///
+/// # Arguments
+/// * `value` – Value to be sanitized.
+fn sanitize(value: &str) -> String {
+    let regex = Regex::new("[^A-Za-z0-9]+").unwrap();
+
+    regex
+        .replace_all(value.trim(), "_")
+        .trim_matches('_')
+        .to_owned()
+}
+
+/// Fail if a part of an identifier contains characters that would make it point into another
+/// directory or be cut off as file extension: path separators, dots and control characters. Colons
+/// are rejected as well, since they are not allowed in file names on Windows and shown as slashes
+/// on macOS.
+///
+/// # Arguments
+/// * `value` – Part of the identifier, e.g. a literal or a formatted date.
+/// * `description` – Description of the part for the error message.
+fn check_file_name_safe(value: &str, description: &str) -> Result<(), Box<dyn std::error::Error>> {
+    match value
+        .chars()
+        .find(|&character| matches!(character, '/' | '\\' | '.' | ':') || character.is_control())
+    {
+        Some(character) => Err(format!(
+            "{} \"{}\" contains {:?}, which is not allowed in identifiers",
+            description, value, character
+        ))?,
+        None => Ok(()),
+    }
+}
+
+/// Whether dates formatted with a `strftime`-like format sort chronologically, i.e. whether it
+/// consists of zero padded specifiers running from the year down in descending significance
+/// (`%Y`, `%m`, `%d`, `%H`, `%M`, `%S` and fractional seconds) without leaving one out, separated
+/// only by literals.
+///
+/// # Arguments
+/// * `format` – Format of a date.
+fn is_sortable_date(format: &str) -> bool {
+    use chrono::format::{Fixed, Item, Numeric, Pad};
+
+    let mut significances = Vec::new();
+
+    for item in chrono::format::StrftimeItems::new(format) {
+        let significance = match item {
+            Item::Literal(_) | Item::OwnedLiteral(_) | Item::Space(_) | Item::OwnedSpace(_) => {
+                continue
+            }
+            Item::Numeric(numeric, Pad::Zero) => match numeric {
+                Numeric::Year => 0,
+                Numeric::Month => 1,
+                Numeric::Day => 2,
+                Numeric::Hour => 3,
+                Numeric::Minute => 4,
+                Numeric::Second => 5,
+                Numeric::Nanosecond => 6,
+                _ => return false,
+            },
+            // Fractional seconds, e.g. `%3f`.
+            Item::Fixed(Fixed::Internal(_)) => 6,
+            _ => return false,
+        };
+
+        significances.push(significance);
+    }
+
+    significances.first() == Some(&0) && significances.windows(2).all(|pair| pair[1] == pair[0] + 1)
+}
+
+impl IdentifierFormat {
+    /// Parse an identifier format.
+    ///
+    /// Fails if the format references unknown variables, or if it does not start with a
+    /// chronological variable, since identifiers would then no longer be sortable, see
+    /// `is_sortable_date`. Also fails if literals or dates contain path separators, dots, colons or
+    /// control characters, see `check_file_name_safe`.
+    ///
+    /// # Arguments
+    /// * `format` – Template containing variables such as `${timestamp_millis}` or `${hash:8}`.
+    pub fn parse(format: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let regex = Regex::new(r"\$\{([a-z_]+)(?::([^}]*))?\}")?;
+
+        let mut segments = Vec::new();
+        let mut end = 0;
+
+        for captures in regex.captures_iter(format) {
+            let variable = captures.get(0).ok_or("Missing match")?;
+
+            if variable.start() > end {
+                let literal = &format[end..variable.start()];
+                check_file_name_safe(literal, "Literal")?;
+
+                segments.push(Segment::Literal(literal.to_owned()));
+            }
+
+            end = variable.end();
+
+            let argument = captures.get(2).map(|argument| argument.as_str());
+
+            let length = |name: &str| -> Result<Option<usize>, Box<dyn std::error::Error>> {
+                match argument {
+                    None => Ok(None),
+                    Some(argument) => Ok(Some(argument.parse().map_err(|error| {
+                        format!("Invalid length for ${{{}}}: {}", name, error)
+                    })?)),
+                }
+            };
+
+            let segment = match (&captures[1], argument) {
+                ("timestamp_seconds", None) => Segment::TimestampSeconds,
+                ("timestamp_millis", None) => Segment::TimestampMillis,
+                ("timestamp_micros", None) => Segment::TimestampMicros,
+                ("date", None) => Segment::Date(DEFAULT_DATE_FORMAT.to_owned()),
+                ("date", Some(argument)) => {
+                    if chrono::format::StrftimeItems::new(argument)
+                        .any(|item| item == chrono::format::Item::Error)
+                    {
+                        Err(format!("Invalid date format: {}", argument))?;
+                    }
+
+                    // Formatting a date with fractional seconds reveals separators and decimal
+                    // signs of the format, e.g. of `%D` or `%.3f`. The date is in UTC like the
+                    // rendered ones, so that time zone specifiers can be formatted as well.
+                    let sample = chrono::NaiveDate::from_ymd_opt(2017, 1, 5)
+                        .and_then(|date| date.and_hms_milli_opt(11, 52, 55, 960))
+                        .map(|date| chrono::TimeZone::from_utc_datetime(&Utc, &date))
+                        .ok_or("Invalid sample date")?;
+                    check_file_name_safe(
+                        &sample.format(argument).to_string(),
+                        &format!("Date format \"{}\" yields", argument),
+                    )?;
+
+                    Segment::Date(argument.to_owned())
+                }
+                ("hash", _) => Segment::Hash(length("hash")?),
+                ("sequence_key", None) => Segment::SequenceKey,
+                ("camera_id", None) => Segment::CameraId,
+                ("camera_model", None) => Segment::CameraModel,
+                ("serial_number", None) => Segment::SerialNumber,
+                ("sequence", _) => Segment::Sequence(length("sequence")?),
+                (name, None) => Err(format!("Unknown variable ${{{}}}", name))?,
+                (name, Some(argument)) => {
+                    Err(format!("Unknown variable ${{{}:{}}}", name, argument))?
+                }
+            };
+
+            segments.push(segment);
+        }
+
+        if end < format.len() {
+            check_file_name_safe(&format[end..], "Literal")?;
+
+            segments.push(Segment::Literal(format[end..].to_owned()));
+        }
+
+        let first_variable = segments
+            .iter()
+            .find(|segment| !matches!(segment, Segment::Literal(_)));
+
+        match first_variable {
+            Some(Segment::TimestampSeconds)
+            | Some(Segment::TimestampMillis)
+            | Some(Segment::TimestampMicros) => {}
+            Some(Segment::Date(format)) if is_sortable_date(format) => {}
+            _ => Err(
+                "Identifier format needs to start with ${timestamp_seconds}, ${timestamp_millis}, \
+                 ${timestamp_micros} or a ${date} whose zero padded specifiers run from the year \
+                 down (%Y, %m, %d, %H, %M, %S), so that identifiers stay chronologically sortable",
+            )?,
+        }
+
+        Ok(IdentifierFormat { segments })
+    }
+
+    /// Whether the format contains a full-precision timestamp and the full hash, so that the
+    /// information used to derive an identifier can be recovered from it.
+    pub fn is_reversible(&self) -> bool {
+        let has_timestamp = self
+            .segments
+            .iter()
+            .any(|segment| matches!(segment, Segment::TimestampMillis | Segment::TimestampMicros));
+
+        let has_hash = self.segments.contains(&Segment::Hash(None));
+
+        has_timestamp && has_hash
+    }
+
+    /// Whether the format references the hash of the image buffer.
+    pub fn uses_hash(&self) -> bool {
+        self.segments
+            .iter()
+            .any(|segment| matches!(segment, Segment::Hash(_)))
+    }
+
+    /// Whether the format references the position of the file among the files of the run.
+    pub fn uses_sequence(&self) -> bool {
+        self.segments
+            .iter()
+            .any(|segment| matches!(segment, Segment::Sequence(_)))
+    }
+
+    /// Assemble an identifier by substituting the variables in the format with file information.
+    ///
+    /// # Arguments
+    /// * `fields` – File information to be substituted.
+    pub fn render(&self, fields: &IdentifierFields) -> Result<String, Box<dyn std::error::Error>> {
+        let mut identifier = String::new();
+
+        let timestamp = |value: i64| -> Result<String, Box<dyn std::error::Error>> {
+            let value: u64 = value
+                .try_into()
+                .map_err(|_| "Timestamps before 1970-01-01T00:00:00Z are not supported")?;
+
+            Ok(format!(
+                "{timestamp:0digits$}",
+                timestamp = value,
+                digits = fields.timestamp_digits as usize
+            ))
+        };
+
+        for segment in &self.segments {
+            let value = match segment {
+                Segment::Literal(literal) => literal.clone(),
+                Segment::TimestampSeconds => timestamp(fields.timestamp.timestamp())?,
+                Segment::TimestampMillis => timestamp(fields.timestamp.timestamp_millis())?,
+                Segment::TimestampMicros => timestamp(fields.timestamp.timestamp_micros())?,
+                Segment::Date(format) => {
+                    let date = fields.timestamp.with_timezone(&Utc);
+
+                    date.format(format).to_string()
+                }
+                Segment::Hash(length) => {
+                    let hash = fields
+                        .hash
+                        .ok_or("Identifier format uses ${hash}, but no hash has been derived")?;
+
+                    let mut hash = data_encoding::HEXLOWER.encode(hash);
+
+                    if let Some(length) = length {
+                        hash.truncate(*length);
+                    }
+
+                    hash
+                }
+                Segment::SequenceKey => crate::sequence_key::format(fields.sequence_key.ok_or(
+                    "Identifier format uses ${sequence_key}, but no sequence key is available",
+                )?),
+                Segment::CameraId => fields
+                    .camera_id
+                    .ok_or("Identifier format uses ${camera_id}, but no camera information is available")?
+                    .to_owned(),
+                Segment::CameraModel => sanitize(fields.camera_model.ok_or(
+                    "Identifier format uses ${camera_model}, but no camera model is available",
+                )?),
+                Segment::SerialNumber => sanitize(fields.serial_number.ok_or(
+                    "Identifier format uses ${serial_number}, but no serial number is available",
+                )?),
+                Segment::Sequence(digits) => format!(
+                    "{sequence:0digits$}",
+                    sequence = fields.sequence,
+                    digits = digits.unwrap_or(0)
+                ),
+            };
+
+            identifier.push_str(&value);
+        }
+
+        Ok(identifier)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! test_render {
+        ($test_name:ident, $format:literal, $expected:literal) => {
+            #[test]
+            fn $test_name() -> Result<(), Box<dyn std::error::Error>> {
+                let timestamp = DateTime::parse_from_str(
+                    "2017-01-05 13:52:55.960 +02:00",
+                    "%Y-%m-%d %H:%M:%S%.f %:z",
+                )?;
+
+                let fields = IdentifierFields {
+                    timestamp: &timestamp,
+                    timestamp_digits: 0,
+                    hash: Some(&[1, 2, 3, 4, 5, 6, 7, 8]),
+                    sequence_key: Some(42),
+                    camera_id: Some("a1b2c3"),
+                    camera_model: Some("Canon EOS 5D Mark III"),
+                    serial_number: Some("012345678901"),
+                    sequence: 7,
+                };
+
+                assert_eq!(
+                    IdentifierFormat::parse($format)?.render(&fields)?,
+                    $expected
+                );
+
+                Ok(())
+            }
+        };
+    }
+
+    test_render!(
+        test_render_timestamp_seconds,
+        "${timestamp_seconds}",
+        "1483617175"
+    );
+
+    test_render!(
+        test_render_timestamp_millis_hash,
+        "${timestamp_millis}-${hash}",
+        "1483617175960-0102030405060708"
+    );
+
+    test_render!(
+        test_render_timestamp_micros,
+        "${timestamp_micros}",
+        "1483617175960000"
+    );
+
+    test_render!(test_render_date_default, "${date}", "20170105T115255,960Z");
+
+    test_render!(
+        test_render_date_hash_prefix,
+        "${date:%Y%m%d-%H%M%S}-${hash:8}",
+        "20170105-115255-01020304"
+    );
+
+    test_render!(
+        test_render_date_iso,
+        "${date:%F_%H%M%S%3f}",
+        "2017-01-05_115255960"
+    );
+
+    test_render!(
+        test_render_date_year_only,
+        "${date:%Y}_${sequence}",
+        "2017_7"
+    );
+
+    test_render!(
+        test_render_camera,
+        "${timestamp_millis}-${camera_model}-${serial_number}",
+        "1483617175960-Canon_EOS_5D_Mark_III-012345678901"
+    );
+
+    test_render!(
+        test_render_camera_id,
+        "${timestamp_millis}-${camera_id}-${hash}",
+        "1483617175960-a1b2c3-0102030405060708"
+    );
+
+    test_render!(
+        test_render_sequence_key,
+        "${timestamp_millis}-${sequence_key}",
+        "1483617175960-s0000000042"
+    );
+
+    test_render!(
+        test_render_sequence,
+        "${timestamp_seconds}_${sequence:4}",
+        "1483617175_0007"
+    );
+
+    test_render!(
+        test_render_literal_prefix,
+        "IMG-${timestamp_seconds}_x",
+        "IMG-1483617175_x"
+    );
+
+    macro_rules! test_parse_error {
+        ($test_name:ident, $format:literal) => {
+            #[test]
+            fn $test_name() {
+                assert!(IdentifierFormat::parse($format).is_err());
+            }
+        };
+    }
+
+    test_parse_error!(
+        test_parse_error_unknown_variable,
+        "${timestamp_millis}-${unknown}"
+    );
+
+    test_parse_error!(test_parse_error_unsortable, "${hash}-${timestamp_millis}");
+
+    test_parse_error!(test_parse_error_unsortable_date, "${date:%d%m%Y}");
+
+    test_parse_error!(
+        test_parse_error_unsortable_date_order,
+        "${date:%Y%d%m}-${hash:8}"
+    );
+
+    test_parse_error!(
+        test_parse_error_unsortable_date_gap,
+        "${date:%Y%m%H%M}-${hash:8}"
+    );
+
+    test_parse_error!(
+        test_parse_error_unsortable_date_padding,
+        "${date:%Y%-m%-d}-${hash:8}"
+    );
+
+    test_parse_error!(
+        test_parse_error_unsortable_date_month_name,
+        "${date:%Y%b%d}-${hash:8}"
+    );
+
+    test_parse_error!(test_parse_error_date_colon, "${date:%F_%T}-${hash:8}");
+
+    test_parse_error!(test_parse_error_date_rfc3339, "${date:%+}");
+
+    test_parse_error!(
+        test_parse_error_literal_colon,
+        "${timestamp_millis}:${hash}"
+    );
+
+    test_parse_error!(
+        test_parse_error_literal_slash,
+        "${timestamp_millis}/${hash}"
+    );
+
+    test_parse_error!(
+        test_parse_error_literal_backslash,
+        "${timestamp_millis}\\${hash}"
+    );
+
+    test_parse_error!(test_parse_error_literal_dot, "${timestamp_millis}.${hash}");
+
+    test_parse_error!(
+        test_parse_error_literal_control,
+        "${timestamp_millis}\t${hash}"
+    );
+
+    test_parse_error!(test_parse_error_date_slash, "${date:%Y/%m/%d}-${hash:8}");
+
+    test_parse_error!(test_parse_error_date_expanded_slash, "${date:%Y-%D}");
+
+    test_parse_error!(test_parse_error_date_fraction, "${date:%Y%m%d%H%M%S%.3f}");
+
+    test_parse_error!(
+        test_parse_error_invalid_length,
+        "${timestamp_millis}-${hash:x}"
+    );
+
+    #[test]
+    fn test_is_reversible() -> Result<(), Box<dyn std::error::Error>> {
+        assert!(IdentifierFormat::parse("${timestamp_millis}-${hash}")?.is_reversible());
+        assert!(!IdentifierFormat::parse("${timestamp_seconds}-${hash}")?.is_reversible());
+        assert!(!IdentifierFormat::parse("${timestamp_millis}-${hash:8}")?.is_reversible());
+
+        Ok(())
+    }
+}
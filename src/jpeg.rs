@@ -0,0 +1,317 @@
+use clap::Arg;
+use sha2::Digest;
+
+/// Start of image.
+const SOI: u8 = 0xd8;
+/// End of image.
+const EOI: u8 = 0xd9;
+/// Start of scan, followed by the entropy-coded data.
+pub const SOS: u8 = 0xda;
+/// Comment.
+const COM: u8 = 0xfe;
+/// Application segment used by Adobe, carrying the color transform of the image.
+const APP14: u8 = 0xee;
+
+/// How JPEG images are hashed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HashMode {
+    /// The pixels decoded by the `image` crate, in the representation of `canonical_pixels`.
+    Decoded,
+    /// The coded image data as stored in the file, see `hash_bitstream`. Does not depend on any
+    /// decoder and is considerably faster.
+    Bitstream,
+    /// The DCT coefficients in a canonical orientation, see `orientation::hash_canonical`. Does
+    /// not change when the image is losslessly rotated or flipped.
+    OrientationIndependent,
+}
+
+/// All JPEG hash modes.
+const HASH_MODES: [HashMode; 3] = [
+    HashMode::Decoded,
+    HashMode::Bitstream,
+    HashMode::OrientationIndependent,
+];
+
+impl HashMode {
+    /// Parse the value of `--jpeg-hash`.
+    ///
+    /// # Arguments
+    /// * `mode` – One of `decoded`, `bitstream` or `orientation-independent`.
+    pub fn parse(mode: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        match mode {
+            "decoded" => Ok(HashMode::Decoded),
+            "bitstream" => Ok(HashMode::Bitstream),
+            "orientation-independent" => Ok(HashMode::OrientationIndependent),
+            mode => Err(format!("Unknown JPEG hash mode: {}", mode))?,
+        }
+    }
+
+    /// The other modes, which are tried when verifying names derived with any mode.
+    pub fn others(self) -> impl Iterator<Item = Self> {
+        HASH_MODES.iter().copied().filter(move |mode| *mode != self)
+    }
+}
+
+/// Command line argument selecting the JPEG hash mode.
+pub fn arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("jpeg hash")
+        .takes_value(true)
+        .long("--jpeg-hash")
+        .possible_values(&["decoded", "bitstream", "orientation-independent"])
+        .default_value("decoded")
+        .help("How JPEG images are hashed. \"decoded\" hashes the decoded pixels, \"bitstream\" the coded image data without decoding it, which is faster and independent of the decoder, \"orientation-independent\" the DCT coefficients in a canonical orientation, so that lossless rotations keep the hash. Verification accepts hashes of any mode")
+}
+
+/// The canonical pixel representation hashed in the `decoded` mode: 8 bits per sample, rows from
+/// top to bottom, pixels from left to right. Grayscale images have a single luma sample per pixel,
+/// all others three samples in the order red, green, blue. YCbCr is converted to RGB as specified
+/// by JFIF, CMYK and YCCK by inverting the Adobe convention. The Exif orientation is not applied
+/// and embedded ICC profiles are ignored, so that editing metadata does not alter the hash.
+///
+/// The conversion to RGB, the inverse DCT and the upsampling of chroma are performed by the
+/// pinned versions of the `image` and `jpeg-decoder` crates. Any change in their output is caught
+/// by the golden hash tests.
+///
+/// # Arguments
+/// * `image` – Decoded JPEG image.
+pub fn canonical_pixels(image: &image::DynamicImage) -> Result<&[u8], Box<dyn std::error::Error>> {
+    match image {
+        image::DynamicImage::ImageLuma8(image) => Ok(image.as_raw()),
+        image::DynamicImage::ImageRgb8(image) => Ok(image.as_raw()),
+        image => Err(format!(
+            "Unexpected pixel format of decoded JPEG image: {:?}",
+            image.color()
+        ))?,
+    }
+}
+
+/// Whether a marker stands alone, without a length and payload.
+///
+/// # Arguments
+/// * `marker` – Second byte of the marker.
+fn is_standalone(marker: u8) -> bool {
+    // TEM and RST0 to RST7.
+    marker == 0x01 || (0xd0..=0xd7).contains(&marker)
+}
+
+/// Whether a segment only holds metadata, i.e. APP0 to APP15 (Exif, XMP, ICC profiles, …) and
+/// comments. APP14 is not, since its color transform decides whether the components are YCbCr or
+/// RGB (respectively YCCK or CMYK), which changes the decoded image.
+///
+/// # Arguments
+/// * `marker` – Second byte of the marker.
+fn is_metadata(marker: u8) -> bool {
+    ((0xe0..=0xef).contains(&marker) && marker != APP14) || marker == COM
+}
+
+/// Walk the segments of a JPEG file up to the end of image marker. Anything after it, e.g.
+/// appended previews, is ignored.
+///
+/// # Arguments
+/// * `data` – Contents of the JPEG file.
+/// * `function` – Function called with the second byte of each marker, the segment following it
+///   including its length and, for start of scan markers, the entropy-coded data of the scan.
+///   Segment and data are empty for markers that stand alone.
+pub fn for_each_segment<F>(data: &[u8], mut function: F) -> Result<(), Box<dyn std::error::Error>>
+where
+    F: FnMut(u8, &[u8], &[u8]) -> Result<(), Box<dyn std::error::Error>>,
+{
+    if !data.starts_with(&[0xff, SOI]) {
+        Err("Missing JPEG start of image marker")?;
+    }
+
+    let mut position = 2;
+
+    loop {
+        if data.get(position) != Some(&0xff) {
+            Err(format!("Expected JPEG marker at offset {}", position))?;
+        }
+
+        // Markers may be preceded by any number of fill bytes.
+        while data.get(position + 1) == Some(&0xff) {
+            position += 1;
+        }
+
+        let marker = *data
+            .get(position + 1)
+            .ok_or("Missing JPEG end of image marker")?;
+
+        position += 2;
+
+        if marker == EOI {
+            return Ok(());
+        }
+
+        if is_standalone(marker) {
+            function(marker, &[], &[])?;
+            continue;
+        }
+
+        let length = match data.get(position..position + 2) {
+            Some(length) => u16::from_be_bytes([length[0], length[1]]) as usize,
+            None => Err(format!("Truncated JPEG segment at offset {}", position))?,
+        };
+
+        let segment = match data.get(position..position + length) {
+            Some(segment) if length >= 2 => segment,
+            _ => Err(format!("Truncated JPEG segment at offset {}", position))?,
+        };
+
+        position += length;
+
+        let start = position;
+
+        if marker == SOS {
+            // The entropy-coded data ends at the first marker other than a stuffed 0xff byte or a
+            // restart marker, which belong to the data.
+            loop {
+                match (data.get(position), data.get(position + 1)) {
+                    (Some(0xff), Some(&next)) if next == 0x00 || (0xd0..=0xd7).contains(&next) => {
+                        position += 2
+                    }
+                    (Some(0xff), Some(_)) => break,
+                    (Some(_), _) => position += 1,
+                    (None, _) => Err("Missing JPEG end of image marker")?,
+                }
+            }
+        }
+
+        function(marker, segment, &data[start..position])?;
+    }
+}
+
+/// Feed the coded image data of a JPEG file into a hasher, without decoding it: all segments
+/// other than metadata (quantization and Huffman tables, frame and scan headers, restart
+/// intervals, …) in the order they appear, followed by the entropy-coded data of each scan.
+/// Application segments and comments are skipped, so that editing metadata does not alter the
+/// hash. Anything after the end of image marker, e.g. appended previews, is ignored as well.
+///
+/// # Arguments
+/// * `data` – Contents of the JPEG file.
+/// * `hasher` – Hasher the coded image data is fed into.
+pub fn hash_bitstream(
+    data: &[u8],
+    hasher: &mut sha2::Sha256,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for_each_segment(data, |marker, segment, entropy_coded| {
+        if !is_metadata(marker) {
+            hasher.input([0xff, marker]);
+            hasher.input(segment);
+            hasher.input(entropy_coded);
+        }
+
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A minimal JPEG file: SOI, an optional APP1 segment, DQT, SOF0, DHT, SOS with entropy-coded
+    /// data containing a stuffed byte and a restart marker, EOI and trailing data.
+    fn jpeg(app1: &[u8], quantization: u8, scan: &[u8]) -> Vec<u8> {
+        let mut data = vec![0xff, SOI];
+
+        if !app1.is_empty() {
+            data.extend(&[0xff, 0xe1]);
+            data.extend(&((app1.len() + 2) as u16).to_be_bytes());
+            data.extend(app1);
+        }
+
+        data.extend(&[0xff, 0xdb, 0x00, 0x04, 0x00, quantization]);
+        data.extend(&[0xff, 0xc0, 0x00, 0x0b, 0x08, 0x00, 0x08, 0x00, 0x08, 0x01]);
+        data.extend(&[0x01, 0x11, 0x00]);
+        data.extend(&[0xff, 0xc4, 0x00, 0x05, 0x00, 0x01, 0x00]);
+        data.extend(&[0xff, SOS, 0x00, 0x08, 0x01, 0x01, 0x00, 0x00, 0x3f, 0x00]);
+        data.extend(scan);
+        data.extend(&[0xff, EOI]);
+        data.extend(b"trailing preview");
+
+        data
+    }
+
+    fn hash(data: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut hasher = sha2::Sha256::new();
+        hash_bitstream(data, &mut hasher)?;
+
+        Ok(hasher.result().to_vec())
+    }
+
+    const SCAN: &[u8] = &[0x12, 0xff, 0x00, 0x34, 0xff, 0xd0, 0x56];
+
+    #[test]
+    fn test_hash_bitstream_skips_metadata() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(
+            hash(&jpeg(b"", 1, SCAN))?,
+            hash(&jpeg(b"Exif\0\0 edited metadata", 1, SCAN))?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash_bitstream_covers_color_transform() -> Result<(), Box<dyn std::error::Error>> {
+        let adobe = |transform: u8| {
+            let mut data = jpeg(b"", 1, SCAN);
+            let segment = [
+                0xff, APP14, 0x00, 0x0e, b'A', b'd', b'o', b'b', b'e', 0x00, 0x64, 0x00, 0x00,
+                0x00, 0x00, transform,
+            ];
+            data.splice(2..2, segment.iter().copied());
+            data
+        };
+
+        assert_ne!(hash(&adobe(0))?, hash(&adobe(1))?);
+        assert_ne!(hash(&adobe(1))?, hash(&jpeg(b"", 1, SCAN))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash_bitstream_ignores_trailing_data() -> Result<(), Box<dyn std::error::Error>> {
+        let mut data = jpeg(b"", 1, SCAN);
+        let expected = hash(&data)?;
+
+        data.extend(&[0xff, SOI, 0xff, EOI]);
+
+        assert_eq!(hash(&data)?, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash_bitstream_covers_image_data() -> Result<(), Box<dyn std::error::Error>> {
+        let expected = hash(&jpeg(b"", 1, SCAN))?;
+
+        assert_ne!(hash(&jpeg(b"", 2, SCAN))?, expected);
+        assert_ne!(
+            hash(&jpeg(b"", 1, &[0x12, 0xff, 0x00, 0x34, 0xff, 0xd0, 0x57]))?,
+            expected
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash_bitstream_invalid() {
+        let data = jpeg(b"", 1, SCAN);
+
+        assert!(hash(b"not a jpeg").is_err());
+        // Truncated within the entropy-coded data.
+        assert!(hash(&data[..data.len() - 20]).is_err());
+        // Truncated within a segment.
+        assert!(hash(&data[..6]).is_err());
+    }
+
+    #[test]
+    fn test_hash_mode_parse() {
+        assert_eq!(HashMode::parse("decoded").unwrap(), HashMode::Decoded);
+        assert_eq!(HashMode::parse("bitstream").unwrap(), HashMode::Bitstream);
+        assert_eq!(
+            HashMode::parse("orientation-independent").unwrap(),
+            HashMode::OrientationIndependent
+        );
+        assert!(HashMode::parse("pixels").is_err());
+    }
+}
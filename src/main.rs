@@ -1,22 +1,53 @@
+mod archive;
+mod audit;
+mod cache;
+mod catalog;
+mod clock_correction;
+mod coefficients;
+mod compare;
+mod date_tag;
+mod dedupe;
+mod identifier_format;
+mod jpeg;
+#[cfg(feature = "libraw")]
 mod libraw;
-
-use chrono::{DateTime, FixedOffset, NaiveDateTime};
-use clap::{App, Arg};
+mod manifest;
+mod metadata;
+mod migrate;
+mod orientation;
+mod phash;
+// With LibRaw, the built-in reader only reads what DNG files record about their conversion. Its
+// readers of the sensor data and previews are only compiled without LibRaw.
+mod raw;
+mod raw_hash;
+#[cfg(feature = "libraw")]
+mod raw_image;
+mod sequence_key;
+mod time_zone;
+mod time_zone_boundaries;
+mod walk;
+
+use chrono::{DateTime, FixedOffset, TimeZone};
+use clap::{App, AppSettings, Arg};
 use image;
 use regex::Regex;
 use sha2::Digest;
 use std::convert::TryInto;
 
-/// Return an identifier based on the provided timestamp and hash.
+/// Return an identifier based on the provided timestamp, sequence key, camera and hash.
 ///
 /// # Arguments
 /// * `timestamp` – Timestamp used in the identifier.
 /// * `timestamp_digits` – Minimum number of digits the timestamp should carry. Will be padded with
 /// zeros from the left.
+/// * `sequence_key` – Key ordering images with identical timestamps, placed after the timestamp.
+/// * `camera_id` – Short discriminator of the camera, placed between timestamp and hash.
 /// * `hash` – Hash used in the identifier.
 fn get_identifier(
     timestamp: &DateTime<FixedOffset>,
     timestamp_digits: u64,
+    sequence_key: Option<u32>,
+    camera_id: Option<&str>,
     hash: Option<&[u8]>,
 ) -> Result<String, Box<dyn std::error::Error>> {
     let millis: u64 = timestamp
@@ -25,9 +56,15 @@ fn get_identifier(
         .map_err(|_| "Timestamps before 1970-01-01T00:00:00Z are not supported")?;
 
     let identifier = format!(
-        "{timestamp:0digits$}{separator}{hash}",
+        "{timestamp:0digits$}{sequence_key}{camera_separator}{camera_id}{separator}{hash}",
         timestamp = millis,
         digits = timestamp_digits as usize,
+        sequence_key = match sequence_key {
+            Some(sequence_key) => format!("-{}", sequence_key::format(sequence_key)),
+            None => "".into(),
+        },
+        camera_separator = if camera_id.is_some() { "-" } else { "" },
+        camera_id = camera_id.unwrap_or(""),
         separator = if hash.is_some() { "-" } else { "" },
         hash = if let Some(hash) = hash {
             data_encoding::HEXLOWER.encode(hash)
@@ -39,16 +76,260 @@ fn get_identifier(
     Ok(identifier)
 }
 
+/// Schemes used to encode identifiers over the lifetime of `ciid`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Scheme {
+    /// Used before 0.2.0. The timestamp is a big-endian sequence of 8 bytes, holding a unix
+    /// timestamp with millisecond precision. Both timestamp and hash are encoded using lowercase
+    /// base32hex without padding.
+    V1,
+    /// Plain decimal unix timestamp with millisecond precision, followed by a lowercase hex
+    /// encoded hash.
+    V2,
+}
+
+/// The scheme used for newly derived identifiers.
+const CURRENT_SCHEME: Scheme = Scheme::V2;
+
+impl Scheme {
+    fn version(self) -> u64 {
+        match self {
+            Scheme::V1 => 1,
+            Scheme::V2 => 2,
+        }
+    }
+
+    fn from_version(version: u64) -> Result<Self, Box<dyn std::error::Error>> {
+        match version {
+            1 => Ok(Scheme::V1),
+            2 => Ok(Scheme::V2),
+            version => Err(format!("Unknown identifier scheme version: {}", version).into()),
+        }
+    }
+}
+
+/// Append the version of the provided scheme to an identifier, e.g. `<identifier>-v2`.
+///
+/// The tag is placed at the end, so that identifiers keep their chronological ordering. Neither
+/// decimal nor hex digits contain a `v`, which keeps the tag unambiguous.
+///
+/// # Arguments
+/// * `identifier` – Identifier to be tagged.
+/// * `scheme` – Scheme the identifier has been encoded with.
+fn tag_scheme_version(identifier: &str, scheme: Scheme) -> String {
+    format!("{}-v{}", identifier, scheme.version())
+}
+
+/// Components of an identifier that has been parsed from a file name.
+#[derive(Debug, PartialEq)]
+struct ParsedIdentifier {
+    scheme: Scheme,
+    /// Whether the identifier carried an explicit scheme version tag.
+    tagged: bool,
+    /// Unix timestamp with millisecond precision.
+    timestamp: u64,
+    sequence_key: Option<u32>,
+    camera_id: Option<String>,
+    hash: Option<Vec<u8>>,
+}
+
+/// Parse an identifier that has been encoded with any known scheme.
+///
+/// If the identifier does not carry a scheme version tag, the scheme is inferred from the shape of
+/// its components. Identifiers without a hash whose timestamp only consists of decimal digits are
+/// considered to be encoded with the current scheme.
+///
+/// # Arguments
+/// * `identifier` – Identifier to be parsed, e.g. a file name without its extension.
+fn parse_identifier(identifier: &str) -> Result<ParsedIdentifier, Box<dyn std::error::Error>> {
+    let regex_tag = Regex::new(r"^(.*)-v([0-9]+)$")?;
+
+    let (identifier, scheme) = match regex_tag.captures(identifier) {
+        Some(captures) => (
+            captures.get(1).map_or("", |capture| capture.as_str()),
+            Some(Scheme::from_version(captures[2].parse()?)?),
+        ),
+        None => (identifier, None),
+    };
+
+    let (timestamp, hash) = match identifier.find('-') {
+        Some(index) => (&identifier[..index], Some(&identifier[index + 1..])),
+        None => (identifier, None),
+    };
+
+    let parse_v1 = || -> Result<ParsedIdentifier, Box<dyn std::error::Error>> {
+        let bytes = data_encoding::BASE32_DNSSEC
+            .decode(timestamp.as_bytes())
+            .map_err(|error| format!("Failed decoding base32 timestamp: {}", error))?;
+
+        let bytes: [u8; 8] = bytes.as_slice().try_into().map_err(|_| {
+            format!(
+                "Expected timestamp to be 8 bytes long, got {} bytes",
+                bytes.len()
+            )
+        })?;
+
+        let hash = match hash {
+            Some(hash) => Some(
+                data_encoding::BASE32_DNSSEC
+                    .decode(hash.as_bytes())
+                    .map_err(|error| format!("Failed decoding base32 hash: {}", error))?,
+            ),
+            None => None,
+        };
+
+        Ok(ParsedIdentifier {
+            scheme: Scheme::V1,
+            tagged: scheme.is_some(),
+            timestamp: u64::from_be_bytes(bytes),
+            sequence_key: None,
+            camera_id: None,
+            hash,
+        })
+    };
+
+    let parse_v2 = || -> Result<ParsedIdentifier, Box<dyn std::error::Error>> {
+        if timestamp.is_empty() || !timestamp.bytes().all(|byte| byte.is_ascii_digit()) {
+            Err(format!("Expected decimal timestamp, got \"{}\"", timestamp))?;
+        }
+
+        let mut components: Vec<&str> = match hash {
+            Some(components) => components.split('-').collect(),
+            None => Vec::new(),
+        };
+
+        let is_hex = |component: &str| {
+            component
+                .bytes()
+                .all(|byte| byte.is_ascii_digit() || (b'a'..=b'f').contains(&byte))
+        };
+
+        let hash = match components.last() {
+            Some(hash)
+                if sequence_key::parse(hash).is_none() && (hash.len() != 6 || !is_hex(hash)) =>
+            {
+                Some(
+                    data_encoding::HEXLOWER
+                        .decode(hash.as_bytes())
+                        .map_err(|error| format!("Failed decoding hex hash: {}", error))?,
+                )
+            }
+            _ => None,
+        };
+
+        if hash.is_some() {
+            components.pop();
+        }
+
+        let sequence_key = components
+            .first()
+            .and_then(|component| sequence_key::parse(component));
+
+        if sequence_key.is_some() {
+            components.remove(0);
+        }
+
+        let camera_id = match components.as_slice() {
+            [] => None,
+            [camera_id] if camera_id.len() == 6 && is_hex(camera_id) => {
+                Some((*camera_id).to_owned())
+            }
+            components => Err(format!(
+                "Unexpected identifier components: {}",
+                components.join("-")
+            ))?,
+        };
+
+        Ok(ParsedIdentifier {
+            scheme: Scheme::V2,
+            tagged: scheme.is_some(),
+            timestamp: timestamp.parse()?,
+            sequence_key,
+            camera_id,
+            hash,
+        })
+    };
+
+    match scheme {
+        Some(Scheme::V1) => parse_v1(),
+        Some(Scheme::V2) => parse_v2(),
+        None => parse_v2().or_else(|error_v2| {
+            parse_v1().map_err(|error_v1| {
+                format!(
+                    "Identifier \"{}\" does not match any known scheme. v1: {}. v2: {}",
+                    identifier, error_v1, error_v2
+                )
+                .into()
+            })
+        }),
+    }
+}
+
+/// Convert a unix timestamp with millisecond precision to a date in UTC.
+///
+/// # Arguments
+/// * `timestamp` – Unix timestamp with millisecond precision.
+fn date_from_timestamp(
+    timestamp: u64,
+) -> Result<DateTime<FixedOffset>, Box<dyn std::error::Error>> {
+    let millis: i64 = timestamp
+        .try_into()
+        .map_err(|_| format!("Timestamp out of range: {}", timestamp))?;
+
+    let date = FixedOffset::east_opt(0)
+        .ok_or("Invalid time zone offset")?
+        .timestamp_millis_opt(millis)
+        .single()
+        .ok_or_else(|| format!("Timestamp out of range: {}", timestamp))?;
+
+    Ok(date)
+}
+
 /// Calls the `exiftool` command line tool and returns the contents of stdout.
 ///
 /// # Arguments
 /// * `args` – Command line arguments to be passed to `exiftool`.
 fn exiftool(args: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
-    let output = std::process::Command::new("exiftool")
+    exiftool_with_stdin(args, None)
+}
+
+/// Calls the `exiftool` command line tool, feeding `stdin` to it, and returns the contents of
+/// stdout. `exiftool` reads from stdin if the file name `-` is passed.
+///
+/// # Arguments
+/// * `args` – Command line arguments to be passed to `exiftool`.
+/// * `stdin` – Data written to the stdin of `exiftool`, if any.
+fn exiftool_with_stdin(
+    args: &[&str],
+    stdin: Option<&[u8]>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let mut child = std::process::Command::new("exiftool")
         .args(args)
-        .output()
+        .stdin(match stdin {
+            Some(_) => std::process::Stdio::piped(),
+            None => std::process::Stdio::null(),
+        })
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
         .map_err(|error| format!("Failed executing command: {}", error))?;
 
+    // Writing on a separate thread, so that `exiftool` can't block on a full stdout pipe while
+    // stdin is still being written.
+    let output = std::thread::scope(|scope| {
+        if let (Some(data), Some(mut child_stdin)) = (stdin, child.stdin.take()) {
+            scope.spawn(move || {
+                use std::io::Write;
+
+                // `exiftool` may stop reading once it has found the metadata.
+                let _ = child_stdin.write_all(data);
+            });
+        }
+
+        child.wait_with_output()
+    })
+    .map_err(|error| format!("Failed executing command: {}", error))?;
+
     let error = match output.status.code() {
         Some(code) if code == 0 => None,
         Some(code) => Some(format!("Process has terminated with code {}", code)),
@@ -74,9 +355,129 @@ fn exiftool(args: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
 #[allow(non_snake_case)]
 #[derive(serde::Deserialize)]
 struct ExifDateTime {
-    SubSecDateTimeOriginal: String,
+    #[serde(default, deserialize_with = "deserialize_lossy_string")]
+    SubSecDateTimeOriginal: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_lossy_string")]
+    DateTimeOriginal: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_lossy_string")]
+    SubSecCreateDate: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_lossy_string")]
+    CreateDate: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_lossy_string")]
+    DateTimeDigitized: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_lossy_string")]
+    SubSecModifyDate: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_lossy_string")]
+    ModifyDate: Option<String>,
     OffsetTimeOriginal: Option<String>,
+    OffsetTimeDigitized: Option<String>,
+    OffsetTime: Option<String>,
     TimeZone: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_lossy_string")]
+    GPSDateStamp: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_lossy_string")]
+    GPSTimeStamp: Option<String>,
+    GPSLatitude: Option<f64>,
+    GPSLatitudeRef: Option<String>,
+    GPSLongitude: Option<f64>,
+    GPSLongitudeRef: Option<String>,
+}
+
+/// Options controlling how the date of an image is derived.
+struct DateOptions {
+    /// Date tags in the order they are tried.
+    date_tags: Vec<date_tag::DateTag>,
+    /// Infer the time zone from GPS data, if the date and the EXIF-data do not include a time zone.
+    time_zone_from_gps: bool,
+    /// Time zone assumed if no time zone information is available.
+    default_time_zone: time_zone::DefaultTimeZone,
+    /// Fail instead of assuming the default time zone.
+    require_time_zone: bool,
+}
+
+impl Default for DateOptions {
+    fn default() -> Self {
+        DateOptions {
+            date_tags: date_tag::DEFAULT_PRIORITY.to_vec(),
+            time_zone_from_gps: false,
+            default_time_zone: Default::default(),
+            require_time_zone: false,
+        }
+    }
+}
+
+impl DateOptions {
+    /// Command line arguments controlling how the date of an image is derived.
+    fn args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+        vec![
+            Arg::with_name("date tags")
+                .takes_value(true)
+                .long("--date-tags")
+                .help("Comma-separated EXIF tags the date is taken from, in order of priority. Defaults to \"DateTimeOriginal,CreateDate,DateTimeDigitized,ModifyDate\""),
+            Arg::with_name("time zone from gps")
+                .long("--time-zone-from-gps")
+                .help("Infers the time zone from the GPS position, if the EXIF-data does not include a time zone, by looking up the time zone whose boundaries contain it in an embedded database"),
+            Arg::with_name("default time zone")
+                .takes_value(true)
+                .long("--default-timezone")
+                .help("Time zone assumed if no time zone information is available. Either an offset (e.g. \"+01:00\") or a name of the IANA time zone database (e.g. \"Europe/Berlin\"). Defaults to \"+00:00\""),
+            Arg::with_name("require time zone")
+                .long("--require-timezone")
+                .conflicts_with("default time zone")
+                .help("Fails instead of assuming a default time zone if no time zone information is available"),
+        ]
+    }
+
+    /// Read the options from parsed command line arguments.
+    ///
+    /// # Arguments
+    /// * `matches` – Command line arguments that include the arguments of `DateOptions::args`.
+    fn from_matches(matches: &clap::ArgMatches) -> Result<Self, Box<dyn std::error::Error>> {
+        let default_time_zone = match matches.value_of("default time zone") {
+            Some(time_zone) => time_zone::DefaultTimeZone::parse(time_zone)?,
+            None => Default::default(),
+        };
+
+        let date_tags = match matches.value_of("date tags") {
+            Some(date_tags) => date_tag::DateTag::parse_priority(date_tags)?,
+            None => date_tag::DEFAULT_PRIORITY.to_vec(),
+        };
+
+        Ok(DateOptions {
+            date_tags,
+            time_zone_from_gps: matches.is_present("time zone from gps"),
+            default_time_zone,
+            require_time_zone: matches.is_present("require time zone"),
+        })
+    }
+}
+
+/// The date when the original media was created, along with where its time zone has been taken
+/// from.
+struct DateOriginal {
+    date: DateTime<FixedOffset>,
+    date_tag: date_tag::DateTag,
+    time_zone_source: time_zone::TimeZoneSource,
+    /// Assumptions made while deriving the date, e.g. that the default time zone has been used.
+    warnings: Vec<String>,
+}
+
+/// Parse a time zone offset, e.g. `+01:00`.
+///
+/// # Arguments
+/// * `time_zone` – Time zone offset to be parsed.
+fn parse_time_zone(time_zone: &str) -> Result<FixedOffset, Box<dyn std::error::Error>> {
+    let mut parsed = chrono::format::Parsed::new();
+    chrono::format::parse(
+        &mut parsed,
+        time_zone,
+        vec![chrono::format::Item::Fixed(
+            chrono::format::Fixed::TimezoneOffset,
+        )]
+        .iter(),
+    )?;
+
+    Ok(parsed.to_fixed_offset()?)
 }
 
 /// Get the date when the original media was created, based on EXIF-data.
@@ -86,65 +487,273 @@ struct ExifDateTime {
 ///
 /// # Arguments
 /// * `exif` – The Exif data to be examined.
+/// * `options` – Options controlling how the date is derived.
 fn get_date_original_from_exif(
     exif: &ExifDateTime,
-) -> Result<DateTime<FixedOffset>, Box<dyn std::error::Error>> {
-    let date = DateTime::parse_from_str(&exif.SubSecDateTimeOriginal, "%Y:%m:%d %H:%M:%S%.f %:z\n");
+    options: &DateOptions,
+) -> Result<DateOriginal, Box<dyn std::error::Error>> {
+    let mut warnings = Vec::new();
+
+    let (date_tag, date) = date_tag::resolve(exif, &options.date_tags, &mut warnings)
+        .map_err(|error| format!("Failed parsing exiftool timestamp: {}", error))?;
+
+    let date = match date {
+        date_tag::TagDate::Zoned(date) => {
+            return Ok(DateOriginal {
+                date,
+                date_tag,
+                time_zone_source: time_zone::TimeZoneSource::Date,
+                warnings,
+            });
+        }
+        date_tag::TagDate::Local(date) => date,
+    };
+
+    let mut gps_time_zone = || {
+        if options.time_zone_from_gps {
+            time_zone::get_time_zone_from_gps(&date, exif, &mut warnings)
+        } else {
+            Ok(None)
+        }
+    };
+
+    let time_zone = match (date_tag.offset_time(exif), &exif.TimeZone) {
+        (Some((time_zone, time_zone_source)), _) => {
+            Some((parse_time_zone(time_zone)?, time_zone_source))
+        }
+        (_, Some(time_zone)) => Some((
+            parse_time_zone(time_zone)?,
+            time_zone::TimeZoneSource::TimeZone,
+        )),
+        _ => gps_time_zone()?,
+    };
 
-    if let Ok(date) = date {
-        return Ok(date);
+    if let Some((time_zone, time_zone_source)) = time_zone {
+        return Ok(DateOriginal {
+            date: DateTime::<FixedOffset>::from_naive_utc_and_offset(
+                date + chrono::Duration::seconds(time_zone.utc_minus_local().into()),
+                time_zone,
+            ),
+            date_tag,
+            time_zone_source,
+            warnings,
+        });
     }
 
-    let date =
-        NaiveDateTime::parse_from_str(&exif.SubSecDateTimeOriginal, "%Y:%m:%d %H:%M:%S%.f\n")
-            .map_err(|error| format!("Failed parsing exiftool timestamp: {}", error))?;
+    if options.require_time_zone {
+        Err("No time zone information available")?;
+    }
 
-    let time_zone = match (&exif.OffsetTimeOriginal, &exif.TimeZone) {
-        (Some(time_zone), _) => time_zone,
-        (_, Some(time_zone)) => time_zone,
-        _ => "+00:00",
-    };
+    let (date, warning) = options.default_time_zone.localize(&date)?;
 
-    let mut parsed = chrono::format::Parsed::new();
-    chrono::format::parse(
-        &mut parsed,
-        &time_zone,
-        vec![chrono::format::Item::Fixed(
-            chrono::format::Fixed::TimezoneOffset,
-        )]
-        .iter(),
-    )?;
+    warnings.push(format!(
+        "No time zone information available, assumed {}",
+        options.default_time_zone
+    ));
+    warnings.extend(warning);
+
+    Ok(DateOriginal {
+        date,
+        date_tag,
+        time_zone_source: time_zone::TimeZoneSource::Default,
+        warnings,
+    })
+}
 
-    let time_zone = parsed.to_fixed_offset()?;
+/// Deserialize a value that `exiftool` may either emit as a string or as a number (e.g. serial
+/// numbers) into a string.
+fn deserialize_lossy_string<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value: Option<serde_json::Value> = serde::Deserialize::deserialize(deserializer)?;
+
+    Ok(value.map(|value| match value {
+        serde_json::Value::String(string) => string,
+        value => value.to_string(),
+    }))
+}
 
-    Ok(DateTime::<FixedOffset>::from_naive_utc_and_offset(
-        date + chrono::Duration::seconds(time_zone.utc_minus_local().into()),
-        time_zone,
-    ))
+#[allow(non_snake_case)]
+#[derive(serde::Deserialize)]
+struct ExifCamera {
+    #[serde(default, deserialize_with = "deserialize_lossy_string")]
+    Make: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_lossy_string")]
+    Model: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_lossy_string")]
+    SerialNumber: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_lossy_string")]
+    InternalSerialNumber: Option<String>,
 }
 
-/// Get the date when the original media was created, based on EXIF-data.
+impl ExifCamera {
+    /// The serial number of the camera body. Some makers only record an internal serial number.
+    fn serial_number(&self) -> Option<&str> {
+        self.SerialNumber
+            .as_deref()
+            .or(self.InternalSerialNumber.as_deref())
+    }
+
+    /// Human readable description of the camera, e.g. `Canon EOS 5D Mark III (012345678901)`.
+    /// Returns `None` if no camera information is available.
+    fn description(&self) -> Option<String> {
+        let name = match (self.Make.as_deref(), self.Model.as_deref()) {
+            (Some(make), Some(model)) if model.starts_with(make) => model.to_owned(),
+            (Some(make), Some(model)) => format!("{} {}", make, model),
+            (Some(make), None) => make.to_owned(),
+            (None, Some(model)) => model.to_owned(),
+            (None, None) => "Unknown camera".to_owned(),
+        };
+
+        match self.serial_number() {
+            Some(serial_number) => Some(format!("{} ({})", name, serial_number)),
+            None if self.Make.is_none() && self.Model.is_none() => None,
+            None => Some(name),
+        }
+    }
+
+    /// A short discriminator of the camera, derived from make, model and serial number. Consists
+    /// of 6 lowercase hex characters. Returns `None` if no camera information is available.
+    fn id(&self) -> Option<String> {
+        if self.Make.is_none() && self.Model.is_none() && self.serial_number().is_none() {
+            return None;
+        }
+
+        let mut hasher = sha2::Sha256::new();
+
+        for field in &[
+            self.Make.as_deref(),
+            self.Model.as_deref(),
+            self.serial_number(),
+        ] {
+            hasher.input(field.unwrap_or("").trim().as_bytes());
+            hasher.input(b"\0");
+        }
+
+        let mut id = data_encoding::HEXLOWER.encode(hasher.result().as_slice());
+        id.truncate(6);
+
+        Some(id)
+    }
+}
+
+/// Counters that order images taken in quick succession.
+#[allow(non_snake_case)]
+#[derive(serde::Deserialize)]
+struct ExifSequence {
+    #[serde(default, deserialize_with = "deserialize_lossy_string")]
+    SequenceNumber: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_lossy_string")]
+    ImageNumber: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_lossy_string")]
+    ShutterCount: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct Exif {
+    #[serde(flatten)]
+    date_time: ExifDateTime,
+    #[serde(flatten)]
+    camera: ExifCamera,
+    #[serde(flatten)]
+    sequence: ExifSequence,
+}
+
+/// Where an image is read from.
+#[derive(Clone, Copy)]
+enum ImageSource<'a> {
+    /// A file on disk.
+    File(&'a std::path::Path),
+    /// Contents of a file that is not on disk, e.g. read from stdin or an archive.
+    Memory {
+        data: &'a [u8],
+        /// Name of the file, whose extension decides how the contents are decoded. `-` if unknown.
+        file_path: &'a std::path::Path,
+    },
+}
+
+impl<'a> ImageSource<'a> {
+    /// Path of the file, or the name given to contents in memory.
+    fn file_path(&self) -> &'a std::path::Path {
+        match self {
+            ImageSource::File(file_path) => file_path,
+            ImageSource::Memory { file_path, .. } => file_path,
+        }
+    }
+
+    fn extension(&self) -> Option<&'a str> {
+        self.file_path()
+            .extension()
+            .and_then(|extension| extension.to_str())
+    }
+
+    /// Whether the image is decoded as JPEG instead of raw. Decided by the extension, or by the
+    /// JPEG start of image marker for contents in memory without extension.
+    fn is_jpeg(&self) -> Result<bool, Box<dyn std::error::Error>> {
+        Ok(match (self.extension(), self) {
+            (Some(extension), _) => Regex::new("(?i)jpe?g")?.is_match(extension),
+            (None, ImageSource::Memory { data, .. }) => data.starts_with(&[0xff, 0xd8, 0xff]),
+            (None, ImageSource::File(_)) => false,
+        })
+    }
+}
+
+/// Read the EXIF-data relevant for deriving identifiers.
 ///
 /// # Arguments
-/// * `file_path` – Path to file for which the timestamp should be read and returned.
-fn get_date_original(
-    file_path: &std::path::Path,
-) -> Result<DateTime<FixedOffset>, Box<dyn std::error::Error>> {
-    let path = match file_path.to_str() {
-        None => Err(format!("Invalid file path: {:?}", file_path)),
-        Some(file_path) => Ok(file_path),
-    }?;
+/// * `file_path` – Path to file for which the EXIF-data should be read and returned.
+fn get_exif(file_path: &std::path::Path) -> Result<Exif, Box<dyn std::error::Error>> {
+    get_exif_from_source(ImageSource::File(file_path))
+}
+
+/// Read the EXIF-data relevant for deriving identifiers from a file or from memory.
+///
+/// # Arguments
+/// * `source` – Image for which the EXIF-data should be read and returned.
+fn get_exif_from_source(source: ImageSource) -> Result<Exif, Box<dyn std::error::Error>> {
+    let (path, stdin) = match source {
+        ImageSource::File(file_path) => match file_path.to_str() {
+            None => Err(format!("Invalid file path: {:?}", file_path)),
+            Some(file_path) => Ok((file_path, None)),
+        }?,
+        ImageSource::Memory { data, .. } => ("-", Some(data)),
+    };
 
-    let output = exiftool(&[
-        "-j",
-        "-SubsecDateTimeOriginal",
-        "-OffsetTimeOriginal",
-        "-TimeZone",
-        path,
-    ])
+    let output = exiftool_with_stdin(
+        &[
+            "-j",
+            "-SubSecDateTimeOriginal",
+            "-DateTimeOriginal",
+            "-SubSecCreateDate",
+            "-CreateDate",
+            "-DateTimeDigitized",
+            "-SubSecModifyDate",
+            "-ModifyDate",
+            "-OffsetTimeOriginal",
+            "-OffsetTimeDigitized",
+            "-OffsetTime",
+            "-TimeZone",
+            "-GPSDateStamp",
+            "-GPSTimeStamp",
+            "-GPSLatitude#",
+            "-GPSLatitudeRef#",
+            "-GPSLongitude#",
+            "-GPSLongitudeRef#",
+            "-Make",
+            "-Model",
+            "-SerialNumber",
+            "-InternalSerialNumber",
+            "-SequenceNumber",
+            "-ImageNumber",
+            "-ShutterCount",
+            path,
+        ],
+        stdin,
+    )
     .map_err(|error| format!("Failed running exiftool: {}", error))?;
 
-    let exifs: Vec<ExifDateTime> = serde_json::from_str(&output)?;
+    let mut exifs: Vec<Exif> = serde_json::from_str(&output)?;
 
     if exifs.len() != 1 {
         Err(format!(
@@ -154,89 +763,236 @@ fn get_date_original(
         ))?;
     }
 
-    let exif = &exifs[0];
-    let date = get_date_original_from_exif(&exif)?;
-
-    Ok(date)
+    Ok(exifs.remove(0))
 }
 
+/// Hash a JPEG image, either its decoded pixels, its coded image data or its DCT coefficients, see
+/// `jpeg::HashMode`. The perceptual hash is derived from the decoded pixels.
+///
+/// # Arguments
+/// * `source` – JPEG image.
+/// * `hasher` – Hasher the pixels, the coded image data or the coefficients are fed into.
+/// * `perceptual` – Whether the perceptual hash should be derived.
+/// * `mode` – What is hashed.
 fn hash_image_jpeg(
-    file_path: &std::path::Path,
+    source: ImageSource,
     hasher: &mut sha2::Sha256,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let image =
-        image::open(file_path).map_err(|error| format!("Failed opening JPEG image: {}", error))?;
+    perceptual: bool,
+    mode: jpeg::HashMode,
+) -> Result<Option<u64>, Box<dyn std::error::Error>> {
+    let image = match mode {
+        jpeg::HashMode::Decoded => {
+            let image = match source {
+                ImageSource::File(file_path) => image::open(file_path),
+                ImageSource::Memory { data, .. } => image::load_from_memory(data),
+            }
+            .map_err(|error| match error {
+                image::ImageError::Unsupported(_) => format!(
+                    "Failed opening JPEG image: {}. Use --jpeg-hash bitstream to hash it without decoding",
+                    error
+                ),
+                error => format!("Failed opening JPEG image: {}", error),
+            })?;
+
+            hasher.input(jpeg::canonical_pixels(&image)?);
 
-    let data = image.to_bytes();
+            Some(image)
+        }
+        jpeg::HashMode::Bitstream | jpeg::HashMode::OrientationIndependent => {
+            let data = match source {
+                ImageSource::File(file_path) => std::borrow::Cow::Owned(
+                    std::fs::read(file_path)
+                        .map_err(|error| format!("Failed opening file: {}", error))?,
+                ),
+                ImageSource::Memory { data, .. } => std::borrow::Cow::Borrowed(data),
+            };
 
-    hasher.input(data);
+            if mode == jpeg::HashMode::Bitstream {
+                jpeg::hash_bitstream(&data, hasher)?;
+            } else {
+                orientation::hash_canonical(&data, hasher)?;
+            }
 
-    Ok(())
+            if perceptual {
+                Some(
+                    image::load_from_memory(&data)
+                        .map_err(|error| format!("Failed opening JPEG image: {}", error))?,
+                )
+            } else {
+                None
+            }
+        }
+    };
+
+    Ok(match image {
+        Some(image) if perceptual => Some(phash::dhash(&image)),
+        _ => None,
+    })
 }
 
+/// Hash the sensor data of a raw file with LibRaw, either as stored or normalized, see
+/// `raw_hash::HashMode`. The perceptual hash is derived from the embedded thumbnail.
+///
+/// # Arguments
+/// * `source` – Raw file.
+/// * `hasher` – Hasher the sensor data is fed into.
+/// * `perceptual` – Whether the perceptual hash should be derived.
+/// * `mode` – How the sensor data is hashed.
+#[cfg(feature = "libraw")]
 fn hash_image_raw(
-    file_path: &std::path::Path,
+    source: ImageSource,
     hasher: &mut sha2::Sha256,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let file_path = match file_path.to_str() {
-        None => Err(format!("Invalid file path: {:?}", file_path)),
-        Some(file_path) => Ok(file_path),
-    }?;
+    perceptual: bool,
+    mode: raw_hash::HashMode,
+) -> Result<Option<u64>, Box<dyn std::error::Error>> {
+    if mode == raw_hash::HashMode::Normalized {
+        return hash_image_raw_normalized(source, hasher, perceptual);
+    }
 
-    let file_path = std::ffi::CString::new(file_path)?;
+    let mut raw_image = match source {
+        ImageSource::File(file_path) => raw_image::RawImage::open_file(file_path)?,
+        ImageSource::Memory { data, .. } => raw_image::RawImage::open_buffer(data)?,
+    };
 
-    unsafe {
-        let flags = 0;
-        let data = libraw::libraw_init(flags);
-        let error_code = libraw::libraw_open_file(data, file_path.as_ptr());
+    raw_image.unpack()?;
 
-        let result = (|| -> Result<(), Box<dyn std::error::Error>> {
-            if error_code != 0 {
-                Err(
-                    std::ffi::CStr::from_ptr(libraw::libraw_strerror(error_code))
-                        .to_string_lossy()
-                        .to_owned(),
-                )
-                .map_err(|error| format!("Failed opening file: {}", error))?;
-            }
+    for row in raw_image.raw_rows()? {
+        hasher.input(row);
+    }
 
-            let error_code = libraw::libraw_unpack(data);
+    if perceptual {
+        Ok(Some(phash::dhash(&raw_image.thumbnail()?)))
+    } else {
+        Ok(None)
+    }
+}
 
-            if error_code != 0 {
-                Err(
-                    std::ffi::CStr::from_ptr(libraw::libraw_strerror(error_code))
-                        .to_string_lossy()
-                        .to_owned(),
-                )?;
-            }
+/// Hash the sensor data of a raw file with the built-in reader, which yields the same bytes as
+/// LibRaw, either as stored or normalized, see `raw_hash::HashMode`. The perceptual hash is derived
+/// from the largest embedded JPEG preview.
+///
+/// # Arguments
+/// * `source` – Raw file.
+/// * `hasher` – Hasher the sensor data is fed into.
+/// * `perceptual` – Whether the perceptual hash should be derived.
+/// * `mode` – How the sensor data is hashed.
+#[cfg(not(feature = "libraw"))]
+fn hash_image_raw(
+    source: ImageSource,
+    hasher: &mut sha2::Sha256,
+    perceptual: bool,
+    mode: raw_hash::HashMode,
+) -> Result<Option<u64>, Box<dyn std::error::Error>> {
+    if mode == raw_hash::HashMode::Normalized {
+        return hash_image_raw_normalized(source, hasher, perceptual);
+    }
 
-            let data = match data.as_ref() {
-                None => Err("Unexpected null pointer in LibRaw data")?,
-                Some(pointer) => pointer,
-            };
+    let data = match source {
+        ImageSource::File(file_path) => std::borrow::Cow::Owned(
+            std::fs::read(file_path).map_err(|error| format!("Failed opening file: {}", error))?,
+        ),
+        ImageSource::Memory { data, .. } => std::borrow::Cow::Borrowed(data),
+    };
 
-            let raw_image = data.rawdata.raw_image as *mut u8;
+    hasher.input(raw::read(&data)?.to_ne_bytes());
 
-            if raw_image.is_null() {
-                Err("Unexpected null pointer in LibRaw data.rawdata.raw_image")?;
-            }
+    if perceptual {
+        Ok(Some(phash::dhash(&raw::thumbnail(&data)?)))
+    } else {
+        Ok(None)
+    }
+}
 
-            let length = data.rawdata.sizes.raw_pitch * (data.rawdata.sizes.raw_height as u32);
+/// Read the sensor data of a raw file with LibRaw.
+///
+/// # Arguments
+/// * `data` – Contents of the raw file.
+#[cfg(feature = "libraw")]
+fn read_sensor_data(data: &[u8]) -> Result<raw::SensorData, Box<dyn std::error::Error>> {
+    let mut raw_image = raw_image::RawImage::open_buffer(data)?;
 
-            let raw_buffer =
-                std::slice::from_raw_parts(raw_image, std::convert::TryInto::try_into(length)?);
+    raw_image.unpack()?;
+    raw_image.sensor_data()
+}
 
-            hasher.input(raw_buffer);
+/// Read the sensor data of a raw file with the built-in reader.
+///
+/// # Arguments
+/// * `data` – Contents of the raw file.
+#[cfg(not(feature = "libraw"))]
+fn read_sensor_data(data: &[u8]) -> Result<raw::SensorData, Box<dyn std::error::Error>> {
+    raw::read(data)
+}
 
-            Ok(())
-        })();
+/// Decode the thumbnail embedded in a raw file with LibRaw.
+///
+/// # Arguments
+/// * `data` – Contents of the raw file.
+#[cfg(feature = "libraw")]
+fn read_thumbnail(data: &[u8]) -> Result<image::DynamicImage, Box<dyn std::error::Error>> {
+    raw_image::RawImage::open_buffer(data)?.thumbnail()
+}
 
-        libraw::libraw_close(data);
+/// Decode the largest JPEG preview embedded in a raw file with the built-in reader.
+///
+/// # Arguments
+/// * `data` – Contents of the raw file.
+#[cfg(not(feature = "libraw"))]
+fn read_thumbnail(data: &[u8]) -> Result<image::DynamicImage, Box<dyn std::error::Error>> {
+    raw::thumbnail(data)
+}
 
-        result
-    }?;
+/// Read the sensor data of a raw file and normalize it, see `raw_hash::normalize`.
+///
+/// # Arguments
+/// * `data` – Contents of the raw file.
+fn normalize_raw(data: &[u8]) -> Result<raw_hash::Normalized, Box<dyn std::error::Error>> {
+    let sensor = read_sensor_data(data)?;
 
-    Ok(())
+    raw_hash::normalize(&sensor, raw::active_area(data, &sensor)?)
+}
+
+/// Hash the normalized sensor data of a raw file, see `raw_hash::normalize`. DNG files that embed
+/// the raw file they have been converted from are hashed by the embedded file, so that they yield
+/// the same hash as the original regardless of how the converter stored the sensor data.
+///
+/// # Arguments
+/// * `source` – Raw file.
+/// * `hasher` – Hasher the normalized sensor data is fed into.
+/// * `perceptual` – Whether the perceptual hash should be derived.
+fn hash_image_raw_normalized(
+    source: ImageSource,
+    hasher: &mut sha2::Sha256,
+    perceptual: bool,
+) -> Result<Option<u64>, Box<dyn std::error::Error>> {
+    let data = match source {
+        ImageSource::File(file_path) => std::borrow::Cow::Owned(
+            std::fs::read(file_path).map_err(|error| format!("Failed opening file: {}", error))?,
+        ),
+        ImageSource::Memory { data, .. } => std::borrow::Cow::Borrowed(data),
+    };
+
+    let original = raw::provenance(&data)?.and_then(|provenance| provenance.original_raw_file_data);
+
+    match &original {
+        Some(original) => normalize_raw(original)
+            .map_err(|error| format!("Failed reading embedded original raw file: {}", error))?,
+        None => normalize_raw(&data)?,
+    }
+    .hash(hasher);
+
+    if perceptual {
+        Ok(Some(phash::dhash(&read_thumbnail(&data)?)))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Hashes derived from the image buffer of a file.
+struct ImageHashes {
+    hash: [u8; 32],
+    /// Perceptual hash, see `phash::dhash`.
+    phash: Option<u64>,
 }
 
 /// Derive a hash for an image file. For the hash, only data contained in the image buffer is
@@ -245,17 +1001,48 @@ fn hash_image_raw(
 /// # Arguments
 /// * `file_path` – Path to file for which the hash should be derived.
 fn hash_image(file_path: &std::path::Path) -> Result<[u8; 32], Box<dyn std::error::Error>> {
-    let mut hasher = sha2::Sha256::new();
-
-    let extension = file_path
-        .extension()
-        .and_then(|extension| extension.to_str());
+    Ok(hash_image_with_phash(file_path, false)?.hash)
+}
 
-    match extension {
-        Some(extension) if Regex::new("(?i)jpe?g")?.is_match(extension) => {
-            hash_image_jpeg(file_path, &mut hasher)
-        }
-        _ => hash_image_raw(file_path, &mut hasher),
+/// Derive a hash for an image file, along with a perceptual hash if requested. The perceptual hash
+/// of raw files is derived from their embedded thumbnail.
+///
+/// # Arguments
+/// * `file_path` – Path to file for which the hashes should be derived.
+/// * `perceptual` – Whether the perceptual hash should be derived.
+fn hash_image_with_phash(
+    file_path: &std::path::Path,
+    perceptual: bool,
+) -> Result<ImageHashes, Box<dyn std::error::Error>> {
+    hash_image_from_source(
+        ImageSource::File(file_path),
+        perceptual,
+        jpeg::HashMode::Decoded,
+        raw_hash::HashMode::Sensor,
+    )
+}
+
+/// Derive the hashes of an image file or of its contents in memory. Both yield the same hashes.
+///
+/// # Arguments
+/// * `source` – Image for which the hashes should be derived.
+/// * `perceptual` – Whether the perceptual hash should be derived.
+/// * `jpeg_hash` – How JPEG images are hashed.
+/// * `raw_hash` – How raw files are hashed.
+fn hash_image_from_source(
+    source: ImageSource,
+    perceptual: bool,
+    jpeg_hash: jpeg::HashMode,
+    raw_hash: raw_hash::HashMode,
+) -> Result<ImageHashes, Box<dyn std::error::Error>> {
+    let mut hasher = sha2::Sha256::new();
+
+    let extension = source.extension();
+
+    let phash = if source.is_jpeg()? {
+        hash_image_jpeg(source, &mut hasher, perceptual, jpeg_hash)
+    } else {
+        hash_image_raw(source, &mut hasher, perceptual, raw_hash)
     }
     .map_err(|error| {
         format!(
@@ -273,19 +1060,454 @@ fn hash_image(file_path: &std::path::Path) -> Result<[u8; 32], Box<dyn std::erro
     let mut hash: [u8; 32] = Default::default();
     hash.copy_from_slice(sha256.as_slice());
 
+    Ok(ImageHashes { hash, phash })
+}
+
+/// The hash carried by a file name, if it carries an identifier with a hash.
+///
+/// # Arguments
+/// * `file_path` – Path to file.
+fn file_name_hash(file_path: &std::path::Path) -> Option<Vec<u8>> {
+    file_path
+        .file_stem()
+        .and_then(|file_stem| file_stem.to_str())
+        .and_then(|file_stem| parse_identifier(file_stem).ok())
+        .and_then(|parsed| parsed.hash)
+}
+
+/// Reconcile the hash of an image with the hash expected from its file name. Names may have been
+/// derived with any JPEG or raw hash mode, so if `hash` does not match, the hashes of the other
+/// modes for the kind of image are derived and returned along with their modes if one matches.
+/// Otherwise, `hash` is returned along with `jpeg_hash` and `raw_hash`.
+///
+/// # Arguments
+/// * `source` – Image the hash has been derived for.
+/// * `jpeg_hash` – JPEG hash mode `hash` has been derived with.
+/// * `raw_hash` – Raw hash mode `hash` has been derived with.
+/// * `hash` – Hash of the image.
+/// * `expected` – Hash carried by the file name, if any.
+fn match_hash_mode(
+    source: ImageSource,
+    jpeg_hash: jpeg::HashMode,
+    raw_hash: raw_hash::HashMode,
+    hash: [u8; 32],
+    expected: Option<&[u8]>,
+) -> Result<(jpeg::HashMode, raw_hash::HashMode, [u8; 32]), Box<dyn std::error::Error>> {
+    match expected {
+        Some(expected) if expected != hash => {
+            let others: Vec<(jpeg::HashMode, raw_hash::HashMode)> = if source.is_jpeg()? {
+                jpeg_hash.others().map(|mode| (mode, raw_hash)).collect()
+            } else {
+                raw_hash.others().map(|mode| (jpeg_hash, mode)).collect()
+            };
+
+            for (jpeg_hash, raw_hash) in others {
+                // Images the other modes can not read, e.g. with arithmetic coding, do not match.
+                if let Ok(other) = hash_image_from_source(source, false, jpeg_hash, raw_hash) {
+                    if expected == other.hash {
+                        return Ok((jpeg_hash, raw_hash, other.hash));
+                    }
+                }
+            }
+
+            Ok((jpeg_hash, raw_hash, hash))
+        }
+        _ => Ok((jpeg_hash, raw_hash, hash)),
+    }
+}
+
+/// Derive the hash of an image, accepting any JPEG or raw hash mode for the hash carried by its
+/// file name, see `match_hash_mode`.
+///
+/// # Arguments
+/// * `file_path` – Path to file for which the hash should be derived.
+/// * `jpeg_hash` – JPEG hash mode that is tried first.
+/// * `raw_hash` – Raw hash mode that is tried first.
+fn hash_image_matching_name(
+    file_path: &std::path::Path,
+    jpeg_hash: jpeg::HashMode,
+    raw_hash: raw_hash::HashMode,
+) -> Result<[u8; 32], Box<dyn std::error::Error>> {
+    let source = ImageSource::File(file_path);
+    let hash = hash_image_from_source(source, false, jpeg_hash, raw_hash)?.hash;
+
+    let (_, _, hash) = match_hash_mode(
+        source,
+        jpeg_hash,
+        raw_hash,
+        hash,
+        file_name_hash(file_path).as_deref(),
+    )?;
+
     Ok(hash)
 }
 
+/// Options of a run that affect what is derived from each file.
+struct DeriveOptions<'a> {
+    timestamp_digits: u64,
+    identifier_format: Option<&'a identifier_format::IdentifierFormat>,
+    clock_corrections: Option<&'a clock_correction::ClockCorrections>,
+    date_options: &'a DateOptions,
+    /// Whether the perceptual hash should be derived.
+    perceptual: bool,
+    jpeg_hash: jpeg::HashMode,
+    raw_hash: raw_hash::HashMode,
+}
+
+/// The identifier of a file, along with the information it has been derived from.
+#[derive(serde::Deserialize, serde::Serialize)]
+struct Derivation {
+    identifier: String,
+    timestamp: DateTime<FixedOffset>,
+    date_tag: date_tag::DateTag,
+    time_zone_source: time_zone::TimeZoneSource,
+    camera: Option<String>,
+    camera_id: Option<String>,
+    sequence_key: Option<sequence_key::SequenceKey>,
+    /// Hash of the image buffer, unless `--no-hash` has been provided.
+    hash: Option<[u8; 32]>,
+    phash: Option<u64>,
+    correction: Option<clock_correction::AppliedCorrection>,
+    warnings: Vec<String>,
+}
+
+/// Derive the identifier of an image file.
+///
+/// # Arguments
+/// * `source` – The image file, or its contents in memory.
+/// * `index` – Position of the file among the files of the run, starting at 0.
+/// * `matches` – Command line arguments.
+/// * `options` – Options of the run.
+fn derive_file(
+    source: ImageSource,
+    index: usize,
+    matches: &clap::ArgMatches,
+    options: &DeriveOptions,
+) -> Result<Derivation, Box<dyn std::error::Error>> {
+    let file_path = source.file_path();
+
+    let exif = get_exif_from_source(source)
+        .map_err(|error| format!("Failed reading metadata: {}", error))?;
+
+    let DateOriginal {
+        date: timestamp,
+        date_tag,
+        time_zone_source,
+        mut warnings,
+    } = get_date_original_from_exif(&exif.date_time, options.date_options)
+        .map_err(|error| format!("Failed deriving timestamp data: {}", error))?;
+
+    let (timestamp, correction) = match options.clock_corrections {
+        Some(clock_corrections) => {
+            let corrected_date = clock_corrections
+                .apply(timestamp, file_path, &exif.camera)
+                .map_err(|error| format!("Failed correcting timestamp: {}", error))?;
+
+            warnings.extend(corrected_date.warning);
+
+            (corrected_date.date, corrected_date.correction)
+        }
+        None => (timestamp, None),
+    };
+
+    let camera_id = exif.camera.id();
+
+    let sequence_key = sequence_key::get_sequence_key(file_path, &exif.sequence);
+
+    if matches.is_present("sequence key") && sequence_key.is_none() {
+        warnings.push("No sequence key available, omitted it from the identifier".to_owned());
+    }
+
+    let (hash, phash) = if !matches.is_present("no hash") || options.perceptual {
+        let hashes = hash_image_from_source(
+            source,
+            options.perceptual,
+            options.jpeg_hash,
+            options.raw_hash,
+        )
+        .map_err(|error| format!("Failed deriving image hash: {}", error))?;
+
+        let hash = if matches.is_present("no hash") {
+            None
+        } else {
+            Some(hashes.hash)
+        };
+
+        (hash, hashes.phash)
+    } else {
+        (None, None)
+    };
+
+    let identifier = match options.identifier_format {
+        Some(identifier_format) => {
+            identifier_format.render(&identifier_format::IdentifierFields {
+                timestamp: &timestamp,
+                timestamp_digits: options.timestamp_digits,
+                hash: hash.as_ref().map(|hash| &hash[..]),
+                sequence_key: sequence_key.map(|sequence_key| sequence_key.value),
+                camera_id: camera_id.as_deref(),
+                camera_model: exif.camera.Model.as_deref(),
+                serial_number: exif.camera.serial_number(),
+                sequence: index as u64 + 1,
+            })?
+        }
+        None => {
+            let camera_id = if matches.is_present("camera id") {
+                Some(
+                    camera_id
+                        .as_deref()
+                        .ok_or("Failed deriving camera id: No camera information available")?,
+                )
+            } else {
+                None
+            };
+
+            let sequence_key = if matches.is_present("sequence key") {
+                sequence_key.map(|sequence_key| sequence_key.value)
+            } else {
+                None
+            };
+
+            get_identifier(
+                &timestamp,
+                options.timestamp_digits,
+                sequence_key,
+                camera_id,
+                hash.as_ref().map(|hash| &hash[..]),
+            )?
+        }
+    };
+
+    let identifier = if matches.is_present("scheme version") {
+        tag_scheme_version(&identifier, CURRENT_SCHEME)
+    } else {
+        identifier
+    };
+
+    Ok(Derivation {
+        identifier,
+        timestamp,
+        date_tag,
+        time_zone_source,
+        camera: exif.camera.description(),
+        camera_id,
+        sequence_key,
+        hash,
+        phash,
+        correction,
+        warnings,
+    })
+}
+
+/// Which part of a file name is compared to the derived identifier.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum VerifyMode {
+    /// Only the hash, so that changes of how the metadata is interpreted are tolerated.
+    Hash,
+    /// Only the timestamp.
+    Timestamp,
+    /// The whole file name.
+    Full,
+    /// The identifier written into the metadata by `--write-metadata`, instead of the file name.
+    Metadata,
+}
+
+impl VerifyMode {
+    /// Name of the compared part of the file name, as used in error messages.
+    fn component(&self) -> &'static str {
+        match self {
+            VerifyMode::Hash => "Hash",
+            VerifyMode::Timestamp => "Timestamp",
+            VerifyMode::Full => "File name",
+            VerifyMode::Metadata => "Metadata",
+        }
+    }
+}
+
+/// Describe how the timestamp and hash carried by a file name differ from the derived ones, as
+/// pairs of the mismatching component and the difference. Returns an empty list if the components
+/// are equal.
+///
+/// # Arguments
+/// * `parsed` – Identifier parsed from the file name.
+/// * `timestamp` – Derived timestamp.
+/// * `hash` – Derived hash.
+fn describe_mismatches(
+    parsed: &ParsedIdentifier,
+    timestamp: &DateTime<FixedOffset>,
+    hash: Option<&[u8]>,
+) -> Result<Vec<(VerifyMode, String)>, Box<dyn std::error::Error>> {
+    let mut mismatches = Vec::new();
+
+    let expected = date_from_timestamp(timestamp.timestamp_millis().try_into()?)?;
+    let actual = date_from_timestamp(parsed.timestamp)?;
+
+    if actual != expected {
+        mismatches.push((
+            VerifyMode::Timestamp,
+            format!(
+                "Expected {}, got {}, off by {}",
+                expected.to_rfc3339_opts(chrono::SecondsFormat::Millis, false),
+                actual.to_rfc3339_opts(chrono::SecondsFormat::Millis, false),
+                clock_correction::format_offset(actual - expected)
+            ),
+        ));
+    }
+
+    if let (Some(expected), Some(actual)) = (hash, &parsed.hash) {
+        if expected != &actual[..] {
+            mismatches.push((
+                VerifyMode::Hash,
+                format!(
+                    "Expected {}, got {}",
+                    data_encoding::HEXLOWER.encode(expected),
+                    data_encoding::HEXLOWER.encode(actual)
+                ),
+            ));
+        }
+    }
+
+    Ok(mismatches)
+}
+
+/// Verify that the identifier written into the metadata of a file matches the derived identifier.
+///
+/// # Arguments
+/// * `file_path` – Path to the file.
+/// * `identifier` – Derived identifier.
+fn verify_metadata(
+    file_path: &std::path::Path,
+    identifier: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match metadata::read(file_path)
+        .map_err(|error| format!("Failed reading metadata: {}", error))?
+    {
+        Some(embedded) if embedded == identifier => Ok(()),
+        Some(embedded) => Err(format!(
+            r#"Metadata mismatch in "{}": Expected {}, got {}"#,
+            file_path.display(),
+            identifier,
+            embedded
+        ))?,
+        None => Err(format!(
+            r#"Metadata does not carry an identifier: "{}""#,
+            file_path.display()
+        ))?,
+    }
+}
+
+/// Verify that the name of a file matches the derived identifier.
+///
+/// # Arguments
+/// * `file_path` – Path to the file.
+/// * `expected_file_path` – Path the file would be renamed to.
+/// * `mode` – Which part of the file name is compared.
+/// * `timestamp` – Derived timestamp.
+/// * `hash` – Derived hash.
+fn verify_file_name(
+    file_path: &std::path::Path,
+    expected_file_path: &std::path::Path,
+    mode: VerifyMode,
+    timestamp: &DateTime<FixedOffset>,
+    hash: Option<&[u8]>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let parsed = file_path
+        .file_stem()
+        .and_then(|file_stem| file_stem.to_str())
+        .ok_or_else(|| format!("Invalid file path: {:?}", file_path).into())
+        .and_then(parse_identifier);
+
+    if mode == VerifyMode::Full {
+        if file_path == expected_file_path {
+            return Ok(());
+        }
+
+        let mut message = format!(
+            r#"File name mismatch: Expected "{}", got "{}""#,
+            expected_file_path.display(),
+            file_path.display()
+        );
+
+        if let Ok(parsed) = &parsed {
+            for (component, difference) in describe_mismatches(parsed, timestamp, hash)? {
+                message = format!(
+                    "{}. {} mismatch: {}",
+                    message,
+                    component.component(),
+                    difference
+                );
+            }
+        }
+
+        Err(message)?;
+    }
+
+    let parsed = parsed.map_err(|error| format!("Failed parsing file name: {}", error))?;
+
+    if mode == VerifyMode::Hash && parsed.hash.is_none() {
+        Err(format!(
+            r#"File name does not carry a hash: "{}""#,
+            file_path.display()
+        ))?;
+    }
+
+    match describe_mismatches(&parsed, timestamp, hash)?
+        .into_iter()
+        .find(|(component, _)| *component == mode)
+    {
+        Some((component, difference)) => Err(format!(
+            r#"{} mismatch in "{}": {}"#,
+            component.component(),
+            file_path.display(),
+            difference
+        ))?,
+        None => Ok(()),
+    }
+}
+
+/// An image, or several images, provided on the command line.
+enum Input {
+    /// An image file.
+    File(std::path::PathBuf),
+    /// A single image read from stdin, named by `--filename-hint` or `-`.
+    Stdin(std::path::PathBuf),
+    /// The images inside an archive, or only the given member.
+    Archive {
+        archive_path: std::path::PathBuf,
+        member: Option<String>,
+    },
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let matches = App::new("ciid - Chronological Image Identifier")
         .version(clap::crate_version!())
         .about(&*("\n".to_owned() + clap::crate_description!()))
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .subcommand(migrate::subcommand())
+        .subcommand(clock_correction::subcommand())
+        .subcommand(dedupe::subcommand())
+        .subcommands(catalog::subcommands())
+        .subcommand(audit::subcommand())
+        .subcommand(manifest::subcommand())
+        .subcommand(compare::subcommand())
         .arg(
             Arg::with_name("file path")
                 .takes_value(true)
                 .required(true)
                 .multiple(true)
-                .help("Path to image file"),
+                .help("Path to image file. \"-\" reads a single image from stdin. ZIP and TAR archives are read member by member, a single member can be selected with \"<archive>!/<member>\""),
+        )
+        .arg(
+            Arg::with_name("extract to")
+                .takes_value(true)
+                .long("--extract-to")
+                .help("Writes images read from archives or stdin to the provided directory, named by the derived identifier. Preserves the file extension. Existing files are not replaced, but reported by a warning. Can not be combined with image files"),
+        )
+        .arg(
+            Arg::with_name("filename hint")
+                .takes_value(true)
+                .long("--filename-hint")
+                .help("File name of the image read from stdin, e.g. \"IMG_1234.CR2\". Its extension decides how the image is decoded, its file counter is used by --sequence-key. Without it, JPEG images are recognized by their contents"),
         )
         .arg(
             Arg::with_name("no hash")
@@ -295,7 +1517,34 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .arg(
             Arg::with_name("verify name")
                 .long("--verify-name")
-                .help("Verifies if the provided file name is equal to the derived identifier"),
+                .help("Verifies if the provided file name is equal to the derived identifier. Same as --verify full. Accepts names derived with any JPEG or raw hash mode"),
+        )
+        .arg(
+            Arg::with_name("verify")
+                .takes_value(true)
+                .long("--verify")
+                .possible_values(&["hash", "timestamp", "full", "metadata"])
+                .conflicts_with("verify name")
+                .help("Verifies if the provided file name matches the derived identifier. \"hash\" and \"timestamp\" only compare the respective part, \"full\" the whole file name. \"hash\" and \"full\" accept names derived with any JPEG or raw hash mode. \"metadata\" compares the identifier written by --write-metadata instead"),
+        )
+        .arg(
+            Arg::with_name("write metadata")
+                .long("--write-metadata")
+                .help("Writes the identifier into the XMP metadata (xmpMM:DocumentID) of the file, or of an XMP sidecar for raw files. Does not alter the image buffer"),
+        )
+        .arg(
+            Arg::with_name("write image unique id")
+                .long("--write-image-unique-id")
+                .requires("write metadata")
+                .help("Additionally writes the first 128 bits of the hash to ImageUniqueID"),
+        )
+        .arg(
+            Arg::with_name("metadata target")
+                .takes_value(true)
+                .long("--metadata-target")
+                .possible_values(&["auto", "file", "sidecar"])
+                .default_value("auto")
+                .help("Where --write-metadata writes to. \"auto\" writes into JPEG files and into XMP sidecars of raw files"),
         )
         .arg(
             Arg::with_name("rename file")
@@ -306,7 +1555,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             Arg::with_name("template")
                 .takes_value(true)
                 .long("--print")
-                .help("Prints provided template to stdout, substituting variables with file information. Available variables: ${file_path}, ${identifier}, ${date_time}, ${timestamp}"),
+                .help("Prints provided template to stdout, substituting variables with file information. Available variables: ${file_path}, ${identifier}, ${date_time}, ${timestamp}, ${date_tag}, ${time_zone_source}, ${camera}, ${camera_id}, ${sequence_key}, ${sequence_key_source}, ${phash}"),
         )
         .arg(
             Arg::with_name("timestamp digits")
@@ -314,55 +1563,323 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .long("--timestamp-digits")
                 .help("Minimum number of digits the timestamp should carry. Will be padded with zeros from the left"),
         )
+        .arg(
+            Arg::with_name("scheme version")
+                .long("--scheme-version")
+                .conflicts_with("identifier format")
+                .help("Appends the version of the identifier scheme to the identifier (e.g. \"-v2\"). Can not be combined with --identifier-format, whose identifiers do not follow a scheme"),
+        )
+        .arg(
+            Arg::with_name("camera id")
+                .long("--camera-id")
+                .help("Inserts a short discriminator of the camera, derived from make, model and serial number, between timestamp and hash"),
+        )
+        .arg(
+            Arg::with_name("phash")
+                .long("--phash")
+                .help("Derives a perceptual hash, which is similar for images that look alike, e.g. after re-encoding. Implied by ${phash} in the --print template"),
+        )
+        .arg(
+            Arg::with_name("sequence key")
+                .long("--sequence-key")
+                .help("Inserts a key ordering images with identical timestamps (e.g. burst shots) after the timestamp, taken from the MakerNotes or the file counter of the file name"),
+        )
+        .arg(
+            Arg::with_name("identifier format")
+                .takes_value(true)
+                .long("--identifier-format")
+                .help("Template the identifier is assembled from. Needs to start with a timestamp or date variable. Available variables: ${timestamp_seconds}, ${timestamp_millis}, ${timestamp_micros}, ${date}, ${date:<format>}, ${hash}, ${hash:<length>}, ${camera_id}, ${camera_model}, ${serial_number}, ${sequence}, ${sequence:<digits>}"),
+        )
+        .arg(jpeg::arg())
+        .arg(raw_hash::arg())
+        .arg(
+            Arg::with_name("clock corrections")
+                .takes_value(true)
+                .long("--clock-corrections")
+                .help("Path to a JSON file with rules correcting the dates of cameras with wrongly set clocks"),
+        )
+        .args(&DateOptions::args())
+        .args(&cache::args())
+        .arg(
+            Arg::with_name("quiet")
+                .long("--quiet")
+                .help("Does not print warnings to stderr, e.g. for files whose time zone has been assumed. They are still listed in the warnings field of --json"),
+        )
+        .arg(
+            Arg::with_name("json")
+                .long("--json")
+                .conflicts_with("template")
+                .help("Prints a JSON object per file to stdout, containing the identifier and the information it has been derived from"),
+        )
         .get_matches();
 
+    if let Some(matches) = matches.subcommand_matches("migrate") {
+        return migrate::run(matches);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("clock-offset") {
+        return clock_correction::run(matches);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("dedupe") {
+        return dedupe::run(matches);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("audit") {
+        return audit::run(matches);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("manifest") {
+        return manifest::run(matches);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("compare") {
+        return compare::run(matches);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("index") {
+        return catalog::run_index(matches);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("query") {
+        return catalog::run_query(matches);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("stats") {
+        return catalog::run_stats(matches);
+    }
+
     let file_paths = matches
         .values_of("file path")
-        .ok_or("No file path provided")?;
+        .ok_or("No file path provided")?
+        .collect::<Vec<_>>();
+
+    let stdin = file_paths.contains(&"-");
+
+    if stdin && file_paths.len() > 1 {
+        Err("Reading from stdin (\"-\") can not be combined with other file paths")?;
+    }
+
+    if matches.is_present("filename hint") && !stdin {
+        Err("--filename-hint can only be used when reading from stdin (\"-\")")?;
+    }
+
+    let inputs = file_paths
+        .into_iter()
+        .map(|file_path| -> Result<Input, Box<dyn std::error::Error>> {
+            if file_path == "-" {
+                return Ok(Input::Stdin(std::path::PathBuf::from(
+                    matches.value_of("filename hint").unwrap_or("-"),
+                )));
+            }
 
-    let file_paths = file_paths
-        .map(|file_path| {
-            std::path::Path::new(file_path)
+            let (path, member) = match archive::split_member_path(file_path) {
+                Some((archive_path, member)) => (archive_path, Some(member.to_owned())),
+                None => (std::path::Path::new(file_path), None),
+            };
+
+            let is_archive = archive::Kind::from_path(path).is_some();
+
+            let path = path
                 .canonicalize()
-                .map_err(|error| format!("Invalid file path: {}", error))
+                .map_err(|error| format!("Invalid file path: {}", error))?;
+
+            Ok(if is_archive {
+                Input::Archive {
+                    archive_path: path,
+                    member,
+                }
+            } else {
+                Input::File(path)
+            })
         })
         .collect::<Result<Vec<_>, _>>()?;
 
+    let in_memory = inputs.iter().any(|input| !matches!(input, Input::File(_)));
+
     let timestamp_digits = matches
         .value_of("timestamp digits")
         .unwrap_or("14")
         .parse::<u64>()
         .map_err(|error| format!("Failed parsing timestamp digits: {}", error))?;
 
-    for file_path in file_paths {
-        let timestamp = get_date_original(&file_path)
-            .map_err(|error| format!("Failed deriving timestamp data: {}", error))?;
+    let identifier_format = match matches.value_of("identifier format") {
+        Some(format) => Some(
+            identifier_format::IdentifierFormat::parse(format)
+                .map_err(|error| format!("Failed parsing identifier format: {}", error))?,
+        ),
+        None => None,
+    };
 
-        let hash = if !matches.is_present("no hash") {
-            Some(
-                hash_image(&file_path)
-                    .map_err(|error| format!("Failed deriving image hash: {}", error))?,
-            )
-        } else {
-            None
-        };
+    if let Some(identifier_format) = &identifier_format {
+        if matches.is_present("no hash") && identifier_format.uses_hash() {
+            Err("The identifier format uses ${hash}, which can not be combined with --no-hash")?;
+        }
 
-        let identifier = if let Some(hash) = hash {
-            get_identifier(&timestamp, timestamp_digits, Some(&hash))?
-        } else {
-            get_identifier(&timestamp, timestamp_digits, None)?
+        if matches.is_present("rename file") && !identifier_format.is_reversible() {
+            eprintln!(
+                "Warning: The identifier format does not contain ${{timestamp_millis}} or \
+                 ${{timestamp_micros}} and the full ${{hash}}. The timestamp and hash can not be \
+                 recovered from the renamed files."
+            );
+        }
+    }
+
+    let clock_corrections = match matches.value_of("clock corrections") {
+        Some(file_path) => Some(
+            clock_correction::ClockCorrections::from_file(std::path::Path::new(file_path))
+                .map_err(|error| format!("Failed reading clock corrections: {}", error))?,
+        ),
+        None => None,
+    };
+
+    let date_options = DateOptions::from_matches(&matches)?;
+
+    let verify_mode = match matches.value_of("verify") {
+        Some("hash") => Some(VerifyMode::Hash),
+        Some("timestamp") => Some(VerifyMode::Timestamp),
+        Some("metadata") => Some(VerifyMode::Metadata),
+        Some(_) => Some(VerifyMode::Full),
+        None if matches.is_present("verify name") => Some(VerifyMode::Full),
+        None => None,
+    };
+
+    if verify_mode == Some(VerifyMode::Hash) && matches.is_present("no hash") {
+        Err("--verify hash can not be combined with --no-hash")?;
+    }
+
+    if matches.is_present("write image unique id") && matches.is_present("no hash") {
+        Err("--write-image-unique-id can not be combined with --no-hash")?;
+    }
+
+    if in_memory {
+        for (name, flag) in &[
+            ("rename file", "--rename-file"),
+            ("write metadata", "--write-metadata"),
+        ] {
+            if matches.is_present(name) {
+                Err(format!(
+                    "{} can not be combined with reading from stdin or archives",
+                    flag
+                ))?;
+            }
+        }
+
+        if verify_mode == Some(VerifyMode::Metadata) {
+            Err("--verify metadata can not be combined with reading from stdin or archives")?;
+        }
+    }
+
+    let extract_to = matches.value_of("extract to").map(std::path::Path::new);
+
+    if extract_to.is_some() && inputs.iter().any(|input| matches!(input, Input::File(_))) {
+        Err("--extract-to only applies to images read from stdin or archives, which can not be combined with image files")?;
+    }
+
+    if let Some(directory) = extract_to {
+        std::fs::create_dir_all(directory)
+            .map_err(|error| format!("Failed creating {}: {}", directory.display(), error))?;
+    }
+
+    let stdin_data = if stdin {
+        let mut data = Vec::new();
+
+        std::io::Read::read_to_end(&mut std::io::stdin(), &mut data)
+            .map_err(|error| format!("Failed reading stdin: {}", error))?;
+
+        Some(data)
+    } else {
+        None
+    };
+
+    let perceptual = matches.is_present("phash")
+        || matches
+            .value_of("template")
+            .is_some_and(|template| template.contains("${phash}"));
+
+    let options = DeriveOptions {
+        timestamp_digits,
+        identifier_format: identifier_format.as_ref(),
+        clock_corrections: clock_corrections.as_ref(),
+        date_options: &date_options,
+        perceptual,
+        jpeg_hash: jpeg::HashMode::parse(matches.value_of("jpeg hash").unwrap_or("decoded"))?,
+        raw_hash: raw_hash::HashMode::parse(matches.value_of("raw hash").unwrap_or("sensor"))?,
+    };
+
+    let cache = match &identifier_format {
+        // Verification has to read the contents of each file, which may have changed without
+        // changing its size or modification time.
+        _ if verify_mode.is_some() => None,
+        // Identifiers containing ${sequence} depend on the other files of the run.
+        Some(identifier_format) if identifier_format.uses_sequence() => None,
+        _ => cache::Cache::from_matches(
+            &matches,
+            &[
+                "no hash",
+                "timestamp digits",
+                "scheme version",
+                "camera id",
+                "sequence key",
+                "identifier format",
+                "date tags",
+                "time zone from gps",
+                "default time zone",
+                "require time zone",
+                "jpeg hash",
+                "raw hash",
+            ],
+            &format!(
+                "ciid\0{}\0{}",
+                perceptual,
+                match matches.value_of("clock corrections") {
+                    Some(file_path) => std::fs::read_to_string(file_path)?,
+                    None => String::new(),
+                }
+            ),
+        )?,
+    };
+
+    let process = |index: usize, source: ImageSource| -> Result<(), Box<dyn std::error::Error>> {
+        let file_path = source.file_path();
+
+        let derive = || derive_file(source, index, &matches, &options);
+
+        let Derivation {
+            identifier,
+            timestamp,
+            date_tag,
+            time_zone_source,
+            camera,
+            camera_id,
+            sequence_key,
+            hash,
+            phash,
+            correction,
+            warnings,
+        } = match (&cache, source) {
+            // Images read from stdin or archives have no modification time to key the cache with.
+            (Some(cache), ImageSource::File(file_path)) => {
+                cache.get_or_derive(file_path, derive)?
+            }
+            _ => derive()?,
         };
 
-        let verify_name = matches.is_present("verify name");
+        if !matches.is_present("quiet") {
+            for warning in &warnings {
+                eprintln!("Warning: {}: {}", file_path.display(), warning);
+            }
+        }
+
         let rename_file = matches.is_present("rename file");
 
-        let hash_file_path = {
+        let named = |identifier: &str| {
             let mut path = match file_path.parent() {
                 Some(parent) => parent.into(),
                 None => std::path::PathBuf::new(),
             };
 
-            path.push(identifier.clone());
+            path.push(identifier);
 
             if let Some(extension) = file_path.extension() {
                 path.set_extension(extension);
@@ -371,17 +1888,135 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             path
         };
 
-        if verify_name {
-            if file_path != hash_file_path {
-                Err(format!(
-                    r#"File name mismatch: Expected "{:?}", got "{:?}""#,
-                    hash_file_path, file_path
-                ))?;
+        let hash_file_path = named(&identifier);
+
+        match verify_mode {
+            Some(VerifyMode::Metadata) => verify_metadata(file_path, &identifier)?,
+            Some(verify_mode) => {
+                let (hash, expected_file_path) = match (verify_mode, hash) {
+                    (VerifyMode::Hash, Some(hash)) | (VerifyMode::Full, Some(hash)) => {
+                        let (jpeg_hash, raw_hash, matched) = match_hash_mode(
+                            source,
+                            options.jpeg_hash,
+                            options.raw_hash,
+                            hash,
+                            file_name_hash(file_path).as_deref(),
+                        )?;
+
+                        if verify_mode == VerifyMode::Full && matched != hash {
+                            // The file name has been derived with another hash mode, so the
+                            // identifier is derived again in that mode to compare against.
+                            let derivation = derive_file(
+                                source,
+                                index,
+                                &matches,
+                                &DeriveOptions {
+                                    jpeg_hash,
+                                    raw_hash,
+                                    ..options
+                                },
+                            )?;
+
+                            (Some(matched), named(&derivation.identifier))
+                        } else {
+                            (Some(matched), hash_file_path.clone())
+                        }
+                    }
+                    (_, hash) => (hash, hash_file_path.clone()),
+                };
+
+                verify_file_name(
+                    file_path,
+                    &expected_file_path,
+                    verify_mode,
+                    &timestamp,
+                    hash.as_ref().map(|hash| &hash[..]),
+                )?
             }
+            None => {}
         }
 
         if rename_file {
-            std::fs::rename(file_path.clone(), hash_file_path)?;
+            std::fs::rename(file_path, &hash_file_path)?;
+        }
+
+        if let (Some(directory), ImageSource::Memory { data, .. }) = (extract_to, source) {
+            let mut path = directory.join(&identifier);
+
+            if let Some(extension) = file_path.extension() {
+                path.set_extension(extension);
+            }
+
+            let written = std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .and_then(|mut file| std::io::Write::write_all(&mut file, data));
+
+            match written {
+                Ok(()) => {}
+                // Archives may contain the same image more than once.
+                Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => {
+                    if !matches.is_present("quiet") {
+                        eprintln!(
+                            "Warning: {}: Not extracted, {} exists already",
+                            file_path.display(),
+                            path.display()
+                        );
+                    }
+                }
+                Err(error) => Err(format!(
+                    "Failed extracting to {}: {}",
+                    path.display(),
+                    error
+                ))?,
+            }
+        }
+
+        if matches.is_present("write metadata") {
+            let file_path = if rename_file {
+                hash_file_path.as_path()
+            } else {
+                file_path
+            };
+
+            let target = metadata::Target::parse(
+                matches.value_of("metadata target").unwrap_or("auto"),
+                file_path,
+            )?;
+
+            let image_unique_id = if matches.is_present("write image unique id") {
+                Some(metadata::image_unique_id(
+                    &hash.ok_or("Failed writing ImageUniqueID: No hash available")?,
+                ))
+            } else {
+                None
+            };
+
+            metadata::write(file_path, target, &identifier, image_unique_id.as_deref())
+                .map_err(|error| format!("Failed writing metadata: {}", error))?;
+        }
+
+        if matches.is_present("json") {
+            let output = serde_json::json!({
+                "file_path": file_path,
+                "identifier": identifier,
+                "date_time": timestamp.to_rfc3339_opts(chrono::SecondsFormat::Millis, false),
+                "timestamp": timestamp.timestamp_millis(),
+                "camera": camera,
+                "date_tag": date_tag,
+                "time_zone_source": time_zone_source,
+                "camera_id": camera_id,
+                "sequence_key": sequence_key.map(|sequence_key| sequence_key.value),
+                "sequence_key_source": sequence_key.map(|sequence_key| sequence_key.source),
+                "phash": phash.map(phash::format),
+                "correction": correction,
+                "warnings": warnings,
+            });
+
+            println!("{}", output);
+
+            return Ok(());
         }
 
         let mut template = matches
@@ -421,25 +2056,131 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             )
             .into();
 
-        print!("{}", template);
-    }
+        let regex_date_tag = Regex::new(r"\$\{date_tag\}").unwrap();
+        template = regex_date_tag
+            .replace_all(&template, regex::NoExpand(&date_tag.to_string()))
+            .into();
 
-    Ok(())
-}
+        let regex_time_zone_source = Regex::new(r"\$\{time_zone_source\}").unwrap();
+        template = regex_time_zone_source
+            .replace_all(&template, regex::NoExpand(&time_zone_source.to_string()))
+            .into();
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        let regex_camera = Regex::new(r"\$\{camera\}").unwrap();
+        template = regex_camera
+            .replace_all(&template, regex::NoExpand(&camera.unwrap_or_default()))
+            .into();
 
-    macro_rules! test_get_identifier {
-        ($test_name:ident, $date:literal, $timestamp_digits:literal, $hash:expr, $expected:literal) => {
-            #[test]
+        let regex_camera_id = Regex::new(r"\$\{camera_id\}").unwrap();
+        template = regex_camera_id
+            .replace_all(&template, regex::NoExpand(&camera_id.unwrap_or_default()))
+            .into();
+
+        let regex_phash = Regex::new(r"\$\{phash\}").unwrap();
+        template = regex_phash
+            .replace_all(
+                &template,
+                regex::NoExpand(&phash.map(phash::format).unwrap_or_default()),
+            )
+            .into();
+
+        let regex_sequence_key = Regex::new(r"\$\{sequence_key\}").unwrap();
+        template = regex_sequence_key
+            .replace_all(
+                &template,
+                regex::NoExpand(
+                    &sequence_key
+                        .map(|sequence_key| sequence_key.value.to_string())
+                        .unwrap_or_default(),
+                ),
+            )
+            .into();
+
+        let regex_sequence_key_source = Regex::new(r"\$\{sequence_key_source\}").unwrap();
+        template = regex_sequence_key_source
+            .replace_all(
+                &template,
+                regex::NoExpand(
+                    &sequence_key
+                        .map(|sequence_key| sequence_key.source.to_string())
+                        .unwrap_or_default(),
+                ),
+            )
+            .into();
+
+        print!("{}", template);
+
+        Ok(())
+    };
+
+    let mut index = 0;
+
+    for input in &inputs {
+        match input {
+            Input::File(file_path) => {
+                process(index, ImageSource::File(file_path))?;
+                index += 1;
+            }
+            Input::Stdin(file_path) => {
+                let data = stdin_data.as_deref().ok_or("Failed reading stdin")?;
+
+                process(index, ImageSource::Memory { data, file_path })?;
+                index += 1;
+            }
+            Input::Archive {
+                archive_path,
+                member,
+            } => archive::for_each_image(archive_path, member.as_deref(), |name, data| {
+                let file_path = archive::member_path(archive_path, name);
+
+                process(
+                    index,
+                    ImageSource::Memory {
+                        data,
+                        file_path: &file_path,
+                    },
+                )
+                .map_err(|error| format!("{}: {}", file_path.display(), error))?;
+                index += 1;
+
+                Ok(())
+            })?,
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! test_get_identifier {
+        ($test_name:ident, $date:literal, $timestamp_digits:literal, $camera_id:expr, $hash:expr, $expected:literal) => {
+            test_get_identifier!(
+                $test_name,
+                $date,
+                $timestamp_digits,
+                None,
+                $camera_id,
+                $hash,
+                $expected
+            );
+        };
+        ($test_name:ident, $date:literal, $timestamp_digits:literal, $sequence_key:expr, $camera_id:expr, $hash:expr, $expected:literal) => {
+            #[test]
             fn $test_name() -> Result<(), Box<dyn std::error::Error>> {
                 let timestamp = DateTime::parse_from_str($date, "%Y-%m-%d %H:%M:%S%.f %:z\n")?;
 
                 assert_eq!(
                     $expected,
-                    get_identifier(&timestamp, $timestamp_digits, $hash)?
+                    get_identifier(
+                        &timestamp,
+                        $timestamp_digits,
+                        $sequence_key,
+                        $camera_id,
+                        $hash
+                    )?
                 );
 
                 Ok(())
@@ -452,6 +2193,7 @@ mod tests {
         "1970-1-1 00:00:00.000 +00:00",
         0,
         None,
+        None,
         "0"
     );
 
@@ -459,6 +2201,7 @@ mod tests {
         test_get_identifier_unix_time,
         "1970-1-1 00:00:00.000 +00:00",
         0,
+        None,
         Some(&[1, 2, 3, 4]),
         "0-01020304"
     );
@@ -467,6 +2210,7 @@ mod tests {
         test_get_identifier_some_date,
         "2009-02-13 23:31:30.123 +00:00",
         0,
+        None,
         Some(&[1, 2, 3, 4]),
         "1234567890123-01020304"
     );
@@ -475,6 +2219,7 @@ mod tests {
         test_get_identifier_unix_time_plus_1_millisecond,
         "1970-1-1 00:00:00.001 +00:00",
         0,
+        None,
         Some(&[1, 2, 3, 4]),
         "1-01020304"
     );
@@ -483,6 +2228,7 @@ mod tests {
         test_get_identifier_unix_time_plus_10_milliseconds,
         "1970-1-1 00:00:00.010 +00:00",
         0,
+        None,
         Some(&[1, 2, 3, 4]),
         "10-01020304"
     );
@@ -491,6 +2237,7 @@ mod tests {
         test_get_identifier_unix_time_plus_1_second,
         "1970-1-1 00:00:01.000 +00:00",
         0,
+        None,
         Some(&[1, 2, 3, 4]),
         "1000-01020304"
     );
@@ -499,6 +2246,7 @@ mod tests {
         test_get_identifier_unix_time_plus_1_minute,
         "1970-1-1 00:01:00.000 +00:00",
         0,
+        None,
         Some(&[1, 2, 3, 4]),
         "60000-01020304"
     );
@@ -507,6 +2255,7 @@ mod tests {
         test_get_identifier_unix_time_plus_1_hour,
         "1970-1-1 01:00:00.000 +00:00",
         0,
+        None,
         Some(&[1, 2, 3, 4]),
         "3600000-01020304"
     );
@@ -515,6 +2264,7 @@ mod tests {
         test_get_identifier_unix_time_plus_1_day,
         "1970-1-2 00:00:00.000 +00:00",
         0,
+        None,
         Some(&[1, 2, 3, 4]),
         "86400000-01020304"
     );
@@ -523,6 +2273,7 @@ mod tests {
         test_get_identifier_unix_time_plus_1_month,
         "1970-2-1 00:00:00.000 +00:00",
         0,
+        None,
         Some(&[1, 2, 3, 4]),
         "2678400000-01020304"
     );
@@ -531,6 +2282,7 @@ mod tests {
         test_get_identifier_unix_time_plus_1_year,
         "1971-1-1 00:00:00.000 +00:00",
         0,
+        None,
         Some(&[1, 2, 3, 4]),
         "31536000000-01020304"
     );
@@ -539,6 +2291,7 @@ mod tests {
         test_get_identifier_unix_time_tz_minus_1,
         "1969-12-31 23:00:00.000 -01:00",
         0,
+        None,
         Some(&[1, 2, 3, 4]),
         "0-01020304"
     );
@@ -547,6 +2300,7 @@ mod tests {
         test_get_identifier_unix_time_tz_plus_1,
         "1970-1-1 01:00:00.000 +01:00",
         0,
+        None,
         Some(&[1, 2, 3, 4]),
         "0-01020304"
     );
@@ -555,6 +2309,7 @@ mod tests {
         test_get_identifier_pad_less,
         "1970-1-1 00:00:01.000 +00:00",
         0,
+        None,
         Some(&[1, 2, 3, 4]),
         "1000-01020304"
     );
@@ -563,16 +2318,393 @@ mod tests {
         test_get_identifier_pad_more,
         "1970-1-1 00:00:00.001 +00:00",
         10,
+        None,
         Some(&[1, 2, 3, 4]),
         "0000000001-01020304"
     );
 
+    macro_rules! test_parse_identifier {
+        ($test_name:ident, $input:literal, $scheme:expr, $tagged:literal, $timestamp:literal, $camera_id:expr, $hash:expr) => {
+            test_parse_identifier!(
+                $test_name, $input, $scheme, $tagged, $timestamp, None, $camera_id, $hash
+            );
+        };
+        ($test_name:ident, $input:literal, $scheme:expr, $tagged:literal, $timestamp:literal, $sequence_key:expr, $camera_id:expr, $hash:expr) => {
+            #[test]
+            fn $test_name() -> Result<(), Box<dyn std::error::Error>> {
+                let hash: Option<&str> = $hash;
+
+                assert_eq!(
+                    parse_identifier($input)?,
+                    ParsedIdentifier {
+                        scheme: $scheme,
+                        tagged: $tagged,
+                        timestamp: $timestamp,
+                        sequence_key: $sequence_key,
+                        camera_id: $camera_id.map(|camera_id: &str| camera_id.to_owned()),
+                        hash: hash
+                            .map(|hash| data_encoding::HEXLOWER.decode(hash.as_bytes()).unwrap()),
+                    }
+                );
+
+                Ok(())
+            }
+        };
+    }
+
+    test_parse_identifier!(
+        test_parse_identifier_v2,
+        "01483617175960-d4f894f5e3414125ffa2b8a94637ab44034f9de931a6ca3ef6025c97cbd28a8d",
+        Scheme::V2,
+        false,
+        1483617175960,
+        None,
+        Some("d4f894f5e3414125ffa2b8a94637ab44034f9de931a6ca3ef6025c97cbd28a8d")
+    );
+
+    test_parse_identifier!(
+        test_parse_identifier_v2_no_hash,
+        "01483617175960",
+        Scheme::V2,
+        false,
+        1483617175960,
+        None,
+        None
+    );
+
+    test_parse_identifier!(
+        test_parse_identifier_v2_tagged,
+        "01483617175960-01020304-v2",
+        Scheme::V2,
+        true,
+        1483617175960,
+        None,
+        Some("01020304")
+    );
+
+    test_parse_identifier!(
+        test_parse_identifier_v2_no_hash_tagged,
+        "0-v2",
+        Scheme::V2,
+        true,
+        0,
+        None,
+        None
+    );
+
+    test_parse_identifier!(
+        test_parse_identifier_v1,
+        "00002mbestj9g-as2eogok1ktdlc6o2c8h46ddtn18ptoo2thpt9vb4n6mk35b5esg",
+        Scheme::V1,
+        false,
+        1483624375960,
+        None,
+        Some("5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9")
+    );
+
+    test_parse_identifier!(
+        test_parse_identifier_v1_tagged,
+        "00002mbef64pg-0410610-v1",
+        Scheme::V1,
+        true,
+        1483617175960,
+        None,
+        Some("01020304")
+    );
+
+    test_parse_identifier!(
+        test_parse_identifier_v1_no_hash,
+        "00002mbef64pg",
+        Scheme::V1,
+        false,
+        1483617175960,
+        None,
+        None
+    );
+
+    #[test]
+    fn test_parse_identifier_unknown_scheme() {
+        assert!(parse_identifier("IMG_1234").is_err());
+        assert!(parse_identifier("01483617175960-v3").is_err());
+    }
+
+    test_get_identifier!(
+        test_get_identifier_camera_id,
+        "1970-1-1 00:00:00.001 +00:00",
+        10,
+        Some("a1b2c3"),
+        Some(&[1, 2, 3, 4]),
+        "0000000001-a1b2c3-01020304"
+    );
+
+    test_get_identifier!(
+        test_get_identifier_camera_id_no_hash,
+        "1970-1-1 00:00:00.001 +00:00",
+        0,
+        Some("a1b2c3"),
+        None,
+        "1-a1b2c3"
+    );
+
+    test_get_identifier!(
+        test_get_identifier_sequence_key,
+        "1970-1-1 00:00:00.001 +00:00",
+        0,
+        Some(42),
+        Some("a1b2c3"),
+        Some(&[1, 2, 3, 4]),
+        "1-s0000000042-a1b2c3-01020304"
+    );
+
+    test_get_identifier!(
+        test_get_identifier_sequence_key_no_hash,
+        "1970-1-1 00:00:00.001 +00:00",
+        0,
+        Some(1234567),
+        None,
+        None,
+        "1-s0001234567"
+    );
+
+    test_parse_identifier!(
+        test_parse_identifier_v2_sequence_key,
+        "01483617175960-s0000000042-a1b2c3-01020304-v2",
+        Scheme::V2,
+        true,
+        1483617175960,
+        Some(42),
+        Some("a1b2c3"),
+        Some("01020304")
+    );
+
+    test_parse_identifier!(
+        test_parse_identifier_v2_sequence_key_no_hash,
+        "01483617175960-s000007",
+        Scheme::V2,
+        false,
+        1483617175960,
+        Some(7),
+        None,
+        None
+    );
+
+    #[test]
+    fn test_sequence_key_sorts_in_shooting_order() -> Result<(), Box<dyn std::error::Error>> {
+        let timestamp =
+            DateTime::parse_from_str("2017-01-05 13:52:55.960 +00:00", "%Y-%m-%d %H:%M:%S%.f %:z")?;
+
+        let mut identifiers = vec![
+            get_identifier(&timestamp, 14, Some(10), None, Some(&[0, 1]))?,
+            get_identifier(&timestamp, 14, Some(9), None, Some(&[255, 255]))?,
+            get_identifier(&timestamp, 14, Some(11), None, Some(&[0, 0]))?,
+        ];
+
+        identifiers.sort();
+
+        assert_eq!(
+            identifiers,
+            vec![
+                "01483624375960-s0000000009-ffff",
+                "01483624375960-s0000000010-0001",
+                "01483624375960-s0000000011-0000",
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sequence_key_sorts_across_digit_boundaries() -> Result<(), Box<dyn std::error::Error>> {
+        let timestamp =
+            DateTime::parse_from_str("2017-01-05 13:52:55.960 +00:00", "%Y-%m-%d %H:%M:%S%.f %:z")?;
+
+        let mut identifiers = vec![
+            get_identifier(&timestamp, 14, Some(u32::MAX), None, None)?,
+            get_identifier(&timestamp, 14, Some(1_000_000), None, None)?,
+            get_identifier(&timestamp, 14, Some(999_999), None, None)?,
+        ];
+
+        identifiers.sort();
+
+        assert_eq!(
+            identifiers,
+            vec![
+                "01483624375960-s0000999999",
+                "01483624375960-s0001000000",
+                "01483624375960-s4294967295",
+            ]
+        );
+
+        for identifier in &identifiers {
+            assert_eq!(
+                get_identifier(
+                    &timestamp,
+                    14,
+                    parse_identifier(identifier)?.sequence_key,
+                    None,
+                    None
+                )?,
+                *identifier
+            );
+        }
+
+        Ok(())
+    }
+
+    macro_rules! test_get_sequence_key {
+        ($test_name:ident, $file_path:literal, $input:literal, $expected:expr) => {
+            #[test]
+            fn $test_name() -> Result<(), Box<dyn std::error::Error>> {
+                let exif: ExifSequence = serde_json::from_str($input)?;
+
+                assert_eq!(
+                    sequence_key::get_sequence_key(std::path::Path::new($file_path), &exif)
+                        .map(|sequence_key| (sequence_key.value, sequence_key.source)),
+                    $expected
+                );
+
+                Ok(())
+            }
+        };
+    }
+
+    test_get_sequence_key!(
+        test_get_sequence_key_sequence_number,
+        "IMG_1234.CR2",
+        r#"{ "SequenceNumber": 3, "ShutterCount": 12345 }"#,
+        Some((3, sequence_key::SequenceKeySource::SequenceNumber))
+    );
+
+    test_get_sequence_key!(
+        test_get_sequence_key_shutter_count,
+        "IMG_1234.CR2",
+        r#"{ "SequenceNumber": "Single", "ShutterCount": 12345 }"#,
+        Some((12345, sequence_key::SequenceKeySource::ShutterCount))
+    );
+
+    test_get_sequence_key!(
+        test_get_sequence_key_image_number,
+        "DSC01234.ARW",
+        r#"{ "ImageNumber": "1234" }"#,
+        Some((1234, sequence_key::SequenceKeySource::ImageNumber))
+    );
+
+    test_get_sequence_key!(
+        test_get_sequence_key_file_name,
+        "/photos/_MG_0042.CR2",
+        r#"{}"#,
+        Some((42, sequence_key::SequenceKeySource::FileName))
+    );
+
+    test_get_sequence_key!(
+        test_get_sequence_key_exceeding_u32,
+        "IMG_1234.CR2",
+        r#"{ "ShutterCount": 4294967296 }"#,
+        Some((1234, sequence_key::SequenceKeySource::FileName))
+    );
+
+    test_get_sequence_key!(
+        test_get_sequence_key_file_name_identifier,
+        "01483617175960-01020304.CR2",
+        r#"{}"#,
+        None
+    );
+
+    test_parse_identifier!(
+        test_parse_identifier_v2_camera_id,
+        "01483617175960-a1b2c3-d4f894f5e3414125ffa2b8a94637ab44034f9de931a6ca3ef6025c97cbd28a8d",
+        Scheme::V2,
+        false,
+        1483617175960,
+        Some("a1b2c3"),
+        Some("d4f894f5e3414125ffa2b8a94637ab44034f9de931a6ca3ef6025c97cbd28a8d")
+    );
+
+    test_parse_identifier!(
+        test_parse_identifier_v2_camera_id_no_hash,
+        "01483617175960-a1b2c3-v2",
+        Scheme::V2,
+        true,
+        1483617175960,
+        Some("a1b2c3"),
+        None
+    );
+
+    macro_rules! test_exif_camera {
+        ($test_name:ident, $input:literal, $description:expr, $id:expr) => {
+            #[test]
+            fn $test_name() -> Result<(), Box<dyn std::error::Error>> {
+                let camera: ExifCamera = serde_json::from_str($input)?;
+                let description: Option<&str> = $description;
+                let id: Option<&str> = $id;
+
+                assert_eq!(camera.description().as_deref(), description);
+                assert_eq!(camera.id().as_deref(), id);
+
+                Ok(())
+            }
+        };
+    }
+
+    test_exif_camera!(test_exif_camera_empty, "{}", None, None);
+
+    test_exif_camera!(
+        test_exif_camera_make_model_serial_number,
+        r#"{
+            "Make": "Canon",
+            "Model": "Canon EOS 5D Mark III",
+            "SerialNumber": 12345678901
+        }"#,
+        Some("Canon EOS 5D Mark III (12345678901)"),
+        Some("a7637b")
+    );
+
+    test_exif_camera!(
+        test_exif_camera_internal_serial_number,
+        r#"{
+            "Make": "SONY",
+            "Model": "ILCE-7M3",
+            "InternalSerialNumber": "0123abcd"
+        }"#,
+        Some("SONY ILCE-7M3 (0123abcd)"),
+        Some("4f8aed")
+    );
+
+    #[test]
+    fn test_exif_flatten() -> Result<(), Box<dyn std::error::Error>> {
+        let exif: Exif = serde_json::from_str(
+            r#"{
+                "SourceFile": "IMG_1234.CR2",
+                "SubSecDateTimeOriginal": "2345:01:23 01:23:45.67+01:00",
+                "Make": "Canon",
+                "Model": "Canon EOS 5D Mark III",
+                "SerialNumber": "12345678901"
+            }"#,
+        )?;
+
+        assert_eq!(
+            get_date_original_from_exif(&exif.date_time, &DateOptions::default())?.date,
+            DateTime::parse_from_str("2345:01:23 01:23:45.67 +01:00", "%Y:%m:%d %H:%M:%S%.f %:z")?
+        );
+        assert_eq!(exif.camera.id().as_deref(), Some("a7637b"));
+
+        Ok(())
+    }
+
     macro_rules! test_get_date_original_from_exif {
         ($test_name:ident, $input:literal, $expected:literal) => {
+            test_get_date_original_from_exif!(
+                $test_name,
+                DateOptions::default(),
+                $input,
+                $expected
+            );
+        };
+        ($test_name:ident, $options:expr, $input:literal, $expected:literal) => {
             #[test]
             fn $test_name() -> Result<(), Box<dyn std::error::Error>> {
                 let exif: ExifDateTime = serde_json::from_str($input)?;
-                let date = get_date_original_from_exif(&exif)?;
+                let date = get_date_original_from_exif(&exif, &$options)?.date;
                 let expected = DateTime::parse_from_str($expected, "%Y:%m:%d %H:%M:%S%.f %:z\n")?;
 
                 assert_eq!(date, expected);
@@ -671,4 +2803,920 @@ mod tests {
         }"#,
         "2345:01:23 01:23:45.67-01:00"
     );
+
+    fn gps() -> DateOptions {
+        DateOptions {
+            time_zone_from_gps: true,
+            ..Default::default()
+        }
+    }
+
+    test_get_date_original_from_exif!(
+        test_get_date_original_from_exif_gps_ignored_by_default,
+        r#"{
+            "SubSecDateTimeOriginal": "2017:01:05 13:52:55.96",
+            "GPSDateStamp": "2017:01:05",
+            "GPSTimeStamp": "11:52:55"
+        }"#,
+        "2017:01:05 13:52:55.96+00:00"
+    );
+
+    test_get_date_original_from_exif!(
+        test_get_date_original_from_exif_gps_timestamp,
+        gps(),
+        r#"{
+            "SubSecDateTimeOriginal": "2017:01:05 13:52:55.96",
+            "GPSDateStamp": "2017:01:05",
+            "GPSTimeStamp": "11:52:55"
+        }"#,
+        "2017:01:05 13:52:55.96+02:00"
+    );
+
+    test_get_date_original_from_exif!(
+        test_get_date_original_from_exif_gps_timestamp_stale_fix,
+        gps(),
+        r#"{
+            "SubSecDateTimeOriginal": "2017:01:05 13:52:55.96",
+            "GPSDateStamp": "2017:01:05",
+            "GPSTimeStamp": "11:49:30"
+        }"#,
+        "2017:01:05 13:52:55.96+02:00"
+    );
+
+    test_get_date_original_from_exif!(
+        test_get_date_original_from_exif_gps_timestamp_half_hour,
+        gps(),
+        r#"{
+            "SubSecDateTimeOriginal": "2017:01:05 01:00:00",
+            "GPSDateStamp": "2017:01:04",
+            "GPSTimeStamp": "19:30:00"
+        }"#,
+        "2017:01:05 01:00:00+05:30"
+    );
+
+    test_get_date_original_from_exif!(
+        test_get_date_original_from_exif_gps_timestamp_too_far_off,
+        gps(),
+        r#"{
+            "SubSecDateTimeOriginal": "2017:01:05 13:52:55.96",
+            "GPSDateStamp": "2017:01:05",
+            "GPSTimeStamp": "11:45:00"
+        }"#,
+        "2017:01:05 13:52:55.96+00:00"
+    );
+
+    test_get_date_original_from_exif!(
+        test_get_date_original_from_exif_gps_position,
+        gps(),
+        r#"{
+            "SubSecDateTimeOriginal": "2017:07:05 13:52:55.96",
+            "GPSLatitude": 52.52,
+            "GPSLatitudeRef": "N",
+            "GPSLongitude": 13.405,
+            "GPSLongitudeRef": "E"
+        }"#,
+        "2017:07:05 13:52:55.96+02:00"
+    );
+
+    test_get_date_original_from_exif!(
+        test_get_date_original_from_exif_gps_position_negative,
+        gps(),
+        r#"{
+            "SubSecDateTimeOriginal": "2017:01:05 01:00:00",
+            "GPSLatitude": 40.7128,
+            "GPSLatitudeRef": "N",
+            "GPSLongitude": 74.006,
+            "GPSLongitudeRef": "W"
+        }"#,
+        "2017:01:05 01:00:00-05:00"
+    );
+
+    // In the Atlantic, resolved to the nautical time zone Etc/GMT+2, or without it to the offset
+    // of the GPS timestamp.
+    test_get_date_original_from_exif!(
+        test_get_date_original_from_exif_gps_position_at_sea,
+        gps(),
+        r#"{
+            "SubSecDateTimeOriginal": "2017:01:05 09:52:55",
+            "GPSLatitude": 0.0,
+            "GPSLongitude": 30.0,
+            "GPSLongitudeRef": "W",
+            "GPSDateStamp": "2017:01:05",
+            "GPSTimeStamp": "11:52:55"
+        }"#,
+        "2017:01:05 09:52:55-02:00"
+    );
+
+    test_get_date_original_from_exif!(
+        test_get_date_original_from_exif_gps_longitude_without_latitude,
+        gps(),
+        r#"{
+            "SubSecDateTimeOriginal": "2017:01:05 01:00:00",
+            "GPSLongitude": 74.0,
+            "GPSLongitudeRef": "W"
+        }"#,
+        "2017:01:05 01:00:00+00:00"
+    );
+
+    test_get_date_original_from_exif!(
+        test_get_date_original_from_exif_gps_ignored_with_offset_time_original,
+        gps(),
+        r#"{
+            "SubSecDateTimeOriginal": "2017:01:05 13:52:55.96",
+            "OffsetTimeOriginal": "+01:00",
+            "GPSDateStamp": "2017:01:05",
+            "GPSTimeStamp": "11:52:55"
+        }"#,
+        "2017:01:05 13:52:55.96+01:00"
+    );
+
+    #[test]
+    fn test_get_date_original_from_exif_time_zone_source() -> Result<(), Box<dyn std::error::Error>>
+    {
+        let source = |input: &str| -> Result<_, Box<dyn std::error::Error>> {
+            let exif: ExifDateTime = serde_json::from_str(input)?;
+
+            Ok(get_date_original_from_exif(&exif, &gps())?.time_zone_source)
+        };
+
+        assert_eq!(
+            source(r#"{ "SubSecDateTimeOriginal": "2017:01:05 13:52:55.96+01:00" }"#)?,
+            time_zone::TimeZoneSource::Date
+        );
+        assert_eq!(
+            source(
+                r#"{ "SubSecDateTimeOriginal": "2017:01:05 13:52:55.96", "TimeZone": "+01:00" }"#
+            )?,
+            time_zone::TimeZoneSource::TimeZone
+        );
+        assert_eq!(
+            source(
+                r#"{ "SubSecDateTimeOriginal": "2017:01:05 13:52:55.96", "GPSDateStamp": "2017:01:05", "GPSTimeStamp": "11:52:55" }"#
+            )?,
+            time_zone::TimeZoneSource::GpsTimestamp
+        );
+        assert_eq!(
+            source(r#"{ "SubSecDateTimeOriginal": "2017:01:05 13:52:55.96" }"#)?,
+            time_zone::TimeZoneSource::Default
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_date_original_from_exif_time_zone_source_gps_position(
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let exif: ExifDateTime = serde_json::from_str(
+            r#"{ "SubSecDateTimeOriginal": "2017:01:05 13:52:55.96", "GPSLatitude": 52.52, "GPSLongitude": 13.405 }"#,
+        )?;
+
+        assert_eq!(
+            get_date_original_from_exif(&exif, &gps())?.time_zone_source,
+            time_zone::TimeZoneSource::GpsPosition(chrono_tz::Europe::Berlin)
+        );
+
+        Ok(())
+    }
+
+    fn default_time_zone(time_zone: &str) -> DateOptions {
+        DateOptions {
+            default_time_zone: time_zone::DefaultTimeZone::parse(time_zone).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    test_get_date_original_from_exif!(
+        test_get_date_original_from_exif_default_time_zone_fixed,
+        default_time_zone("-03:30"),
+        r#"{ "SubSecDateTimeOriginal": "2017:01:05 13:52:55.96" }"#,
+        "2017:01:05 13:52:55.96-03:30"
+    );
+
+    test_get_date_original_from_exif!(
+        test_get_date_original_from_exif_default_time_zone_named_winter,
+        default_time_zone("Europe/Berlin"),
+        r#"{ "SubSecDateTimeOriginal": "2017:01:05 13:52:55.96" }"#,
+        "2017:01:05 13:52:55.96+01:00"
+    );
+
+    test_get_date_original_from_exif!(
+        test_get_date_original_from_exif_default_time_zone_named_summer,
+        default_time_zone("Europe/Berlin"),
+        r#"{ "SubSecDateTimeOriginal": "2017:07:05 13:52:55.96" }"#,
+        "2017:07:05 13:52:55.96+02:00"
+    );
+
+    test_get_date_original_from_exif!(
+        test_get_date_original_from_exif_default_time_zone_named_ambiguous,
+        default_time_zone("Europe/Berlin"),
+        r#"{ "SubSecDateTimeOriginal": "2017:10:29 02:30:00" }"#,
+        "2017:10:29 02:30:00+02:00"
+    );
+
+    test_get_date_original_from_exif!(
+        test_get_date_original_from_exif_default_time_zone_ignored_with_time_zone,
+        default_time_zone("Europe/Berlin"),
+        r#"{ "SubSecDateTimeOriginal": "2017:07:05 13:52:55.96", "TimeZone": "-01:00" }"#,
+        "2017:07:05 13:52:55.96-01:00"
+    );
+
+    #[test]
+    fn test_get_date_original_from_exif_default_time_zone_warnings(
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let warnings =
+            |options: DateOptions, input: &str| -> Result<_, Box<dyn std::error::Error>> {
+                let exif: ExifDateTime = serde_json::from_str(input)?;
+
+                Ok(get_date_original_from_exif(&exif, &options)?.warnings)
+            };
+
+        assert_eq!(
+            warnings(
+                DateOptions::default(),
+                r#"{ "SubSecDateTimeOriginal": "2017:01:05 13:52:55.96", "TimeZone": "+01:00" }"#
+            )?,
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            warnings(
+                DateOptions::default(),
+                r#"{ "SubSecDateTimeOriginal": "2017:01:05 13:52:55.96" }"#
+            )?,
+            vec!["No time zone information available, assumed +00:00"]
+        );
+        assert_eq!(
+            warnings(
+                default_time_zone("Europe/Berlin"),
+                r#"{ "SubSecDateTimeOriginal": "2017:10:29 02:30:00" }"#
+            )?
+            .len(),
+            2
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_date_original_from_exif_default_time_zone_nonexistent(
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let exif: ExifDateTime =
+            serde_json::from_str(r#"{ "SubSecDateTimeOriginal": "2017:03:26 02:30:00" }"#)?;
+
+        assert!(get_date_original_from_exif(&exif, &default_time_zone("Europe/Berlin")).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_date_original_from_exif_require_time_zone() -> Result<(), Box<dyn std::error::Error>>
+    {
+        let options = DateOptions {
+            require_time_zone: true,
+            ..Default::default()
+        };
+
+        let exif: ExifDateTime =
+            serde_json::from_str(r#"{ "SubSecDateTimeOriginal": "2017:01:05 13:52:55.96" }"#)?;
+
+        assert!(get_date_original_from_exif(&exif, &options).is_err());
+
+        let exif: ExifDateTime = serde_json::from_str(
+            r#"{ "SubSecDateTimeOriginal": "2017:01:05 13:52:55.96", "OffsetTimeOriginal": "+01:00" }"#,
+        )?;
+
+        assert!(get_date_original_from_exif(&exif, &options).is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_default_time_zone_parse() {
+        assert!(time_zone::DefaultTimeZone::parse("+01:00").is_ok());
+        assert!(time_zone::DefaultTimeZone::parse("America/New_York").is_ok());
+        assert!(time_zone::DefaultTimeZone::parse("Mars/Olympus_Mons").is_err());
+    }
+
+    macro_rules! test_get_date_original_from_exif_date_tag {
+        ($test_name:ident, $input:literal, $expected:literal, $date_tag:expr) => {
+            test_get_date_original_from_exif_date_tag!(
+                $test_name,
+                DateOptions::default(),
+                $input,
+                $expected,
+                $date_tag
+            );
+        };
+        ($test_name:ident, $options:expr, $input:literal, $expected:literal, $date_tag:expr) => {
+            #[test]
+            fn $test_name() -> Result<(), Box<dyn std::error::Error>> {
+                let exif: ExifDateTime = serde_json::from_str($input)?;
+                let date_original = get_date_original_from_exif(&exif, &$options)?;
+                let expected = DateTime::parse_from_str($expected, "%Y:%m:%d %H:%M:%S%.f %:z")?;
+
+                assert_eq!(date_original.date, expected);
+                assert_eq!(date_original.date_tag, $date_tag);
+
+                Ok(())
+            }
+        };
+    }
+
+    fn date_tags(date_tags: &str) -> DateOptions {
+        DateOptions {
+            date_tags: date_tag::DateTag::parse_priority(date_tags).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    test_get_date_original_from_exif_date_tag!(
+        test_date_tag_date_time_original_without_sub_seconds,
+        r#"{ "DateTimeOriginal": "2017:01:05 13:52:55" }"#,
+        "2017:01:05 13:52:55+00:00",
+        date_tag::DateTag::DateTimeOriginal
+    );
+
+    test_get_date_original_from_exif_date_tag!(
+        test_date_tag_prefers_sub_seconds,
+        r#"{
+            "SubSecDateTimeOriginal": "2017:01:05 13:52:55.96",
+            "DateTimeOriginal": "2017:01:05 13:52:55"
+        }"#,
+        "2017:01:05 13:52:55.96+00:00",
+        date_tag::DateTag::DateTimeOriginal
+    );
+
+    test_get_date_original_from_exif_date_tag!(
+        test_date_tag_without_seconds,
+        r#"{ "DateTimeOriginal": "2017:01:05 13:52" }"#,
+        "2017:01:05 13:52:00+00:00",
+        date_tag::DateTag::DateTimeOriginal
+    );
+
+    test_get_date_original_from_exif_date_tag!(
+        test_date_tag_trailing_whitespace,
+        r#"{ "DateTimeOriginal": "2017:01:05 13:52:55\n" }"#,
+        "2017:01:05 13:52:55+00:00",
+        date_tag::DateTag::DateTimeOriginal
+    );
+
+    test_get_date_original_from_exif_date_tag!(
+        test_date_tag_create_date_only,
+        r#"{ "SubSecCreateDate": "2017:01:05 13:52:55.12", "CreateDate": "2017:01:05 13:52:55" }"#,
+        "2017:01:05 13:52:55.12+00:00",
+        date_tag::DateTag::CreateDate
+    );
+
+    test_get_date_original_from_exif_date_tag!(
+        test_date_tag_date_time_digitized_only,
+        r#"{ "DateTimeDigitized": "2017:01:05 13:52:55+01:00" }"#,
+        "2017:01:05 13:52:55+01:00",
+        date_tag::DateTag::DateTimeDigitized
+    );
+
+    test_get_date_original_from_exif_date_tag!(
+        test_date_tag_modify_date_only,
+        r#"{ "ModifyDate": "2017:01:05 13:52:55" }"#,
+        "2017:01:05 13:52:55+00:00",
+        date_tag::DateTag::ModifyDate
+    );
+
+    test_get_date_original_from_exif_date_tag!(
+        test_date_tag_placeholder,
+        r#"{ "DateTimeOriginal": "0000:00:00 00:00:00", "CreateDate": "2017:01:05 13:52:55" }"#,
+        "2017:01:05 13:52:55+00:00",
+        date_tag::DateTag::CreateDate
+    );
+
+    test_get_date_original_from_exif_date_tag!(
+        test_date_tag_placeholder_blank,
+        r#"{ "DateTimeOriginal": "    :  :     :  :  ", "ModifyDate": "2017:01:05 13:52:55" }"#,
+        "2017:01:05 13:52:55+00:00",
+        date_tag::DateTag::ModifyDate
+    );
+
+    test_get_date_original_from_exif_date_tag!(
+        test_date_tag_invalid,
+        r#"{ "DateTimeOriginal": "2017:13:45 13:52:55", "CreateDate": "2017:01:05 13:52:55" }"#,
+        "2017:01:05 13:52:55+00:00",
+        date_tag::DateTag::CreateDate
+    );
+
+    test_get_date_original_from_exif_date_tag!(
+        test_date_tag_priority,
+        date_tags("ModifyDate,DateTimeOriginal"),
+        r#"{ "DateTimeOriginal": "2017:01:05 13:52:55", "ModifyDate": "2018:02:03 04:05:06" }"#,
+        "2018:02:03 04:05:06+00:00",
+        date_tag::DateTag::ModifyDate
+    );
+
+    test_get_date_original_from_exif_date_tag!(
+        test_date_tag_offset_time_original,
+        r#"{
+            "DateTimeOriginal": "2017:01:05 13:52:55",
+            "OffsetTimeOriginal": "+01:00",
+            "OffsetTimeDigitized": "+02:00",
+            "OffsetTime": "+03:00"
+        }"#,
+        "2017:01:05 13:52:55+01:00",
+        date_tag::DateTag::DateTimeOriginal
+    );
+
+    test_get_date_original_from_exif_date_tag!(
+        test_date_tag_offset_time_digitized,
+        r#"{
+            "CreateDate": "2017:01:05 13:52:55",
+            "OffsetTimeOriginal": "+01:00",
+            "OffsetTimeDigitized": "+02:00",
+            "OffsetTime": "+03:00"
+        }"#,
+        "2017:01:05 13:52:55+02:00",
+        date_tag::DateTag::CreateDate
+    );
+
+    test_get_date_original_from_exif_date_tag!(
+        test_date_tag_offset_time,
+        r#"{
+            "ModifyDate": "2017:01:05 13:52:55",
+            "OffsetTimeOriginal": "+01:00",
+            "OffsetTime": "+03:00"
+        }"#,
+        "2017:01:05 13:52:55+03:00",
+        date_tag::DateTag::ModifyDate
+    );
+
+    macro_rules! test_get_date_original_from_exif_date_tag_error {
+        ($test_name:ident, $options:expr, $input:literal) => {
+            #[test]
+            fn $test_name() -> Result<(), Box<dyn std::error::Error>> {
+                let exif: ExifDateTime = serde_json::from_str($input)?;
+
+                assert!(get_date_original_from_exif(&exif, &$options).is_err());
+
+                Ok(())
+            }
+        };
+    }
+
+    test_get_date_original_from_exif_date_tag_error!(
+        test_date_tag_missing,
+        DateOptions::default(),
+        r#"{}"#
+    );
+
+    test_get_date_original_from_exif_date_tag_error!(
+        test_date_tag_placeholders_only,
+        DateOptions::default(),
+        r#"{ "DateTimeOriginal": "0000:00:00 00:00:00", "ModifyDate": "0000:00:00 00:00:00" }"#
+    );
+
+    test_get_date_original_from_exif_date_tag_error!(
+        test_date_tag_priority_excludes,
+        date_tags("DateTimeOriginal"),
+        r#"{ "ModifyDate": "2017:01:05 13:52:55" }"#
+    );
+
+    #[test]
+    fn test_date_tag_placeholder_warning() -> Result<(), Box<dyn std::error::Error>> {
+        let exif: ExifDateTime = serde_json::from_str(
+            r#"{ "DateTimeOriginal": "0000:00:00 00:00:00", "CreateDate": "2017:01:05 13:52:55+01:00" }"#,
+        )?;
+
+        assert_eq!(
+            get_date_original_from_exif(&exif, &DateOptions::default())?.warnings,
+            vec![r#"Ignored placeholder date "0000:00:00 00:00:00" in DateTimeOriginal"#]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_date_tag_parse_priority() {
+        assert_eq!(
+            date_tag::DateTag::parse_priority("CreateDate, ModifyDate").unwrap(),
+            vec![date_tag::DateTag::CreateDate, date_tag::DateTag::ModifyDate]
+        );
+        assert!(date_tag::DateTag::parse_priority("FileModifyDate").is_err());
+        assert!(date_tag::DateTag::parse_priority("CreateDate,CreateDate").is_err());
+    }
+
+    macro_rules! test_verify_file_name {
+        ($test_name:ident, $file_name:literal, $mode:expr, $expected:expr) => {
+            #[test]
+            fn $test_name() -> Result<(), Box<dyn std::error::Error>> {
+                let timestamp = DateTime::parse_from_rfc3339("2017-01-05T13:52:55.960+02:00")?;
+                let hash = [1, 2, 3, 4];
+
+                let result = verify_file_name(
+                    std::path::Path::new($file_name),
+                    std::path::Path::new("01483617175960-01020304.jpg"),
+                    $mode,
+                    &timestamp,
+                    Some(&hash),
+                )
+                .map_err(|error| error.to_string());
+
+                assert_eq!(result, $expected);
+
+                Ok(())
+            }
+        };
+    }
+
+    test_verify_file_name!(
+        test_verify_full,
+        "01483617175960-01020304.jpg",
+        VerifyMode::Full,
+        Ok(())
+    );
+
+    test_verify_file_name!(
+        test_verify_full_mismatch,
+        "01483624375960-01020304.jpg",
+        VerifyMode::Full,
+        Err(r#"File name mismatch: Expected "01483617175960-01020304.jpg", got "01483624375960-01020304.jpg". Timestamp mismatch: Expected 2017-01-05T11:52:55.960+00:00, got 2017-01-05T13:52:55.960+00:00, off by +02:00:00.000"#.to_owned())
+    );
+
+    test_verify_file_name!(
+        test_verify_full_unparsable,
+        "IMG_1234.jpg",
+        VerifyMode::Full,
+        Err(
+            r#"File name mismatch: Expected "01483617175960-01020304.jpg", got "IMG_1234.jpg""#
+                .to_owned()
+        )
+    );
+
+    test_verify_file_name!(
+        test_verify_hash_tolerates_timestamp,
+        "01483624375960-01020304.jpg",
+        VerifyMode::Hash,
+        Ok(())
+    );
+
+    test_verify_file_name!(
+        test_verify_hash_tolerates_camera_id,
+        "01483617175960-a1b2c3-01020304-v2.jpg",
+        VerifyMode::Hash,
+        Ok(())
+    );
+
+    test_verify_file_name!(
+        test_verify_hash_mismatch,
+        "01483617175960-01020305.jpg",
+        VerifyMode::Hash,
+        Err(
+            r#"Hash mismatch in "01483617175960-01020305.jpg": Expected 01020304, got 01020305"#
+                .to_owned()
+        )
+    );
+
+    test_verify_file_name!(
+        test_verify_hash_missing,
+        "01483617175960.jpg",
+        VerifyMode::Hash,
+        Err(r#"File name does not carry a hash: "01483617175960.jpg""#.to_owned())
+    );
+
+    test_verify_file_name!(
+        test_verify_timestamp_tolerates_hash,
+        "01483617175960-01020305.jpg",
+        VerifyMode::Timestamp,
+        Ok(())
+    );
+
+    test_verify_file_name!(
+        test_verify_timestamp_mismatch,
+        "01483617175000-01020304.jpg",
+        VerifyMode::Timestamp,
+        Err(r#"Timestamp mismatch in "01483617175000-01020304.jpg": Expected 2017-01-05T11:52:55.960+00:00, got 2017-01-05T11:52:55.000+00:00, off by -00:00:00.960"#.to_owned())
+    );
+
+    /// Golden hashes of the JPEG corpus in `tests/files/jpeg`, generated by `generate.py`. A
+    /// mismatch means that the canonical pixel representation changed, which would invalidate
+    /// identifiers derived by earlier releases.
+    macro_rules! test_hash_image_golden {
+        ($test_name:ident, $file_name:literal, $mode:expr, $expected:expr) => {
+            #[test]
+            fn $test_name() {
+                let file_path = std::path::Path::new("tests/files/jpeg").join($file_name);
+
+                let result = hash_image_from_source(
+                    ImageSource::File(&file_path),
+                    false,
+                    $mode,
+                    raw_hash::HashMode::Sensor,
+                )
+                .map(|hashes| data_encoding::HEXLOWER.encode(&hashes.hash))
+                .map_err(|error| error.to_string());
+
+                assert_eq!(result, $expected);
+            }
+        };
+    }
+
+    test_hash_image_golden!(
+        test_hash_image_golden_grayscale_decoded,
+        "grayscale.jpg",
+        jpeg::HashMode::Decoded,
+        Ok("8b63e586e96d2a5104196cae16e4397dfae1fd5b799f829d49f675bf6acb1a02".to_owned())
+    );
+
+    test_hash_image_golden!(
+        test_hash_image_golden_grayscale_bitstream,
+        "grayscale.jpg",
+        jpeg::HashMode::Bitstream,
+        Ok("c414cbf7b24764f4cea03e0886207bd676bde6273b2c2729233198599cfc37a9".to_owned())
+    );
+
+    test_hash_image_golden!(
+        test_hash_image_golden_grayscale_orientation_independent,
+        "grayscale.jpg",
+        jpeg::HashMode::OrientationIndependent,
+        Ok("c60fa3fc1cfab82af74f835af9830cdb3b70af5facb27f51bb468f238fc356f0".to_owned())
+    );
+
+    // Baseline, restart markers and progressive coding of the same image decode to identical pixels.
+    test_hash_image_golden!(
+        test_hash_image_golden_ycbcr_444_decoded,
+        "ycbcr-444.jpg",
+        jpeg::HashMode::Decoded,
+        Ok("f0f01b2a15678c9aa75b617674557540290e7297c5fdb5e2b90456132fc0b210".to_owned())
+    );
+
+    test_hash_image_golden!(
+        test_hash_image_golden_ycbcr_444_bitstream,
+        "ycbcr-444.jpg",
+        jpeg::HashMode::Bitstream,
+        Ok("8d9fbb1c90a44f8631d83e825ac6ca774e9c77ab3077ebdac8091edc1fe09896".to_owned())
+    );
+
+    test_hash_image_golden!(
+        test_hash_image_golden_ycbcr_444_orientation_independent,
+        "ycbcr-444.jpg",
+        jpeg::HashMode::OrientationIndependent,
+        Ok("87780f40874c18cce6e4049980b3a560e308b41123db363cde57a010faa5449b".to_owned())
+    );
+
+    test_hash_image_golden!(
+        test_hash_image_golden_ycbcr_420_decoded,
+        "ycbcr-420.jpg",
+        jpeg::HashMode::Decoded,
+        Ok("43124bc074fc6634daf098afa00feae25e0d7ea7d2a6aca5c41eb1287fc0e553".to_owned())
+    );
+
+    test_hash_image_golden!(
+        test_hash_image_golden_ycbcr_420_bitstream,
+        "ycbcr-420.jpg",
+        jpeg::HashMode::Bitstream,
+        Ok("66ee252a66b81dbe92685b72f9e8dfcdffb43e668aa7ba0961ab1d21f1b2c297".to_owned())
+    );
+
+    test_hash_image_golden!(
+        test_hash_image_golden_ycbcr_420_orientation_independent,
+        "ycbcr-420.jpg",
+        jpeg::HashMode::OrientationIndependent,
+        Ok("eb44e1f4a3331e5076ad83ecda46d49723e32a5d8d8a173adb4d618df2db12dd".to_owned())
+    );
+
+    test_hash_image_golden!(
+        test_hash_image_golden_restart_markers_decoded,
+        "restart-markers.jpg",
+        jpeg::HashMode::Decoded,
+        Ok("f0f01b2a15678c9aa75b617674557540290e7297c5fdb5e2b90456132fc0b210".to_owned())
+    );
+
+    test_hash_image_golden!(
+        test_hash_image_golden_restart_markers_bitstream,
+        "restart-markers.jpg",
+        jpeg::HashMode::Bitstream,
+        Ok("b9a88327f7058a1c3838c1c77e8fc339bc5717dc0b7f11adc98aa9819bd377f5".to_owned())
+    );
+
+    test_hash_image_golden!(
+        test_hash_image_golden_restart_markers_orientation_independent,
+        "restart-markers.jpg",
+        jpeg::HashMode::OrientationIndependent,
+        Ok("87780f40874c18cce6e4049980b3a560e308b41123db363cde57a010faa5449b".to_owned())
+    );
+
+    test_hash_image_golden!(
+        test_hash_image_golden_progressive_decoded,
+        "progressive.jpg",
+        jpeg::HashMode::Decoded,
+        Ok("f0f01b2a15678c9aa75b617674557540290e7297c5fdb5e2b90456132fc0b210".to_owned())
+    );
+
+    test_hash_image_golden!(
+        test_hash_image_golden_progressive_bitstream,
+        "progressive.jpg",
+        jpeg::HashMode::Bitstream,
+        Ok("d7da750885f75be8d8124313d1816bd70ebc961c460487992ed547afc0fdbf95".to_owned())
+    );
+
+    test_hash_image_golden!(
+        test_hash_image_golden_progressive_orientation_independent,
+        "progressive.jpg",
+        jpeg::HashMode::OrientationIndependent,
+        Ok("87780f40874c18cce6e4049980b3a560e308b41123db363cde57a010faa5449b".to_owned())
+    );
+
+    test_hash_image_golden!(
+        test_hash_image_golden_cmyk_decoded,
+        "cmyk.jpg",
+        jpeg::HashMode::Decoded,
+        Ok("ce3be2b19ada3f330fc32290214a73fceaac427b0ccd97abefbab1f0c3e854f6".to_owned())
+    );
+
+    test_hash_image_golden!(
+        test_hash_image_golden_cmyk_bitstream,
+        "cmyk.jpg",
+        jpeg::HashMode::Bitstream,
+        Ok("fe090689a189e86e22b29ace09eb9b09bbfc19850e6cd5325f43e17a8b1533f3".to_owned())
+    );
+
+    test_hash_image_golden!(
+        test_hash_image_golden_cmyk_orientation_independent,
+        "cmyk.jpg",
+        jpeg::HashMode::OrientationIndependent,
+        Ok("ed478c5062d0aa09327f610711b9b84ecac3aa08e828596ce207d0261f9f7e89".to_owned())
+    );
+
+    test_hash_image_golden!(
+        test_hash_image_golden_12_bit_decoded,
+        "12-bit.jpg",
+        jpeg::HashMode::Decoded,
+        Err(
+            "Failed hashing .jpg file: Failed opening JPEG image: The decoder for Jpeg does not support the format features SamplePrecision(12). Use --jpeg-hash bitstream to hash it without decoding"
+                .to_owned()
+        )
+    );
+
+    test_hash_image_golden!(
+        test_hash_image_golden_12_bit_bitstream,
+        "12-bit.jpg",
+        jpeg::HashMode::Bitstream,
+        Ok("a1d857c1569a12b8dc75bc290f39e156eb8f443c5ea839b28a744a4351fcd244".to_owned())
+    );
+
+    test_hash_image_golden!(
+        test_hash_image_golden_12_bit_orientation_independent,
+        "12-bit.jpg",
+        jpeg::HashMode::OrientationIndependent,
+        Ok("4569d81268bc89b6471ca93c141707f506fd7becdff7bd7875b905588c62aac0".to_owned())
+    );
+
+    // Successive approximation codes the same coefficients as ycbcr-420.jpg.
+
+    test_hash_image_golden!(
+        test_hash_image_golden_successive_approximation_decoded,
+        "successive-approximation.jpg",
+        jpeg::HashMode::Decoded,
+        Ok("43124bc074fc6634daf098afa00feae25e0d7ea7d2a6aca5c41eb1287fc0e553".to_owned())
+    );
+
+    test_hash_image_golden!(
+        test_hash_image_golden_successive_approximation_bitstream,
+        "successive-approximation.jpg",
+        jpeg::HashMode::Bitstream,
+        Ok("e68db6844ffeeb2162ec661de6aa148613b3d250835ac760132c2f853b032505".to_owned())
+    );
+
+    test_hash_image_golden!(
+        test_hash_image_golden_successive_approximation_orientation_independent,
+        "successive-approximation.jpg",
+        jpeg::HashMode::OrientationIndependent,
+        Ok("eb44e1f4a3331e5076ad83ecda46d49723e32a5d8d8a173adb4d618df2db12dd".to_owned())
+    );
+
+    // All eight orientations of the same image, with their coefficients transformed the way
+    // `jpegtran` transforms them, and a progressive encoding of one of them.
+
+    test_hash_image_golden!(
+        test_hash_image_golden_orientation_orientation_independent,
+        "orientation.jpg",
+        jpeg::HashMode::OrientationIndependent,
+        Ok("a039e1839b6072c8a30ceb325a6aa583b530964b3b91685c5bda11e8f361ef7d".to_owned())
+    );
+
+    test_hash_image_golden!(
+        test_hash_image_golden_orientation_flip_horizontal_orientation_independent,
+        "orientation-flip-horizontal.jpg",
+        jpeg::HashMode::OrientationIndependent,
+        Ok("a039e1839b6072c8a30ceb325a6aa583b530964b3b91685c5bda11e8f361ef7d".to_owned())
+    );
+
+    test_hash_image_golden!(
+        test_hash_image_golden_orientation_flip_vertical_orientation_independent,
+        "orientation-flip-vertical.jpg",
+        jpeg::HashMode::OrientationIndependent,
+        Ok("a039e1839b6072c8a30ceb325a6aa583b530964b3b91685c5bda11e8f361ef7d".to_owned())
+    );
+
+    test_hash_image_golden!(
+        test_hash_image_golden_orientation_rotate_90_orientation_independent,
+        "orientation-rotate-90.jpg",
+        jpeg::HashMode::OrientationIndependent,
+        Ok("a039e1839b6072c8a30ceb325a6aa583b530964b3b91685c5bda11e8f361ef7d".to_owned())
+    );
+
+    test_hash_image_golden!(
+        test_hash_image_golden_orientation_rotate_180_orientation_independent,
+        "orientation-rotate-180.jpg",
+        jpeg::HashMode::OrientationIndependent,
+        Ok("a039e1839b6072c8a30ceb325a6aa583b530964b3b91685c5bda11e8f361ef7d".to_owned())
+    );
+
+    test_hash_image_golden!(
+        test_hash_image_golden_orientation_rotate_270_orientation_independent,
+        "orientation-rotate-270.jpg",
+        jpeg::HashMode::OrientationIndependent,
+        Ok("a039e1839b6072c8a30ceb325a6aa583b530964b3b91685c5bda11e8f361ef7d".to_owned())
+    );
+
+    test_hash_image_golden!(
+        test_hash_image_golden_orientation_transpose_orientation_independent,
+        "orientation-transpose.jpg",
+        jpeg::HashMode::OrientationIndependent,
+        Ok("a039e1839b6072c8a30ceb325a6aa583b530964b3b91685c5bda11e8f361ef7d".to_owned())
+    );
+
+    test_hash_image_golden!(
+        test_hash_image_golden_orientation_transverse_orientation_independent,
+        "orientation-transverse.jpg",
+        jpeg::HashMode::OrientationIndependent,
+        Ok("a039e1839b6072c8a30ceb325a6aa583b530964b3b91685c5bda11e8f361ef7d".to_owned())
+    );
+
+    test_hash_image_golden!(
+        test_hash_image_golden_orientation_rotate_90_progressive_orientation_independent,
+        "orientation-rotate-90-progressive.jpg",
+        jpeg::HashMode::OrientationIndependent,
+        Ok("a039e1839b6072c8a30ceb325a6aa583b530964b3b91685c5bda11e8f361ef7d".to_owned())
+    );
+
+    // Chroma subsampled horizontally only is subsampled vertically only once rotated.
+
+    test_hash_image_golden!(
+        test_hash_image_golden_orientation_422_orientation_independent,
+        "orientation-422.jpg",
+        jpeg::HashMode::OrientationIndependent,
+        Ok("025256ade7ed3bccb0b65ab34d9afed0504b0e0754113da9685345d4cc38db12".to_owned())
+    );
+
+    test_hash_image_golden!(
+        test_hash_image_golden_orientation_422_rotate_90_orientation_independent,
+        "orientation-422-rotate-90.jpg",
+        jpeg::HashMode::OrientationIndependent,
+        Ok("025256ade7ed3bccb0b65ab34d9afed0504b0e0754113da9685345d4cc38db12".to_owned())
+    );
+
+    /// Golden hashes of the raw files listed in `tests/files/raw/hashes.txt`, generated with
+    /// LibRaw. Runs with either backend, so that a mismatch without the `libraw` feature means
+    /// that the built-in reader diverges from LibRaw.
+    #[test]
+    fn test_hash_image_golden_raw() -> Result<(), Box<dyn std::error::Error>> {
+        let directory = std::path::Path::new("tests/files/raw");
+        let list = std::fs::read_to_string(directory.join("hashes.txt"))?;
+
+        let mut mismatches = Vec::new();
+
+        for line in list
+            .lines()
+            .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+
+            let (mode, expected, file_name) = match fields.as_slice() {
+                [mode, expected, file_name] => {
+                    (raw_hash::HashMode::parse(mode)?, *expected, *file_name)
+                }
+                _ => Err(format!("Invalid line: {}", line))?,
+            };
+
+            let file_path = directory.join(file_name);
+
+            let result = hash_image_from_source(
+                ImageSource::File(&file_path),
+                false,
+                jpeg::HashMode::Decoded,
+                mode,
+            )
+            .map(|hashes| data_encoding::HEXLOWER.encode(&hashes.hash))
+            .map_err(|error| error.to_string());
+
+            if result.as_deref() != Ok(expected) {
+                mismatches.push(format!(
+                    "{} ({:?}): expected {}, got {:?}",
+                    file_path.display(),
+                    mode,
+                    expected,
+                    result
+                ));
+            }
+        }
+
+        assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+
+        Ok(())
+    }
 }
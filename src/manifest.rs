@@ -0,0 +1,506 @@
+use crate::{hash_image, walk};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use sha2::Digest;
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("manifest")
+        .about("Prints a manifest of the hashes of image files, or verifies image files against a manifest")
+        .arg(
+            Arg::with_name("path")
+                .takes_value(true)
+                .multiple(true)
+                .required_unless("check")
+                .help("Path to file or directory. Directories are searched recursively, for image files with --format ciid and for all files otherwise"),
+        )
+        .arg(
+            Arg::with_name("format")
+                .takes_value(true)
+                .long("--format")
+                .possible_values(&["ciid", "sha256sum", "bagit"])
+                .default_value("ciid")
+                .help("Format of the manifest: `ciid` lists the hashes of the image buffers, `sha256sum` and `bagit` list the hashes of the whole files"),
+        )
+        .arg(
+            Arg::with_name("base directory")
+                .takes_value(true)
+                .long("--base-dir")
+                .conflicts_with("check")
+                .help("Directory the paths in the manifest are relative to, i.e. the directory the manifest is stored in. For --format bagit, the root of the bag. Defaults to the current directory"),
+        )
+        .arg(
+            Arg::with_name("check")
+                .takes_value(true)
+                .long("--check")
+                .value_name("manifest")
+                .help("Verifies the files listed in the manifest. If paths are provided, files that are not listed in the manifest are reported as well"),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .long("--quiet")
+                .requires("check")
+                .help("Only reports files that are not OK"),
+        )
+}
+
+/// Format of a manifest. All formats list one file per line, as hex encoded SHA-256 hash, followed
+/// by the path of the file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    /// Hashes of the image buffers, as in the file names, in the line format of `sha256sum`. Only
+    /// `ciid manifest --check` is able to verify it.
+    Ciid,
+    /// Hashes of the whole files, verifiable with `sha256sum --check`.
+    Sha256sum,
+    /// Hashes of the whole files, as in the `manifest-sha256.txt` of a BagIt bag.
+    Bagit,
+}
+
+impl Format {
+    fn parse(format: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        match format {
+            "ciid" => Ok(Format::Ciid),
+            "sha256sum" => Ok(Format::Sha256sum),
+            "bagit" => Ok(Format::Bagit),
+            format => Err(format!("Unknown manifest format: {}", format))?,
+        }
+    }
+
+    /// Collect the files to be listed in the manifest. Manifests of image buffers only list image
+    /// files, manifests of whole files list all files.
+    ///
+    /// # Arguments
+    /// * `paths` – Paths to files or directories.
+    fn walk(self, paths: &[&str]) -> Result<Vec<std::path::PathBuf>, Box<dyn std::error::Error>> {
+        match self {
+            Format::Ciid => walk::walk(paths),
+            Format::Sha256sum | Format::Bagit => walk::walk_all(paths),
+        }
+    }
+
+    /// Derive the hash of a file that is listed in the manifest.
+    ///
+    /// # Arguments
+    /// * `file_path` – Path to file.
+    fn hash(self, file_path: &std::path::Path) -> Result<[u8; 32], Box<dyn std::error::Error>> {
+        match self {
+            Format::Ciid => hash_image(file_path),
+            Format::Sha256sum | Format::Bagit => hash_file(file_path),
+        }
+    }
+
+    /// Format a line of the manifest.
+    ///
+    /// # Arguments
+    /// * `hash` – Hash of the file.
+    /// * `file_path` – Path to the file, as written to the manifest.
+    fn format_line(self, hash: &[u8], file_path: &str) -> String {
+        let hash = data_encoding::HEXLOWER.encode(hash);
+
+        match self {
+            // Like `sha256sum`, paths containing a backslash or line break are escaped, which is
+            // marked by a leading backslash.
+            Format::Ciid | Format::Sha256sum if file_path.contains(['\\', '\n', '\r']) => {
+                let file_path = file_path
+                    .replace('\\', "\\\\")
+                    .replace('\n', "\\n")
+                    .replace('\r', "\\r");
+
+                format!("\\{}  {}", hash, file_path)
+            }
+            Format::Ciid | Format::Sha256sum => format!("{}  {}", hash, file_path),
+            // BagIt requires percent-encoding of `%` and line breaks.
+            Format::Bagit => format!(
+                "{}  {}",
+                hash,
+                file_path
+                    .replace('%', "%25")
+                    .replace('\n', "%0A")
+                    .replace('\r', "%0D")
+            ),
+        }
+    }
+
+    /// Parse a line of the manifest into the hash and the path of a file.
+    ///
+    /// # Arguments
+    /// * `line` – Line of the manifest.
+    fn parse_line(self, line: &str) -> Result<(Vec<u8>, String), Box<dyn std::error::Error>> {
+        let (escaped, line) = match (self, line.strip_prefix('\\')) {
+            (Format::Ciid, Some(line)) | (Format::Sha256sum, Some(line)) => (true, line),
+            _ => (false, line),
+        };
+
+        let invalid = || format!("Invalid manifest line: {}", line);
+
+        let (hash, file_path) = line.split_once(char::is_whitespace).ok_or_else(invalid)?;
+
+        let hash = data_encoding::HEXLOWER_PERMISSIVE
+            .decode(hash.as_bytes())
+            .map_err(|_| invalid())?;
+
+        if hash.len() != 32 {
+            Err(invalid())?;
+        }
+
+        let file_path = match self {
+            // `sha256sum` separates hash and path by two spaces, or by a space and an asterisk in
+            // binary mode.
+            Format::Ciid | Format::Sha256sum => file_path
+                .strip_prefix(|c| c == ' ' || c == '*')
+                .ok_or_else(invalid)?,
+            Format::Bagit => file_path.trim_start(),
+        };
+
+        let file_path = match self {
+            Format::Ciid | Format::Sha256sum if escaped => {
+                unescape(file_path).ok_or_else(invalid)?
+            }
+            Format::Ciid | Format::Sha256sum => file_path.to_owned(),
+            Format::Bagit => file_path
+                .replace("%0A", "\n")
+                .replace("%0a", "\n")
+                .replace("%0D", "\r")
+                .replace("%0d", "\r")
+                .replace("%25", "%"),
+        };
+
+        if file_path.is_empty() {
+            Err(invalid())?;
+        }
+
+        Ok((hash, file_path))
+    }
+}
+
+/// Reverse the escaping of paths in the format of `sha256sum`. Returns `None` for unknown escape
+/// sequences.
+///
+/// # Arguments
+/// * `file_path` – Escaped path.
+fn unescape(file_path: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(file_path.len());
+    let mut chars = file_path.chars();
+
+    while let Some(c) = chars.next() {
+        unescaped.push(match c {
+            '\\' => match chars.next()? {
+                '\\' => '\\',
+                'n' => '\n',
+                'r' => '\r',
+                _ => return None,
+            },
+            c => c,
+        });
+    }
+
+    Some(unescaped)
+}
+
+/// Path of a file as written to a manifest: relative to the base directory and separated by
+/// forward slashes. BagIt manifests only list files of the `data` directory of the bag.
+///
+/// # Arguments
+/// * `base_directory` – Canonical path of the directory paths are relative to.
+/// * `file_path` – Path to the file.
+/// * `format` – Format of the manifest.
+fn relative_path(
+    base_directory: &std::path::Path,
+    file_path: &std::path::Path,
+    format: Format,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let canonical_path = file_path
+        .canonicalize()
+        .map_err(|error| format!("Invalid file path: {}", error))?;
+
+    let components = canonical_path
+        .strip_prefix(base_directory)
+        .map_err(|_| {
+            format!(
+                "{} is not within the base directory {}, see --base-dir",
+                file_path.display(),
+                base_directory.display()
+            )
+        })?
+        .iter()
+        .map(|component| {
+            component
+                .to_str()
+                .ok_or_else(|| format!("Invalid file path: {:?}", file_path))
+        })
+        .collect::<Result<Vec<&str>, _>>()?;
+
+    if format == Format::Bagit && components.first() != Some(&"data") {
+        Err(format!(
+            "{} is not within the data directory of the bag {}, see --base-dir",
+            file_path.display(),
+            base_directory.display()
+        ))?;
+    }
+
+    Ok(components.join("/"))
+}
+
+/// Derive the SHA-256 hash of the whole file, including metadata.
+///
+/// # Arguments
+/// * `file_path` – Path to file.
+fn hash_file(file_path: &std::path::Path) -> Result<[u8; 32], Box<dyn std::error::Error>> {
+    let mut file = std::fs::File::open(file_path)?;
+    let mut hasher = sha2::Sha256::new();
+
+    std::io::copy(&mut file, &mut hasher)?;
+
+    let mut hash = [0; 32];
+    hash.copy_from_slice(hasher.result().as_slice());
+
+    Ok(hash)
+}
+
+/// Print a manifest of files.
+///
+/// # Arguments
+/// * `paths` – Paths to files or directories.
+/// * `base_directory` – Directory the paths in the manifest are relative to.
+/// * `format` – Format of the manifest.
+fn print(
+    paths: &[&str],
+    base_directory: &std::path::Path,
+    format: Format,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let base_directory = base_directory.canonicalize().map_err(|error| {
+        format!(
+            "Invalid base directory {}: {}",
+            base_directory.display(),
+            error
+        )
+    })?;
+
+    let file_paths = format.walk(paths)?;
+
+    let hashes = walk::par_map(&file_paths, |file_path| {
+        format.hash(file_path).map_err(|error| error.to_string())
+    });
+
+    for (file_path, hash) in file_paths.iter().zip(hashes) {
+        let hash = hash.map_err(|error| format!("{}: {}", file_path.display(), error))?;
+        let file_path = relative_path(&base_directory, file_path, format)?;
+
+        println!("{}", format.format_line(&hash, &file_path));
+    }
+
+    Ok(())
+}
+
+/// Verify the files listed in a manifest, printing a line for each file in the format of
+/// `sha256sum --check`.
+///
+/// # Arguments
+/// * `manifest_path` – Path to the manifest. Paths in the manifest are relative to its directory.
+/// * `paths` – Paths to files or directories whose files are expected to be listed in the
+///   manifest.
+/// * `format` – Format of the manifest.
+/// * `quiet` – Only report files that are not OK.
+fn check(
+    manifest_path: &std::path::Path,
+    paths: &[&str],
+    format: Format,
+    quiet: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let manifest = std::fs::read_to_string(manifest_path)
+        .map_err(|error| format!("Failed reading {}: {}", manifest_path.display(), error))?;
+
+    let directory = manifest_path
+        .parent()
+        .ok_or_else(|| format!("Invalid file path: {:?}", manifest_path))?;
+
+    let entries = manifest
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let (hash, file_path) = format.parse_line(line)?;
+            Ok((hash, directory.join(file_path)))
+        })
+        .collect::<Result<Vec<_>, Box<dyn std::error::Error>>>()?;
+
+    let file_paths: Vec<std::path::PathBuf> = entries
+        .iter()
+        .map(|(_, file_path)| file_path.clone())
+        .collect();
+
+    let hashes = walk::par_map(&file_paths, |file_path| {
+        format.hash(file_path).map_err(|error| error.to_string())
+    });
+
+    let mut failed = 0;
+
+    for ((expected, file_path), hash) in entries.iter().zip(hashes) {
+        match hash {
+            Ok(hash) if hash[..] == expected[..] => {
+                if !quiet {
+                    println!("{}: OK", file_path.display());
+                }
+            }
+            Ok(_) => {
+                failed += 1;
+                println!("{}: FAILED", file_path.display());
+            }
+            Err(_) => {
+                failed += 1;
+                println!("{}: FAILED open or read", file_path.display());
+            }
+        }
+    }
+
+    // The manifest is not expected to list itself.
+    let listed: std::collections::HashSet<std::path::PathBuf> = file_paths
+        .iter()
+        .chain(std::iter::once(&manifest_path.to_owned()))
+        .filter_map(|file_path| std::fs::canonicalize(file_path).ok())
+        .collect();
+
+    for file_path in format.walk(paths)? {
+        if !listed.contains(&std::fs::canonicalize(&file_path)?) {
+            println!("{}: NOT IN MANIFEST", file_path.display());
+        }
+    }
+
+    if failed > 0 {
+        Err(format!(
+            "{} of {} file(s) failed verification",
+            failed,
+            entries.len()
+        ))?;
+    }
+
+    Ok(())
+}
+
+/// Print a manifest of image files, or verify image files against a manifest.
+///
+/// # Arguments
+/// * `matches` – Command line arguments of the `manifest` subcommand.
+pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let paths: Vec<&str> = matches
+        .values_of("path")
+        .map(|paths| paths.collect())
+        .unwrap_or_default();
+
+    let format = Format::parse(matches.value_of("format").ok_or("No format provided")?)?;
+
+    match matches.value_of("check") {
+        Some(manifest_path) => check(
+            std::path::Path::new(manifest_path),
+            &paths,
+            format,
+            matches.is_present("quiet"),
+        ),
+        None => print(
+            &paths,
+            std::path::Path::new(matches.value_of("base directory").unwrap_or(".")),
+            format,
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9";
+
+    macro_rules! test_line {
+        ($test_name:ident, $format:expr, $file_path:expr, $line:expr) => {
+            #[test]
+            fn $test_name() {
+                let hash = data_encoding::HEXLOWER.decode(HASH.as_bytes()).unwrap();
+                let line = $format.format_line(&hash, $file_path);
+
+                assert_eq!(line, $line);
+                assert_eq!(
+                    $format.parse_line(&line).unwrap(),
+                    (hash, $file_path.to_owned())
+                );
+            }
+        };
+    }
+
+    test_line!(
+        test_line_ciid,
+        Format::Ciid,
+        "data/IMG_1234.jpg",
+        format!("{}  data/IMG_1234.jpg", HASH)
+    );
+
+    test_line!(
+        test_line_sha256sum_escaped,
+        Format::Sha256sum,
+        "data/a\\b\nc.jpg",
+        format!("\\{}  data/a\\\\b\\nc.jpg", HASH)
+    );
+
+    test_line!(
+        test_line_bagit,
+        Format::Bagit,
+        "data/100%\nc.jpg",
+        format!("{}  data/100%25%0Ac.jpg", HASH)
+    );
+
+    test_line!(
+        test_line_bagit_backslash,
+        Format::Bagit,
+        "data/a\\b.jpg",
+        format!("{}  data/a\\b.jpg", HASH)
+    );
+
+    #[test]
+    fn test_relative_path() -> Result<(), Box<dyn std::error::Error>> {
+        let directory = std::env::temp_dir().join("ciid-manifest-relative-path");
+        std::fs::create_dir_all(directory.join("data").join("sub"))?;
+        let directory = directory.canonicalize()?;
+
+        let file_path = directory.join("data").join("sub").join("IMG_1234.jpg");
+        std::fs::write(&file_path, "")?;
+        let manifest_path = directory.join("manifest-sha256.txt");
+        std::fs::write(&manifest_path, "")?;
+
+        assert_eq!(
+            relative_path(&directory, &file_path, Format::Bagit)?,
+            "data/sub/IMG_1234.jpg"
+        );
+        assert_eq!(
+            relative_path(&directory.join("data"), &file_path, Format::Sha256sum)?,
+            "sub/IMG_1234.jpg"
+        );
+        assert!(relative_path(&directory.join("data"), &file_path, Format::Bagit).is_err());
+        assert!(relative_path(&directory, &manifest_path, Format::Bagit).is_err());
+        assert!(relative_path(
+            &directory.join("data").join("sub"),
+            &manifest_path,
+            Format::Ciid
+        )
+        .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_line_binary_mode() {
+        assert_eq!(
+            Format::Sha256sum
+                .parse_line(&format!("{} *IMG_1234.jpg", HASH))
+                .unwrap()
+                .1,
+            "IMG_1234.jpg"
+        );
+    }
+
+    #[test]
+    fn test_parse_line_invalid() {
+        assert!(Format::Ciid.parse_line("IMG_1234.jpg").is_err());
+        assert!(Format::Ciid.parse_line("5704ec43  IMG_1234.jpg").is_err());
+        assert!(Format::Ciid
+            .parse_line(&format!("\\{}  a\\x.jpg", HASH))
+            .is_err());
+        assert!(Format::Ciid.parse_line(&format!("{}  ", HASH)).is_err());
+    }
+}
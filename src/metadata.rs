@@ -0,0 +1,201 @@
+use crate::exiftool;
+
+/// Prefix of the identifier in the XMP `DocumentID`, which distinguishes it from document ids
+/// assigned by other software.
+const DOCUMENT_ID_PREFIX: &str = "ciid:";
+
+/// Number of bytes of the hash written to `ImageUniqueID`, which holds 128 bits as 32 hex
+/// characters.
+const IMAGE_UNIQUE_ID_BYTES: usize = 16;
+
+/// Where the identifier is written to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Target {
+    /// Into the metadata of the image file itself.
+    File,
+    /// Into an XMP sidecar next to the image file, see `sidecar_path`.
+    Sidecar,
+}
+
+impl Target {
+    /// Parse the value of `--metadata-target`. `auto` writes into JPEG files and into sidecars of
+    /// raw files, so that raw files are never modified.
+    ///
+    /// # Arguments
+    /// * `target` – One of `auto`, `file` or `sidecar`.
+    /// * `file_path` – Path to the image file.
+    pub fn parse(
+        target: &str,
+        file_path: &std::path::Path,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        match target {
+            "auto" if is_jpeg(file_path) => Ok(Target::File),
+            "auto" | "sidecar" => Ok(Target::Sidecar),
+            "file" => Ok(Target::File),
+            target => Err(format!("Unknown metadata target: {}", target))?,
+        }
+    }
+}
+
+/// Whether the file extension belongs to a JPEG image.
+///
+/// # Arguments
+/// * `file_path` – Path to the image file.
+fn is_jpeg(file_path: &std::path::Path) -> bool {
+    file_path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            extension.eq_ignore_ascii_case("jpg") || extension.eq_ignore_ascii_case("jpeg")
+        })
+}
+
+/// Path of the XMP sidecar of an image file. Raw files use the sidecar without their extension,
+/// e.g. `IMG_1234.xmp` for `IMG_1234.CR2`, as other applications do. JPEG images keep their
+/// extension, e.g. `IMG_1234.JPG.xmp`, so that the JPEG of a RAW+JPEG pair does not share the
+/// sidecar of the raw file.
+///
+/// # Arguments
+/// * `file_path` – Path to the image file.
+pub fn sidecar_path(file_path: &std::path::Path) -> std::path::PathBuf {
+    if is_jpeg(file_path) {
+        let mut sidecar_path = file_path.as_os_str().to_owned();
+        sidecar_path.push(".xmp");
+
+        std::path::PathBuf::from(sidecar_path)
+    } else {
+        file_path.with_extension("xmp")
+    }
+}
+
+/// The value written to `ImageUniqueID`: the first 128 bits of the hash as lowercase hex.
+///
+/// # Arguments
+/// * `hash` – Hash of the image buffer.
+pub fn image_unique_id(hash: &[u8]) -> String {
+    data_encoding::HEXLOWER.encode(&hash[..IMAGE_UNIQUE_ID_BYTES.min(hash.len())])
+}
+
+fn to_str(file_path: &std::path::Path) -> Result<&str, Box<dyn std::error::Error>> {
+    Ok(file_path
+        .to_str()
+        .ok_or_else(|| format!("Invalid file path: {:?}", file_path))?)
+}
+
+/// Write the identifier into the XMP `DocumentID` of an image file or its sidecar. Only metadata
+/// is written, the image buffer and thereby the hash stay untouched.
+///
+/// # Arguments
+/// * `file_path` – Path to the image file.
+/// * `target` – Whether to write into the file or its sidecar.
+/// * `identifier` – Identifier to be written.
+/// * `image_unique_id` – Value to be written to `ImageUniqueID`, if any.
+pub fn write(
+    file_path: &std::path::Path,
+    target: Target,
+    identifier: &str,
+    image_unique_id: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let document_id = format!("-XMP-xmpMM:DocumentID={}{}", DOCUMENT_ID_PREFIX, identifier);
+
+    let mut args = vec!["-q", "-m", "-overwrite_original", document_id.as_str()];
+
+    let image_unique_id = image_unique_id.map(|image_unique_id| match target {
+        Target::File => format!("-EXIF:ImageUniqueID={}", image_unique_id),
+        Target::Sidecar => format!("-XMP-exif:ImageUniqueID={}", image_unique_id),
+    });
+
+    if let Some(image_unique_id) = &image_unique_id {
+        args.push(image_unique_id);
+    }
+
+    let sidecar_path = sidecar_path(file_path);
+
+    match target {
+        Target::File => args.push(to_str(file_path)?),
+        Target::Sidecar if sidecar_path.exists() => args.push(to_str(&sidecar_path)?),
+        // Creates the sidecar, copying the XMP metadata of the image file.
+        Target::Sidecar => {
+            args.extend(&["-o", to_str(&sidecar_path)?, to_str(file_path)?]);
+        }
+    }
+
+    exiftool(&args).map_err(|error| format!("Failed running exiftool: {}", error))?;
+
+    Ok(())
+}
+
+/// Read the identifier from the XMP `DocumentID` of an image file, falling back to its sidecar.
+/// Returns `None` if neither carries an identifier.
+///
+/// # Arguments
+/// * `file_path` – Path to the image file.
+pub fn read(file_path: &std::path::Path) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    #[derive(serde::Deserialize)]
+    #[allow(non_snake_case)]
+    struct Xmp {
+        DocumentID: Option<String>,
+    }
+
+    let sidecar_path = sidecar_path(file_path);
+
+    let mut args = vec!["-j", "-XMP-xmpMM:DocumentID", to_str(file_path)?];
+
+    if sidecar_path.exists() {
+        args.push(to_str(&sidecar_path)?);
+    }
+
+    let output = exiftool(&args).map_err(|error| format!("Failed running exiftool: {}", error))?;
+
+    let xmps: Vec<Xmp> = serde_json::from_str(&output)?;
+
+    Ok(xmps
+        .into_iter()
+        .filter_map(|xmp| xmp.DocumentID)
+        .find_map(|document_id| {
+            document_id
+                .strip_prefix(DOCUMENT_ID_PREFIX)
+                .map(str::to_owned)
+        }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target() {
+        let target =
+            |target, file_path| Target::parse(target, std::path::Path::new(file_path)).unwrap();
+
+        assert_eq!(target("auto", "a.JPG"), Target::File);
+        assert_eq!(target("auto", "a.jpeg"), Target::File);
+        assert_eq!(target("auto", "a.CR2"), Target::Sidecar);
+        assert_eq!(target("file", "a.CR2"), Target::File);
+        assert_eq!(target("sidecar", "a.jpg"), Target::Sidecar);
+    }
+
+    #[test]
+    fn test_sidecar_path() {
+        assert_eq!(
+            sidecar_path(std::path::Path::new("/images/IMG_1234.CR2")),
+            std::path::PathBuf::from("/images/IMG_1234.xmp")
+        );
+        assert_eq!(
+            sidecar_path(std::path::Path::new("/images/IMG_1234.JPG")),
+            std::path::PathBuf::from("/images/IMG_1234.JPG.xmp")
+        );
+    }
+
+    #[test]
+    fn test_image_unique_id() {
+        assert_eq!(
+            image_unique_id(
+                &data_encoding::HEXLOWER
+                    .decode(b"5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9")
+                    .unwrap()
+            ),
+            "5704ec43140d3adab0d813111219aded"
+        );
+    }
+}
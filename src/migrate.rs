@@ -0,0 +1,205 @@
+use crate::{
+    date_from_timestamp, get_date_original_from_exif, get_exif, get_identifier,
+    hash_image_from_source, jpeg, parse_identifier, raw_hash, sequence_key, tag_scheme_version,
+    DateOptions, ImageSource, CURRENT_SCHEME,
+};
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("migrate")
+        .about("Renames files named with an identifier of an older scheme to the current scheme")
+        .arg(
+            Arg::with_name("file path")
+                .takes_value(true)
+                .required(true)
+                .multiple(true)
+                .help("Path to image file"),
+        )
+        .arg(
+            Arg::with_name("dry run")
+                .long("--dry-run")
+                .help("Reports which files would be renamed, without renaming them"),
+        )
+        .arg(
+            Arg::with_name("rederive")
+                .long("--rederive")
+                .help("Derives the identifier from the image instead of transcoding the file name"),
+        )
+        .args(&DateOptions::args())
+        .arg(
+            Arg::with_name("timestamp digits")
+                .takes_value(true)
+                .long("--timestamp-digits")
+                .help("Minimum number of digits the timestamp should carry. Will be padded with zeros from the left"),
+        )
+        .arg(
+            Arg::with_name("scheme version")
+                .long("--scheme-version")
+                .help("Appends the version of the identifier scheme to the identifier (e.g. \"-v2\")"),
+        )
+        .arg(jpeg::arg())
+        .arg(raw_hash::arg())
+}
+
+/// Derive the path a file should be renamed to, so that its name carries an identifier of the
+/// current scheme. Returns `None` if the file name is already up to date.
+///
+/// # Arguments
+/// * `file_path` – Path to file that should be migrated.
+/// * `matches` – Command line arguments of the `migrate` subcommand.
+/// * `timestamp_digits` – Minimum number of digits the timestamp should carry.
+fn migrate_file_path(
+    file_path: &std::path::Path,
+    matches: &ArgMatches,
+    timestamp_digits: u64,
+) -> Result<Option<std::path::PathBuf>, Box<dyn std::error::Error>> {
+    let file_stem = file_path
+        .file_stem()
+        .and_then(|file_stem| file_stem.to_str())
+        .ok_or_else(|| format!("Invalid file path: {:?}", file_path))?;
+
+    let parsed = parse_identifier(file_stem)?;
+
+    let identifier = if matches.is_present("rederive") {
+        let exif =
+            get_exif(file_path).map_err(|error| format!("Failed reading metadata: {}", error))?;
+
+        let date_options = DateOptions::from_matches(matches)?;
+
+        let timestamp = get_date_original_from_exif(&exif.date_time, &date_options)
+            .map_err(|error| format!("Failed deriving timestamp data: {}", error))?
+            .date;
+
+        let sequence_key = if parsed.sequence_key.is_some() {
+            Some(
+                sequence_key::get_sequence_key(file_path, &exif.sequence)
+                    .ok_or("Failed deriving sequence key: No sequence information available")?
+                    .value,
+            )
+        } else {
+            None
+        };
+
+        let camera_id = if parsed.camera_id.is_some() {
+            Some(
+                exif.camera
+                    .id()
+                    .ok_or("Failed deriving camera id: No camera information available")?,
+            )
+        } else {
+            None
+        };
+
+        let hash = if parsed.hash.is_some() {
+            let jpeg_hash =
+                jpeg::HashMode::parse(matches.value_of("jpeg hash").unwrap_or("decoded"))?;
+            let raw_hash =
+                raw_hash::HashMode::parse(matches.value_of("raw hash").unwrap_or("sensor"))?;
+
+            Some(
+                hash_image_from_source(ImageSource::File(file_path), false, jpeg_hash, raw_hash)
+                    .map_err(|error| format!("Failed deriving image hash: {}", error))?
+                    .hash,
+            )
+        } else {
+            None
+        };
+
+        get_identifier(
+            &timestamp,
+            timestamp_digits,
+            sequence_key,
+            camera_id.as_deref(),
+            hash.as_ref().map(|hash| &hash[..]),
+        )?
+    } else {
+        let timestamp = date_from_timestamp(parsed.timestamp)?;
+
+        get_identifier(
+            &timestamp,
+            timestamp_digits,
+            parsed.sequence_key,
+            parsed.camera_id.as_deref(),
+            parsed.hash.as_deref(),
+        )?
+    };
+
+    let identifier = if matches.is_present("scheme version") || parsed.tagged {
+        tag_scheme_version(&identifier, CURRENT_SCHEME)
+    } else {
+        identifier
+    };
+
+    if identifier == file_stem {
+        return Ok(None);
+    }
+
+    let mut path = match file_path.parent() {
+        Some(parent) => parent.into(),
+        None => std::path::PathBuf::new(),
+    };
+
+    path.push(identifier);
+
+    if let Some(extension) = file_path.extension() {
+        path.set_extension(extension);
+    }
+
+    Ok(Some(path))
+}
+
+/// Rename files from an older identifier scheme to the current one, printing a report line for
+/// each file.
+///
+/// # Arguments
+/// * `matches` – Command line arguments of the `migrate` subcommand.
+pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let file_paths = matches
+        .values_of("file path")
+        .ok_or("No file path provided")?;
+
+    let timestamp_digits = matches
+        .value_of("timestamp digits")
+        .unwrap_or("14")
+        .parse::<u64>()
+        .map_err(|error| format!("Failed parsing timestamp digits: {}", error))?;
+
+    let dry_run = matches.is_present("dry run");
+
+    let mut failures = 0;
+
+    for file_path in file_paths.map(std::path::Path::new) {
+        let result = migrate_file_path(file_path, matches, timestamp_digits).and_then(|path| {
+            match path {
+                None => println!("current {}", file_path.display()),
+                Some(path) if path.exists() => {
+                    Err(format!("Target file already exists: {}", path.display()))?
+                }
+                Some(path) if dry_run => {
+                    println!(
+                        "rename  {} -> {} (dry run)",
+                        file_path.display(),
+                        path.display()
+                    )
+                }
+                Some(path) => {
+                    std::fs::rename(file_path, &path)?;
+                    println!("rename  {} -> {}", file_path.display(), path.display());
+                }
+            }
+
+            Ok(())
+        });
+
+        if let Err(error) = result {
+            failures += 1;
+            eprintln!("failed  {}: {}", file_path.display(), error);
+        }
+    }
+
+    if failures > 0 {
+        Err(format!("Failed migrating {} file(s)", failures))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,242 @@
+use crate::coefficients;
+use sha2::Digest;
+
+/// One of the eight orientations an image can be brought into by rotating it in steps of 90
+/// degrees and flipping it. Maps coordinates of the transformed image onto the original one: first
+/// transposed, then flipped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Transform {
+    transpose: bool,
+    flip_horizontal: bool,
+    flip_vertical: bool,
+}
+
+/// All eight orientations, starting with the identity.
+const TRANSFORMS: [Transform; 8] = [
+    Transform::new(false, false, false),
+    Transform::new(false, true, false),
+    Transform::new(false, false, true),
+    Transform::new(false, true, true),
+    Transform::new(true, false, false),
+    Transform::new(true, true, false),
+    Transform::new(true, false, true),
+    Transform::new(true, true, true),
+];
+
+impl Transform {
+    const fn new(transpose: bool, flip_horizontal: bool, flip_vertical: bool) -> Self {
+        Transform {
+            transpose,
+            flip_horizontal,
+            flip_vertical,
+        }
+    }
+
+    /// Position in the original image of a position in the transformed one.
+    ///
+    /// # Arguments
+    /// * `x` – Column in the transformed image.
+    /// * `y` – Row in the transformed image.
+    /// * `width` – Width of the original image.
+    /// * `height` – Height of the original image.
+    fn source(self, x: usize, y: usize, width: usize, height: usize) -> (usize, usize) {
+        let (x, y) = if self.transpose { (y, x) } else { (x, y) };
+
+        (
+            if self.flip_horizontal {
+                width - 1 - x
+            } else {
+                x
+            },
+            if self.flip_vertical {
+                height - 1 - y
+            } else {
+                y
+            },
+        )
+    }
+
+    /// Sampling factors and dimensions in blocks of a component in this orientation.
+    ///
+    /// # Arguments
+    /// * `component` – Component of the original image.
+    fn header(self, component: &coefficients::Component) -> [i32; 4] {
+        let (horizontal, vertical) = (component.horizontal as i32, component.vertical as i32);
+        let (width, height) = (component.blocks_wide as i32, component.blocks_high as i32);
+
+        if self.transpose {
+            [vertical, horizontal, height, width]
+        } else {
+            [horizontal, vertical, width, height]
+        }
+    }
+
+    /// Dequantized coefficients of the blocks of a component in this orientation, each in natural
+    /// order, blocks in raster order.
+    ///
+    /// Transposing a block transposes its coefficients, flipping it negates the coefficients of
+    /// odd horizontal or vertical frequency. This is exactly how lossless transformations like
+    /// `jpegtran -rotate 90` rewrite the coded image.
+    ///
+    /// # Arguments
+    /// * `component` – Component of the original image.
+    fn blocks(self, component: &coefficients::Component) -> impl Iterator<Item = [i32; 64]> + '_ {
+        let (width, height) = (component.blocks_wide, component.blocks_high);
+        let quantization = component.quantization();
+
+        // Position in the original block and whether to negate, per coefficient.
+        let mut mapping = [(0, false); 64];
+
+        for (position, entry) in mapping.iter_mut().enumerate() {
+            let (u, v) = if self.transpose {
+                (position / 8, position % 8)
+            } else {
+                (position % 8, position / 8)
+            };
+
+            *entry = (
+                v * 8 + u,
+                (self.flip_horizontal && u % 2 == 1) != (self.flip_vertical && v % 2 == 1),
+            );
+        }
+
+        let (blocks_wide, blocks_high) = if self.transpose {
+            (height, width)
+        } else {
+            (width, height)
+        };
+
+        (0..blocks_high).flat_map(move |y| {
+            (0..blocks_wide).map(move |x| {
+                let (x, y) = self.source(x, y, width, height);
+                let block = component.block(x, y);
+                let mut values = [0; 64];
+
+                for (value, (source, negate)) in values.iter_mut().zip(mapping.iter()) {
+                    *value = block[*source].wrapping_mul(i32::from(quantization[*source]));
+
+                    if *negate {
+                        *value = value.wrapping_neg();
+                    }
+                }
+
+                values
+            })
+        })
+    }
+}
+
+/// Compare the coefficients of an image in two orientations lexicographically, component by
+/// component, each with its header followed by its blocks.
+///
+/// # Arguments
+/// * `components` – Components of the original image.
+/// * `a` – First orientation.
+/// * `b` – Second orientation.
+fn compare(
+    components: &[coefficients::Component],
+    a: Transform,
+    b: Transform,
+) -> std::cmp::Ordering {
+    components
+        .iter()
+        .map(|component| {
+            a.header(component)
+                .cmp(&b.header(component))
+                .then_with(|| a.blocks(component).cmp(b.blocks(component)))
+        })
+        .find(|ordering| *ordering != std::cmp::Ordering::Equal)
+        .unwrap_or(std::cmp::Ordering::Equal)
+}
+
+/// Feed the DCT coefficients of a JPEG image into a hasher, in a canonical orientation: of the
+/// eight orientations reachable by rotations and flips, the one whose coefficients are the
+/// lexicographically smallest. Losslessly rotating or flipping the image, e.g. with `jpegtran`,
+/// therefore does not alter the hash, as long as its dimensions are multiples of the MCU size.
+/// Neither does re-encoding the coefficients, e.g. converting between baseline and progressive.
+///
+/// The coefficients are hashed instead of the decoded pixels, since decoders compute the inverse
+/// DCT with rounding that is not symmetric under transposition.
+///
+/// # Arguments
+/// * `data` – Contents of the JPEG file.
+/// * `hasher` – Hasher the coefficients are fed into.
+pub fn hash_canonical(
+    data: &[u8],
+    hasher: &mut sha2::Sha256,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let components = coefficients::decode(data)?;
+
+    let transform = TRANSFORMS
+        .iter()
+        .copied()
+        .min_by(|a, b| compare(&components, *a, *b))
+        .unwrap_or(TRANSFORMS[0]);
+
+    let mut buffer = [0; 4 * 64];
+
+    for component in &components {
+        for (value, bytes) in transform
+            .header(component)
+            .iter()
+            .zip(buffer.chunks_exact_mut(4))
+        {
+            bytes.copy_from_slice(&value.to_be_bytes());
+        }
+
+        hasher.input(&buffer[..4 * 4]);
+
+        for block in transform.blocks(component) {
+            for (value, bytes) in block.iter().zip(buffer.chunks_exact_mut(4)) {
+                bytes.copy_from_slice(&value.to_be_bytes());
+            }
+
+            hasher.input(&buffer[..]);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Positions in the original image of all positions of a transformed 3x2 image.
+    fn positions(transform: Transform) -> Vec<(usize, usize)> {
+        let (width, height) = if transform.transpose { (2, 3) } else { (3, 2) };
+
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| transform.source(x, y, 3, 2)))
+            .collect()
+    }
+
+    #[test]
+    fn test_transforms_are_distinct_permutations() {
+        let mut expected = positions(TRANSFORMS[0]);
+        expected.sort();
+
+        let mut all = Vec::new();
+
+        for transform in TRANSFORMS.iter() {
+            let mut sorted = positions(*transform);
+            all.push(sorted.clone());
+
+            sorted.sort();
+            assert_eq!(sorted, expected);
+        }
+
+        all.sort();
+        all.dedup();
+        assert_eq!(all.len(), 8);
+    }
+
+    #[test]
+    fn test_transform_rotate_90() {
+        // Rotating clockwise by 90 degrees moves the bottom left corner to the top left.
+        assert_eq!(
+            positions(Transform::new(true, false, true)),
+            vec![(0, 1), (0, 0), (1, 1), (1, 0), (2, 1), (2, 0)]
+        );
+    }
+}
@@ -0,0 +1,166 @@
+/// Width of the grayscale image the perceptual hash is derived from. One column more than bits per
+/// row, since each bit compares two neighbouring pixels.
+const WIDTH: u32 = 9;
+
+/// Height of the grayscale image the perceptual hash is derived from.
+const HEIGHT: u32 = 8;
+
+/// Derive a perceptual hash (dHash) of an image. The image is scaled down to 9×8 grayscale pixels,
+/// each bit records whether a pixel is brighter than its right neighbour. Images that look alike
+/// yield hashes with a small Hamming distance, even after re-encoding or resizing.
+///
+/// # Arguments
+/// * `image` – Decoded image.
+pub fn dhash(image: &image::DynamicImage) -> u64 {
+    let pixels = image
+        .grayscale()
+        .resize_exact(WIDTH, HEIGHT, image::imageops::FilterType::Triangle)
+        .to_luma8();
+
+    let mut hash = 0;
+
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH - 1 {
+            let left = pixels.get_pixel(x, y)[0];
+            let right = pixels.get_pixel(x + 1, y)[0];
+
+            hash = (hash << 1) | u64::from(left > right);
+        }
+    }
+
+    hash
+}
+
+/// Number of bits that differ between two perceptual hashes.
+///
+/// # Arguments
+/// * `a` – Perceptual hash.
+/// * `b` – Perceptual hash.
+pub fn distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Format a perceptual hash as 16 lowercase hex characters.
+///
+/// # Arguments
+/// * `phash` – Perceptual hash.
+pub fn format(phash: u64) -> String {
+    format!("{:016x}", phash)
+}
+
+/// Group perceptual hashes whose Hamming distance to every other member of the group is at most
+/// `max_distance`. Hashes are visited in order, each joins the first group it is close enough to
+/// all members of, so that chains of hashes that are each close to the next one do not end up in
+/// a single group. Returns groups with more than one member as indices into `phashes`, in
+/// ascending order.
+///
+/// # Arguments
+/// * `phashes` – Perceptual hashes to be grouped.
+/// * `max_distance` – Maximum number of differing bits for images to be considered similar.
+pub fn group_similar(phashes: &[u64], max_distance: u32) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+
+    for (index, &phash) in phashes.iter().enumerate() {
+        let group = groups.iter_mut().find(|group| {
+            group
+                .iter()
+                .all(|&member| distance(phashes[member], phash) <= max_distance)
+        });
+
+        match group {
+            Some(group) => group.push(index),
+            None => groups.push(vec![index]),
+        }
+    }
+
+    groups.retain(|group| group.len() > 1);
+
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(width: u32, height: u32, reverse: bool) -> image::DynamicImage {
+        image::DynamicImage::ImageLuma8(image::ImageBuffer::from_fn(width, height, |x, _| {
+            let value = (x * 255 / (width - 1)) as u8;
+
+            image::Luma([if reverse { 255 - value } else { value }])
+        }))
+    }
+
+    #[test]
+    fn test_dhash_scale_invariant() {
+        assert_eq!(
+            dhash(&gradient(90, 80, true)),
+            dhash(&gradient(900, 800, true))
+        );
+    }
+
+    #[test]
+    fn test_dhash_resized_and_reencoded() -> Result<(), Box<dyn std::error::Error>> {
+        let file = std::fs::File::open(
+            "tests/files/01483624375960-5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9.jpg",
+        )?;
+
+        // Decoding at a reduced scale keeps the test fast, while still resizing the image.
+        let mut decoder = image::codecs::jpeg::JpegDecoder::new(std::io::BufReader::new(file))?;
+        decoder.scale(1000, 1000)?;
+        let image = image::DynamicImage::from_decoder(decoder)?;
+
+        let resized = image.resize(300, 300, image::imageops::FilterType::Triangle);
+
+        let mut data = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut data, 50).encode_image(&resized)?;
+        let reencoded = image::load_from_memory(&data)?;
+
+        assert!(distance(dhash(&image), dhash(&reencoded)) <= 4);
+        assert!(distance(dhash(&image), dhash(&gradient(90, 80, false))) > 10);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dhash_gradient() {
+        assert_eq!(dhash(&gradient(90, 80, false)), 0);
+        assert_eq!(dhash(&gradient(90, 80, true)), u64::MAX);
+    }
+
+    #[test]
+    fn test_distance() {
+        assert_eq!(distance(0b1011, 0b1011), 0);
+        assert_eq!(distance(0b1011, 0b0010), 2);
+        assert_eq!(distance(0, u64::MAX), 64);
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(format(0xd4f894f5), "00000000d4f894f5");
+    }
+
+    #[test]
+    fn test_group_similar() {
+        assert_eq!(
+            group_similar(&[0b0000, 0b1111_0000, 0b0001, 0b1111_0001, 0b0011], 1),
+            vec![vec![0, 2], vec![1, 3]]
+        );
+        assert_eq!(
+            group_similar(&[0b0000, 0b0011], 1),
+            Vec::<Vec<usize>>::new()
+        );
+    }
+
+    #[test]
+    fn test_group_similar_chain() {
+        // Each hash is within the maximum distance of the next one, but not of all others.
+        assert_eq!(
+            group_similar(&[0b0000, 0b0001, 0b0011, 0b0111], 1),
+            vec![vec![0, 1], vec![2, 3]]
+        );
+        assert_eq!(
+            group_similar(&[0b0001, 0b0000, 0b0011], 1),
+            vec![vec![0, 1]]
+        );
+    }
+}
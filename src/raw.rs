@@ -0,0 +1,2043 @@
+/// Maximum number of pixels of the sensor data, which guards against allocating huge buffers for
+/// corrupt dimensions.
+#[cfg(not(feature = "libraw"))]
+const MAX_PIXELS: usize = 1 << 28;
+
+const TAG_NEW_SUBFILE_TYPE: u16 = 0xfe;
+const TAG_IMAGE_WIDTH: u16 = 0x100;
+const TAG_IMAGE_LENGTH: u16 = 0x101;
+#[cfg(not(feature = "libraw"))]
+const TAG_BITS_PER_SAMPLE: u16 = 0x102;
+#[cfg(not(feature = "libraw"))]
+const TAG_COMPRESSION: u16 = 0x103;
+const TAG_PHOTOMETRIC_INTERPRETATION: u16 = 0x106;
+#[cfg(not(feature = "libraw"))]
+const TAG_MAKE: u16 = 0x10f;
+#[cfg(not(feature = "libraw"))]
+const TAG_STRIP_OFFSETS: u16 = 0x111;
+#[cfg(not(feature = "libraw"))]
+const TAG_SAMPLES_PER_PIXEL: u16 = 0x115;
+#[cfg(not(feature = "libraw"))]
+const TAG_ROWS_PER_STRIP: u16 = 0x116;
+#[cfg(not(feature = "libraw"))]
+const TAG_STRIP_BYTE_COUNTS: u16 = 0x117;
+const TAG_SOFTWARE: u16 = 0x131;
+#[cfg(not(feature = "libraw"))]
+const TAG_TILE_WIDTH: u16 = 0x142;
+#[cfg(not(feature = "libraw"))]
+const TAG_TILE_LENGTH: u16 = 0x143;
+#[cfg(not(feature = "libraw"))]
+const TAG_TILE_OFFSETS: u16 = 0x144;
+const TAG_SUB_IFDS: u16 = 0x14a;
+#[cfg(not(feature = "libraw"))]
+const TAG_JPEG_INTERCHANGE_FORMAT: u16 = 0x201;
+#[cfg(not(feature = "libraw"))]
+const TAG_JPEG_INTERCHANGE_FORMAT_LENGTH: u16 = 0x202;
+#[cfg(not(feature = "libraw"))]
+const TAG_SONY_TONE_CURVE: u16 = 0x7010;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_MAKER_NOTE: u16 = 0x927c;
+const TAG_DNG_VERSION: u16 = 0xc612;
+#[cfg(not(feature = "libraw"))]
+const TAG_LINEARIZATION_TABLE: u16 = 0xc618;
+#[cfg(not(feature = "libraw"))]
+const TAG_CR2_SLICE: u16 = 0xc640;
+const TAG_ORIGINAL_RAW_FILE_NAME: u16 = 0xc68b;
+const TAG_ORIGINAL_RAW_FILE_DATA: u16 = 0xc68c;
+const TAG_ACTIVE_AREA: u16 = 0xc68d;
+/// Tag of the Nikon maker note that holds the predictors and the curve of compressed NEF files.
+#[cfg(not(feature = "libraw"))]
+const TAG_NIKON_COMPRESSION_DATA: u16 = 0x96;
+/// Tag of the Canon maker note that holds the dimensions and the borders of the sensor.
+const TAG_CANON_SENSOR_INFO: u16 = 0xe0;
+
+/// Size of the blocks `OriginalRawFileData` is compressed in.
+const ORIGINAL_RAW_FILE_BLOCK_SIZE: usize = 1 << 16;
+
+const PHOTOMETRIC_CFA: u32 = 32803;
+const PHOTOMETRIC_LINEAR_RAW: u32 = 34892;
+
+#[cfg(not(feature = "libraw"))]
+const COMPRESSION_NONE: u32 = 1;
+#[cfg(not(feature = "libraw"))]
+const COMPRESSION_LOSSLESS_JPEG: u32 = 7;
+#[cfg(not(feature = "libraw"))]
+const COMPRESSION_NIKON: u32 = 34713;
+#[cfg(not(feature = "libraw"))]
+const COMPRESSION_SONY: u32 = 32767;
+
+/// Huffman trees of compressed NEF files: 12-bit lossy, 12-bit lossy after split, 12-bit
+/// lossless, 14-bit lossy, 14-bit lossy after split and 14-bit lossless. The first 16 bytes are
+/// the number of codes per length, followed by the values.
+#[cfg(not(feature = "libraw"))]
+const NIKON_TREES: [[u8; 32]; 6] = [
+    [
+        0, 1, 5, 1, 1, 1, 1, 1, 1, 2, 0, 0, 0, 0, 0, 0, 5, 4, 3, 6, 2, 7, 1, 0, 8, 9, 11, 10, 12,
+        0, 0, 0,
+    ],
+    [
+        0, 1, 5, 1, 1, 1, 1, 1, 1, 2, 0, 0, 0, 0, 0, 0, 0x39, 0x5a, 0x38, 0x27, 0x16, 5, 4, 3, 2,
+        1, 0, 11, 12, 12, 0, 0,
+    ],
+    [
+        0, 1, 4, 2, 3, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5, 4, 6, 3, 7, 2, 8, 1, 9, 0, 10, 11, 12,
+        0, 0, 0,
+    ],
+    [
+        0, 1, 4, 3, 1, 1, 1, 1, 1, 2, 0, 0, 0, 0, 0, 0, 5, 6, 4, 7, 8, 3, 9, 2, 1, 0, 10, 11, 12,
+        13, 14, 0,
+    ],
+    [
+        0, 1, 5, 1, 1, 1, 1, 1, 1, 1, 2, 0, 0, 0, 0, 0, 8, 0x5c, 0x4b, 0x3a, 0x29, 7, 6, 5, 4, 3,
+        2, 1, 0, 13, 14, 0,
+    ],
+    [
+        0, 1, 4, 2, 2, 3, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 7, 6, 8, 5, 9, 4, 10, 3, 11, 12, 2, 0, 1,
+        13, 14, 0,
+    ],
+];
+
+/// Sensor data of a raw file: the values LibRaw stores in `rawdata.raw_image`, row by row.
+#[derive(Debug, PartialEq)]
+pub struct SensorData {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u16>,
+}
+
+impl SensorData {
+    #[cfg(not(feature = "libraw"))]
+    fn new(width: usize, height: usize) -> Result<Self, Box<dyn std::error::Error>> {
+        let size = width
+            .checked_mul(height)
+            .filter(|&size| size > 0 && size <= MAX_PIXELS)
+            .ok_or_else(|| format!("Invalid raw image dimensions: {}x{}", width, height))?;
+
+        Ok(SensorData {
+            width,
+            height,
+            pixels: vec![0; size],
+        })
+    }
+
+    /// Set a pixel, ignoring positions outside of the sensor data like LibRaw does.
+    #[cfg(not(feature = "libraw"))]
+    fn set(&mut self, row: usize, col: usize, value: u16) {
+        if row < self.height && col < self.width {
+            self.pixels[row * self.width + col] = value;
+        }
+    }
+
+    /// The sensor data as bytes in native byte order, the way LibRaw lays it out in memory.
+    #[cfg(not(feature = "libraw"))]
+    pub fn to_ne_bytes(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|pixel| pixel.to_ne_bytes())
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ByteOrder {
+    LittleEndian,
+    BigEndian,
+}
+
+/// Entry of an image file directory. `offset` points to the value, which is stored inline for
+/// values of up to 4 bytes.
+struct Entry {
+    tag: u16,
+    kind: u16,
+    count: usize,
+    offset: usize,
+}
+
+/// Image file directory.
+struct Ifd {
+    entries: Vec<Entry>,
+    /// Offset of the next IFD in the chain, 0 for the last one.
+    next: usize,
+}
+
+impl Ifd {
+    fn get(&self, tag: u16) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.tag == tag)
+    }
+}
+
+/// TIFF structure that CR2, NEF, ARW and DNG files are built on.
+struct Tiff<'a> {
+    data: &'a [u8],
+    order: ByteOrder,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a [u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        let order = match data.get(..4) {
+            Some(b"II*\0") => ByteOrder::LittleEndian,
+            Some(b"MM\0*") => ByteOrder::BigEndian,
+            _ => Err("Not a TIFF-based raw file")?,
+        };
+
+        Ok(Tiff { data, order })
+    }
+
+    fn bytes(&self, offset: usize, length: usize) -> Result<&'a [u8], Box<dyn std::error::Error>> {
+        offset
+            .checked_add(length)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or_else(|| "Unexpected end of file".into())
+    }
+
+    fn u16(&self, offset: usize) -> Result<u16, Box<dyn std::error::Error>> {
+        let bytes = self.bytes(offset, 2)?;
+        let bytes = [bytes[0], bytes[1]];
+
+        Ok(match self.order {
+            ByteOrder::LittleEndian => u16::from_le_bytes(bytes),
+            ByteOrder::BigEndian => u16::from_be_bytes(bytes),
+        })
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32, Box<dyn std::error::Error>> {
+        let bytes = self.bytes(offset, 4)?;
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+
+        Ok(match self.order {
+            ByteOrder::LittleEndian => u32::from_le_bytes(bytes),
+            ByteOrder::BigEndian => u32::from_be_bytes(bytes),
+        })
+    }
+
+    /// Parse the IFD at an offset. Entries of unknown types are skipped.
+    fn ifd(&self, offset: usize) -> Result<Ifd, Box<dyn std::error::Error>> {
+        let count = self.u16(offset)? as usize;
+        let mut entries = Vec::with_capacity(count);
+
+        for index in 0..count {
+            let entry = offset + 2 + index * 12;
+            let tag = self.u16(entry)?;
+            let kind = self.u16(entry + 2)?;
+            let count = self.u32(entry + 4)? as usize;
+
+            let size: usize = match kind {
+                1 | 2 | 6 | 7 => 1,
+                3 | 8 => 2,
+                4 | 9 | 11 | 13 => 4,
+                5 | 10 | 12 => 8,
+                _ => continue,
+            };
+
+            let offset = if size.saturating_mul(count) <= 4 {
+                entry + 8
+            } else {
+                self.u32(entry + 8)? as usize
+            };
+
+            entries.push(Entry {
+                tag,
+                kind,
+                count,
+                offset,
+            });
+        }
+
+        let next = self.u32(offset + 2 + count * 12).unwrap_or(0) as usize;
+
+        Ok(Ifd { entries, next })
+    }
+
+    /// Parse all IFDs: first the chain starting at the header, in order, followed by the IFDs
+    /// referenced as `SubIFDs`.
+    fn ifds(&self) -> Result<Vec<Ifd>, Box<dyn std::error::Error>> {
+        let mut ifds: Vec<Ifd> = Vec::new();
+        let mut visited = std::collections::HashSet::new();
+        let mut offset = self.u32(4)? as usize;
+
+        while offset != 0 && visited.insert(offset) && ifds.len() < 64 {
+            let ifd = self.ifd(offset)?;
+            offset = ifd.next;
+            ifds.push(ifd);
+        }
+
+        let mut index = 0;
+
+        while index < ifds.len() && ifds.len() < 64 {
+            for offset in self.values(&ifds[index], TAG_SUB_IFDS)? {
+                if visited.insert(offset as usize) {
+                    let ifd = self.ifd(offset as usize)?;
+                    ifds.push(ifd);
+                }
+            }
+
+            index += 1;
+        }
+
+        Ok(ifds)
+    }
+
+    /// Integer values of a tag, empty if the tag is not present.
+    fn values(&self, ifd: &Ifd, tag: u16) -> Result<Vec<u32>, Box<dyn std::error::Error>> {
+        let entry = match ifd.get(tag) {
+            Some(entry) => entry,
+            None => return Ok(Vec::new()),
+        };
+
+        (0..entry.count)
+            .map(|index| match entry.kind {
+                1 | 7 => Ok(u32::from(self.bytes(entry.offset + index, 1)?[0])),
+                3 => Ok(u32::from(self.u16(entry.offset + index * 2)?)),
+                4 | 13 => self.u32(entry.offset + index * 4),
+                kind => Err(format!("Unexpected type {} of tag {:#x}", kind, tag).into()),
+            })
+            .collect()
+    }
+
+    /// First integer value of a tag.
+    fn value(&self, ifd: &Ifd, tag: u16) -> Result<Option<u32>, Box<dyn std::error::Error>> {
+        Ok(self.values(ifd, tag)?.first().copied())
+    }
+
+    /// First integer value of a tag that is required.
+    fn required(&self, ifd: &Ifd, tag: u16) -> Result<usize, Box<dyn std::error::Error>> {
+        Ok(self
+            .value(ifd, tag)?
+            .ok_or_else(|| format!("Missing tag {:#x}", tag))? as usize)
+    }
+
+    fn string(&self, ifd: &Ifd, tag: u16) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(match ifd.get(tag) {
+            Some(entry) => String::from_utf8_lossy(self.bytes(entry.offset, entry.count)?)
+                .trim_end_matches('\0')
+                .to_owned(),
+            None => String::new(),
+        })
+    }
+}
+
+/// Reads bits most significant first, in the way of dcraw's `getbithuff`, including its handling
+/// of running out of data.
+#[cfg(not(feature = "libraw"))]
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    buffer: u32,
+    bits: i32,
+    /// Whether a marker has been reached, after which only zeros are read.
+    marker: bool,
+    /// Whether `0xff` bytes are followed by a stuffed `0x00`, as in JPEG streams.
+    zero_after_ff: bool,
+}
+
+#[cfg(not(feature = "libraw"))]
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8], position: usize, zero_after_ff: bool) -> Self {
+        BitReader {
+            data,
+            position,
+            buffer: 0,
+            bits: 0,
+            marker: false,
+            zero_after_ff,
+        }
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        let byte = self.data.get(self.position).copied();
+        self.position += 1;
+        byte
+    }
+
+    /// Discard the buffered bits.
+    fn reset(&mut self) {
+        self.buffer = 0;
+        self.bits = 0;
+        self.marker = false;
+    }
+
+    fn read(&mut self, count: i32, huffman: Option<&Huffman>) -> u32 {
+        if count > 25 {
+            return 0;
+        }
+
+        if count < 0 {
+            self.reset();
+            return 0;
+        }
+
+        if count == 0 || self.bits < 0 {
+            return 0;
+        }
+
+        while !self.marker && self.bits < count {
+            let byte = match self.byte() {
+                Some(byte) => byte,
+                None => break,
+            };
+
+            if self.zero_after_ff && byte == 0xff && self.byte() != Some(0) {
+                self.marker = true;
+                break;
+            }
+
+            self.buffer = (self.buffer << 8) + u32::from(byte);
+            self.bits += 8;
+        }
+
+        let value = if self.bits == 0 {
+            0
+        } else {
+            self.buffer << (32 - self.bits) >> (32 - count)
+        };
+
+        match huffman {
+            Some(huffman) => {
+                let entry = huffman.table[value as usize];
+                self.bits -= i32::from(entry >> 8);
+                u32::from(entry & 0xff)
+            }
+            None => {
+                self.bits -= count;
+                value
+            }
+        }
+    }
+
+    /// Read a number of bits. Negative counts discard the buffered bits.
+    fn bits(&mut self, count: i32) -> u32 {
+        self.read(count, None)
+    }
+
+    /// Read the value of a Huffman code.
+    fn huffman(&mut self, huffman: &Huffman) -> u32 {
+        self.read(huffman.bits, Some(huffman))
+    }
+}
+
+/// Lookup table of a Huffman code, indexed by the next `bits` bits. Each entry holds the code
+/// length in the upper and the value in the lower byte.
+#[cfg(not(feature = "libraw"))]
+struct Huffman {
+    bits: i32,
+    table: Vec<u16>,
+}
+
+#[cfg(not(feature = "libraw"))]
+impl Huffman {
+    /// Build the lookup table in the way of dcraw's `make_decoder_ref`. Returns the number of
+    /// values used along with the table.
+    ///
+    /// # Arguments
+    /// * `counts` – Number of codes per length, from 1 to 16 bits.
+    /// * `values` – Values of the codes, ordered by length.
+    fn new(counts: &[u8], values: &[u8]) -> (Self, usize) {
+        let bits = (1..=16)
+            .rev()
+            .find(|&length| counts[length - 1] != 0)
+            .unwrap_or(0);
+
+        let size = 1 << bits;
+        let mut table = vec![0; size];
+        let mut position = 0;
+        let mut used = 0;
+
+        for length in 1..=bits {
+            for _ in 0..counts[length - 1] {
+                let value = u16::from(values.get(used).copied().unwrap_or(0));
+                used += 1;
+
+                for _ in 0..1 << (bits - length) {
+                    if position < size {
+                        table[position] = (length as u16) << 8 | value;
+                        position += 1;
+                    }
+                }
+            }
+        }
+
+        (
+            Huffman {
+                bits: bits as i32,
+                table,
+            },
+            used,
+        )
+    }
+}
+
+/// Decoder of lossless JPEG streams, following dcraw's `ljpeg_start` and `ljpeg_row`.
+#[cfg(not(feature = "libraw"))]
+struct LosslessJpeg<'a> {
+    reader: BitReader<'a>,
+    algorithm: u8,
+    bits: i32,
+    height: usize,
+    width: usize,
+    components: usize,
+    predictor: u8,
+    restart: usize,
+    tables: Vec<Huffman>,
+    /// Index into `tables` per component.
+    huffman: [Option<usize>; 20],
+    vertical_predictors: [i32; 6],
+    /// The current and the previous row.
+    rows: Vec<u16>,
+    dng_version: u32,
+}
+
+#[cfg(not(feature = "libraw"))]
+impl<'a> LosslessJpeg<'a> {
+    /// Parse the markers of a stream up to the start of the scan.
+    ///
+    /// # Arguments
+    /// * `data` – Contents of the file.
+    /// * `offset` – Offset of the stream.
+    /// * `dng_version` – Version of the DNG file, 0 for other files.
+    fn start(
+        data: &'a [u8],
+        offset: usize,
+        dng_version: u32,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let invalid = "Invalid lossless JPEG stream";
+
+        if data.get(offset + 1) != Some(&0xd8) {
+            Err(invalid)?;
+        }
+
+        let mut position = offset + 2;
+        let mut algorithm = 0;
+        let mut bits = 0;
+        let mut height = 0;
+        let mut width = 0;
+        let mut components = 0;
+        let mut predictor = 0;
+        let mut restart = usize::MAX;
+        let mut tables = Vec::new();
+        let mut huffman = [None; 20];
+        let mut scan = false;
+
+        for _ in 0..=1024 {
+            let header = data.get(position..position + 4).ok_or(invalid)?;
+            let tag = u16::from_be_bytes([header[0], header[1]]);
+            let length = (u16::from_be_bytes([header[2], header[3]]) as usize)
+                .checked_sub(2)
+                .ok_or(invalid)?;
+
+            if tag <= 0xff00 {
+                Err(invalid)?;
+            }
+
+            let segment = data
+                .get(position + 4..position + 4 + length)
+                .ok_or(invalid)?;
+            let byte = |index: usize| segment.get(index).copied().ok_or(invalid);
+
+            position += 4 + length;
+
+            match tag {
+                0xffc0 | 0xffc1 | 0xffc3 => {
+                    if tag == 0xffc3 && ((byte(7)? >> 4) * (byte(7)? & 15)).wrapping_sub(1) & 3 != 0
+                    {
+                        Err("sRAW and mRAW files are not supported")?;
+                    }
+
+                    algorithm = (tag & 0xff) as u8;
+                    bits = i32::from(byte(0)?);
+                    height = usize::from(u16::from_be_bytes([byte(1)?, byte(2)?]));
+                    width = usize::from(u16::from_be_bytes([byte(3)?, byte(4)?]));
+                    components = usize::from(byte(5)?);
+
+                    // dcraw skips a byte after frame headers of this length outside of DNG files.
+                    if length == 9 && dng_version == 0 {
+                        position += 1;
+                    }
+                }
+                0xffc4 => {
+                    let mut index = 0;
+
+                    while index < length {
+                        let table = usize::from(segment[index]);
+                        index += 1;
+
+                        if table & !0x13 != 0 || index + 16 > length {
+                            break;
+                        }
+
+                        let (decoder, used) =
+                            Huffman::new(&segment[index..index + 16], &segment[index + 16..]);
+
+                        index += 16 + used;
+                        huffman[table] = Some(tables.len());
+                        tables.push(decoder);
+                    }
+                }
+                0xffda => {
+                    let count = usize::from(byte(0)?);
+                    predictor = byte(1 + count * 2)?;
+                    bits -= i32::from(byte(3 + count * 2)? & 15);
+                }
+                0xffdd => {
+                    restart = usize::from(u16::from_be_bytes([byte(0)?, byte(1)?]));
+                }
+                _ => {}
+            }
+
+            if tag == 0xffda {
+                scan = true;
+                break;
+            }
+        }
+
+        if !scan
+            || bits <= 0
+            || bits > 16
+            || height == 0
+            || width == 0
+            || components == 0
+            || components > 6
+        {
+            Err(invalid)?;
+        }
+
+        if huffman[0].is_none() {
+            Err("Missing Huffman table")?;
+        }
+
+        for index in 1..huffman.len() {
+            if huffman[index].is_none() {
+                huffman[index] = huffman[index - 1];
+            }
+        }
+
+        Ok(LosslessJpeg {
+            reader: BitReader::new(data, position, true),
+            algorithm,
+            bits,
+            height,
+            width,
+            components,
+            predictor,
+            restart: if restart == 0 { usize::MAX } else { restart },
+            tables,
+            huffman,
+            vertical_predictors: [0; 6],
+            rows: vec![0; width * components * 2],
+            dng_version,
+        })
+    }
+
+    /// Decode the difference to the predicted value of a sample.
+    fn diff(&mut self, component: usize) -> Result<i32, Box<dyn std::error::Error>> {
+        let table = self.huffman[component].ok_or("Missing Huffman table")?;
+        let length = self.reader.huffman(&self.tables[table]) as i32;
+
+        if length == 16 && (self.dng_version == 0 || self.dng_version >= 0x0101_0000) {
+            return Ok(-32768);
+        }
+
+        if length == 0 {
+            return Ok(0);
+        }
+
+        if length > 16 {
+            Err("Invalid Huffman code")?;
+        }
+
+        let mut diff = self.reader.bits(length) as i32;
+
+        if diff & (1 << (length - 1)) == 0 {
+            diff -= (1 << length) - 1;
+        }
+
+        Ok(diff)
+    }
+
+    /// Decode the next row, with the samples of all components interleaved.
+    ///
+    /// # Arguments
+    /// * `row` – Index of the row, counting from 0.
+    fn row(&mut self, row: usize) -> Result<&[u16], Box<dyn std::error::Error>> {
+        let length = self.width * self.components;
+
+        if (row * self.width).checked_rem(self.restart) == Some(0) {
+            self.vertical_predictors = [1 << (self.bits - 1); 6];
+
+            if row > 0 {
+                // Skip to the restart marker.
+                self.reader.position = self.reader.position.saturating_sub(2);
+
+                let mut marker: u16 = 0;
+
+                while let Some(byte) = self.reader.byte() {
+                    marker = (marker << 8) | u16::from(byte);
+
+                    if marker >> 4 == 0xffd {
+                        break;
+                    }
+                }
+            }
+
+            self.reader.reset();
+        }
+
+        let current = (row & 1) * length;
+        let previous = ((row + 1) & 1) * length;
+
+        for col in 0..self.width {
+            for component in 0..self.components {
+                let index = col * self.components + component;
+                let diff = self.diff(component)?;
+
+                let mut prediction = if col > 0 {
+                    i32::from(self.rows[current + index - self.components])
+                } else {
+                    self.vertical_predictors[component] += diff;
+                    self.vertical_predictors[component] - diff
+                };
+
+                if row > 0 && col > 0 {
+                    let up = i32::from(self.rows[previous + index]);
+                    let up_left = i32::from(self.rows[previous + index - self.components]);
+
+                    prediction = match self.predictor {
+                        1 => prediction,
+                        2 => up,
+                        3 => up_left,
+                        4 => prediction + up - up_left,
+                        5 => prediction + ((up - up_left) >> 1),
+                        6 => up + ((prediction - up_left) >> 1),
+                        7 => (prediction + up) >> 1,
+                        _ => 0,
+                    };
+                }
+
+                self.rows[current + index] = (prediction + diff) as u16;
+            }
+        }
+
+        Ok(&self.rows[current..current + length])
+    }
+}
+
+/// Identity mapping of raw values, which linearization curves are applied to.
+#[cfg(not(feature = "libraw"))]
+fn identity_curve() -> Vec<u16> {
+    (0..=u16::MAX).collect()
+}
+
+/// Position of a sample of a CR2 stream in the sensor data. Canon encodes the sensor data as
+/// vertical slices of `width` columns, followed by a last slice of `last_width` columns.
+///
+/// # Arguments
+/// * `index` – Index of the sample in the stream.
+/// * `slices` – Number of slices, width of the slices and width of the last slice.
+/// * `height` – Height of the sensor data.
+#[cfg(not(feature = "libraw"))]
+fn cr2_slice_position(
+    index: usize,
+    slices: [usize; 3],
+    height: usize,
+) -> Result<(usize, usize), Box<dyn std::error::Error>> {
+    let [count, width, last_width] = slices;
+
+    if width == 0 || last_width == 0 {
+        Err("Invalid CR2 slices")?;
+    }
+
+    let slice = (index / (width * height)).min(count);
+    let index = index - slice * width * height;
+    let slice_width = if slice == count { last_width } else { width };
+
+    Ok((index / slice_width, index % slice_width + slice * width))
+}
+
+/// Read the sensor data of a CR2 file, as in LibRaw's `lossless_jpeg_load_raw`.
+#[cfg(not(feature = "libraw"))]
+fn read_cr2(tiff: &Tiff, ifds: &[Ifd]) -> Result<SensorData, Box<dyn std::error::Error>> {
+    let ifd = ifds.get(3).ok_or("Missing raw IFD")?;
+    let offset = tiff.required(ifd, TAG_STRIP_OFFSETS)?;
+    let slices = tiff.values(ifd, TAG_CR2_SLICE)?;
+
+    let slices = match slices[..] {
+        [count, width, last_width] if count > 0 => {
+            Some([count as usize, width as usize, last_width as usize])
+        }
+        _ => None,
+    };
+
+    let mut jpeg = LosslessJpeg::start(tiff.data, offset, 0)?;
+
+    let (mut width, mut height) = (jpeg.width, jpeg.height);
+
+    if jpeg.components % 2 == 0 {
+        width *= jpeg.components;
+
+        if width > 4 * height {
+            width /= 2;
+            height *= 2;
+        }
+    }
+
+    let mut sensor = SensorData::new(width, height)?;
+    let samples = jpeg.width * jpeg.components;
+    let (mut row, mut col) = (0, 0);
+
+    for jpeg_row in 0..jpeg.height {
+        let values = jpeg.row(jpeg_row)?.to_vec();
+
+        for (jpeg_col, &value) in values.iter().enumerate() {
+            if let Some(slices) = slices {
+                let (slice_row, slice_col) =
+                    cr2_slice_position(jpeg_row * samples + jpeg_col, slices, height)?;
+
+                row = slice_row as isize;
+                col = slice_col as isize;
+            }
+
+            // LibRaw shifts the sensor data of cameras with this width by two columns.
+            if width == 3984 {
+                col -= 2;
+
+                if col < 0 {
+                    row -= 1;
+                    col += width as isize;
+                }
+            }
+
+            if row >= 0 {
+                sensor.set(row as usize, col as usize, value);
+            }
+
+            col += 1;
+
+            if col >= width as isize {
+                col = 0;
+                row += 1;
+            }
+        }
+    }
+
+    Ok(sensor)
+}
+
+/// Find the Nikon maker note and the offset of the compression data within it.
+#[cfg(not(feature = "libraw"))]
+fn nikon_compression_data<'a>(
+    tiff: &Tiff<'a>,
+    ifd: &Ifd,
+) -> Result<(Tiff<'a>, usize), Box<dyn std::error::Error>> {
+    let exif = tiff.ifd(tiff.required(ifd, TAG_EXIF_IFD)?)?;
+    let maker_note = exif.get(TAG_MAKER_NOTE).ok_or("Missing maker note")?;
+
+    if tiff.bytes(maker_note.offset, 6)? != b"Nikon\0" {
+        Err("Unsupported maker note")?;
+    }
+
+    // The maker note embeds a TIFF structure of its own, with offsets relative to its header.
+    let maker_note = Tiff::new(
+        tiff.data
+            .get(maker_note.offset + 10..)
+            .ok_or("Unexpected end of file")?,
+    )?;
+
+    let ifd = maker_note.ifd(maker_note.u32(4)? as usize)?;
+
+    let offset = ifd
+        .get(TAG_NIKON_COMPRESSION_DATA)
+        .ok_or("Missing Nikon compression data")?
+        .offset;
+
+    Ok((maker_note, offset))
+}
+
+/// Read the sensor data of a compressed NEF file, as in LibRaw's `nikon_load_raw`.
+#[cfg(not(feature = "libraw"))]
+fn read_nef(tiff: &Tiff, ifds: &[Ifd]) -> Result<SensorData, Box<dyn std::error::Error>> {
+    let ifd = raw_ifd(tiff, ifds)?;
+    let width = tiff.required(ifd, TAG_IMAGE_WIDTH)?;
+    let height = tiff.required(ifd, TAG_IMAGE_LENGTH)?;
+    let bits = tiff.required(ifd, TAG_BITS_PER_SAMPLE)?;
+    let offset = tiff.required(ifd, TAG_STRIP_OFFSETS)?;
+    let bytes: u32 = tiff.values(ifd, TAG_STRIP_BYTE_COUNTS)?.iter().sum();
+
+    let compression = tiff.required(ifd, TAG_COMPRESSION)? as u32;
+
+    // LibRaw reads NEF files of these sizes as packed or uncompressed sensor data.
+    let uncompressed = [
+        width.div_ceil(10) * 16 * height,
+        width * height * 3 / 2,
+        width * height * 3,
+        width * height * 2,
+    ];
+
+    if compression != COMPRESSION_NIKON || uncompressed.contains(&(bytes as usize)) {
+        Err(format!("Unsupported NEF compression: {}", compression))?;
+    }
+
+    let (maker_note, meta) = nikon_compression_data(tiff, &ifds[0])?;
+    let version = maker_note.bytes(meta, 2)?;
+    let mut position = meta + 2;
+
+    if version[0] == 0x49 || version[1] == 0x58 {
+        position += 2110;
+    }
+
+    let mut tree = if version[0] == 0x46 { 2 } else { 0 };
+
+    if bits == 14 {
+        tree += 3;
+    }
+
+    let mut vertical_predictors = [[0u16; 2]; 2];
+
+    for (index, predictor) in vertical_predictors.iter_mut().flatten().enumerate() {
+        *predictor = maker_note.u16(position + index * 2)?;
+    }
+
+    position += 8;
+
+    let max = (1 << bits) & 0x7fff;
+    let size = usize::from(maker_note.u16(position)?);
+    let step = if size > 1 { max / (size - 1) } else { 0 };
+    let mut curve = identity_curve();
+    let mut split = 0;
+
+    position += 2;
+
+    if version == [0x44, 0x20] && step > 0 {
+        for index in 0..size {
+            if let Some(value) = curve.get_mut(index * step) {
+                *value = maker_note.u16(position + index * 2)?;
+            }
+        }
+
+        for index in 0..max {
+            let base = index - index % step;
+            let weight = index % step;
+
+            curve[index] = ((usize::from(curve[base]) * (step - weight)
+                + usize::from(curve[base + step]) * weight)
+                / step) as u16;
+        }
+
+        split = usize::from(maker_note.u16(meta + 562)?);
+    } else if version[0] != 0x46 && size <= 0x4001 {
+        for (index, value) in curve.iter_mut().take(size).enumerate() {
+            *value = maker_note.u16(position + index * 2)?;
+        }
+    }
+
+    let tree_huffman =
+        |tree: usize| Huffman::new(&NIKON_TREES[tree][..16], &NIKON_TREES[tree][16..]).0;
+
+    let mut huffman = tree_huffman(tree);
+    let mut reader = BitReader::new(tiff.data, offset, false);
+    let mut sensor = SensorData::new(width, height)?;
+
+    for row in 0..height {
+        if split > 0 && row == split {
+            huffman = tree_huffman(tree + 1);
+        }
+
+        let mut horizontal_predictors = [0u16; 2];
+
+        for col in 0..width {
+            let code = reader.huffman(&huffman) as i32;
+            let length = code & 15;
+            let shift = code >> 4;
+
+            let mut diff = (((reader.bits(length - shift) << 1) + 1) << shift >> 1) as i32;
+
+            // dcraw relies on the x86 behaviour of shifting by -1 for codes of length 0.
+            if diff & 1i32.wrapping_shl((length - 1) as u32) == 0 {
+                diff -= (1 << length) - i32::from(shift == 0);
+            }
+
+            if col < 2 {
+                let predictor = &mut vertical_predictors[row & 1][col];
+                *predictor = predictor.wrapping_add(diff as u16);
+                horizontal_predictors[col] = *predictor;
+            } else {
+                let predictor = &mut horizontal_predictors[col & 1];
+                *predictor = predictor.wrapping_add(diff as u16);
+            }
+
+            let index = (horizontal_predictors[col & 1] as i16).clamp(0, 0x3fff) as usize;
+
+            sensor.set(row, col, curve[index]);
+        }
+    }
+
+    Ok(sensor)
+}
+
+/// Decode a block of 16 pixels of a compressed ARW file: the maximum and minimum value with their
+/// positions, followed by 7-bit deltas to the minimum for the remaining pixels.
+///
+/// # Arguments
+/// * `block` – 16 bytes of the block, followed by at least one more byte.
+#[cfg(not(feature = "libraw"))]
+fn sony_block(block: &[u8]) -> [u16; 16] {
+    let byte = |index: usize| u32::from(block.get(index).copied().unwrap_or(0));
+    let header = byte(0) | byte(1) << 8 | byte(2) << 16 | byte(3) << 24;
+
+    let max = 0x7ff & header;
+    let min = 0x7ff & header >> 11;
+    let index_max = (0x0f & header >> 22) as usize;
+    let index_min = (0x0f & header >> 26) as usize;
+
+    let mut shift = 0;
+
+    while shift < 4 && 0x80 << shift <= max as i32 - min as i32 {
+        shift += 1;
+    }
+
+    let mut pixels = [0; 16];
+    let mut bit = 30;
+
+    for (index, pixel) in pixels.iter_mut().enumerate() {
+        *pixel = if index == index_max {
+            max
+        } else if index == index_min {
+            min
+        } else {
+            let delta = (byte(bit >> 3) | byte((bit >> 3) + 1) << 8) >> (bit & 7) & 0x7f;
+            bit += 7;
+            ((delta << shift) + min).min(0x7ff)
+        } as u16;
+    }
+
+    pixels
+}
+
+/// Read the sensor data of an ARW file, as in LibRaw's `sony_arw2_load_raw` and
+/// `unpacked_load_raw`.
+#[cfg(not(feature = "libraw"))]
+fn read_arw(tiff: &Tiff, ifds: &[Ifd]) -> Result<SensorData, Box<dyn std::error::Error>> {
+    let ifd = raw_ifd(tiff, ifds)?;
+    let width = tiff.required(ifd, TAG_IMAGE_WIDTH)?;
+    let height = tiff.required(ifd, TAG_IMAGE_LENGTH)?;
+    let offset = tiff.required(ifd, TAG_STRIP_OFFSETS)?;
+    let bytes = tiff.values(ifd, TAG_STRIP_BYTE_COUNTS)?.iter().sum::<u32>() as usize;
+    let compression = tiff.required(ifd, TAG_COMPRESSION)? as u32;
+
+    let mut sensor = SensorData::new(width, height)?;
+
+    if bytes == width * height * 2 && [COMPRESSION_NONE, COMPRESSION_SONY].contains(&compression) {
+        return read_unpacked(tiff, offset, sensor);
+    }
+
+    if bytes != width * height || compression != COMPRESSION_SONY {
+        Err(format!("Unsupported ARW compression: {}", compression))?;
+    }
+
+    let mut curve = identity_curve();
+
+    if let Some(ifd) = ifds
+        .iter()
+        .find(|ifd| ifd.get(TAG_SONY_TONE_CURVE).is_some())
+    {
+        let mut points = [0, 0, 0, 0, 0, 4095];
+
+        for (point, value) in points[1..5]
+            .iter_mut()
+            .zip(tiff.values(ifd, TAG_SONY_TONE_CURVE)?)
+        {
+            *point = (value >> 2 & 0xfff) as usize;
+        }
+
+        for segment in 0..5 {
+            for index in points[segment] + 1..=points[segment + 1] {
+                curve[index] = curve[index - 1].wrapping_add(1 << segment);
+            }
+        }
+    }
+
+    for row in 0..height {
+        let data = tiff.bytes(offset + row * width, width)?;
+        let mut col = 0;
+
+        for block in (0..).step_by(16) {
+            if col + 30 >= width {
+                break;
+            }
+
+            for pixel in sony_block(&data[block.min(data.len())..]).iter() {
+                sensor.set(row, col, curve[usize::from(*pixel) << 1] >> 2);
+                col += 2;
+            }
+
+            col -= if col & 1 == 1 { 1 } else { 31 };
+        }
+    }
+
+    Ok(sensor)
+}
+
+/// Read uncompressed 16-bit sensor data in the byte order of the file, as in LibRaw's
+/// `unpacked_load_raw`.
+#[cfg(not(feature = "libraw"))]
+fn read_unpacked(
+    tiff: &Tiff,
+    offset: usize,
+    mut sensor: SensorData,
+) -> Result<SensorData, Box<dyn std::error::Error>> {
+    for (index, pixel) in sensor.pixels.iter_mut().enumerate() {
+        *pixel = tiff.u16(offset + index * 2)?;
+    }
+
+    Ok(sensor)
+}
+
+/// Read the sensor data of a DNG file, as in LibRaw's `packed_dng_load_raw` and
+/// `lossless_dng_load_raw`.
+#[cfg(not(feature = "libraw"))]
+fn read_dng(
+    tiff: &Tiff,
+    ifds: &[Ifd],
+    dng_version: u32,
+) -> Result<SensorData, Box<dyn std::error::Error>> {
+    let ifd = raw_ifd(tiff, ifds)?;
+    let width = tiff.required(ifd, TAG_IMAGE_WIDTH)?;
+    let height = tiff.required(ifd, TAG_IMAGE_LENGTH)?;
+    let bits = tiff.required(ifd, TAG_BITS_PER_SAMPLE)?;
+    let samples = tiff.value(ifd, TAG_SAMPLES_PER_PIXEL)?.unwrap_or(1) as usize;
+    let compression = tiff.required(ifd, TAG_COMPRESSION)? as u32;
+
+    let mut curve = identity_curve();
+    let table = tiff.values(ifd, TAG_LINEARIZATION_TABLE)?;
+
+    if let Some(&last) = table.last() {
+        let length = table.len().min(curve.len());
+
+        for (index, value) in curve.iter_mut().enumerate() {
+            *value = if index < length {
+                table[index] as u16
+            } else {
+                last as u16
+            };
+        }
+    }
+
+    let (tile_width, tile_length, offsets) = match ifd.get(TAG_TILE_OFFSETS) {
+        Some(_) => (
+            tiff.required(ifd, TAG_TILE_WIDTH)?,
+            tiff.required(ifd, TAG_TILE_LENGTH)?,
+            tiff.values(ifd, TAG_TILE_OFFSETS)?,
+        ),
+        None => (
+            width,
+            tiff.value(ifd, TAG_ROWS_PER_STRIP)?
+                .map_or(height, |rows| rows as usize),
+            tiff.values(ifd, TAG_STRIP_OFFSETS)?,
+        ),
+    };
+
+    if tile_width == 0 || tile_length == 0 || samples == 0 {
+        Err("Invalid DNG tiles")?;
+    }
+
+    let mut sensor = SensorData::new(width, height)?;
+    let tiles_across = width.div_ceil(tile_width);
+
+    for (index, &offset) in offsets.iter().enumerate() {
+        let tile_row = index / tiles_across * tile_length;
+        let tile_col = index % tiles_across * tile_width;
+        let offset = offset as usize;
+
+        if tile_row >= height {
+            break;
+        }
+
+        match compression {
+            COMPRESSION_NONE => {
+                let mut reader = BitReader::new(tiff.data, offset, false);
+                let mut pixels = vec![0; tile_width * samples];
+
+                for row in 0..tile_length.min(height - tile_row) {
+                    if bits == 16 {
+                        for pixel in pixels.iter_mut() {
+                            *pixel = tiff.u16(reader.position)?;
+                            reader.position += 2;
+                        }
+                    } else {
+                        reader.reset();
+
+                        for pixel in pixels.iter_mut() {
+                            *pixel = reader.bits(bits as i32) as u16;
+                        }
+                    }
+
+                    for col in 0..tile_width {
+                        let value = curve[usize::from(pixels[col * samples])];
+                        sensor.set(tile_row + row, tile_col + col, value);
+                    }
+                }
+            }
+            COMPRESSION_LOSSLESS_JPEG => {
+                let mut jpeg = LosslessJpeg::start(tiff.data, offset, dng_version)?;
+
+                if jpeg.algorithm != 0xc3 {
+                    Err("Lossy DNG files are not supported")?;
+                }
+
+                let (mut row, mut col) = (0, 0);
+
+                for jpeg_row in 0..jpeg.height {
+                    let values = jpeg.row(jpeg_row)?;
+
+                    for value in values.iter().step_by(samples) {
+                        sensor.set(tile_row + row, tile_col + col, curve[usize::from(*value)]);
+
+                        col += 1;
+
+                        if col >= tile_width || col >= width {
+                            col = 0;
+                            row += 1;
+                        }
+                    }
+                }
+            }
+            compression => Err(format!("Unsupported DNG compression: {}", compression))?,
+        }
+    }
+
+    Ok(sensor)
+}
+
+/// Find the IFD holding the full-size sensor data: the largest one with a color filter array.
+fn raw_ifd<'a>(tiff: &Tiff, ifds: &'a [Ifd]) -> Result<&'a Ifd, Box<dyn std::error::Error>> {
+    let mut raw: Option<(usize, &Ifd)> = None;
+    let mut linear = false;
+
+    for ifd in ifds {
+        if tiff.value(ifd, TAG_NEW_SUBFILE_TYPE)?.unwrap_or(0) != 0 {
+            continue;
+        }
+
+        match tiff.value(ifd, TAG_PHOTOMETRIC_INTERPRETATION)? {
+            Some(PHOTOMETRIC_CFA) => {}
+            Some(PHOTOMETRIC_LINEAR_RAW) => {
+                linear = true;
+                continue;
+            }
+            _ => continue,
+        }
+
+        let size = tiff.required(ifd, TAG_IMAGE_WIDTH)? * tiff.required(ifd, TAG_IMAGE_LENGTH)?;
+
+        if raw.is_none_or(|(largest, _)| size > largest) {
+            raw = Some((size, ifd));
+        }
+    }
+
+    match raw {
+        Some((_, ifd)) => Ok(ifd),
+        None if linear => Err("Linear raw files are not supported".into()),
+        None => Err("Missing raw IFD".into()),
+    }
+}
+
+/// Raw formats the built-in reader reads.
+#[cfg(not(feature = "libraw"))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Cr2,
+    Nef,
+    Arw,
+    /// DNG file of the given version.
+    Dng(u32),
+}
+
+#[cfg(not(feature = "libraw"))]
+impl Format {
+    /// Detect the format of a raw file.
+    ///
+    /// # Arguments
+    /// * `tiff` – The raw file.
+    /// * `ifds` – Image file directories of the raw file.
+    fn detect(tiff: &Tiff, ifds: &[Ifd]) -> Result<Self, Box<dyn std::error::Error>> {
+        let ifd = ifds.first().ok_or("Missing IFD")?;
+
+        if let Some(entry) = ifd.get(TAG_DNG_VERSION) {
+            let version = tiff.bytes(entry.offset, 4)?;
+
+            return Ok(Format::Dng(u32::from_be_bytes([
+                version[0], version[1], version[2], version[3],
+            ])));
+        }
+
+        if tiff.data.get(8..10) == Some(b"CR") {
+            return Ok(Format::Cr2);
+        }
+
+        let make = tiff.string(ifd, TAG_MAKE)?;
+
+        if make.starts_with("NIKON") {
+            Ok(Format::Nef)
+        } else if make.starts_with("SONY") {
+            Ok(Format::Arw)
+        } else {
+            Err(format!("Unsupported raw file of make {:?}", make))?
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Format::Cr2 => "CR2",
+            Format::Nef => "NEF",
+            Format::Arw => "ARW",
+            Format::Dng(_) => "DNG",
+        }
+    }
+
+    /// Whether the sensor data of real files of the format has been compared to the one LibRaw
+    /// yields, see `tests/files/raw/hashes.txt`. The readers of the other formats are only used
+    /// with the `unverified-raw-readers` feature, which serves to verify them.
+    fn is_verified(self) -> bool {
+        self == Format::Cr2 || cfg!(feature = "unverified-raw-readers")
+    }
+
+    /// Read the sensor data of a raw file of the format.
+    ///
+    /// # Arguments
+    /// * `tiff` – The raw file.
+    /// * `ifds` – Image file directories of the raw file.
+    fn read(self, tiff: &Tiff, ifds: &[Ifd]) -> Result<SensorData, Box<dyn std::error::Error>> {
+        match self {
+            Format::Cr2 => read_cr2(tiff, ifds),
+            Format::Nef => read_nef(tiff, ifds),
+            Format::Arw => read_arw(tiff, ifds),
+            Format::Dng(version) => read_dng(tiff, ifds, version),
+        }
+    }
+}
+
+/// Read the sensor data of a raw file. The values match the ones LibRaw yields in
+/// `rawdata.raw_image`, so that hashes do not depend on whether `ciid` has been built with LibRaw.
+/// Supports CR2 files, as well as NEF, ARW and DNG files with the `unverified-raw-readers` feature,
+/// see `Format::is_verified`.
+///
+/// # Arguments
+/// * `data` – Contents of the raw file.
+#[cfg(not(feature = "libraw"))]
+pub fn read(data: &[u8]) -> Result<SensorData, Box<dyn std::error::Error>> {
+    let tiff = Tiff::new(data)?;
+    let ifds = tiff.ifds()?;
+    let format = Format::detect(&tiff, &ifds)?;
+
+    if !format.is_verified() {
+        Err(format!(
+            "{} files are not supported without LibRaw yet, since the built-in reader has not been verified against LibRaw for them",
+            format.name()
+        ))?;
+    }
+
+    format.read(&tiff, &ifds)
+}
+
+/// Area of the sensor data that holds the image: rows `top..bottom`, columns `left..right`.
+/// Sensors surround it with masked pixels, which measure the black level.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize)]
+pub struct Area {
+    pub top: usize,
+    pub left: usize,
+    pub bottom: usize,
+    pub right: usize,
+}
+
+impl Area {
+    pub fn width(&self) -> usize {
+        self.right - self.left
+    }
+
+    pub fn height(&self) -> usize {
+        self.bottom - self.top
+    }
+}
+
+/// Find the sensor borders recorded in the maker note of a CR2 file, if they describe sensor data
+/// of the given dimensions. LibRaw crops to the same borders.
+///
+/// # Arguments
+/// * `tiff` – The CR2 file.
+/// * `ifd` – First IFD of the file.
+/// * `width` – Width of the sensor data.
+/// * `height` – Height of the sensor data.
+fn canon_sensor_area(
+    tiff: &Tiff,
+    ifd: &Ifd,
+    width: usize,
+    height: usize,
+) -> Result<Option<[u32; 4]>, Box<dyn std::error::Error>> {
+    let exif = match tiff.value(ifd, TAG_EXIF_IFD)? {
+        Some(offset) => tiff.ifd(offset as usize)?,
+        None => return Ok(None),
+    };
+
+    // Canon maker notes are a plain IFD, with offsets relative to the start of the file.
+    let maker_note = match exif.get(TAG_MAKER_NOTE) {
+        Some(entry) => tiff.ifd(entry.offset)?,
+        None => return Ok(None),
+    };
+
+    Ok(match tiff.values(&maker_note, TAG_CANON_SENSOR_INFO)?[..] {
+        [_, sensor_width, sensor_height, _, _, left, top, right, bottom, ..]
+            if sensor_width as usize == width && sensor_height as usize == height =>
+        {
+            // The borders are inclusive.
+            Some([top, left, bottom + 1, right + 1])
+        }
+        _ => None,
+    })
+}
+
+/// Find the area of the sensor data that holds the image, if the metadata of the raw file records
+/// it: the `ActiveArea` of DNG files and the sensor borders in the maker note of CR2 files. Other
+/// files, including files that are not based on TIFF, are assumed to hold no masked pixels.
+///
+/// # Arguments
+/// * `data` – Contents of the raw file.
+/// * `sensor` – Sensor data of the raw file.
+pub fn active_area(
+    data: &[u8],
+    sensor: &SensorData,
+) -> Result<Option<Area>, Box<dyn std::error::Error>> {
+    let tiff = match Tiff::new(data) {
+        Ok(tiff) => tiff,
+        Err(_) => return Ok(None),
+    };
+
+    let ifds = tiff.ifds()?;
+    let ifd = ifds.first().ok_or("Missing IFD")?;
+
+    let area = if ifd.get(TAG_DNG_VERSION).is_some() {
+        match tiff.values(raw_ifd(&tiff, &ifds)?, TAG_ACTIVE_AREA)?[..] {
+            [top, left, bottom, right] => Some([top, left, bottom, right]),
+            _ => None,
+        }
+    } else if data.get(8..10) == Some(b"CR") {
+        canon_sensor_area(&tiff, ifd, sensor.width, sensor.height)?
+    } else {
+        None
+    };
+
+    match area {
+        None => Ok(None),
+        Some([top, left, bottom, right])
+            if top < bottom
+                && left < right
+                && bottom as usize <= sensor.height
+                && right as usize <= sensor.width =>
+        {
+            Ok(Some(Area {
+                top: top as usize,
+                left: left as usize,
+                bottom: bottom as usize,
+                right: right as usize,
+            }))
+        }
+        Some(area) => Err(format!(
+            "Active area {:?} exceeds the sensor data of {}x{} pixels",
+            area, sensor.width, sensor.height
+        ))?,
+    }
+}
+
+/// What a DNG file records about the raw file it has been converted from.
+#[derive(Debug, Default, PartialEq)]
+pub struct Provenance {
+    /// Software that wrote the DNG file, e.g. "Adobe DNG Converter 16.0".
+    pub software: Option<String>,
+    /// Name of the raw file the DNG file has been converted from.
+    pub original_raw_file_name: Option<String>,
+    /// Contents of that raw file, if the converter embedded it.
+    pub original_raw_file_data: Option<Vec<u8>>,
+}
+
+/// Decompress the data fork of `OriginalRawFileData`, which is always big-endian: its length,
+/// followed by the offsets of its blocks relative to the start of the tag, followed by the blocks,
+/// each compressed with zlib. The resource fork and the Finder info of Mac OS are ignored.
+///
+/// # Arguments
+/// * `tag` – Value of the `OriginalRawFileData` tag.
+fn original_raw_file_data(tag: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    use std::io::Read;
+
+    let u32_at = |offset: usize| -> Result<usize, Box<dyn std::error::Error>> {
+        match tag.get(offset..offset + 4) {
+            Some(bytes) => {
+                Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+            }
+            None => Err("Truncated original raw file data".into()),
+        }
+    };
+
+    let length = u32_at(0)?;
+    let mut data = Vec::new();
+
+    for block in 0..length.div_ceil(ORIGINAL_RAW_FILE_BLOCK_SIZE) {
+        let (start, end) = (u32_at(4 + block * 4)?, u32_at(8 + block * 4)?);
+        let compressed = tag
+            .get(start..end)
+            .ok_or("Truncated original raw file data")?;
+        let expected =
+            (length - block * ORIGINAL_RAW_FILE_BLOCK_SIZE).min(ORIGINAL_RAW_FILE_BLOCK_SIZE);
+
+        let read = flate2::read::ZlibDecoder::new(compressed)
+            .take(expected as u64)
+            .read_to_end(&mut data)
+            .map_err(|error| format!("Failed decompressing original raw file data: {}", error))?;
+
+        if read != expected {
+            Err("Truncated original raw file data")?;
+        }
+    }
+
+    Ok(data)
+}
+
+/// Read what a DNG file records about the raw file it has been converted from. `None` for files
+/// other than DNG.
+///
+/// # Arguments
+/// * `data` – Contents of the raw file.
+pub fn provenance(data: &[u8]) -> Result<Option<Provenance>, Box<dyn std::error::Error>> {
+    let tiff = match Tiff::new(data) {
+        Ok(tiff) => tiff,
+        Err(_) => return Ok(None),
+    };
+
+    let ifds = tiff.ifds()?;
+    let ifd = ifds.first().ok_or("Missing IFD")?;
+
+    if ifd.get(TAG_DNG_VERSION).is_none() {
+        return Ok(None);
+    }
+
+    let string = |tag| -> Result<Option<String>, Box<dyn std::error::Error>> {
+        Ok(Some(tiff.string(ifd, tag)?).filter(|value| !value.is_empty()))
+    };
+
+    Ok(Some(Provenance {
+        software: string(TAG_SOFTWARE)?,
+        original_raw_file_name: string(TAG_ORIGINAL_RAW_FILE_NAME)?,
+        original_raw_file_data: match ifd.get(TAG_ORIGINAL_RAW_FILE_DATA) {
+            Some(entry) => Some(original_raw_file_data(
+                tiff.bytes(entry.offset, entry.count)?,
+            )?),
+            None => None,
+        },
+    }))
+}
+
+/// Decode the largest JPEG preview embedded in a raw file.
+///
+/// # Arguments
+/// * `data` – Contents of the raw file.
+#[cfg(not(feature = "libraw"))]
+pub fn thumbnail(data: &[u8]) -> Result<image::DynamicImage, Box<dyn std::error::Error>> {
+    let tiff = Tiff::new(data)?;
+    let mut previews = Vec::new();
+
+    for ifd in tiff.ifds()? {
+        let strip_offsets = tiff.values(&ifd, TAG_STRIP_OFFSETS)?;
+        let strip_byte_counts = tiff.values(&ifd, TAG_STRIP_BYTE_COUNTS)?;
+
+        let (offset, length) = match (
+            tiff.value(&ifd, TAG_JPEG_INTERCHANGE_FORMAT)?,
+            tiff.value(&ifd, TAG_JPEG_INTERCHANGE_FORMAT_LENGTH)?,
+            &strip_offsets[..],
+            &strip_byte_counts[..],
+        ) {
+            (Some(offset), Some(length), _, _) => (offset, length),
+            (_, _, &[offset], &[length]) => (offset, length),
+            _ => continue,
+        };
+
+        if let Ok(preview) = tiff.bytes(offset as usize, length as usize) {
+            if preview.starts_with(&[0xff, 0xd8]) {
+                previews.push(preview);
+            }
+        }
+    }
+
+    previews.sort_by_key(|preview| std::cmp::Reverse(preview.len()));
+
+    previews
+        .into_iter()
+        .find_map(|preview| image::load_from_memory(preview).ok())
+        .ok_or_else(|| "Missing JPEG thumbnail".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Placeholder for the offset of the image data in `build_tiff`.
+    const DATA: u32 = u32::MAX;
+
+    /// Build a TIFF file with a single IFD, followed by the image data.
+    fn build_tiff(little_endian: bool, entries: &[(u16, u16, Vec<u32>)], image: &[u8]) -> Vec<u8> {
+        let u16_bytes = |value: u16| {
+            if little_endian {
+                value.to_le_bytes()
+            } else {
+                value.to_be_bytes()
+            }
+        };
+        let u32_bytes = |value: u32| {
+            if little_endian {
+                value.to_le_bytes()
+            } else {
+                value.to_be_bytes()
+            }
+        };
+
+        let encode = |kind: u16, values: &[u32]| -> Vec<u8> {
+            values
+                .iter()
+                .flat_map(|&value| match kind {
+                    1 | 2 | 7 => vec![value as u8],
+                    3 => u16_bytes(value as u16).to_vec(),
+                    _ => u32_bytes(value).to_vec(),
+                })
+                .collect()
+        };
+
+        let ifd_end = 8 + 2 + entries.len() * 12 + 4;
+        let extra_length: usize = entries
+            .iter()
+            .map(|(_, kind, values)| encode(*kind, values).len())
+            .filter(|&length| length > 4)
+            .sum();
+        let data_offset = (ifd_end + extra_length) as u32;
+
+        let mut header = if little_endian {
+            b"II*\0".to_vec()
+        } else {
+            b"MM\0*".to_vec()
+        };
+        header.extend(&u32_bytes(8));
+        header.extend(&u16_bytes(entries.len() as u16));
+
+        let mut extra = Vec::new();
+
+        for (tag, kind, values) in entries {
+            let values: Vec<u32> = values
+                .iter()
+                .map(|&value| if value == DATA { data_offset } else { value })
+                .collect();
+            let mut value = encode(*kind, &values);
+
+            header.extend(&u16_bytes(*tag));
+            header.extend(&u16_bytes(*kind));
+            header.extend(&u32_bytes(values.len() as u32));
+
+            if value.len() > 4 {
+                header.extend(&u32_bytes((ifd_end + extra.len()) as u32));
+                extra.extend(value);
+            } else {
+                value.resize(4, 0);
+                header.extend(value);
+            }
+        }
+
+        header.extend(&u32_bytes(0));
+        header.extend(extra);
+        header.extend(image);
+        header
+    }
+
+    /// Encode samples as lossless JPEG stream with predictor 1 and a single Huffman table, in
+    /// which every difference category has a code of 5 bits.
+    #[cfg(not(feature = "libraw"))]
+    fn encode_lossless_jpeg(width: u16, height: u16, components: u8, samples: &[u16]) -> Vec<u8> {
+        let bits = 12;
+        let components_usize = usize::from(components);
+
+        let mut data = vec![0xff, 0xd8, 0xff, 0xc3];
+        data.extend(&(8 + 3 * u16::from(components)).to_be_bytes());
+        data.push(bits);
+        data.extend(&height.to_be_bytes());
+        data.extend(&width.to_be_bytes());
+        data.push(components);
+
+        for component in 0..components {
+            data.extend(&[component + 1, 0x11, 0]);
+        }
+
+        data.extend(&[0xff, 0xc4, 0, 2 + 1 + 16 + 17, 0]);
+        data.extend(&[0, 0, 0, 0, 17, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        data.extend(0..=16);
+
+        data.extend(&[0xff, 0xda]);
+        data.extend(&(6 + 2 * u16::from(components)).to_be_bytes());
+        data.push(components);
+
+        for component in 0..components {
+            data.extend(&[component + 1, 0]);
+        }
+
+        data.extend(&[1, 0, 0]);
+
+        let mut bit_buffer: Vec<bool> = Vec::new();
+        let mut push = |value: u32, count: u32| {
+            for bit in (0..count).rev() {
+                bit_buffer.push(value >> bit & 1 == 1);
+            }
+        };
+
+        let row_length = usize::from(width) * components_usize;
+
+        for (index, &sample) in samples.iter().enumerate() {
+            let (row, position) = (index / row_length, index % row_length);
+
+            let prediction = if position >= components_usize {
+                samples[index - components_usize]
+            } else if row > 0 {
+                samples[index - row_length]
+            } else {
+                1 << (bits - 1)
+            };
+
+            let diff = i32::from(sample) - i32::from(prediction);
+            let category = 32 - diff.unsigned_abs().leading_zeros();
+
+            push(category, 5);
+
+            if category > 0 {
+                let value = if diff < 0 {
+                    diff + (1 << category) - 1
+                } else {
+                    diff
+                };
+
+                push(value as u32, category);
+            }
+        }
+
+        let padding = (8 - bit_buffer.len() % 8) % 8;
+        bit_buffer.extend(std::iter::repeat_n(true, padding));
+
+        for byte in bit_buffer.chunks(8) {
+            let byte = byte.iter().fold(0u8, |byte, &bit| byte << 1 | bit as u8);
+
+            data.push(byte);
+
+            if byte == 0xff {
+                data.push(0);
+            }
+        }
+
+        data.extend(&[0xff, 0xd9]);
+        data
+    }
+
+    #[test]
+    #[cfg(not(feature = "libraw"))]
+    fn test_nikon_trees() {
+        for tree in NIKON_TREES.iter() {
+            let (huffman, used) = Huffman::new(&tree[..16], &tree[16..]);
+
+            assert!(used <= 16);
+            assert_eq!(huffman.table.len(), 1 << huffman.bits);
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "libraw"))]
+    fn test_huffman() {
+        let (huffman, _) = Huffman::new(&NIKON_TREES[2][..16], &NIKON_TREES[2][16..]);
+        let mut reader = BitReader::new(&[0b0001_0000, 0], 0, false);
+
+        // The only code of length 2 is `00`, followed by `010` as first code of length 3.
+        assert_eq!(reader.huffman(&huffman), 5);
+        assert_eq!(reader.huffman(&huffman), 4);
+    }
+
+    #[test]
+    #[cfg(not(feature = "libraw"))]
+    fn test_bit_reader() {
+        let mut reader = BitReader::new(&[0xab, 0xcd, 0xef], 0, false);
+
+        assert_eq!(reader.bits(12), 0xabc);
+        assert_eq!(reader.bits(12), 0xdef);
+        assert_eq!(reader.bits(8), 0);
+    }
+
+    #[test]
+    #[cfg(not(feature = "libraw"))]
+    fn test_bit_reader_zero_after_ff() {
+        let mut reader = BitReader::new(&[0xff, 0x00, 0x12, 0xff, 0xd9, 0x34], 0, true);
+
+        assert_eq!(reader.bits(8), 0xff);
+        assert_eq!(reader.bits(8), 0x12);
+        assert_eq!(reader.bits(8), 0);
+    }
+
+    #[test]
+    #[cfg(not(feature = "libraw"))]
+    fn test_lossless_jpeg() {
+        let samples = [
+            2048, 1000, 2050, 1010, 2047, 990, 4095, 0, //
+            2100, 1100, 2000, 900, 0, 4095, 2048, 2048,
+        ];
+        let data = encode_lossless_jpeg(4, 2, 2, &samples);
+        let mut jpeg = LosslessJpeg::start(&data, 0, 0).unwrap();
+
+        assert_eq!((jpeg.width, jpeg.height, jpeg.components), (4, 2, 2));
+        assert_eq!(jpeg.row(0).unwrap(), &samples[..8]);
+        assert_eq!(jpeg.row(1).unwrap(), &samples[8..]);
+    }
+
+    #[test]
+    #[cfg(not(feature = "libraw"))]
+    fn test_lossless_jpeg_invalid() {
+        assert!(LosslessJpeg::start(&[0xff, 0xd8, 0xff, 0xd9], 0, 0).is_err());
+        assert!(LosslessJpeg::start(&[0, 0], 0, 0).is_err());
+    }
+
+    #[test]
+    #[cfg(not(feature = "libraw"))]
+    fn test_cr2_slice_position() {
+        // Two slices of 2 columns and a last slice of 3 columns, 2 rows high.
+        let position = |index| cr2_slice_position(index, [2, 2, 3], 2).unwrap();
+
+        assert_eq!(position(0), (0, 0));
+        assert_eq!(position(1), (0, 1));
+        assert_eq!(position(2), (1, 0));
+        assert_eq!(position(4), (0, 2));
+        assert_eq!(position(8), (0, 4));
+        assert_eq!(position(10), (0, 6));
+        assert_eq!(position(11), (1, 4));
+    }
+
+    #[test]
+    #[cfg(not(feature = "libraw"))]
+    fn test_sony_block() {
+        // Maximum 0x7ff at position 1, minimum 0x100 at position 0, all deltas 0x7f.
+        let header: u32 = 0x7ff | 0x100 << 11 | 1 << 22;
+        let mut block = header.to_le_bytes().to_vec();
+        block.extend(&[0xff; 13]);
+
+        let pixels = sony_block(&block);
+
+        assert_eq!(pixels[0], 0x100);
+        assert_eq!(pixels[1], 0x7ff);
+        assert_eq!(pixels[2], 0x7ff);
+        assert_eq!(pixels[15], 0x7ff);
+    }
+
+    /// Read the sensor data of a raw file, regardless of whether the reader of its format has been
+    /// verified.
+    #[cfg(not(feature = "libraw"))]
+    fn read_unverified(data: &[u8]) -> Result<SensorData, Box<dyn std::error::Error>> {
+        let tiff = Tiff::new(data)?;
+        let ifds = tiff.ifds()?;
+
+        Format::detect(&tiff, &ifds)?.read(&tiff, &ifds)
+    }
+
+    #[cfg(not(feature = "libraw"))]
+    fn uncompressed_dng() -> Vec<u8> {
+        build_tiff(
+            true,
+            &[
+                (TAG_NEW_SUBFILE_TYPE, 4, vec![0]),
+                (TAG_IMAGE_WIDTH, 4, vec![2]),
+                (TAG_IMAGE_LENGTH, 4, vec![2]),
+                (TAG_BITS_PER_SAMPLE, 3, vec![16]),
+                (TAG_COMPRESSION, 3, vec![1]),
+                (TAG_PHOTOMETRIC_INTERPRETATION, 3, vec![PHOTOMETRIC_CFA]),
+                (TAG_STRIP_OFFSETS, 4, vec![DATA]),
+                (TAG_DNG_VERSION, 1, vec![1, 4, 0, 0]),
+            ],
+            &[1, 0, 2, 0, 3, 0, 0, 1],
+        )
+    }
+
+    #[test]
+    #[cfg(not(feature = "libraw"))]
+    fn test_read_dng_uncompressed() {
+        let sensor = read_unverified(&uncompressed_dng()).unwrap();
+
+        assert_eq!((sensor.width, sensor.height), (2, 2));
+        assert_eq!(sensor.pixels, vec![1, 2, 3, 256]);
+    }
+
+    #[test]
+    #[cfg(not(feature = "libraw"))]
+    fn test_read_dng_packed_linearized() {
+        let data = build_tiff(
+            false,
+            &[
+                (TAG_IMAGE_WIDTH, 4, vec![2]),
+                (TAG_IMAGE_LENGTH, 4, vec![1]),
+                (TAG_BITS_PER_SAMPLE, 3, vec![12]),
+                (TAG_COMPRESSION, 3, vec![1]),
+                (TAG_PHOTOMETRIC_INTERPRETATION, 3, vec![PHOTOMETRIC_CFA]),
+                (TAG_STRIP_OFFSETS, 4, vec![DATA]),
+                (TAG_LINEARIZATION_TABLE, 3, vec![10, 20, 30]),
+                (TAG_DNG_VERSION, 1, vec![1, 4, 0, 0]),
+            ],
+            &[0x00, 0x00, 0x05],
+        );
+
+        assert_eq!(read_unverified(&data).unwrap().pixels, vec![10, 30]);
+    }
+
+    #[test]
+    #[cfg(not(feature = "libraw"))]
+    fn test_read_dng_lossless_jpeg() {
+        let samples = [100, 200, 300, 400, 500, 600, 700, 800];
+        let data = build_tiff(
+            true,
+            &[
+                (TAG_IMAGE_WIDTH, 4, vec![4]),
+                (TAG_IMAGE_LENGTH, 4, vec![2]),
+                (TAG_BITS_PER_SAMPLE, 3, vec![12]),
+                (TAG_COMPRESSION, 3, vec![7]),
+                (TAG_PHOTOMETRIC_INTERPRETATION, 3, vec![PHOTOMETRIC_CFA]),
+                (TAG_STRIP_OFFSETS, 4, vec![DATA]),
+                (TAG_DNG_VERSION, 1, vec![1, 4, 0, 0]),
+            ],
+            &encode_lossless_jpeg(2, 2, 2, &samples),
+        );
+
+        assert_eq!(read_unverified(&data).unwrap().pixels, samples.to_vec());
+    }
+
+    #[test]
+    #[cfg(not(feature = "libraw"))]
+    fn test_read_linear_dng() {
+        let data = build_tiff(
+            true,
+            &[
+                (TAG_IMAGE_WIDTH, 4, vec![2]),
+                (TAG_IMAGE_LENGTH, 4, vec![2]),
+                (
+                    TAG_PHOTOMETRIC_INTERPRETATION,
+                    3,
+                    vec![PHOTOMETRIC_LINEAR_RAW],
+                ),
+                (TAG_DNG_VERSION, 1, vec![1, 4, 0, 0]),
+            ],
+            &[],
+        );
+
+        assert_eq!(
+            read_unverified(&data).err().unwrap().to_string(),
+            "Linear raw files are not supported"
+        );
+    }
+
+    #[test]
+    fn test_active_area_dng() {
+        let data = build_tiff(
+            true,
+            &[
+                (TAG_NEW_SUBFILE_TYPE, 4, vec![0]),
+                (TAG_IMAGE_WIDTH, 4, vec![4]),
+                (TAG_IMAGE_LENGTH, 4, vec![3]),
+                (TAG_PHOTOMETRIC_INTERPRETATION, 3, vec![PHOTOMETRIC_CFA]),
+                (TAG_DNG_VERSION, 1, vec![1, 4, 0, 0]),
+                (TAG_ACTIVE_AREA, 4, vec![1, 1, 3, 3]),
+            ],
+            &[],
+        );
+        let sensor = |width, height| SensorData {
+            width,
+            height,
+            pixels: vec![0; width * height],
+        };
+
+        assert_eq!(
+            active_area(&data, &sensor(4, 3)).unwrap(),
+            Some(Area {
+                top: 1,
+                left: 1,
+                bottom: 3,
+                right: 3,
+            })
+        );
+        assert!(active_area(&data, &sensor(2, 2)).is_err());
+        assert_eq!(active_area(b"not a raw file", &sensor(4, 3)).unwrap(), None);
+    }
+
+    #[test]
+    fn test_provenance() {
+        use std::io::Write;
+
+        let original = b"original raw file".to_vec();
+
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&original).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        // Length, offsets of the start and end of the single block, compressed block.
+        let mut fork = (original.len() as u32).to_be_bytes().to_vec();
+        fork.extend(&12u32.to_be_bytes());
+        fork.extend(&(12 + compressed.len() as u32).to_be_bytes());
+        fork.extend(compressed);
+
+        let data = build_tiff(
+            true,
+            &[
+                (
+                    TAG_SOFTWARE,
+                    2,
+                    b"Converter 1.0\0".iter().map(|&byte| byte.into()).collect(),
+                ),
+                (TAG_DNG_VERSION, 1, vec![1, 4, 0, 0]),
+                (
+                    TAG_ORIGINAL_RAW_FILE_NAME,
+                    1,
+                    b"IMG_0001.CR2\0".iter().map(|&byte| byte.into()).collect(),
+                ),
+                (
+                    TAG_ORIGINAL_RAW_FILE_DATA,
+                    7,
+                    fork.iter().map(|&byte| byte.into()).collect(),
+                ),
+            ],
+            &[],
+        );
+
+        assert_eq!(
+            provenance(&data).unwrap(),
+            Some(Provenance {
+                software: Some("Converter 1.0".to_owned()),
+                original_raw_file_name: Some("IMG_0001.CR2".to_owned()),
+                original_raw_file_data: Some(original),
+            })
+        );
+    }
+
+    #[test]
+    fn test_provenance_not_dng() {
+        assert_eq!(provenance(b"not a raw file").unwrap(), None);
+        assert_eq!(
+            provenance(&build_tiff(true, &[(TAG_SOFTWARE, 2, vec![0x41, 0])], &[])).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "libraw"))]
+    fn test_read_unsupported() {
+        assert!(read(b"not a raw file").is_err());
+        assert!(read(&build_tiff(true, &[(TAG_MAKE, 2, vec![0x41, 0])], &[])).is_err());
+    }
+
+    #[test]
+    #[cfg(all(not(feature = "libraw"), not(feature = "unverified-raw-readers")))]
+    fn test_read_unverified_format() {
+        assert_eq!(
+            read(&uncompressed_dng()).err().map(|error| error.to_string()),
+            Some("DNG files are not supported without LibRaw yet, since the built-in reader has not been verified against LibRaw for them".to_owned())
+        );
+    }
+}
@@ -0,0 +1,236 @@
+use crate::raw;
+use clap::Arg;
+use sha2::Digest;
+
+/// How raw files are hashed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HashMode {
+    /// The sensor data as unpacked by LibRaw, see `raw::SensorData`.
+    Sensor,
+    /// The sensor data in the representation of `normalize`, so that raw files and their DNG
+    /// conversions yield the same hash.
+    Normalized,
+}
+
+/// All raw hash modes.
+const HASH_MODES: [HashMode; 2] = [HashMode::Sensor, HashMode::Normalized];
+
+impl HashMode {
+    /// Parse the value of `--raw-hash`.
+    ///
+    /// # Arguments
+    /// * `mode` – Either `sensor` or `normalized`.
+    pub fn parse(mode: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        match mode {
+            "sensor" => Ok(HashMode::Sensor),
+            "normalized" => Ok(HashMode::Normalized),
+            mode => Err(format!("Unknown raw hash mode: {}", mode))?,
+        }
+    }
+
+    /// The other modes, which are tried when verifying names derived with any mode.
+    pub fn others(self) -> impl Iterator<Item = Self> {
+        HASH_MODES.iter().copied().filter(move |mode| *mode != self)
+    }
+}
+
+/// Command line argument selecting the raw hash mode.
+pub fn arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("raw hash")
+        .takes_value(true)
+        .long("--raw-hash")
+        .possible_values(&["sensor", "normalized"])
+        .default_value("sensor")
+        .help("How raw files are hashed. \"sensor\" hashes the sensor data as stored, \"normalized\" the sensor data cropped to its active area and stripped of unused low bits, or the original raw file embedded in a DNG file, so that raw files and their DNG conversions yield the same hash. Verification accepts hashes of any mode")
+}
+
+/// Sensor data in the representation hashed in the `normalized` mode.
+#[derive(Debug, PartialEq)]
+pub struct Normalized {
+    /// Width of the sensor data before cropping.
+    pub sensor_width: usize,
+    /// Height of the sensor data before cropping.
+    pub sensor_height: usize,
+    /// Area the sensor data has been cropped to.
+    pub area: raw::Area,
+    /// Number of low bits that were zero in all values and have been dropped.
+    pub shift: u32,
+    /// Values of the cropped sensor data, row by row.
+    pub pixels: Vec<u16>,
+}
+
+impl Normalized {
+    /// Feed the normalized sensor data into a hasher: width and height as 32-bit big-endian
+    /// integers, followed by the values as 16-bit big-endian integers, row by row.
+    ///
+    /// # Arguments
+    /// * `hasher` – Hasher the normalized sensor data is fed into.
+    pub fn hash(&self, hasher: &mut sha2::Sha256) {
+        hasher.input((self.area.width() as u32).to_be_bytes());
+        hasher.input((self.area.height() as u32).to_be_bytes());
+
+        for row in self.pixels.chunks(self.area.width()) {
+            let bytes: Vec<u8> = row.iter().flat_map(|value| value.to_be_bytes()).collect();
+
+            hasher.input(bytes);
+        }
+    }
+}
+
+/// Normalize the sensor data of a raw file, removing what converters like the Adobe DNG Converter
+/// change while keeping the image intact:
+///
+/// - the masked pixels around the active area are cropped, since converters may keep or drop them
+/// - low bits that are zero in all values are dropped, since converters may scale values up to a
+///   higher bit depth
+///
+/// The compression of the sensor data does not matter, since the decoded values are hashed. Values
+/// that converters alter in any other way, e.g. by subtracting the black level or demosaicing,
+/// yield a different hash. `ciid compare` reports why.
+///
+/// # Arguments
+/// * `sensor` – Sensor data of the raw file.
+/// * `area` – Active area of the sensor data, see `raw::active_area`. All of it if `None`.
+pub fn normalize(
+    sensor: &raw::SensorData,
+    area: Option<raw::Area>,
+) -> Result<Normalized, Box<dyn std::error::Error>> {
+    let area = area.unwrap_or(raw::Area {
+        top: 0,
+        left: 0,
+        bottom: sensor.height,
+        right: sensor.width,
+    });
+
+    if area.top >= area.bottom
+        || area.left >= area.right
+        || area.bottom > sensor.height
+        || area.right > sensor.width
+    {
+        Err(format!(
+            "Active area {:?} exceeds the sensor data of {}x{} pixels",
+            area, sensor.width, sensor.height
+        ))?;
+    }
+
+    let mut pixels = Vec::with_capacity(area.width() * area.height());
+
+    for row in sensor
+        .pixels
+        .chunks(sensor.width)
+        .take(area.bottom)
+        .skip(area.top)
+    {
+        pixels.extend_from_slice(&row[area.left..area.right]);
+    }
+
+    let shift = pixels
+        .iter()
+        .filter(|&&value| value != 0)
+        .map(|value| value.trailing_zeros())
+        .min()
+        .unwrap_or(0);
+
+    for value in pixels.iter_mut() {
+        *value >>= shift;
+    }
+
+    Ok(Normalized {
+        sensor_width: sensor.width,
+        sensor_height: sensor.height,
+        area,
+        shift,
+        pixels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sensor(width: usize, height: usize, pixels: &[u16]) -> raw::SensorData {
+        raw::SensorData {
+            width,
+            height,
+            pixels: pixels.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_normalize_crop() -> Result<(), Box<dyn std::error::Error>> {
+        let sensor = sensor(4, 3, &[1, 1, 1, 1, 1, 5, 7, 1, 1, 9, 3, 1]);
+        let area = raw::Area {
+            top: 1,
+            left: 1,
+            bottom: 3,
+            right: 3,
+        };
+
+        let normalized = normalize(&sensor, Some(area))?;
+
+        assert_eq!(normalized.area, area);
+        assert_eq!(normalized.pixels, vec![5, 7, 9, 3]);
+        assert_eq!(normalized.shift, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_normalize_bit_depth() -> Result<(), Box<dyn std::error::Error>> {
+        let original = normalize(&sensor(2, 2, &[0, 3, 5, 4095]), None)?;
+        let scaled = normalize(&sensor(2, 2, &[0, 3 << 4, 5 << 4, 4095 << 4]), None)?;
+
+        assert_eq!(original.shift, 0);
+        assert_eq!(scaled.shift, 4);
+        assert_eq!(scaled.pixels, original.pixels);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_normalize_invalid_area() {
+        let area = raw::Area {
+            top: 0,
+            left: 0,
+            bottom: 2,
+            right: 3,
+        };
+
+        assert!(normalize(&sensor(2, 2, &[1, 2, 3, 4]), Some(area)).is_err());
+    }
+
+    #[test]
+    fn test_normalized_hash() -> Result<(), Box<dyn std::error::Error>> {
+        let hash = |normalized: &Normalized| {
+            let mut hasher = sha2::Sha256::new();
+            normalized.hash(&mut hasher);
+            hasher.result().to_vec()
+        };
+
+        let uncropped = normalize(&sensor(3, 1, &[2, 4, 6]), None)?;
+        let cropped = normalize(
+            &sensor(4, 2, &[0, 0, 0, 0, 0, 1, 2, 3]),
+            Some(raw::Area {
+                top: 1,
+                left: 1,
+                bottom: 2,
+                right: 4,
+            }),
+        )?;
+
+        assert_eq!(hash(&uncropped), hash(&cropped));
+        assert_ne!(
+            hash(&uncropped),
+            hash(&normalize(&sensor(1, 3, &[2, 4, 6]), None)?)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash_mode_parse() {
+        assert_eq!(HashMode::parse("sensor").unwrap(), HashMode::Sensor);
+        assert_eq!(HashMode::parse("normalized").unwrap(), HashMode::Normalized);
+        assert!(HashMode::parse("cropped").is_err());
+    }
+}
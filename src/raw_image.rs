@@ -0,0 +1,425 @@
+use crate::{libraw, raw};
+
+/// Error reported by LibRaw.
+#[derive(Debug, PartialEq)]
+pub struct Error {
+    /// What has been attempted, e.g. "opening file".
+    action: &'static str,
+    /// Error code returned by LibRaw, see `LibRaw_errors`.
+    pub code: i32,
+    /// Description of the error code by `libraw_strerror`.
+    pub message: String,
+}
+
+impl Error {
+    /// Turn an error code returned by LibRaw into a result.
+    ///
+    /// # Arguments
+    /// * `action` – What has been attempted, e.g. "opening file".
+    /// * `code` – Error code returned by LibRaw.
+    fn check(action: &'static str, code: i32) -> Result<(), Self> {
+        if code == libraw::LibRaw_errors_LIBRAW_SUCCESS {
+            return Ok(());
+        }
+
+        // `libraw_strerror` returns a pointer to a static string for every code.
+        let message = unsafe { std::ffi::CStr::from_ptr(libraw::libraw_strerror(code)) }
+            .to_string_lossy()
+            .into_owned();
+
+        Err(Error {
+            action,
+            code,
+            message,
+        })
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(formatter, "Failed {}: {}", self.action, self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Layout of the unpacked sensor data. LibRaw fills exactly one of the buffers in
+/// `libraw_rawdata_t`, depending on the decoder of the file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Layout {
+    /// One 16-bit sample per pixel in `raw_image`, e.g. Bayer or X-Trans sensor data.
+    Raw,
+    /// Three 16-bit samples per pixel in `color3_image`, e.g. linear DNGs.
+    Color3,
+    /// Four 16-bit samples per pixel in `color4_image`, e.g. sRAW and mRAW.
+    Color4,
+    /// One 32-bit float sample per pixel in `float_image`, e.g. floating point DNGs.
+    Float,
+    /// Three 32-bit float samples per pixel in `float3_image`, e.g. linear HDR DNGs.
+    Float3,
+    /// Four 32-bit float samples per pixel in `float4_image`.
+    Float4,
+}
+
+impl Layout {
+    /// Number of bytes of a single pixel.
+    fn pixel_size(self) -> usize {
+        match self {
+            Layout::Raw => 2,
+            Layout::Color3 => 3 * 2,
+            Layout::Color4 => 4 * 2,
+            Layout::Float => 4,
+            Layout::Float3 => 3 * 4,
+            Layout::Float4 => 4 * 4,
+        }
+    }
+}
+
+/// Locate the buffer LibRaw unpacked the sensor data into, returning its rows without the padding
+/// LibRaw may append to each row.
+///
+/// # Arguments
+/// * `rawdata` – Raw data of an unpacked file.
+fn raw_rows(rawdata: &libraw::libraw_rawdata_t) -> Result<Vec<&[u8]>, Box<dyn std::error::Error>> {
+    let (layout, buffer) = [
+        (Layout::Raw, rawdata.raw_image as *const u8),
+        (Layout::Color3, rawdata.color3_image as *const u8),
+        (Layout::Color4, rawdata.color4_image as *const u8),
+        (Layout::Float, rawdata.float_image as *const u8),
+        (Layout::Float3, rawdata.float3_image as *const u8),
+        (Layout::Float4, rawdata.float4_image as *const u8),
+    ]
+    .iter()
+    .copied()
+    .find(|(_, buffer)| !buffer.is_null())
+    .ok_or("LibRaw did not unpack any raw data buffer")?;
+
+    let sizes = &rawdata.sizes;
+    let row_size = usize::from(sizes.raw_width) * layout.pixel_size();
+    let height = usize::from(sizes.raw_height);
+
+    if row_size == 0 || height == 0 {
+        Err(format!(
+            "Invalid raw image dimensions: {}x{}",
+            sizes.raw_width, sizes.raw_height
+        ))?;
+    }
+
+    // Some decoders leave `raw_pitch` unset, their rows are not padded.
+    let pitch = match sizes.raw_pitch as usize {
+        0 => row_size,
+        pitch if pitch < row_size => Err(format!(
+            "Raw data of layout {:?} has a pitch of {} bytes, but rows of {} pixels take {} bytes",
+            layout, pitch, sizes.raw_width, row_size
+        ))?,
+        pitch => pitch,
+    };
+
+    // LibRaw allocates `raw_pitch` bytes for each of the `raw_height` rows of the sensor data.
+    let buffer = unsafe { std::slice::from_raw_parts(buffer, pitch * height) };
+
+    Ok(buffer
+        .chunks_exact(pitch)
+        .map(|row| &row[..row_size])
+        .collect())
+}
+
+/// Copy the sensor data out of the buffer LibRaw unpacked it into, see `raw_rows`. Only sensor
+/// data with one sample per pixel, i.e. of `Layout::Raw`, is supported.
+///
+/// # Arguments
+/// * `rawdata` – Raw data of an unpacked file.
+fn sensor_data(
+    rawdata: &libraw::libraw_rawdata_t,
+) -> Result<raw::SensorData, Box<dyn std::error::Error>> {
+    let rows = raw_rows(rawdata)?;
+
+    if rawdata.raw_image.is_null() {
+        Err("Sensor data with several samples per pixel can not be normalized")?;
+    }
+
+    let pixels = rows
+        .iter()
+        .flat_map(|row| row.chunks_exact(2))
+        .map(|pixel| u16::from_ne_bytes([pixel[0], pixel[1]]))
+        .collect();
+
+    Ok(raw::SensorData {
+        width: usize::from(rawdata.sizes.raw_width),
+        height: usize::from(rawdata.sizes.raw_height),
+        pixels,
+    })
+}
+
+/// Raw file opened with LibRaw. Owns the LibRaw handle, which is closed when dropped. Files opened
+/// from memory borrow the buffer, since LibRaw reads from it until closed.
+pub struct RawImage<'a> {
+    data: std::ptr::NonNull<libraw::libraw_data_t>,
+    buffer: std::marker::PhantomData<&'a [u8]>,
+}
+
+impl<'a> RawImage<'a> {
+    fn init() -> Result<Self, Box<dyn std::error::Error>> {
+        let flags = 0;
+        let data = unsafe { libraw::libraw_init(flags) };
+
+        Ok(RawImage {
+            data: std::ptr::NonNull::new(data).ok_or("Failed initializing LibRaw")?,
+            buffer: std::marker::PhantomData,
+        })
+    }
+
+    /// Open a raw file.
+    ///
+    /// # Arguments
+    /// * `file_path` – Path to the raw file.
+    pub fn open_file(
+        file_path: &std::path::Path,
+    ) -> Result<RawImage<'static>, Box<dyn std::error::Error>> {
+        let file_path = file_path
+            .to_str()
+            .ok_or_else(|| format!("Invalid file path: {:?}", file_path))?;
+        let file_path = std::ffi::CString::new(file_path)?;
+
+        let raw_image = RawImage::init()?;
+
+        Error::check("opening file", unsafe {
+            libraw::libraw_open_file(raw_image.data.as_ptr(), file_path.as_ptr())
+        })?;
+
+        Ok(raw_image)
+    }
+
+    /// Open a raw file from memory.
+    ///
+    /// # Arguments
+    /// * `buffer` – Contents of the raw file.
+    pub fn open_buffer(buffer: &'a [u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        let raw_image = RawImage::init()?;
+
+        Error::check("opening buffer", unsafe {
+            libraw::libraw_open_buffer(
+                raw_image.data.as_ptr(),
+                buffer.as_ptr() as *const std::os::raw::c_void,
+                buffer.len(),
+            )
+        })?;
+
+        Ok(raw_image)
+    }
+
+    fn data(&self) -> &libraw::libraw_data_t {
+        // The handle is valid until dropped.
+        unsafe { self.data.as_ref() }
+    }
+
+    /// Unpack the sensor data, making it available through `raw_rows`.
+    pub fn unpack(&mut self) -> Result<(), Error> {
+        Error::check("unpacking raw data", unsafe {
+            libraw::libraw_unpack(self.data.as_ptr())
+        })
+    }
+
+    /// Dimensions of the sensor data and of the visible image.
+    #[allow(dead_code)]
+    pub fn sizes(&self) -> libraw::libraw_image_sizes_t {
+        self.data().rawdata.sizes
+    }
+
+    /// Black and white levels, color matrices and the linearization curve.
+    #[allow(dead_code)]
+    pub fn color(&self) -> &libraw::libraw_colordata_t {
+        &self.data().rawdata.color
+    }
+
+    /// The rows of the unpacked sensor data in native byte order, see `Layout` and `raw_rows`.
+    pub fn raw_rows(&self) -> Result<Vec<&[u8]>, Box<dyn std::error::Error>> {
+        raw_rows(&self.data().rawdata)
+    }
+
+    /// The unpacked sensor data, see `sensor_data`.
+    pub fn sensor_data(&self) -> Result<raw::SensorData, Box<dyn std::error::Error>> {
+        sensor_data(&self.data().rawdata)
+    }
+
+    /// Unpack and decode the thumbnail embedded in the raw file.
+    pub fn thumbnail(&mut self) -> Result<image::DynamicImage, Box<dyn std::error::Error>> {
+        Error::check("unpacking thumbnail", unsafe {
+            libraw::libraw_unpack_thumb(self.data.as_ptr())
+        })?;
+
+        let thumbnail = &self.data().thumbnail;
+
+        if thumbnail.thumb.is_null() {
+            Err("Unexpected null pointer in LibRaw data.thumbnail.thumb")?;
+        }
+
+        // LibRaw allocates `tlength` bytes for the unpacked thumbnail.
+        let buffer = unsafe {
+            std::slice::from_raw_parts(
+                thumbnail.thumb as *const u8,
+                std::convert::TryInto::try_into(thumbnail.tlength)?,
+            )
+        };
+
+        match thumbnail.tformat {
+            libraw::LibRaw_thumbnail_formats_LIBRAW_THUMBNAIL_JPEG => {
+                image::load_from_memory(buffer)
+                    .map_err(|error| format!("Failed decoding JPEG thumbnail: {}", error).into())
+            }
+            libraw::LibRaw_thumbnail_formats_LIBRAW_THUMBNAIL_BITMAP => image::RgbImage::from_raw(
+                thumbnail.twidth.into(),
+                thumbnail.theight.into(),
+                buffer.to_vec(),
+            )
+            .map(image::DynamicImage::ImageRgb8)
+            .ok_or_else(|| "Bitmap thumbnail is smaller than its dimensions".into()),
+            format => Err(format!("Unsupported thumbnail format: {}", format).into()),
+        }
+    }
+}
+
+impl Drop for RawImage<'_> {
+    fn drop(&mut self) {
+        unsafe { libraw::libraw_close(self.data.as_ptr()) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_buffer_invalid() {
+        assert!(RawImage::open_buffer(b"not a raw file").is_err());
+    }
+
+    #[test]
+    fn test_open_file_missing() {
+        assert!(RawImage::open_file(std::path::Path::new("/nonexistent.CR2")).is_err());
+    }
+
+    #[test]
+    fn test_error_display() {
+        let error = Error {
+            action: "opening file",
+            code: -1,
+            message: "Unsupported file format or not RAW file".to_owned(),
+        };
+
+        assert_eq!(
+            error.to_string(),
+            "Failed opening file: Unsupported file format or not RAW file"
+        );
+    }
+
+    /// Raw data of an unpacked file with a single buffer pointing into `buffer`.
+    fn rawdata<T>(
+        layout: Layout,
+        buffer: &mut [T],
+        raw_width: u16,
+        raw_height: u16,
+        raw_pitch: u32,
+    ) -> libraw::libraw_rawdata_t {
+        // All fields are plain data or pointers, for which zero is a valid value.
+        let mut rawdata: libraw::libraw_rawdata_t = unsafe { std::mem::zeroed() };
+
+        let pointer = buffer.as_mut_ptr();
+
+        match layout {
+            Layout::Raw => rawdata.raw_image = pointer as _,
+            Layout::Color3 => rawdata.color3_image = pointer as _,
+            Layout::Color4 => rawdata.color4_image = pointer as _,
+            Layout::Float => rawdata.float_image = pointer as _,
+            Layout::Float3 => rawdata.float3_image = pointer as _,
+            Layout::Float4 => rawdata.float4_image = pointer as _,
+        }
+
+        rawdata.sizes.raw_width = raw_width;
+        rawdata.sizes.raw_height = raw_height;
+        rawdata.sizes.raw_pitch = raw_pitch;
+
+        rawdata
+    }
+
+    macro_rules! test_raw_rows {
+        ($($name:ident: $layout:expr, $buffer:expr, $raw_width:expr, $raw_height:expr, $raw_pitch:expr, $expected:expr,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                let mut buffer = $buffer;
+                let expected = $expected;
+                let expected = unsafe {
+                    std::slice::from_raw_parts(
+                        expected.as_ptr() as *const u8,
+                        std::mem::size_of_val(&expected),
+                    )
+                };
+                let rawdata = rawdata($layout, &mut buffer, $raw_width, $raw_height, $raw_pitch);
+
+                assert_eq!(raw_rows(&rawdata).unwrap().concat(), expected);
+            }
+        )*
+        }
+    }
+
+    test_raw_rows! {
+        test_raw_rows_raw: Layout::Raw, [1u16, 2, 3, 4, 5, 6], 3, 2, 6, [1u16, 2, 3, 4, 5, 6],
+        test_raw_rows_raw_padded: Layout::Raw, [1u16, 2, 0, 3, 4, 0], 2, 2, 6, [1u16, 2, 3, 4],
+        test_raw_rows_color3: Layout::Color3, [[1u16, 2, 3], [4, 5, 6]], 1, 2, 6, [[1u16, 2, 3], [4, 5, 6]],
+        test_raw_rows_color4: Layout::Color4, [[1u16, 2, 3, 4], [5, 6, 7, 8]], 2, 1, 16, [[1u16, 2, 3, 4], [5, 6, 7, 8]],
+        test_raw_rows_float: Layout::Float, [0.25f32, 0.5, 0.75, 1.0], 2, 2, 8, [0.25f32, 0.5, 0.75, 1.0],
+        test_raw_rows_float3: Layout::Float3, [[0.25f32, 0.5, 0.75], [1.0, 1.5, 2.0]], 2, 1, 0, [[0.25f32, 0.5, 0.75], [1.0, 1.5, 2.0]],
+        test_raw_rows_float3_padded: Layout::Float3, [[0.25f32, 0.5, 0.75], [0.0, 0.0, 0.0], [1.0, 1.5, 2.0], [0.0, 0.0, 0.0]], 1, 2, 24, [[0.25f32, 0.5, 0.75], [1.0, 1.5, 2.0]],
+        test_raw_rows_float4: Layout::Float4, [[0.25f32, 0.5, 0.75, 1.0]], 1, 1, 16, [[0.25f32, 0.5, 0.75, 1.0]],
+    }
+
+    #[test]
+    fn test_raw_rows_empty() {
+        let rawdata: libraw::libraw_rawdata_t = unsafe { std::mem::zeroed() };
+
+        assert!(raw_rows(&rawdata)
+            .unwrap_err()
+            .to_string()
+            .starts_with("LibRaw did not unpack any raw data"));
+    }
+
+    #[test]
+    fn test_raw_rows_no_dimensions() {
+        let mut buffer = [1u16, 2];
+        let rawdata = rawdata(Layout::Raw, &mut buffer, 0, 0, 0);
+
+        assert!(raw_rows(&rawdata).is_err());
+    }
+
+    #[test]
+    fn test_sensor_data_padded() {
+        let mut buffer = [1u16, 2, 0, 3, 4, 0];
+        let rawdata = rawdata(Layout::Raw, &mut buffer, 2, 2, 6);
+
+        assert_eq!(
+            sensor_data(&rawdata).unwrap(),
+            raw::SensorData {
+                width: 2,
+                height: 2,
+                pixels: vec![1, 2, 3, 4],
+            }
+        );
+    }
+
+    #[test]
+    fn test_sensor_data_color4() {
+        let mut buffer = [[1u16, 2, 3, 4], [5, 6, 7, 8]];
+        let rawdata = rawdata(Layout::Color4, &mut buffer, 2, 1, 16);
+
+        assert!(sensor_data(&rawdata).is_err());
+    }
+
+    #[test]
+    fn test_raw_rows_pitch_too_small() {
+        let mut buffer = [[1u16, 2, 3], [4, 5, 6]];
+        let rawdata = rawdata(Layout::Color3, &mut buffer, 2, 1, 4);
+
+        assert!(raw_rows(&rawdata).is_err());
+    }
+}
@@ -0,0 +1,118 @@
+use crate::ExifSequence;
+use regex::Regex;
+
+/// Number of digits of a sequence key in an identifier, enough for any `u32`. Keys are padded with
+/// zeros from the left, so that identifiers with the same timestamp sort in shooting order.
+const DIGITS: usize = 10;
+
+/// Prefix of a sequence key in an identifier. Neither decimal nor hex digits contain an `s`, which
+/// keeps the key distinguishable from timestamp, camera id and hash.
+pub const PREFIX: char = 's';
+
+/// Where the sequence key of an image has been taken from.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SequenceKeySource {
+    /// Position of the frame within a burst, recorded in the MakerNotes.
+    SequenceNumber,
+    /// Number of the image, recorded in the MakerNotes.
+    ImageNumber,
+    /// Number of shutter actuations of the camera body.
+    ShutterCount,
+    /// File counter of the original file name, e.g. `IMG_1234.CR2`.
+    FileName,
+}
+
+impl std::fmt::Display for SequenceKeySource {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let source = match self {
+            SequenceKeySource::SequenceNumber => "sequence_number",
+            SequenceKeySource::ImageNumber => "image_number",
+            SequenceKeySource::ShutterCount => "shutter_count",
+            SequenceKeySource::FileName => "file_name",
+        };
+
+        write!(formatter, "{}", source)
+    }
+}
+
+/// Key ordering images whose timestamps are identical, e.g. frames of a burst.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct SequenceKey {
+    pub value: u32,
+    pub source: SequenceKeySource,
+}
+
+/// Format a sequence key the way it is placed in identifiers, e.g. `s0000000042`.
+///
+/// # Arguments
+/// * `value` – Value of the sequence key.
+pub fn format(value: u32) -> String {
+    format!(
+        "{prefix}{value:0digits$}",
+        prefix = PREFIX,
+        value = value,
+        digits = DIGITS
+    )
+}
+
+/// Parse a sequence key from an identifier component. Returns `None` if the component is not a
+/// sequence key.
+///
+/// # Arguments
+/// * `component` – Identifier component, e.g. `s0000000042`.
+pub fn parse(component: &str) -> Option<u32> {
+    let digits = component.strip_prefix(PREFIX)?;
+
+    if digits.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+
+    digits.parse().ok()
+}
+
+/// Extract the file counter from file names as assigned by cameras, e.g. `IMG_1234`, `_MG_1234`,
+/// `DSC01234` or `DSCF1234`.
+///
+/// # Arguments
+/// * `file_path` – Path to the original file.
+fn get_file_counter(file_path: &std::path::Path) -> Option<u32> {
+    let regex = Regex::new(r"^[A-Za-z_]+([0-9]+)$").unwrap();
+
+    let file_stem = file_path.file_stem()?.to_str()?;
+
+    regex.captures(file_stem)?[1].parse().ok()
+}
+
+/// Derive the key ordering images with identical timestamps. Prefers counters recorded by the
+/// camera over the file counter of the file name, since the file name is lost when a file is
+/// renamed. Sources whose value exceeds `u32` are skipped, since the key would no longer sort in
+/// shooting order. Returns `None` if no source is available.
+///
+/// # Arguments
+/// * `file_path` – Path to the image file.
+/// * `exif` – The Exif data to be examined.
+pub fn get_sequence_key(file_path: &std::path::Path, exif: &ExifSequence) -> Option<SequenceKey> {
+    let counters = [
+        (&exif.SequenceNumber, SequenceKeySource::SequenceNumber),
+        (&exif.ImageNumber, SequenceKeySource::ImageNumber),
+        (&exif.ShutterCount, SequenceKeySource::ShutterCount),
+    ];
+
+    for (value, source) in &counters {
+        if let Some(value) = value
+            .as_deref()
+            .and_then(|value| value.trim().parse::<u32>().ok())
+        {
+            return Some(SequenceKey {
+                value,
+                source: *source,
+            });
+        }
+    }
+
+    get_file_counter(file_path).map(|value| SequenceKey {
+        value,
+        source: SequenceKeySource::FileName,
+    })
+}
@@ -0,0 +1,439 @@
+use crate::{parse_time_zone, time_zone_boundaries, ExifDateTime};
+use chrono::{DateTime, FixedOffset, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+
+/// Granularity of time zone offsets in use. All offsets are multiples of 15 minutes.
+const OFFSET_GRANULARITY_SECONDS: i64 = 15 * 60;
+
+/// Maximum deviation of the GPS timestamp from the date of the image, after subtracting the time
+/// zone offset. GPS receivers may record the time of their last fix instead of the time the image
+/// has been taken.
+const MAX_GPS_DEVIATION_SECONDS: i64 = 5 * 60;
+
+/// Where the time zone of a date has been taken from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimeZoneSource {
+    /// The date includes a time zone.
+    Date,
+    OffsetTimeOriginal,
+    OffsetTimeDigitized,
+    OffsetTime,
+    TimeZone,
+    /// Time zone whose boundaries contain the GPS position.
+    GpsPosition(chrono_tz::Tz),
+    /// Difference between the local date and the UTC time recorded by the GPS receiver, if the
+    /// GPS position can not be resolved to a time zone.
+    GpsTimestamp,
+    /// No time zone information available, the default time zone has been assumed.
+    Default,
+}
+
+impl std::fmt::Display for TimeZoneSource {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let source = match self {
+            TimeZoneSource::Date => "date",
+            TimeZoneSource::OffsetTimeOriginal => "offset_time_original",
+            TimeZoneSource::OffsetTimeDigitized => "offset_time_digitized",
+            TimeZoneSource::OffsetTime => "offset_time",
+            TimeZoneSource::TimeZone => "time_zone",
+            TimeZoneSource::GpsPosition(time_zone) => {
+                return write!(formatter, "gps_position:{}", time_zone.name());
+            }
+            TimeZoneSource::GpsTimestamp => "gps_timestamp",
+            TimeZoneSource::Default => "default",
+        };
+
+        write!(formatter, "{}", source)
+    }
+}
+
+impl std::str::FromStr for TimeZoneSource {
+    type Err = String;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        Ok(match source {
+            "date" => TimeZoneSource::Date,
+            "offset_time_original" => TimeZoneSource::OffsetTimeOriginal,
+            "offset_time_digitized" => TimeZoneSource::OffsetTimeDigitized,
+            "offset_time" => TimeZoneSource::OffsetTime,
+            "time_zone" => TimeZoneSource::TimeZone,
+            "gps_timestamp" => TimeZoneSource::GpsTimestamp,
+            "default" => TimeZoneSource::Default,
+            source => match source.strip_prefix("gps_position:") {
+                Some(time_zone) => TimeZoneSource::GpsPosition(
+                    time_zone
+                        .parse()
+                        .map_err(|_| format!("Unknown time zone: {}", time_zone))?,
+                ),
+                None => Err(format!("Unknown time zone source: {}", source))?,
+            },
+        })
+    }
+}
+
+impl serde::Serialize for TimeZoneSource {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for TimeZoneSource {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Read a GPS coordinate, negated if its reference points south or west.
+///
+/// # Arguments
+/// * `value` – Coordinate in degrees.
+/// * `reference` – Reference of the coordinate, e.g. `N` or `W`.
+/// * `negative` – Reference of negative coordinates.
+/// * `range` – Valid absolute value of the coordinate.
+fn gps_coordinate(
+    value: Option<f64>,
+    reference: Option<&str>,
+    negative: char,
+    range: f64,
+) -> Option<f64> {
+    let value = value?;
+
+    let value = match reference {
+        Some(reference) if reference.starts_with(negative) && value > 0.0 => -value,
+        _ => value,
+    };
+
+    if value.is_finite() && value.abs() <= range {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+/// Infer the time zone of the local date when an image has been taken from its GPS position, by
+/// looking up the time zone of the IANA time zone database whose boundaries contain it, see
+/// `time_zone_boundaries::lookup`. The rules of that time zone are applied to the local date, so
+/// that daylight saving time is considered. The UTC time recorded by the GPS receiver only decides
+/// between both offsets of a local date that occurs twice, see `localize`.
+///
+/// Without a GPS position, or if it can not be resolved to a time zone, which is reported in
+/// `warnings`, falls back to the difference between the local date and the UTC time recorded by
+/// the GPS receiver, see `offset_from_gps_timestamp`. Returns `None` if neither is available.
+///
+/// # Arguments
+/// * `date` – Local date when the image has been taken, without time zone.
+/// * `exif` – The Exif data to be examined.
+/// * `warnings` – Assumptions made while inferring the time zone are appended to it.
+pub fn get_time_zone_from_gps(
+    date: &NaiveDateTime,
+    exif: &ExifDateTime,
+    warnings: &mut Vec<String>,
+) -> Result<Option<(FixedOffset, TimeZoneSource)>, Box<dyn std::error::Error>> {
+    let latitude = gps_coordinate(exif.GPSLatitude, exif.GPSLatitudeRef.as_deref(), 'S', 90.0);
+    let longitude = gps_coordinate(
+        exif.GPSLongitude,
+        exif.GPSLongitudeRef.as_deref(),
+        'W',
+        180.0,
+    );
+
+    let gps_date_time = match (&exif.GPSDateStamp, &exif.GPSTimeStamp) {
+        (Some(date), Some(time)) => NaiveDate::parse_from_str(date.trim(), "%Y:%m:%d")
+            .ok()
+            .zip(NaiveTime::parse_from_str(time.trim(), "%H:%M:%S%.f").ok())
+            .map(|(date, time)| date.and_time(time)),
+        _ => None,
+    };
+
+    if let (Some(latitude), Some(longitude)) = (latitude, longitude) {
+        match time_zone_boundaries::lookup(latitude, longitude) {
+            Ok(Some(time_zone)) => {
+                let (date, warning) = localize(&time_zone, date, gps_date_time)?;
+                warnings.extend(warning);
+
+                return Ok(Some((
+                    *date.offset(),
+                    TimeZoneSource::GpsPosition(time_zone),
+                )));
+            }
+            Ok(None) => warnings.push(format!(
+                "GPS position {}, {} lies outside of all time zone boundaries",
+                latitude, longitude
+            )),
+            Err(error) => warnings.push(error.to_string()),
+        }
+    }
+
+    Ok(gps_date_time
+        .and_then(|gps_date_time| offset_from_gps_timestamp(date, &gps_date_time))
+        .map(|time_zone| (time_zone, TimeZoneSource::GpsTimestamp)))
+}
+
+/// Derive the time zone offset of a local date from the UTC time recorded by the GPS receiver,
+/// rounding their difference to whole time zone offsets. `None` if the difference deviates from
+/// them by more than `MAX_GPS_DEVIATION_SECONDS`, e.g. because the GPS receiver recorded a stale
+/// fix.
+///
+/// # Arguments
+/// * `date` – Local date without time zone.
+/// * `gps_date_time` – UTC time recorded by the GPS receiver.
+fn offset_from_gps_timestamp(
+    date: &NaiveDateTime,
+    gps_date_time: &NaiveDateTime,
+) -> Option<FixedOffset> {
+    let difference = (*date - *gps_date_time).num_seconds();
+    let offset = (difference as f64 / OFFSET_GRANULARITY_SECONDS as f64).round() as i64
+        * OFFSET_GRANULARITY_SECONDS;
+
+    if (difference - offset).abs() <= MAX_GPS_DEVIATION_SECONDS {
+        FixedOffset::east_opt(std::convert::TryFrom::try_from(offset).ok()?)
+    } else {
+        None
+    }
+}
+
+/// Attach a time zone of the IANA time zone database to a local date. Local dates that occur twice,
+/// because clocks have been turned back at the end of daylight saving time, get the offset that
+/// agrees with the UTC time recorded by the GPS receiver, or the earlier offset along with a
+/// warning.
+///
+/// # Arguments
+/// * `time_zone` – Time zone of the local date.
+/// * `date` – Local date without time zone.
+/// * `gps_date_time` – UTC time recorded by the GPS receiver, if any.
+fn localize(
+    time_zone: &chrono_tz::Tz,
+    date: &NaiveDateTime,
+    gps_date_time: Option<NaiveDateTime>,
+) -> Result<(DateTime<FixedOffset>, Option<String>), Box<dyn std::error::Error>> {
+    match time_zone.from_local_datetime(date) {
+        LocalResult::Single(date) => Ok((date.fixed_offset(), None)),
+        LocalResult::Ambiguous(earliest, latest) => {
+            let matches_gps = |candidate: &DateTime<chrono_tz::Tz>| {
+                gps_date_time.is_some_and(|gps_date_time| {
+                    (candidate.naive_utc() - gps_date_time).num_seconds().abs()
+                        <= MAX_GPS_DEVIATION_SECONDS
+                })
+            };
+
+            if matches_gps(&earliest) {
+                return Ok((earliest.fixed_offset(), None));
+            }
+
+            if matches_gps(&latest) {
+                return Ok((latest.fixed_offset(), None));
+            }
+
+            Ok((
+                earliest.fixed_offset(),
+                Some(format!(
+                    "Local date {} is ambiguous in time zone {}, assumed offset {} instead of {}",
+                    date,
+                    time_zone.name(),
+                    earliest.fixed_offset().offset(),
+                    latest.fixed_offset().offset()
+                )),
+            ))
+        }
+        LocalResult::None => Err(format!(
+            "Local date {} does not exist in time zone {}",
+            date,
+            time_zone.name()
+        ))?,
+    }
+}
+
+/// Time zone assumed for dates without any time zone information.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DefaultTimeZone {
+    Fixed(FixedOffset),
+    /// A time zone of the IANA time zone database, considering daylight saving time.
+    Named(chrono_tz::Tz),
+}
+
+impl Default for DefaultTimeZone {
+    fn default() -> Self {
+        DefaultTimeZone::Fixed(FixedOffset::east_opt(0).unwrap())
+    }
+}
+
+impl std::fmt::Display for DefaultTimeZone {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DefaultTimeZone::Fixed(time_zone) => write!(formatter, "{}", time_zone),
+            DefaultTimeZone::Named(time_zone) => write!(formatter, "{}", time_zone.name()),
+        }
+    }
+}
+
+impl DefaultTimeZone {
+    /// Parse a fixed time zone offset (e.g. `+01:00`) or the name of a time zone of the IANA time
+    /// zone database (e.g. `Europe/Berlin`).
+    ///
+    /// # Arguments
+    /// * `time_zone` – Time zone to be parsed.
+    pub fn parse(time_zone: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        if let Ok(time_zone) = parse_time_zone(time_zone) {
+            return Ok(DefaultTimeZone::Fixed(time_zone));
+        }
+
+        let time_zone = time_zone.parse::<chrono_tz::Tz>().map_err(|_| {
+            format!(
+                r#"Invalid time zone "{}", expected an offset like "+01:00" or a name like "Europe/Berlin""#,
+                time_zone
+            )
+        })?;
+
+        Ok(DefaultTimeZone::Named(time_zone))
+    }
+
+    /// Attach the time zone to a local date. Returns a warning along with the date if the local
+    /// date is ambiguous, which happens when clocks are turned back at the end of daylight saving
+    /// time.
+    ///
+    /// # Arguments
+    /// * `date` – Local date without time zone.
+    pub fn localize(
+        &self,
+        date: &NaiveDateTime,
+    ) -> Result<(DateTime<FixedOffset>, Option<String>), Box<dyn std::error::Error>> {
+        let time_zone = match self {
+            DefaultTimeZone::Fixed(time_zone) => {
+                return Ok((
+                    DateTime::<FixedOffset>::from_naive_utc_and_offset(
+                        *date - *time_zone,
+                        *time_zone,
+                    ),
+                    None,
+                ));
+            }
+            DefaultTimeZone::Named(time_zone) => time_zone,
+        };
+
+        localize(time_zone, date, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! test_localize {
+        ($test_name:ident, $time_zone:literal, $date:literal, $gps:expr, $expected:literal) => {
+            #[test]
+            fn $test_name() -> Result<(), Box<dyn std::error::Error>> {
+                let time_zone: chrono_tz::Tz = $time_zone.parse()?;
+                let date = NaiveDateTime::parse_from_str($date, "%Y-%m-%d %H:%M:%S")?;
+                let gps: Option<&str> = $gps;
+                let gps = match gps {
+                    Some(gps) => Some(NaiveDateTime::parse_from_str(gps, "%Y-%m-%d %H:%M:%S")?),
+                    None => None,
+                };
+
+                let (date, _) = localize(&time_zone, &date, gps)?;
+
+                assert_eq!(date.to_rfc3339(), $expected);
+
+                Ok(())
+            }
+        };
+    }
+
+    test_localize!(
+        test_localize_china,
+        "Asia/Shanghai",
+        "2017-01-05 13:52:55",
+        None,
+        "2017-01-05T13:52:55+08:00"
+    );
+
+    test_localize!(
+        test_localize_half_hour,
+        "Asia/Kolkata",
+        "2017-01-05 13:52:55",
+        None,
+        "2017-01-05T13:52:55+05:30"
+    );
+
+    test_localize!(
+        test_localize_spain_summer,
+        "Europe/Madrid",
+        "2017-07-05 13:52:55",
+        None,
+        "2017-07-05T13:52:55+02:00"
+    );
+
+    test_localize!(
+        test_localize_ambiguous_earliest,
+        "Europe/Berlin",
+        "2017-10-29 02:30:00",
+        None,
+        "2017-10-29T02:30:00+02:00"
+    );
+
+    test_localize!(
+        test_localize_ambiguous_gps,
+        "Europe/Berlin",
+        "2017-10-29 02:30:00",
+        Some("2017-10-29 01:30:10"),
+        "2017-10-29T02:30:00+01:00"
+    );
+
+    #[test]
+    fn test_localize_skipped() -> Result<(), Box<dyn std::error::Error>> {
+        let time_zone: chrono_tz::Tz = "Europe/Berlin".parse()?;
+        let date = NaiveDateTime::parse_from_str("2017-03-26 02:30:00", "%Y-%m-%d %H:%M:%S")?;
+
+        assert!(localize(&time_zone, &date, None).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_gps_coordinate() {
+        assert_eq!(
+            gps_coordinate(Some(74.0), Some("W"), 'W', 180.0),
+            Some(-74.0)
+        );
+        assert_eq!(
+            gps_coordinate(Some(-74.0), Some("W"), 'W', 180.0),
+            Some(-74.0)
+        );
+        assert_eq!(
+            gps_coordinate(Some(33.9), Some("S"), 'S', 90.0),
+            Some(-33.9)
+        );
+        assert_eq!(gps_coordinate(Some(52.5), Some("N"), 'S', 90.0), Some(52.5));
+        assert_eq!(gps_coordinate(Some(91.0), None, 'S', 90.0), None);
+        assert_eq!(gps_coordinate(None, Some("N"), 'S', 90.0), None);
+    }
+
+    #[test]
+    fn test_time_zone_source_serialize() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(
+            TimeZoneSource::GpsPosition("Europe/Berlin".parse()?).to_string(),
+            "gps_position:Europe/Berlin"
+        );
+        assert_eq!(
+            serde_json::to_string(&TimeZoneSource::OffsetTimeOriginal)?,
+            "\"offset_time_original\""
+        );
+
+        for source in &[
+            TimeZoneSource::Date,
+            TimeZoneSource::GpsPosition("America/New_York".parse()?),
+            TimeZoneSource::GpsTimestamp,
+            TimeZoneSource::Default,
+        ] {
+            let serialized = serde_json::to_string(source)?;
+
+            assert_eq!(
+                serde_json::from_str::<TimeZoneSource>(&serialized)?,
+                *source
+            );
+        }
+
+        Ok(())
+    }
+}
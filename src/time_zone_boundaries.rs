@@ -0,0 +1,305 @@
+use std::convert::TryInto;
+
+/// Boundaries of the time zones of the IANA time zone database, generated by `data/generate.py`
+/// from the releases of the timezone-boundary-builder project.
+static DATABASE: &[u8] = include_bytes!("../data/time_zones.bin");
+
+/// Bytes every database starts with, followed by the number of time zones.
+const MAGIC: &[u8] = b"ciid-tz1";
+
+/// Coordinates are stored as integer multiples of this fraction of a degree.
+const COORDINATE_SCALE: f64 = 100_000.0;
+
+/// Area of a time zone, along with its bounding box.
+#[derive(Debug, PartialEq)]
+struct Polygon {
+    /// Minimum longitude, minimum latitude, maximum longitude, maximum latitude.
+    bounds: [i32; 4],
+    /// Outer ring followed by holes, each as longitude and latitude pairs.
+    rings: Vec<Vec<(i32, i32)>>,
+}
+
+impl Polygon {
+    /// Whether a position lies within the polygon, by counting how many edges a ray towards east
+    /// crosses.
+    ///
+    /// # Arguments
+    /// * `longitude` – Longitude of the position, scaled by `COORDINATE_SCALE`.
+    /// * `latitude` – Latitude of the position, scaled by `COORDINATE_SCALE`.
+    fn contains(&self, longitude: i32, latitude: i32) -> bool {
+        let [min_longitude, min_latitude, max_longitude, max_latitude] = self.bounds;
+
+        if longitude < min_longitude
+            || longitude > max_longitude
+            || latitude < min_latitude
+            || latitude > max_latitude
+        {
+            return false;
+        }
+
+        let mut inside = false;
+
+        for ring in &self.rings {
+            for (index, &(x1, y1)) in ring.iter().enumerate() {
+                let (x2, y2) = ring[(index + 1) % ring.len()];
+
+                if (y1 > latitude) != (y2 > latitude) {
+                    let crossing = f64::from(x1)
+                        + f64::from(latitude - y1) * f64::from(x2 - x1) / f64::from(y2 - y1);
+
+                    if f64::from(longitude) < crossing {
+                        inside = !inside;
+                    }
+                }
+            }
+        }
+
+        inside
+    }
+}
+
+/// Time zone of the IANA time zone database, along with its area.
+#[derive(Debug, PartialEq)]
+struct Zone {
+    name: String,
+    polygons: Vec<Polygon>,
+}
+
+/// Parse a time zone boundary database. All integers are little-endian:
+///
+/// - `MAGIC`, followed by the number of time zones as `u32`
+/// - per time zone: length of its name as `u8`, its name, number of polygons as `u32`
+/// - per polygon: bounding box as four `i32`, number of rings as `u32`
+/// - per ring: number of points as `u32`, followed by longitude and latitude of each point as
+///   `i32`, scaled by `COORDINATE_SCALE`
+///
+/// # Arguments
+/// * `data` – Contents of the database.
+fn parse(data: &[u8]) -> Result<Vec<Zone>, Box<dyn std::error::Error>> {
+    let mut position = 0;
+
+    let mut take = |length: usize| -> Result<&[u8], Box<dyn std::error::Error>> {
+        let bytes = data
+            .get(position..position + length)
+            .ok_or("Truncated time zone boundary database")?;
+        position += length;
+
+        Ok(bytes)
+    };
+
+    if take(MAGIC.len())? != MAGIC {
+        Err("Invalid time zone boundary database")?;
+    }
+
+    let mut zones = Vec::new();
+
+    macro_rules! read {
+        (u8) => {
+            take(1)?[0]
+        };
+        (u32) => {
+            u32::from_le_bytes(take(4)?.try_into()?) as usize
+        };
+        (i32) => {
+            i32::from_le_bytes(take(4)?.try_into()?)
+        };
+    }
+
+    for _ in 0..read!(u32) {
+        let length = usize::from(read!(u8));
+        let name = String::from_utf8(take(length)?.to_vec())?;
+        let mut polygons = Vec::new();
+
+        for _ in 0..read!(u32) {
+            let bounds = [read!(i32), read!(i32), read!(i32), read!(i32)];
+            let mut rings = Vec::new();
+
+            for _ in 0..read!(u32) {
+                let mut ring = Vec::new();
+
+                for _ in 0..read!(u32) {
+                    ring.push((read!(i32), read!(i32)));
+                }
+
+                rings.push(ring);
+            }
+
+            polygons.push(Polygon { bounds, rings });
+        }
+
+        zones.push(Zone { name, polygons });
+    }
+
+    Ok(zones)
+}
+
+/// Find the time zone whose area contains a position.
+///
+/// # Arguments
+/// * `zones` – Time zones to be searched.
+/// * `latitude` – Latitude in degrees, positive towards north.
+/// * `longitude` – Longitude in degrees, positive towards east.
+fn find(zones: &[Zone], latitude: f64, longitude: f64) -> Option<&Zone> {
+    let latitude = (latitude * COORDINATE_SCALE).round() as i32;
+    let longitude = (longitude * COORDINATE_SCALE).round() as i32;
+
+    zones.iter().find(|zone| {
+        zone.polygons
+            .iter()
+            .any(|polygon| polygon.contains(longitude, latitude))
+    })
+}
+
+/// Resolve a position to the time zone of the IANA time zone database whose boundaries contain it,
+/// using the embedded database. Positions at sea resolve to the nautical time zones (e.g.
+/// `Etc/GMT-1`), as long as the database has been generated including oceans.
+///
+/// # Arguments
+/// * `latitude` – Latitude in degrees, positive towards north.
+/// * `longitude` – Longitude in degrees, positive towards east.
+pub fn lookup(
+    latitude: f64,
+    longitude: f64,
+) -> Result<Option<chrono_tz::Tz>, Box<dyn std::error::Error>> {
+    static ZONES: std::sync::OnceLock<Result<Vec<Zone>, String>> = std::sync::OnceLock::new();
+
+    let zones = ZONES
+        .get_or_init(|| parse(DATABASE).map_err(|error| error.to_string()))
+        .as_ref()
+        .map_err(|error| format!("Failed reading time zone boundary database: {}", error))?;
+
+    if zones.is_empty() {
+        Err("The embedded time zone boundary database is empty. Generate it with data/generate.py before building")?;
+    }
+
+    match find(zones, latitude, longitude) {
+        Some(zone) => Ok(Some(zone.name.parse().map_err(|_| {
+            format!("Unknown time zone in boundary database: {}", zone.name)
+        })?)),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Rings of a polygon, each as longitude and latitude pairs in degrees.
+    type Rings = Vec<Vec<(f64, f64)>>;
+
+    /// Encode time zones in the format of `parse`, as `data/generate.py` does.
+    fn encode(zones: &[(&str, Vec<Rings>)]) -> Vec<u8> {
+        let scale = |value: f64| (value * COORDINATE_SCALE).round() as i32;
+
+        let mut data = MAGIC.to_vec();
+        data.extend(&(zones.len() as u32).to_le_bytes());
+
+        for (name, polygons) in zones {
+            data.push(name.len() as u8);
+            data.extend(name.as_bytes());
+            data.extend(&(polygons.len() as u32).to_le_bytes());
+
+            for rings in polygons {
+                let points = rings[0].iter();
+                let longitudes = points.clone().map(|&(longitude, _)| scale(longitude));
+                let latitudes = points.map(|&(_, latitude)| scale(latitude));
+
+                for bound in &[
+                    longitudes.clone().min().unwrap(),
+                    latitudes.clone().min().unwrap(),
+                    longitudes.max().unwrap(),
+                    latitudes.max().unwrap(),
+                ] {
+                    data.extend(&bound.to_le_bytes());
+                }
+
+                data.extend(&(rings.len() as u32).to_le_bytes());
+
+                for ring in rings {
+                    data.extend(&(ring.len() as u32).to_le_bytes());
+
+                    for &(longitude, latitude) in ring {
+                        data.extend(&scale(longitude).to_le_bytes());
+                        data.extend(&scale(latitude).to_le_bytes());
+                    }
+                }
+            }
+        }
+
+        data
+    }
+
+    fn square(west: f64, south: f64, east: f64, north: f64) -> Vec<(f64, f64)> {
+        vec![(west, south), (east, south), (east, north), (west, north)]
+    }
+
+    fn zones() -> Vec<Zone> {
+        parse(&encode(&[
+            // A zone with a hole, which is covered by a zone listed later.
+            (
+                "Europe/Berlin",
+                vec![vec![
+                    square(6.0, 47.0, 15.0, 55.0),
+                    square(8.0, 49.0, 9.0, 50.0),
+                ]],
+            ),
+            ("Europe/Zurich", vec![vec![square(8.0, 49.0, 9.0, 50.0)]]),
+            // A triangle, so that positions within its bounding box may lie outside.
+            (
+                "Asia/Kolkata",
+                vec![vec![vec![(68.0, 24.0), (90.0, 24.0), (78.0, 8.0)]]],
+            ),
+        ]))
+        .unwrap()
+    }
+
+    fn find_name(latitude: f64, longitude: f64) -> Option<String> {
+        find(&zones(), latitude, longitude).map(|zone| zone.name.clone())
+    }
+
+    #[test]
+    fn test_find() {
+        assert_eq!(find_name(52.52, 13.405), Some("Europe/Berlin".to_owned()));
+        assert_eq!(find_name(49.5, 8.5), Some("Europe/Zurich".to_owned()));
+        assert_eq!(find_name(20.0, 78.0), Some("Asia/Kolkata".to_owned()));
+        assert_eq!(find_name(10.0, 70.0), None);
+        assert_eq!(find_name(-33.9, 151.2), None);
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(parse(b"").is_err());
+        assert!(parse(b"something").is_err());
+
+        let data = encode(&[("Europe/Berlin", vec![vec![square(6.0, 47.0, 15.0, 55.0)]])]);
+        assert!(parse(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_embedded_database() {
+        assert!(parse(DATABASE).is_ok());
+    }
+
+    #[test]
+    fn test_lookup() -> Result<(), Box<dyn std::error::Error>> {
+        for (latitude, longitude, expected) in &[
+            (52.52, 13.405, "Europe/Berlin"),
+            (47.3769, 8.5417, "Europe/Zurich"),
+            (22.5726, 88.3639, "Asia/Kolkata"),
+            (40.7128, -74.006, "America/New_York"),
+            (-33.8688, 151.2093, "Australia/Sydney"),
+            // In the Atlantic, covered by the nautical time zones.
+            (0.0, -30.0, "Etc/GMT+2"),
+        ] {
+            assert_eq!(
+                lookup(*latitude, *longitude)?.map(|time_zone| time_zone.name()),
+                Some(*expected),
+                "{}, {}",
+                latitude,
+                longitude
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,107 @@
+use rayon::prelude::*;
+
+/// Extensions of files that are picked up when searching directories. JPEG images and the raw
+/// formats supported by LibRaw.
+const IMAGE_EXTENSIONS: &[&str] = &[
+    "jpg", "jpeg", "3fr", "arw", "cr2", "cr3", "crw", "dcr", "dng", "erf", "iiq", "kdc", "mos",
+    "mrw", "nef", "nrw", "orf", "pef", "raf", "raw", "rw2", "rwl", "sr2", "srf", "srw", "x3f",
+];
+
+/// Whether the file extension belongs to an image format `ciid` can hash.
+///
+/// # Arguments
+/// * `file_path` – Path to file.
+pub fn is_image(file_path: &std::path::Path) -> bool {
+    match file_path
+        .extension()
+        .and_then(|extension| extension.to_str())
+    {
+        Some(extension) => IMAGE_EXTENSIONS.contains(&extension.to_lowercase().as_str()),
+        None => false,
+    }
+}
+
+/// Collect image files, searching directories recursively. Files that are provided explicitly
+/// are always included. The result is sorted, so that it does not depend on the order in which
+/// the file system lists directory entries. Files reached by several paths, e.g. through
+/// overlapping directories, are only included once.
+///
+/// # Arguments
+/// * `paths` – Paths to files or directories.
+pub fn walk<P: AsRef<std::path::Path>>(
+    paths: &[P],
+) -> Result<Vec<std::path::PathBuf>, Box<dyn std::error::Error>> {
+    walk_matching(paths, is_image)
+}
+
+/// Collect all regular files, regardless of their extension, searching directories recursively.
+/// The result is sorted, see `walk`.
+///
+/// # Arguments
+/// * `paths` – Paths to files or directories.
+pub fn walk_all<P: AsRef<std::path::Path>>(
+    paths: &[P],
+) -> Result<Vec<std::path::PathBuf>, Box<dyn std::error::Error>> {
+    walk_matching(paths, |_| true)
+}
+
+/// Collect the files of directories that match a predicate, searching directories recursively.
+/// Files that are provided explicitly are always included.
+///
+/// # Arguments
+/// * `paths` – Paths to files or directories.
+/// * `predicate` – Whether a file found in a directory is included.
+fn walk_matching<P: AsRef<std::path::Path>>(
+    paths: &[P],
+    predicate: fn(&std::path::Path) -> bool,
+) -> Result<Vec<std::path::PathBuf>, Box<dyn std::error::Error>> {
+    let mut file_paths = Vec::new();
+
+    for path in paths {
+        let path = path.as_ref();
+
+        if !path.is_dir() {
+            file_paths.push(path.to_owned());
+            continue;
+        }
+
+        for entry in walkdir::WalkDir::new(path) {
+            let entry = entry.map_err(|error| {
+                format!("Failed reading directory {}: {}", path.display(), error)
+            })?;
+
+            if entry.file_type().is_file() && predicate(entry.path()) {
+                file_paths.push(entry.into_path());
+            }
+        }
+    }
+
+    file_paths.sort();
+
+    // Paths like `x/a.jpg` and `./x/a.jpg` lead to the same file. Paths that can not be resolved
+    // are kept, so that reading them reports why.
+    let mut canonical_paths = std::collections::HashSet::new();
+
+    file_paths.retain(|file_path| match std::fs::canonicalize(file_path) {
+        Ok(canonical_path) => canonical_paths.insert(canonical_path),
+        Err(_) => true,
+    });
+
+    Ok(file_paths)
+}
+
+/// Apply a function to each file in parallel. The results keep the order of the files.
+///
+/// # Arguments
+/// * `file_paths` – Paths to files.
+/// * `function` – Function to be applied to each file.
+pub fn par_map<T, F>(file_paths: &[std::path::PathBuf], function: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(&std::path::Path) -> T + Sync + Send,
+{
+    file_paths
+        .par_iter()
+        .map(|file_path| function(file_path))
+        .collect()
+}
@@ -0,0 +1,429 @@
+#!/usr/bin/env python3
+"""Minimal JPEG encoder generating the corpus of the golden hash tests.
+
+The images are encoded by hand, so that the corpus covers features common encoders do not produce
+by default and does not depend on any particular encoder version. Regenerating the corpus with this
+script is only necessary when adding images; the golden hashes have to be updated in that case.
+
+Usage: python3 generate.py tests/files/jpeg
+"""
+import math, struct, sys, os
+
+ZIGZAG = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5,
+    12, 19, 26, 33, 40, 48, 41, 34, 27, 20, 13, 6, 7, 14, 21, 28,
+    35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51,
+    58, 59, 52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+]
+
+Q_LUMA = [
+    16, 11, 10, 16, 24, 40, 51, 61, 12, 12, 14, 19, 26, 58, 60, 55,
+    14, 13, 16, 24, 40, 57, 69, 56, 14, 17, 22, 29, 51, 87, 80, 62,
+    18, 22, 37, 56, 68, 109, 103, 77, 24, 35, 55, 64, 81, 104, 113, 92,
+    49, 64, 78, 87, 103, 121, 120, 101, 72, 92, 95, 98, 112, 100, 103, 99,
+]
+Q_CHROMA = [
+    17, 18, 24, 47, 99, 99, 99, 99, 18, 21, 26, 66, 99, 99, 99, 99,
+    24, 26, 56, 99, 99, 99, 99, 99, 47, 66, 99, 99, 99, 99, 99, 99,
+] + [99] * 32
+
+# Generic Huffman tables: all DC categories at length 5, all AC symbols at length 8.
+DC_SYMBOLS = list(range(16))
+AC_SYMBOLS = [0x00, 0xF0] + [(r << 4) | s for r in range(16) for s in range(1, 15)]
+
+
+# Progressive scans with successive approximation, as in libjpeg's jpeg_simple_progression:
+# components, spectral selection start and end, successive approximation high and low.
+SUCCESSIVE_APPROXIMATION = [
+    ([0, 1, 2], 0, 0, 0, 1),
+    ([0], 1, 5, 0, 2),
+    ([2], 1, 63, 0, 1),
+    ([1], 1, 63, 0, 1),
+    ([0], 6, 63, 0, 2),
+    ([0], 1, 63, 2, 1),
+    ([0, 1, 2], 0, 0, 1, 0),
+    ([2], 1, 63, 1, 0),
+    ([1], 1, 63, 1, 0),
+    ([0], 1, 63, 1, 0),
+]
+
+
+def table(symbols, length):
+    bits = [0] * 16
+    bits[length - 1] = len(symbols)
+    codes = {symbol: (index, length) for index, symbol in enumerate(symbols)}
+    return bits, codes
+
+
+DC_BITS, DC_CODES = table(DC_SYMBOLS, 5)
+AC_BITS, AC_CODES = table(AC_SYMBOLS, 8)
+
+
+class BitWriter:
+    def __init__(self):
+        self.out = bytearray()
+        self.acc = 0
+        self.n = 0
+
+    def write(self, value, length):
+        for i in range(length - 1, -1, -1):
+            self.acc = (self.acc << 1) | ((value >> i) & 1)
+            self.n += 1
+            if self.n == 8:
+                self.out.append(self.acc)
+                if self.acc == 0xFF:
+                    self.out.append(0x00)
+                self.acc = 0
+                self.n = 0
+
+    def flush(self):
+        if self.n:
+            self.write((1 << (8 - self.n)) - 1, 8 - self.n)
+
+
+def fdct(block):
+    out = [0.0] * 64
+    for v in range(8):
+        for u in range(8):
+            s = 0.0
+            for y in range(8):
+                for x in range(8):
+                    s += block[y * 8 + x] * math.cos((2 * x + 1) * u * math.pi / 16) * math.cos(
+                        (2 * y + 1) * v * math.pi / 16)
+            cu = 1 / math.sqrt(2) if u == 0 else 1
+            cv = 1 / math.sqrt(2) if v == 0 else 1
+            out[v * 8 + u] = 0.25 * cu * cv * s
+    return out
+
+
+def category(value):
+    value = abs(value)
+    size = 0
+    while value:
+        size += 1
+        value >>= 1
+    return size
+
+
+def write_value(writer, value, size):
+    if value < 0:
+        value += (1 << size) - 1
+    writer.write(value, size)
+
+
+def quantized_blocks(plane, width, height, quant, shift):
+    """Blocks of a component plane in raster order, zigzag ordered and quantized."""
+    bw, bh = (width + 7) // 8, (height + 7) // 8
+    blocks = []
+    for by in range(bh):
+        row = []
+        for bx in range(bw):
+            block = []
+            for y in range(8):
+                for x in range(8):
+                    px = min(bx * 8 + x, width - 1)
+                    py = min(by * 8 + y, height - 1)
+                    block.append(plane[py][px] - shift)
+            coefficients = fdct(block)
+            row.append([int(round(coefficients[ZIGZAG[k]] / quant[ZIGZAG[k]])) for k in range(64)])
+        blocks.append(row)
+    return blocks
+
+
+def point_transform(value, shift):
+    """Successive approximation of an AC coefficient: its magnitude shifted right."""
+    return -(-value >> shift) if value < 0 else value >> shift
+
+
+def encode_ac(writer, block, start, end, shift=0):
+    run = 0
+    for k in range(start, end + 1):
+        value = point_transform(block[k], shift)
+        if value == 0:
+            run += 1
+            continue
+        while run > 15:
+            writer.write(*AC_CODES[0xF0])
+            run -= 16
+        size = category(value)
+        writer.write(*AC_CODES[(run << 4) | size])
+        write_value(writer, value, size)
+        run = 0
+    if run:
+        writer.write(*AC_CODES[0x00])
+
+
+def encode_ac_refinement(writer, block, start, end, shift):
+    """Refine the AC coefficients by one bit, in the way of libjpeg's encode_mcu_AC_refine, with
+    an end of band run of a single block."""
+    magnitudes = [abs(value) >> shift for value in block]
+    last = max([k for k in range(start, end + 1) if magnitudes[k] == 1], default=0)
+    run = 0
+    corrections = []
+    for k in range(start, end + 1):
+        magnitude = magnitudes[k]
+        if magnitude == 0:
+            run += 1
+            continue
+        while run > 15 and k <= last:
+            writer.write(*AC_CODES[0xF0])
+            run -= 16
+            for bit in corrections:
+                writer.write(bit, 1)
+            corrections = []
+        if magnitude > 1:
+            corrections.append(magnitude & 1)
+            continue
+        writer.write(*AC_CODES[(run << 4) | 1])
+        writer.write(1 if block[k] > 0 else 0, 1)
+        for bit in corrections:
+            writer.write(bit, 1)
+        corrections = []
+        run = 0
+    if run or corrections:
+        writer.write(*AC_CODES[0x00])
+        for bit in corrections:
+            writer.write(bit, 1)
+
+
+INVERSE_ZIGZAG = [ZIGZAG.index(n) for n in range(64)]
+
+# Lossless transforms as performed by jpegtran, composed of transpositions and flips.
+TRANSFORMS = {
+    "flip-horizontal": ["flip-horizontal"],
+    "flip-vertical": ["flip-vertical"],
+    "transpose": ["transpose"],
+    "transverse": ["transpose", "flip-horizontal", "flip-vertical"],
+    "rotate-90": ["transpose", "flip-horizontal"],
+    "rotate-180": ["flip-horizontal", "flip-vertical"],
+    "rotate-270": ["transpose", "flip-vertical"],
+}
+
+
+def transform_block(block, operation):
+    """Transform the zigzag ordered coefficients of a block."""
+    out = [0] * 64
+    for k in range(64):
+        v, u = divmod(ZIGZAG[k], 8)
+        if operation == "flip-horizontal":
+            out[k] = -block[k] if u % 2 else block[k]
+        elif operation == "flip-vertical":
+            out[k] = -block[k] if v % 2 else block[k]
+        else:
+            out[k] = block[INVERSE_ZIGZAG[u * 8 + v]]
+    return out
+
+
+def transform_component(component, operation):
+    h, v, blocks, cw, ch = component
+    if operation == "flip-horizontal":
+        blocks = [[transform_block(block, operation) for block in reversed(row)] for row in blocks]
+    elif operation == "flip-vertical":
+        blocks = [[transform_block(block, operation) for block in row] for row in reversed(blocks)]
+    else:
+        blocks = [[transform_block(row[column], operation) for row in blocks] for column in range(len(blocks[0]))]
+        h, v, cw, ch = v, h, ch, cw
+    return h, v, blocks, cw, ch
+
+
+def segment(marker, payload):
+    return bytes([0xFF, marker]) + struct.pack(">H", len(payload) + 2) + payload
+
+
+def encode(path, planes, width, height, sampling, quant_ids, precision=8, progressive=False,
+           restart=0, adobe=None, transform=None, script=None):
+    """Encode component planes (already at their sampled resolution), optionally transforming the
+    coded image losslessly. Transforms require dimensions that are multiples of the MCU size."""
+    shift = 1 << (precision - 1)
+    hmax = max(h for h, v in sampling)
+    vmax = max(v for h, v in sampling)
+    quants = [Q_LUMA, Q_CHROMA]
+    scale = 16 if precision == 12 else 1
+    components = []
+    for index, plane in enumerate(planes):
+        h, v = sampling[index]
+        cw = (width * h + hmax - 1) // hmax
+        ch = (height * v + vmax - 1) // vmax
+        quant = [q * scale for q in quants[quant_ids[index]]]
+        blocks = quantized_blocks(plane, cw, ch, quant, shift)
+        components.append((h, v, blocks, cw, ch))
+
+    for operation in TRANSFORMS.get(transform, []):
+        components = [transform_component(component, operation) for component in components]
+        if operation == "transpose":
+            width, height = height, width
+            sampling = [(v, h) for h, v in sampling]
+            quants = [[quant[(n % 8) * 8 + n // 8] for n in range(64)] for quant in quants]
+            hmax, vmax = vmax, hmax
+
+    data = bytearray([0xFF, 0xD8])
+    if adobe is None:
+        data += segment(0xE0, b"JFIF\0\x01\x01\x00\x00\x01\x00\x01\x00\x00")
+    else:
+        data += segment(0xEE, b"Adobe" + struct.pack(">HHHB", 100, 0, 0, adobe))
+    for qid in sorted(set(quant_ids)):
+        q = quants[qid]
+        if precision == 12:
+            data += segment(0xDB, bytes([0x10 | qid]) + b"".join(struct.pack(">H", q[ZIGZAG[k]] * scale) for k in range(64)))
+        else:
+            data += segment(0xDB, bytes([qid]) + bytes(q[ZIGZAG[k]] for k in range(64)))
+    sof = 0xC2 if progressive or script else (0xC1 if precision == 12 else 0xC0)
+    payload = struct.pack(">BHHB", precision, height, width, len(planes))
+    for index in range(len(planes)):
+        h, v = sampling[index]
+        payload += bytes([index + 1, (h << 4) | v, quant_ids[index]])
+    data += segment(sof, payload)
+    data += segment(0xC4, bytes([0x00]) + bytes(DC_BITS) + bytes(DC_SYMBOLS))
+    data += segment(0xC4, bytes([0x10]) + bytes(AC_BITS) + bytes(AC_SYMBOLS))
+    if restart:
+        data += segment(0xDD, struct.pack(">H", restart))
+
+    mcux = (width + 8 * hmax - 1) // (8 * hmax)
+    mcuy = (height + 8 * vmax - 1) // (8 * vmax)
+
+    def block_at(component, bx, by):
+        h, v, blocks, cw, ch = component
+        by = min(by, len(blocks) - 1)
+        bx = min(bx, len(blocks[0]) - 1)
+        return blocks[by][bx]
+
+    def scan(ids, ss, se, interleaved, ah=0, al=0):
+        header = bytes([len(ids)])
+        for index in ids:
+            header += bytes([index + 1, 0x00])
+        header += bytes([ss, se, (ah << 4) | al])
+        out = segment(0xDA, header)
+        writer = BitWriter()
+        predictors = [0] * len(planes)
+        units = []
+        if interleaved:
+            for my in range(mcuy):
+                for mx in range(mcux):
+                    unit = []
+                    for index in ids:
+                        h, v = sampling[index]
+                        for y in range(v):
+                            for x in range(h):
+                                unit.append((index, block_at(components[index], mx * h + x, my * v + y)))
+                    units.append(unit)
+        else:
+            (index,) = ids
+            h, v, blocks, cw, ch = components[index]
+            for by in range((ch + 7) // 8):
+                for bx in range((cw + 7) // 8):
+                    units.append([(index, blocks[by][bx])])
+        marker = 0
+        for number, unit in enumerate(units):
+            if restart and number and number % restart == 0:
+                writer.flush()
+                writer.out += bytes([0xFF, 0xD0 + marker])
+                marker = (marker + 1) % 8
+                predictors = [0] * len(planes)
+            for index, block in unit:
+                if ss == 0 and ah:
+                    writer.write((block[0] >> al) & 1, 1)
+                elif ss == 0:
+                    diff = (block[0] >> al) - predictors[index]
+                    predictors[index] = block[0] >> al
+                    size = category(diff)
+                    writer.write(*DC_CODES[size])
+                    write_value(writer, diff, size)
+                    if se > 0:
+                        encode_ac(writer, block, 1, se)
+                elif ah:
+                    encode_ac_refinement(writer, block, ss, se, al)
+                else:
+                    encode_ac(writer, block, ss, se, al)
+        writer.flush()
+        return out + bytes(writer.out)
+
+    all_ids = list(range(len(planes)))
+    if script:
+        for ids, ss, se, ah, al in script:
+            data += scan(ids, ss, se, len(ids) > 1, ah, al)
+    elif progressive:
+        data += scan(all_ids, 0, 0, True)
+        for index in all_ids:
+            data += scan([index], 1, 5, False)
+        for index in reversed(all_ids):
+            data += scan([index], 6, 63, False)
+    else:
+        data += scan(all_ids, 0, 63, len(all_ids) > 1)
+    data += bytes([0xFF, 0xD9])
+    with open(path, "wb") as f:
+        f.write(data)
+
+
+def pattern(width, height, channel, maximum=255):
+    plane = []
+    for y in range(height):
+        row = []
+        for x in range(width):
+            cx, cy = x - width / 2, y - height / 2
+            inside = cx * cx + cy * cy < (min(width, height) / 3) ** 2
+            value = (x * (5 + 3 * channel) + y * (7 - 2 * channel) + (90 if inside else 0)) % 256
+            row.append(value * maximum // 255)
+        plane.append(row)
+    return plane
+
+
+def ycbcr(width, height):
+    r, g, b = (pattern(width, height, c) for c in range(3))
+    Y = [[0] * width for _ in range(height)]
+    Cb = [[0] * width for _ in range(height)]
+    Cr = [[0] * width for _ in range(height)]
+    for y in range(height):
+        for x in range(width):
+            R, G, B = r[y][x], g[y][x], b[y][x]
+            Y[y][x] = max(0, min(255, round(0.299 * R + 0.587 * G + 0.114 * B)))
+            Cb[y][x] = max(0, min(255, round(-0.168736 * R - 0.331264 * G + 0.5 * B + 128)))
+            Cr[y][x] = max(0, min(255, round(0.5 * R - 0.418688 * G - 0.081312 * B + 128)))
+    return Y, Cb, Cr
+
+
+def downsample(plane, width, height):
+    w, h = (width + 1) // 2, (height + 1) // 2
+    out = []
+    for y in range(h):
+        row = []
+        for x in range(w):
+            values = [plane[min(2 * y + dy, height - 1)][min(2 * x + dx, width - 1)] for dy in (0, 1) for dx in (0, 1)]
+            row.append((sum(values) + 2) // 4)
+        out.append(row)
+    return out
+
+
+if __name__ == "__main__":
+    directory = sys.argv[1]
+    os.makedirs(directory, exist_ok=True)
+    W, H = 37, 21
+
+    encode(os.path.join(directory, "grayscale.jpg"), [pattern(W, H, 0)], W, H, [(1, 1)], [0])
+
+    Y, Cb, Cr = ycbcr(W, H)
+    encode(os.path.join(directory, "ycbcr-444.jpg"), [Y, Cb, Cr], W, H, [(1, 1)] * 3, [0, 1, 1])
+    encode(os.path.join(directory, "ycbcr-420.jpg"), [Y, downsample(Cb, W, H), downsample(Cr, W, H)], W, H,
+           [(2, 2), (1, 1), (1, 1)], [0, 1, 1])
+    encode(os.path.join(directory, "restart-markers.jpg"), [Y, Cb, Cr], W, H, [(1, 1)] * 3, [0, 1, 1], restart=2)
+    encode(os.path.join(directory, "progressive.jpg"), [Y, Cb, Cr], W, H, [(1, 1)] * 3, [0, 1, 1], progressive=True)
+    encode(os.path.join(directory, "successive-approximation.jpg"), [Y, downsample(Cb, W, H), downsample(Cr, W, H)],
+           W, H, [(2, 2), (1, 1), (1, 1)], [0, 1, 1], script=SUCCESSIVE_APPROXIMATION)
+    encode(os.path.join(directory, "cmyk.jpg"), [pattern(W, H, c) for c in range(4)], W, H, [(1, 1)] * 4,
+           [0, 0, 0, 0], adobe=0)
+    encode(os.path.join(directory, "12-bit.jpg"), [pattern(W, H, 0, 4095)], W, H, [(1, 1)], [0], precision=12)
+
+    # Lossless transforms require dimensions that are multiples of the MCU size.
+    W, H = 32, 16
+    Y, Cb, Cr = ycbcr(W, H)
+    for transform in [None] + sorted(TRANSFORMS):
+        name = "orientation-" + transform if transform else "orientation"
+        encode(os.path.join(directory, name + ".jpg"), [Y, downsample(Cb, W, H), downsample(Cr, W, H)], W, H,
+               [(2, 2), (1, 1), (1, 1)], [0, 1, 1], transform=transform)
+    encode(os.path.join(directory, "orientation-rotate-90-progressive.jpg"),
+           [Y, downsample(Cb, W, H), downsample(Cr, W, H)], W, H, [(2, 2), (1, 1), (1, 1)], [0, 1, 1],
+           transform="rotate-90", script=SUCCESSIVE_APPROXIMATION)
+    encode(os.path.join(directory, "orientation-422.jpg"), [Y, [row[::2] for row in Cb], [row[::2] for row in Cr]],
+           W, H, [(2, 1), (1, 1), (1, 1)], [0, 1, 1])
+    encode(os.path.join(directory, "orientation-422-rotate-90.jpg"),
+           [Y, [row[::2] for row in Cb], [row[::2] for row in Cr]], W, H, [(2, 1), (1, 1), (1, 1)], [0, 1, 1],
+           transform="rotate-90")
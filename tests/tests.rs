@@ -148,6 +148,7 @@ fn test_verify_name() -> Result<(), Box<dyn std::error::Error>> {
 
     for file in files {
         let output = std::process::Command::new("./target/debug/ciid")
+            .arg("--quiet")
             .arg("--verify-name")
             .arg(file)
             .output()?;
@@ -173,6 +174,7 @@ fn test_verify_name_multiple() -> Result<(), Box<dyn std::error::Error>> {
     }
 
     let output = std::process::Command::new("./target/debug/ciid")
+        .arg("--quiet")
         .arg("--verify-name")
         .args(files)
         .output()?;
@@ -182,3 +184,994 @@ fn test_verify_name_multiple() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn test_warnings() -> Result<(), Box<dyn std::error::Error>> {
+    let file_path = "./tests/files/01483624375960-5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9.jpg";
+
+    let output = std::process::Command::new("./target/debug/ciid")
+        .arg(file_path)
+        .output()?;
+
+    assert_eq!(
+        std::str::from_utf8(&output.stderr)?,
+        format!(
+            "Warning: {}: No time zone information available, assumed +00:00\n",
+            std::fs::canonicalize(file_path)?.display()
+        )
+    );
+    assert!(output.status.success());
+
+    let output = std::process::Command::new("./target/debug/ciid")
+        .arg("--quiet")
+        .arg(file_path)
+        .output()?;
+
+    assert_eq!(std::str::from_utf8(&output.stderr)?, "");
+    assert!(output.status.success());
+
+    Ok(())
+}
+
+fn copy_to_temp_dir(
+    test_name: &str,
+    file_name: &str,
+) -> Result<std::path::PathBuf, Box<dyn std::error::Error>> {
+    let directory = std::env::temp_dir().join(format!("ciid-{}", test_name));
+
+    if directory.exists() {
+        std::fs::remove_dir_all(&directory)?;
+    }
+
+    std::fs::create_dir_all(&directory)?;
+
+    let file_path = directory.join(file_name);
+
+    std::fs::copy(
+        "./tests/files/01483624375960-5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9.jpg",
+        &file_path,
+    )?;
+
+    Ok(file_path)
+}
+
+#[test]
+fn test_migrate_dry_run() -> Result<(), Box<dyn std::error::Error>> {
+    let file_path = copy_to_temp_dir(
+        "test_migrate_dry_run",
+        "00002mbestj9g-as2eogok1ktdlc6o2c8h46ddtn18ptoo2thpt9vb4n6mk35b5esg.jpg",
+    )?;
+
+    let output = std::process::Command::new("./target/debug/ciid")
+        .arg("migrate")
+        .arg("--dry-run")
+        .arg(&file_path)
+        .output()?;
+
+    assert_eq!(
+        std::str::from_utf8(&output.stdout)?,
+        format!(
+            "rename  {} -> {} (dry run)\n",
+            file_path.display(),
+            file_path.with_file_name("01483624375960-5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9.jpg").display()
+        )
+    );
+    assert_eq!(std::str::from_utf8(&output.stderr)?, "");
+    assert!(output.status.success());
+    assert!(file_path.exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_migrate_scheme_version() -> Result<(), Box<dyn std::error::Error>> {
+    let file_path = copy_to_temp_dir(
+        "test_migrate_scheme_version",
+        "00002mbestj9g-as2eogok1ktdlc6o2c8h46ddtn18ptoo2thpt9vb4n6mk35b5esg-v1.jpg",
+    )?;
+
+    let output = std::process::Command::new("./target/debug/ciid")
+        .arg("migrate")
+        .arg(&file_path)
+        .output()?;
+
+    let migrated_file_path = file_path.with_file_name(
+        "01483624375960-5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9-v2.jpg",
+    );
+
+    assert_eq!(
+        std::str::from_utf8(&output.stdout)?,
+        format!(
+            "rename  {} -> {}\n",
+            file_path.display(),
+            migrated_file_path.display()
+        )
+    );
+    assert_eq!(std::str::from_utf8(&output.stderr)?, "");
+    assert!(output.status.success());
+    assert!(!file_path.exists());
+    assert!(migrated_file_path.exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_identifier_format() -> Result<(), Box<dyn std::error::Error>> {
+    let output = std::process::Command::new("./target/debug/ciid")
+        .arg("--identifier-format")
+        .arg("${date:%Y%m%d-%H%M%S}-${hash:8}")
+        .arg("./tests/files/01483617175960-d4f894f5e3414125ffa2b8a94637ab44034f9de931a6ca3ef6025c97cbd28a8d.CR2")
+        .output()?;
+
+    assert_eq!(
+        std::str::from_utf8(&output.stdout)?,
+        "20170105-115255-d4f894f5\n"
+    );
+    assert_eq!(std::str::from_utf8(&output.stderr)?, "");
+    assert!(output.status.success());
+
+    Ok(())
+}
+
+#[test]
+fn test_identifier_format_unsortable() -> Result<(), Box<dyn std::error::Error>> {
+    let output = std::process::Command::new("./target/debug/ciid")
+        .arg("--identifier-format")
+        .arg("${hash:8}-${timestamp_millis}")
+        .arg("./tests/files/01483617175960-d4f894f5e3414125ffa2b8a94637ab44034f9de931a6ca3ef6025c97cbd28a8d.CR2")
+        .output()?;
+
+    assert_eq!(std::str::from_utf8(&output.stdout)?, "");
+    assert!(!output.status.success());
+
+    Ok(())
+}
+
+#[test]
+fn test_identifier_format_path_separator() -> Result<(), Box<dyn std::error::Error>> {
+    let output = std::process::Command::new("./target/debug/ciid")
+        .arg("--identifier-format")
+        .arg("${date:%Y/%m/%d}-${hash:8}")
+        .arg("./tests/files/01483624375960-5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9.jpg")
+        .output()?;
+
+    assert_eq!(std::str::from_utf8(&output.stdout)?, "");
+    assert!(std::str::from_utf8(&output.stderr)?.contains("is not allowed in identifiers"));
+    assert!(!output.status.success());
+
+    Ok(())
+}
+
+#[test]
+fn test_identifier_format_scheme_version() -> Result<(), Box<dyn std::error::Error>> {
+    let output = std::process::Command::new("./target/debug/ciid")
+        .arg("--identifier-format")
+        .arg("${timestamp_millis}-${hash}")
+        .arg("--scheme-version")
+        .arg("./tests/files/01483624375960-5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9.jpg")
+        .output()?;
+
+    assert_eq!(std::str::from_utf8(&output.stdout)?, "");
+    assert!(!output.status.success());
+
+    Ok(())
+}
+
+#[test]
+fn test_json_clock_corrections() -> Result<(), Box<dyn std::error::Error>> {
+    let directory = std::env::temp_dir().join("ciid-test_json_clock_corrections");
+    std::fs::create_dir_all(&directory)?;
+
+    let clock_corrections = directory.join("clock-corrections.json");
+    std::fs::write(
+        &clock_corrections,
+        r#"[{ "path": "**/*.CR2", "offset": "+00:03:12" }]"#,
+    )?;
+
+    let output = std::process::Command::new("./target/debug/ciid")
+        .arg("--json")
+        .arg("--no-hash")
+        .arg("--clock-corrections")
+        .arg(&clock_corrections)
+        .arg("./tests/files/01483617175960-d4f894f5e3414125ffa2b8a94637ab44034f9de931a6ca3ef6025c97cbd28a8d.CR2")
+        .output()?;
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+
+    assert_eq!(json["identifier"], "01483617367960");
+    assert_eq!(json["date_time"], "2017-01-05T13:56:07.960+02:00");
+    assert_eq!(json["correction"]["rule"], 0);
+    assert_eq!(json["correction"]["offset"], "+00:03:12.000");
+    assert_eq!(std::str::from_utf8(&output.stderr)?, "");
+    assert!(output.status.success());
+
+    Ok(())
+}
+
+#[test]
+fn test_dedupe() -> Result<(), Box<dyn std::error::Error>> {
+    let file_path = copy_to_temp_dir("test_dedupe", "a.jpg")?;
+    let directory = file_path.parent().ok_or("Missing directory")?;
+
+    std::fs::create_dir(directory.join("sub"))?;
+    std::fs::copy(&file_path, directory.join("sub").join("b.JPG"))?;
+    std::fs::write(directory.join("notes.txt"), "")?;
+
+    let output = std::process::Command::new("./target/debug/ciid")
+        .arg("dedupe")
+        .arg("--action")
+        .arg("quarantine")
+        .arg("--quarantine-dir")
+        .arg(directory.join("quarantine"))
+        .arg(directory)
+        .output()?;
+
+    assert_eq!(
+        std::str::from_utf8(&output.stdout)?,
+        format!(
+            "duplicates 5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9\n  \
+             keep    {keep} (1485333 bytes)\n  \
+             dup     {duplicate} (1485333 bytes)\n\
+             1 duplicate set(s), 1 duplicate file(s), 1485333 bytes reclaimable\n\
+             move    {duplicate} -> {quarantined}\n",
+            keep = file_path.display(),
+            duplicate = directory.join("sub").join("b.JPG").display(),
+            quarantined = directory
+                .join("quarantine")
+                .join("sub")
+                .join("b.JPG")
+                .display()
+        )
+    );
+    assert_eq!(std::str::from_utf8(&output.stderr)?, "");
+    assert!(output.status.success());
+    assert!(file_path.exists());
+    assert!(directory
+        .join("quarantine")
+        .join("sub")
+        .join("b.JPG")
+        .exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_dedupe_overlapping_roots() -> Result<(), Box<dyn std::error::Error>> {
+    let file_path = copy_to_temp_dir("test_dedupe_overlapping_roots", "a.jpg")?;
+    let directory = file_path.parent().ok_or("Missing directory")?;
+    let link_path = directory.join("b.jpg");
+
+    // The same file is found through both roots, and through a hard link.
+    std::fs::hard_link(&file_path, &link_path)?;
+
+    for action in &["delete", "hardlink", "quarantine"] {
+        let output = std::process::Command::new("./target/debug/ciid")
+            .arg("dedupe")
+            .arg("--action")
+            .arg(action)
+            .arg("--quarantine-dir")
+            .arg(directory.join("quarantine"))
+            .arg("--yes")
+            .arg(directory)
+            .arg(directory.join("."))
+            .output()?;
+
+        assert_eq!(
+            std::str::from_utf8(&output.stdout)?,
+            "0 duplicate set(s), 0 duplicate file(s), 0 bytes reclaimable\n"
+        );
+        assert_eq!(std::str::from_utf8(&output.stderr)?, "");
+        assert!(output.status.success());
+        assert!(file_path.exists());
+        assert!(link_path.exists());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_dedupe_quarantine_same_name() -> Result<(), Box<dyn std::error::Error>> {
+    let file_path = copy_to_temp_dir("test_dedupe_quarantine_same_name", "a.jpg")?;
+    let directory = file_path.parent().ok_or("Missing directory")?;
+    let quarantine = directory.join("quarantine");
+
+    for sub in &["x", "y"] {
+        std::fs::create_dir(directory.join(sub))?;
+        std::fs::copy(&file_path, directory.join(sub).join("b.JPG"))?;
+    }
+
+    // Duplicates found in different directories, but at the same relative path.
+    let output = std::process::Command::new("./target/debug/ciid")
+        .arg("dedupe")
+        .arg("--action")
+        .arg("quarantine")
+        .arg("--quarantine-dir")
+        .arg(&quarantine)
+        .arg(&file_path)
+        .arg(directory.join("x"))
+        .arg(directory.join("y"))
+        .output()?;
+
+    let stdout = std::str::from_utf8(&output.stdout)?;
+
+    assert!(stdout.contains(&format!(
+        "move    {} -> {}\n",
+        directory.join("x").join("b.JPG").display(),
+        quarantine.join("b.JPG").display()
+    )));
+    assert!(stdout.contains(&format!(
+        "move    {} -> {}\n",
+        directory.join("y").join("b.JPG").display(),
+        quarantine.join("b-1.JPG").display()
+    )));
+    assert_eq!(std::str::from_utf8(&output.stderr)?, "");
+    assert!(output.status.success());
+    assert!(file_path.exists());
+    assert!(quarantine.join("b.JPG").exists());
+    assert!(quarantine.join("b-1.JPG").exists());
+    assert!(!directory.join("x").join("b.JPG").exists());
+    assert!(!directory.join("y").join("b.JPG").exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_dedupe_similar() -> Result<(), Box<dyn std::error::Error>> {
+    let file_path = copy_to_temp_dir("test_dedupe_similar", "a.jpg")?;
+
+    let output = std::process::Command::new("./target/debug/ciid")
+        .arg("dedupe")
+        .arg("--similar")
+        .arg("10")
+        .arg(&file_path)
+        .arg("./tests/files/01483624375960-5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9.jpg")
+        .output()?;
+
+    assert_eq!(
+        std::str::from_utf8(&output.stdout)?,
+        format!(
+            "similar 2 files\n  \
+             1f12131386963307  0 {}\n  \
+             1f12131386963307  0 ./tests/files/01483624375960-5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9.jpg\n\
+             1 similar set(s)\n",
+            file_path.display()
+        )
+    );
+    assert_eq!(std::str::from_utf8(&output.stderr)?, "");
+    assert!(output.status.success());
+
+    Ok(())
+}
+
+#[test]
+fn test_catalog() -> Result<(), Box<dyn std::error::Error>> {
+    let file_path = copy_to_temp_dir("test_catalog", "a.jpg")?;
+    let catalog = file_path.with_file_name("catalog.sqlite");
+
+    let output = std::process::Command::new("./target/debug/ciid")
+        .arg("index")
+        .arg("--catalog")
+        .arg(&catalog)
+        .arg(file_path.parent().ok_or("Missing directory")?)
+        .output()?;
+
+    assert_eq!(
+        std::str::from_utf8(&output.stdout)?,
+        format!(
+            "index   {} -> 01483624375960-5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9\n",
+            file_path.display()
+        )
+    );
+    assert_eq!(std::str::from_utf8(&output.stderr)?, "");
+    assert!(output.status.success());
+
+    let output = std::process::Command::new("./target/debug/ciid")
+        .arg("query")
+        .arg("--catalog")
+        .arg(&catalog)
+        .arg("--from")
+        .arg("2017-01-05")
+        .arg("--to")
+        .arg("2017-01-06")
+        .output()?;
+
+    assert_eq!(
+        std::str::from_utf8(&output.stdout)?,
+        format!(
+            "01483624375960-5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9 {}\n",
+            file_path.canonicalize()?.display()
+        )
+    );
+    assert_eq!(std::str::from_utf8(&output.stderr)?, "");
+    assert!(output.status.success());
+
+    let output = std::process::Command::new("./target/debug/ciid")
+        .arg("stats")
+        .arg("--catalog")
+        .arg(&catalog)
+        .output()?;
+
+    assert_eq!(
+        std::str::from_utf8(&output.stdout)?,
+        "files             1\n\
+         images            1\n\
+         bytes             1485333\n\
+         first             2017-01-05T13:52:55.960+00:00\n\
+         last              2017-01-05T13:52:55.960+00:00\n\
+         format            jpg 1\n\
+         date_tag          DateTimeOriginal 1\n\
+         time_zone_source  default 1\n"
+    );
+    assert_eq!(std::str::from_utf8(&output.stderr)?, "");
+    assert!(output.status.success());
+
+    Ok(())
+}
+
+#[test]
+fn test_catalog_missing() -> Result<(), Box<dyn std::error::Error>> {
+    let file_path = copy_to_temp_dir("test_catalog_missing", "a.jpg")?;
+    let catalog = file_path.with_file_name("catalog.sqlite");
+
+    for subcommand in &["query", "stats"] {
+        let output = std::process::Command::new("./target/debug/ciid")
+            .arg(subcommand)
+            .arg("--catalog")
+            .arg(&catalog)
+            .output()?;
+
+        assert_eq!(std::str::from_utf8(&output.stdout)?, "");
+        assert!(std::str::from_utf8(&output.stderr)?.contains("does not exist"));
+        assert!(!output.status.success());
+    }
+
+    assert!(!catalog.exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_cache() -> Result<(), Box<dyn std::error::Error>> {
+    let file_path = copy_to_temp_dir("test_cache", "a.jpg")?;
+    let cache_file = file_path.with_file_name("cache.sqlite");
+
+    for args in &[&[][..], &[][..], &["--rehash"][..]] {
+        let output = std::process::Command::new("./target/debug/ciid")
+            .arg("--quiet")
+            .arg("--cache-file")
+            .arg(&cache_file)
+            .args(*args)
+            .arg(&file_path)
+            .output()?;
+
+        assert_eq!(
+            std::str::from_utf8(&output.stdout)?,
+            "01483624375960-5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9\n"
+        );
+        assert_eq!(std::str::from_utf8(&output.stderr)?, "");
+        assert!(output.status.success());
+    }
+
+    assert!(cache_file.exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_cache_verify_corrupted() -> Result<(), Box<dyn std::error::Error>> {
+    let file_path = copy_to_temp_dir(
+        "test_cache_verify_corrupted",
+        "01483624375960-5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9.jpg",
+    )?;
+    let cache_file = file_path.with_file_name("cache.sqlite");
+
+    let output = std::process::Command::new("./target/debug/ciid")
+        .arg("--quiet")
+        .arg("--cache-file")
+        .arg(&cache_file)
+        .arg(&file_path)
+        .output()?;
+
+    assert!(output.status.success());
+
+    // Flip a bit within the entropy-coded data, keeping size and modification time.
+    let modified = std::fs::metadata(&file_path)?.modified()?;
+    let mut data = std::fs::read(&file_path)?;
+    let index = (data.len() / 2..data.len())
+        .find(|&index| data[index] < 0xf0)
+        .ok_or("No byte to corrupt")?;
+    data[index] ^= 0x01;
+    std::fs::write(&file_path, &data)?;
+    std::fs::OpenOptions::new()
+        .write(true)
+        .open(&file_path)?
+        .set_modified(modified)?;
+
+    for args in &[&["--verify", "hash"][..], &["--verify-name"][..]] {
+        let output = std::process::Command::new("./target/debug/ciid")
+            .arg("--quiet")
+            .arg("--cache-file")
+            .arg(&cache_file)
+            .args(*args)
+            .arg(&file_path)
+            .output()?;
+
+        assert!(!output.status.success());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_audit() -> Result<(), Box<dyn std::error::Error>> {
+    let ok = copy_to_temp_dir(
+        "test_audit",
+        "01483624375960-5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9.jpg",
+    )?;
+    let directory = ok.parent().ok_or("Missing directory")?;
+
+    let corrupt = directory.join(
+        "01483624375960-0000000000000000000000000000000000000000000000000000000000000000.jpg",
+    );
+    std::fs::copy(&ok, &corrupt)?;
+
+    let renamed = directory.join("IMG_1234.jpg");
+    std::fs::copy(&ok, &renamed)?;
+
+    let undecodable = directory.join(
+        "01483624375960-1111111111111111111111111111111111111111111111111111111111111111.jpg",
+    );
+    std::fs::write(&undecodable, "")?;
+
+    let output = std::process::Command::new("./target/debug/ciid")
+        .arg("audit")
+        .arg("--quiet")
+        .arg(directory)
+        .output()?;
+
+    let stdout = std::str::from_utf8(&output.stdout)?;
+    let lines: Vec<&str> = stdout.lines().collect();
+
+    assert_eq!(lines.len(), 3);
+    assert_eq!(
+        lines[0],
+        format!(
+            "corrupt     {}: expected 0000000000000000000000000000000000000000000000000000000000000000, got 5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9",
+            corrupt.display()
+        )
+    );
+    assert!(lines[1].starts_with(&format!("renamed     {}: ", renamed.display())));
+    assert_eq!(
+        lines[2],
+        "4 file(s): 1 ok, 1 corrupt, 1 renamed, 1 undecodable"
+    );
+
+    let stderr = std::str::from_utf8(&output.stderr)?;
+    let lines: Vec<&str> = stderr.lines().collect();
+
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with(&format!("undecodable {}: ", undecodable.display())));
+    assert_eq!(
+        lines[1],
+        "Error: \"Found 1 corrupt and 1 undecodable file(s)\""
+    );
+    assert!(!output.status.success());
+
+    Ok(())
+}
+
+#[test]
+fn test_verify_hash() -> Result<(), Box<dyn std::error::Error>> {
+    let file_path = copy_to_temp_dir(
+        "test_verify_hash",
+        "01483617175960-5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9.jpg",
+    )?;
+
+    let output = std::process::Command::new("./target/debug/ciid")
+        .arg("--quiet")
+        .arg("--verify")
+        .arg("hash")
+        .arg("--print")
+        .arg("")
+        .arg(&file_path)
+        .output()?;
+
+    assert_eq!(std::str::from_utf8(&output.stdout)?, "");
+    assert_eq!(std::str::from_utf8(&output.stderr)?, "");
+    assert!(output.status.success());
+
+    let output = std::process::Command::new("./target/debug/ciid")
+        .arg("--quiet")
+        .arg("--verify")
+        .arg("timestamp")
+        .arg("--print")
+        .arg("")
+        .arg(&file_path)
+        .output()?;
+
+    assert_eq!(std::str::from_utf8(&output.stdout)?, "");
+    assert!(std::str::from_utf8(&output.stderr)?.contains("off by -02:00:00.000"));
+    assert!(!output.status.success());
+
+    Ok(())
+}
+
+#[test]
+fn test_write_metadata() -> Result<(), Box<dyn std::error::Error>> {
+    let file_path = copy_to_temp_dir("test_write_metadata", "a.jpg")?;
+
+    let output = std::process::Command::new("./target/debug/ciid")
+        .arg("--write-metadata")
+        .arg("--write-image-unique-id")
+        .arg(&file_path)
+        .output()?;
+
+    assert_eq!(
+        std::str::from_utf8(&output.stdout)?,
+        "01483624375960-5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9\n"
+    );
+    assert_eq!(std::str::from_utf8(&output.stderr)?, "");
+    assert!(output.status.success());
+
+    let output = std::process::Command::new("./target/debug/ciid")
+        .arg("--verify")
+        .arg("metadata")
+        .arg(&file_path)
+        .output()?;
+
+    assert_eq!(
+        std::str::from_utf8(&output.stdout)?,
+        "01483624375960-5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9\n"
+    );
+    assert_eq!(std::str::from_utf8(&output.stderr)?, "");
+    assert!(output.status.success());
+
+    Ok(())
+}
+
+#[test]
+fn test_verify_metadata_raw_jpeg_pair() -> Result<(), Box<dyn std::error::Error>> {
+    let file_path = copy_to_temp_dir("test_verify_metadata_raw_jpeg_pair", "IMG_1234.jpg")?;
+
+    // Sidecar of the raw file `IMG_1234.CR2`, carrying the identifier of the JPEG image.
+    std::fs::write(
+        file_path.with_extension("xmp"),
+        "<x:xmpmeta xmlns:x='adobe:ns:meta/'>\
+         <rdf:RDF xmlns:rdf='http://www.w3.org/1999/02/22-rdf-syntax-ns#'>\
+         <rdf:Description rdf:about='' xmlns:xmpMM='http://ns.adobe.com/xap/1.0/mm/'>\
+         <xmpMM:DocumentID>ciid:01483624375960-5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9</xmpMM:DocumentID>\
+         </rdf:Description>\
+         </rdf:RDF>\
+         </x:xmpmeta>",
+    )?;
+
+    let output = std::process::Command::new("./target/debug/ciid")
+        .arg("--verify")
+        .arg("metadata")
+        .arg(&file_path)
+        .output()?;
+
+    assert_eq!(std::str::from_utf8(&output.stdout)?, "");
+    assert!(std::str::from_utf8(&output.stderr)?.contains("Metadata does not carry an identifier"));
+    assert!(!output.status.success());
+
+    Ok(())
+}
+
+#[test]
+fn test_manifest() -> Result<(), Box<dyn std::error::Error>> {
+    let file_path = copy_to_temp_dir("test_manifest", "a.jpg")?;
+    let directory = file_path.parent().ok_or("Missing directory")?;
+
+    let output = std::process::Command::new("./target/debug/ciid")
+        .arg("manifest")
+        .arg("--base-dir")
+        .arg(directory)
+        .arg(&file_path)
+        .output()?;
+
+    assert_eq!(
+        std::str::from_utf8(&output.stdout)?,
+        "5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9  a.jpg\n"
+    );
+    assert_eq!(std::str::from_utf8(&output.stderr)?, "");
+    assert!(output.status.success());
+
+    let manifest_path = directory.join("manifest-ciid.txt");
+    std::fs::write(
+        &manifest_path,
+        "5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9  a.jpg\n\
+         0000000000000000000000000000000000000000000000000000000000000000  b.jpg\n",
+    )?;
+    std::fs::copy(&file_path, directory.join("b.jpg"))?;
+    std::fs::copy(&file_path, directory.join("c.jpg"))?;
+
+    let output = std::process::Command::new("./target/debug/ciid")
+        .arg("manifest")
+        .arg("--check")
+        .arg(&manifest_path)
+        .arg(directory)
+        .output()?;
+
+    assert_eq!(
+        std::str::from_utf8(&output.stdout)?,
+        format!(
+            "{}: OK\n{}: FAILED\n{}: NOT IN MANIFEST\n",
+            directory.join("a.jpg").display(),
+            directory.join("b.jpg").display(),
+            directory.join("c.jpg").display()
+        )
+    );
+    assert_eq!(
+        std::str::from_utf8(&output.stderr)?,
+        "Error: \"1 of 2 file(s) failed verification\"\n"
+    );
+    assert!(!output.status.success());
+
+    Ok(())
+}
+
+#[test]
+fn test_manifest_bagit_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+    let file_path = copy_to_temp_dir("test_manifest_bagit_round_trip", "a.jpg")?;
+    let bag = file_path.parent().ok_or("Missing directory")?;
+    let data = bag.join("data");
+
+    std::fs::create_dir_all(data.join("sub"))?;
+    std::fs::rename(&file_path, data.join("sub").join("a.jpg"))?;
+    std::fs::write(data.join("notes.txt"), "notes")?;
+
+    let output = std::process::Command::new("./target/debug/ciid")
+        .arg("manifest")
+        .arg("--format")
+        .arg("bagit")
+        .arg("--base-dir")
+        .arg(bag)
+        .arg(&data)
+        .output()?;
+
+    let manifest = std::str::from_utf8(&output.stdout)?;
+    let lines: Vec<&str> = manifest.lines().collect();
+
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].ends_with("  data/notes.txt"));
+    assert!(lines[1].ends_with("  data/sub/a.jpg"));
+    assert_eq!(std::str::from_utf8(&output.stderr)?, "");
+    assert!(output.status.success());
+
+    let manifest_path = bag.join("manifest-sha256.txt");
+    std::fs::write(&manifest_path, manifest)?;
+
+    let output = std::process::Command::new("./target/debug/ciid")
+        .arg("manifest")
+        .arg("--format")
+        .arg("bagit")
+        .arg("--check")
+        .arg(&manifest_path)
+        .arg(bag)
+        .output()?;
+
+    assert_eq!(
+        std::str::from_utf8(&output.stdout)?,
+        format!(
+            "{}: OK\n{}: OK\n",
+            bag.join("data/notes.txt").display(),
+            bag.join("data/sub/a.jpg").display()
+        )
+    );
+    assert_eq!(std::str::from_utf8(&output.stderr)?, "");
+    assert!(output.status.success());
+
+    Ok(())
+}
+
+#[test]
+fn test_stdin() -> Result<(), Box<dyn std::error::Error>> {
+    let data = std::fs::read(
+        "./tests/files/01483624375960-5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9.jpg",
+    )?;
+
+    for args in &[vec!["-"], vec!["-", "--filename-hint", "IMG_1234.JPG"]] {
+        let mut child = std::process::Command::new("./target/debug/ciid")
+            .arg("--quiet")
+            .args(args)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()?;
+
+        std::io::Write::write_all(&mut child.stdin.take().ok_or("Missing stdin")?, &data)?;
+
+        let output = child.wait_with_output()?;
+
+        assert_eq!(
+            std::str::from_utf8(&output.stdout)?,
+            "01483624375960-5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9\n"
+        );
+        assert_eq!(std::str::from_utf8(&output.stderr)?, "");
+        assert!(output.status.success());
+    }
+
+    let output = std::process::Command::new("./target/debug/ciid")
+        .arg("-")
+        .arg("--rename-file")
+        .stdin(std::process::Stdio::null())
+        .output()?;
+
+    assert_eq!(
+        std::str::from_utf8(&output.stderr)?,
+        "Error: \"--rename-file can not be combined with reading from stdin or archives\"\n"
+    );
+    assert!(!output.status.success());
+
+    Ok(())
+}
+
+#[test]
+fn test_archive() -> Result<(), Box<dyn std::error::Error>> {
+    let file_path = copy_to_temp_dir("test_archive", "a.jpg")?;
+    let directory = file_path.parent().ok_or("Missing directory")?;
+    let archive_path = directory.join("batch.tar");
+
+    let mut builder = tar::Builder::new(std::fs::File::create(&archive_path)?);
+    builder.append_path_with_name(&file_path, "DCIM/IMG_1.jpg")?;
+    builder.append_path_with_name(&file_path, "DCIM/IMG_1 copy.jpg")?;
+    builder.append_path_with_name(&file_path, "DCIM/notes.txt")?;
+    builder.into_inner()?;
+
+    let extract_to = directory.join("extracted");
+
+    let output = std::process::Command::new("./target/debug/ciid")
+        .arg("--quiet")
+        .arg("--print")
+        .arg("${file_path} ${identifier}\n")
+        .arg("--extract-to")
+        .arg(&extract_to)
+        .arg(&archive_path)
+        .output()?;
+
+    assert_eq!(
+        std::str::from_utf8(&output.stdout)?,
+        format!(
+            "{archive}!/DCIM/IMG_1.jpg 01483624375960-5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9\n\
+             {archive}!/DCIM/IMG_1 copy.jpg 01483624375960-5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9\n",
+            archive = archive_path.display()
+        )
+    );
+    assert_eq!(std::str::from_utf8(&output.stderr)?, "");
+    assert!(output.status.success());
+
+    let extracted = extract_to.join(
+        "01483624375960-5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9.jpg",
+    );
+
+    assert_eq!(std::fs::read_dir(&extract_to)?.count(), 1);
+    assert_eq!(std::fs::read(&extracted)?, std::fs::read(&file_path)?);
+
+    // Extracting again keeps the existing file, reporting it by a warning.
+    let output = std::process::Command::new("./target/debug/ciid")
+        .arg("--extract-to")
+        .arg(&extract_to)
+        .arg(format!("{}!/DCIM/IMG_1.jpg", archive_path.display()))
+        .output()?;
+
+    assert!(std::str::from_utf8(&output.stderr)?.contains(&format!(
+        "Warning: {}!/DCIM/IMG_1.jpg: Not extracted, {} exists already\n",
+        archive_path.display(),
+        extracted.display()
+    )));
+    assert!(output.status.success());
+
+    let output = std::process::Command::new("./target/debug/ciid")
+        .arg("--quiet")
+        .arg("--extract-to")
+        .arg(&extract_to)
+        .arg(&file_path)
+        .output()?;
+
+    assert!(std::str::from_utf8(&output.stderr)?
+        .contains("--extract-to only applies to images read from stdin or archives"));
+    assert!(!output.status.success());
+
+    let output = std::process::Command::new("./target/debug/ciid")
+        .arg("--quiet")
+        .arg(format!("{}!/DCIM/IMG_2.jpg", archive_path.display()))
+        .output()?;
+
+    assert!(std::str::from_utf8(&output.stderr)?.contains("No member DCIM/IMG_2.jpg in archive"));
+    assert!(!output.status.success());
+
+    Ok(())
+}
+
+#[test]
+fn test_jpeg_hash_bitstream() -> Result<(), Box<dyn std::error::Error>> {
+    let output = std::process::Command::new("./target/debug/ciid")
+        .arg("--quiet")
+        .arg("--jpeg-hash")
+        .arg("bitstream")
+        .arg("./tests/files/01483624375960-5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9.jpg")
+        .output()?;
+
+    assert_eq!(
+        std::str::from_utf8(&output.stdout)?,
+        "01483624375960-8c2953d8b9907804b372dc442516dce8af63659405dcefd9a1b31dbcdebbc09b\n"
+    );
+    assert_eq!(std::str::from_utf8(&output.stderr)?, "");
+    assert!(output.status.success());
+
+    // Names derived with any mode verify, regardless of the mode used for verification.
+    for (file_name, jpeg_hash) in &[
+        (
+            "01483624375960-8c2953d8b9907804b372dc442516dce8af63659405dcefd9a1b31dbcdebbc09b.jpg",
+            "decoded",
+        ),
+        (
+            "01483624375960-5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9.jpg",
+            "bitstream",
+        ),
+        (
+            "01483624375960-be9f69097dbc4f3a4b09b99aa063df26c13c3450440bd4cb30d913468754dc36.jpg",
+            "orientation-independent",
+        ),
+    ] {
+        let file_path = copy_to_temp_dir("test_jpeg_hash_bitstream", file_name)?;
+
+        for verify in &["hash", "full"] {
+            let output = std::process::Command::new("./target/debug/ciid")
+                .arg("--quiet")
+                .arg("--verify")
+                .arg(verify)
+                .arg("--jpeg-hash")
+                .arg(jpeg_hash)
+                .arg("--print")
+                .arg("")
+                .arg(&file_path)
+                .output()?;
+
+            assert_eq!(std::str::from_utf8(&output.stderr)?, "");
+            assert!(output.status.success());
+        }
+
+        let output = std::process::Command::new("./target/debug/ciid")
+            .arg("audit")
+            .arg("--jpeg-hash")
+            .arg(jpeg_hash)
+            .arg("--quiet")
+            .arg(&file_path)
+            .output()?;
+
+        assert!(std::str::from_utf8(&output.stdout)?.contains("1 ok"));
+        assert!(output.status.success());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_compare_not_raw() -> Result<(), Box<dyn std::error::Error>> {
+    let file_path = "./tests/files/01483624375960-5704ec43140d3adab0d813111219adedc28cf71817639ea7eb25cd6a0cab2bb9.jpg";
+
+    let output = std::process::Command::new("./target/debug/ciid")
+        .arg("compare")
+        .arg(file_path)
+        .arg(file_path)
+        .output()?;
+
+    let stdout = std::str::from_utf8(&output.stdout)?;
+    let lines: Vec<&str> = stdout.lines().collect();
+
+    assert_eq!(lines.len(), 5);
+    assert!(lines[0].starts_with(&format!("original   {}: ", file_path)));
+    assert!(lines[1].starts_with(&format!("conversion {}: ", file_path)));
+    assert_eq!(lines[2], "divergent");
+    assert!(lines[3].starts_with("- The original can not be normalized: "));
+    assert!(lines[4].starts_with("- The conversion can not be normalized: "));
+    assert_eq!(
+        std::str::from_utf8(&output.stderr)?,
+        "Error: \"The normalized hashes of the original and the conversion diverge\"\n"
+    );
+    assert!(!output.status.success());
+
+    Ok(())
+}